@@ -0,0 +1,551 @@
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::Path;
+
+use crate::api::fetch_prices_with_failures;
+use crate::formats::{self, FileFormat};
+use crate::history::{fetch_price_history, PriceSeries};
+use crate::money;
+use crate::notify::NotifySettings;
+use crate::portfolio::{Holding, Portfolio};
+
+// Alert rules live in their own file next to the portfolio, written in any
+// of the portfolio formats:
+//
+//     [[rules]]
+//     name = "AAPL breakout"
+//     kind = "price-above"
+//     ticker = "AAPL"
+//     price = 200
+//
+// `alerts check` evaluates every rule against fresh quotes and reports the
+// ones that fire. Percentages are in percent (5 is 5%). An optional
+// `notify` section says where fired alerts are delivered (see notify.rs).
+
+pub const DEFAULT_LOOKBACK_DAYS: u32 = 365;
+
+fn default_lookback_days() -> u32 {
+    DEFAULT_LOOKBACK_DAYS
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum Condition {
+    /// The quote is at or above `price`.
+    PriceAbove { ticker: String, price: Decimal },
+    /// The quote is at or below `price`.
+    PriceBelow { ticker: String, price: Decimal },
+    /// The quote moved more than `percent` either way since the previous
+    /// close. Without a ticker, applies to every holding.
+    DailyMove {
+        #[serde(default)]
+        ticker: Option<String>,
+        percent: Decimal,
+    },
+    /// The ticker's share of the portfolio's value is more than `band`
+    /// points away from `target`.
+    WeightDrift { ticker: String, target: Decimal, band: Decimal },
+    /// The quote (or, without a ticker, the portfolio's value) is at least
+    /// `percent` below its highest close in the last `lookback_days`.
+    Drawdown {
+        #[serde(default)]
+        ticker: Option<String>,
+        percent: Decimal,
+        #[serde(default = "default_lookback_days")]
+        lookback_days: u32,
+    },
+}
+
+impl Condition {
+    /// Whether checking the condition needs price history.
+    pub fn needs_history(&self) -> bool {
+        matches!(self, Condition::DailyMove { .. } | Condition::Drawdown { .. })
+    }
+
+    fn lookback_days(&self) -> u32 {
+        match self {
+            Condition::Drawdown { lookback_days, .. } => *lookback_days,
+            // A week back always covers the previous close
+            Condition::DailyMove { .. } => 7,
+            _ => 0,
+        }
+    }
+
+    fn tickers(&self) -> Vec<String> {
+        match self {
+            Condition::PriceAbove { ticker, .. }
+            | Condition::PriceBelow { ticker, .. }
+            | Condition::WeightDrift { ticker, .. } => vec![ticker.clone()],
+            Condition::DailyMove { ticker, .. } | Condition::Drawdown { ticker, .. } => {
+                ticker.iter().cloned().collect()
+            }
+        }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let subject = |ticker: &Option<String>, all: &'static str| ticker.clone().unwrap_or_else(|| all.to_string());
+        match self {
+            Condition::PriceAbove { ticker, price } => write!(f, "{} at or above {}", ticker, price),
+            Condition::PriceBelow { ticker, price } => write!(f, "{} at or below {}", ticker, price),
+            Condition::DailyMove { ticker, percent } => {
+                write!(f, "{} moves more than {}% in a day", subject(ticker, "any holding"), percent)
+            }
+            Condition::WeightDrift { ticker, target, band } => {
+                write!(f, "{} weight outside {}% ± {} points", ticker, target, band)
+            }
+            Condition::Drawdown { ticker, percent, lookback_days } => write!(
+                f,
+                "{} {}% or more below its {}-day high",
+                subject(ticker, "portfolio"),
+                percent,
+                lookback_days
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Rule {
+    /// Shown in place of the condition when given.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(flatten)]
+    pub condition: Condition,
+}
+
+impl Rule {
+    pub fn label(&self) -> String {
+        self.name.clone().unwrap_or_else(|| self.condition.to_string())
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RuleSet {
+    #[serde(default)]
+    pub rules: Vec<Rule>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notify: Option<NotifySettings>,
+}
+
+impl RuleSet {
+    /// Load rules from a JSON, TOML or YAML file (by extension).
+    pub fn load_from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read alert rules: {:?}", path))?;
+        let format = FileFormat::from_path(path);
+        if format == FileFormat::Csv {
+            bail!("Alert rules must be JSON, TOML or YAML, not CSV");
+        }
+        let (value, _) = formats::parse(&content, format)?;
+        let set: RuleSet = serde_json::from_value(value)
+            .with_context(|| format!("Invalid alert rules in {:?}", path))?;
+        set.validate()?;
+        Ok(set)
+    }
+
+    fn validate(&self) -> Result<()> {
+        for (index, rule) in self.rules.iter().enumerate() {
+            let positive = |name: &str, value: Decimal| {
+                if value <= Decimal::ZERO {
+                    bail!("rules[{}]: {} must be positive, got {}", index, name, value);
+                }
+                Ok(())
+            };
+            match &rule.condition {
+                Condition::PriceAbove { price, .. } | Condition::PriceBelow { price, .. } => positive("price", *price)?,
+                Condition::DailyMove { percent, .. } => positive("percent", *percent)?,
+                Condition::WeightDrift { target, band, .. } => {
+                    if *target < Decimal::ZERO || *target > Decimal::ONE_HUNDRED {
+                        bail!("rules[{}]: target must be a percentage between 0 and 100, got {}", index, target);
+                    }
+                    positive("band", *band)?;
+                }
+                Condition::Drawdown { percent, lookback_days, .. } => {
+                    positive("percent", *percent)?;
+                    if *lookback_days == 0 {
+                        bail!("rules[{}]: lookback_days must be at least 1", index);
+                    }
+                }
+            }
+            if rule.condition.tickers().iter().any(|t| t.trim().is_empty()) {
+                bail!("rules[{}]: ticker must not be empty", index);
+            }
+        }
+        if let Some(notify) = &self.notify {
+            if notify.dedupe_minutes < 0 {
+                bail!("notify: dedupe_minutes must not be negative, got {}", notify.dedupe_minutes);
+            }
+        }
+        Ok(())
+    }
+
+    /// Tickers the rules name that may not be in the portfolio.
+    pub fn tickers(&self) -> Vec<String> {
+        self.rules.iter().flat_map(|r| r.condition.tickers()).collect()
+    }
+
+    /// Days of price history the rules look back over, or `None` if none
+    /// of them needs history.
+    pub fn lookback_days(&self) -> Option<u32> {
+        self.rules
+            .iter()
+            .filter(|r| r.condition.needs_history())
+            .map(|r| r.condition.lookback_days())
+            .max()
+    }
+}
+
+/// A rule that fired.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Alert {
+    pub rule: String,
+    pub kind: &'static str,
+    /// `None` for portfolio-wide alerts.
+    pub ticker: Option<String>,
+    /// What was measured: a price, or a percentage for moves, weights and
+    /// drawdowns.
+    pub value: Decimal,
+    pub threshold: Decimal,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AlertReport {
+    pub checked_at: DateTime<Utc>,
+    pub rules: usize,
+    pub fired: Vec<Alert>,
+    /// Tickers that could not be quoted. They are left out of the check, so
+    /// a rule on one fails instead of firing on a default price.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub unquoted: Vec<String>,
+}
+
+/// Fetch fresh quotes (and history, if a rule needs it) and check the rules.
+pub async fn check(portfolio: &Portfolio, rules: &RuleSet) -> Result<AlertReport> {
+    let mut tickers = portfolio.get_tickers();
+    tickers.extend(rules.tickers());
+    tickers.sort();
+    tickers.dedup();
+    let (mut quotes, unquoted) = fetch_prices_with_failures(&tickers).await?;
+    for ticker in &unquoted {
+        quotes.remove(ticker);
+    }
+    let today = Utc::now().date_naive();
+    let history = match rules.lookback_days() {
+        Some(days) => fetch_price_history(&tickers, today - chrono::Duration::days(days as i64), today).await?,
+        None => HashMap::new(),
+    };
+    let fired = evaluate(rules, &portfolio.holdings, &quotes, &history, today)?;
+    Ok(AlertReport { checked_at: Utc::now(), rules: rules.rules.len(), fired, unquoted })
+}
+
+/// Check every rule against the quotes and, for moves and drawdowns, the
+/// daily closes before today.
+pub fn evaluate(
+    rules: &RuleSet,
+    holdings: &[Holding],
+    quotes: &HashMap<String, Decimal>,
+    history: &HashMap<String, PriceSeries>,
+    today: chrono::NaiveDate,
+) -> Result<Vec<Alert>> {
+    let quote = |ticker: &str| quotes.get(ticker).copied().with_context(|| format!("No quote for {}", ticker));
+    let mut quantities: BTreeMap<&str, Decimal> = BTreeMap::new();
+    for h in holdings {
+        *quantities.entry(h.ticker.as_str()).or_default() += h.quantity;
+    }
+
+    let mut fired = Vec::new();
+    for rule in &rules.rules {
+        let mut fire = |ticker: Option<&str>, value: Decimal, threshold: Decimal, message: String| {
+            fired.push(Alert {
+                rule: rule.label(),
+                kind: kind_name(&rule.condition),
+                ticker: ticker.map(str::to_string),
+                value,
+                threshold,
+                message,
+            })
+        };
+        match &rule.condition {
+            Condition::PriceAbove { ticker, price } => {
+                let current = quote(ticker)?;
+                if current >= *price {
+                    fire(Some(ticker), current, *price, format!("{} is at {}, at or above {}", ticker, current, price));
+                }
+            }
+            Condition::PriceBelow { ticker, price } => {
+                let current = quote(ticker)?;
+                if current <= *price {
+                    fire(Some(ticker), current, *price, format!("{} is at {}, at or below {}", ticker, current, price));
+                }
+            }
+            Condition::DailyMove { ticker, percent } => {
+                let tickers: Vec<&str> = match ticker {
+                    Some(ticker) => vec![ticker.as_str()],
+                    None => quantities.keys().copied().collect(),
+                };
+                for ticker in tickers {
+                    let Some(previous) = history.get(ticker).and_then(|s| previous_close(s, today)) else {
+                        continue;
+                    };
+                    let current = quote(ticker)?;
+                    let change = money::round_percent(money::percentage(current - previous, previous));
+                    if change.abs() > *percent {
+                        fire(Some(ticker), change, *percent,
+                             format!("{} moved {:+}% today ({} -> {})", ticker, change, previous, current));
+                    }
+                }
+            }
+            Condition::WeightDrift { ticker, target, band } => {
+                let values: BTreeMap<&str, Decimal> = quantities
+                    .iter()
+                    .map(|(t, q)| Ok((*t, *q * quote(t)?)))
+                    .collect::<Result<_>>()?;
+                let total: Decimal = values.values().sum();
+                let weight = money::round_percent(money::percentage(
+                    values.get(ticker.as_str()).copied().unwrap_or_default(),
+                    total,
+                ));
+                if (weight - target).abs() > *band {
+                    fire(Some(ticker), weight, *target,
+                         format!("{} is {}% of the portfolio, target {}% ± {}", ticker, weight, target, band));
+                }
+            }
+            Condition::Drawdown { ticker, percent, lookback_days } => {
+                let from = today - chrono::Duration::days(*lookback_days as i64);
+                let (current, closes): (Decimal, Vec<Decimal>) = match ticker {
+                    Some(ticker) => {
+                        let closes = history
+                            .get(ticker)
+                            .map(|s| s.range(from..today).map(|(_, c)| *c).collect())
+                            .unwrap_or_default();
+                        (quote(ticker)?, closes)
+                    }
+                    None => {
+                        let current = quantities.iter().map(|(t, q)| Ok(*q * quote(t)?)).sum::<Result<Decimal>>()?;
+                        (current, portfolio_closes(&quantities, history, from, today))
+                    }
+                };
+                let peak = closes.into_iter().fold(current, Decimal::max);
+                let depth = money::round_percent(money::percentage(peak - current, peak));
+                if depth >= *percent {
+                    let subject = ticker.as_deref().unwrap_or("Portfolio");
+                    fire(ticker.as_deref(), depth, *percent,
+                         format!("{} is {}% below its {}-day high of {}", subject, depth, lookback_days,
+                                 money::round(peak, 2)));
+                }
+            }
+        }
+    }
+    Ok(fired)
+}
+
+pub fn kind_name(condition: &Condition) -> &'static str {
+    match condition {
+        Condition::PriceAbove { .. } => "price-above",
+        Condition::PriceBelow { .. } => "price-below",
+        Condition::DailyMove { .. } => "daily-move",
+        Condition::WeightDrift { .. } => "weight-drift",
+        Condition::Drawdown { .. } => "drawdown",
+    }
+}
+
+/// The close before the latest trading day up to `today`: the latest close
+/// is today's quote (or, on a weekend, Friday's), so the one before it is
+/// what the day's move is measured from.
+fn previous_close(series: &PriceSeries, today: chrono::NaiveDate) -> Option<Decimal> {
+    series.range(..=today).rev().nth(1).map(|(_, close)| *close)
+}
+
+/// Value of the current positions at each close before `today`, on days
+/// every holding has a price.
+fn portfolio_closes(
+    quantities: &BTreeMap<&str, Decimal>,
+    history: &HashMap<String, PriceSeries>,
+    from: chrono::NaiveDate,
+    today: chrono::NaiveDate,
+) -> Vec<Decimal> {
+    let Some(first) = quantities.keys().next().and_then(|t| history.get(*t)) else {
+        return Vec::new();
+    };
+    first
+        .range(from..today)
+        .filter_map(|(date, _)| {
+            quantities
+                .iter()
+                .map(|(t, q)| history.get(*t).and_then(|s| s.get(date)).map(|c| *q * c))
+                .sum::<Option<Decimal>>()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+    use rust_decimal_macros::dec;
+    use std::io::Write;
+    use tempfile::Builder;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    fn holding(ticker: &str, quantity: Decimal) -> Holding {
+        Holding {
+            ticker: ticker.to_string(),
+            quantity,
+            cost_basis: dec!(1),
+            date_purchased: "2024-01-01".to_string(),
+        }
+    }
+
+    fn rules(conditions: Vec<Condition>) -> RuleSet {
+        RuleSet {
+            rules: conditions.into_iter().map(|condition| Rule { name: None, condition }).collect(),
+            notify: None,
+        }
+    }
+
+    fn market() -> (Vec<Holding>, HashMap<String, Decimal>, HashMap<String, PriceSeries>) {
+        let holdings = vec![holding("AAA", dec!(10)), holding("BBB", dec!(5)), holding("AAA", dec!(10))];
+        let quotes = HashMap::from([("AAA".to_string(), dec!(90)), ("BBB".to_string(), dec!(40))]);
+        // Wednesday to Friday; the Friday close is today's quote
+        let history = HashMap::from([
+            ("AAA".to_string(), PriceSeries::from([(date(2024, 6, 5), dec!(120)), (date(2024, 6, 6), dec!(100)), (date(2024, 6, 7), dec!(90))])),
+            ("BBB".to_string(), PriceSeries::from([(date(2024, 6, 5), dec!(40)), (date(2024, 6, 6), dec!(40)), (date(2024, 6, 7), dec!(40))])),
+        ]);
+        (holdings, quotes, history)
+    }
+
+    fn check(conditions: Vec<Condition>, today: NaiveDate) -> Vec<Alert> {
+        let (holdings, quotes, history) = market();
+        evaluate(&rules(conditions), &holdings, &quotes, &history, today).unwrap()
+    }
+
+    #[test]
+    fn test_price_thresholds() {
+        let fired = check(
+            vec![
+                Condition::PriceAbove { ticker: "AAA".to_string(), price: dec!(90) },
+                Condition::PriceAbove { ticker: "AAA".to_string(), price: dec!(95) },
+                Condition::PriceBelow { ticker: "BBB".to_string(), price: dec!(45) },
+            ],
+            date(2024, 6, 7),
+        );
+        assert_eq!(fired.len(), 2);
+        assert_eq!(fired[0].kind, "price-above");
+        assert_eq!(fired[1].ticker.as_deref(), Some("BBB"));
+    }
+
+    #[test]
+    fn test_daily_move_uses_close_before_latest() {
+        let any = vec![Condition::DailyMove { ticker: None, percent: dec!(5) }];
+        // Friday: 100 -> 90 is -10%
+        let fired = check(any.clone(), date(2024, 6, 7));
+        assert_eq!(fired.len(), 1);
+        assert_eq!(fired[0].value, dec!(-10));
+        // Sunday: still measured against Thursday
+        assert_eq!(check(any, date(2024, 6, 9)).len(), 1);
+        assert!(check(vec![Condition::DailyMove { ticker: None, percent: dec!(10) }], date(2024, 6, 7)).is_empty());
+    }
+
+    #[test]
+    fn test_weight_drift() {
+        // AAA is 1800 of 2000: 90%
+        let drift = |target, band| Condition::WeightDrift { ticker: "AAA".to_string(), target, band };
+        let fired = check(vec![drift(dec!(80), dec!(5)), drift(dec!(88), dec!(5))], date(2024, 6, 7));
+        assert_eq!(fired.len(), 1);
+        assert_eq!(fired[0].value, dec!(90));
+        assert_eq!(fired[0].threshold, dec!(80));
+    }
+
+    #[test]
+    fn test_drawdown_of_ticker_and_portfolio() {
+        let drawdown = |ticker: Option<&str>, percent| Condition::Drawdown {
+            ticker: ticker.map(str::to_string),
+            percent,
+            lookback_days: 30,
+        };
+        let fired = check(
+            vec![drawdown(Some("AAA"), dec!(25)), drawdown(Some("BBB"), dec!(1)), drawdown(None, dec!(20))],
+            date(2024, 6, 7),
+        );
+        // AAA 120 -> 90 is 25%; portfolio 2600 -> 2000 is 23.08%
+        assert_eq!(fired.len(), 2);
+        assert_eq!(fired[0].value, dec!(25));
+        assert_eq!(fired[1].ticker, None);
+        assert_eq!(fired[1].value, dec!(23.08));
+    }
+
+    #[test]
+    fn test_missing_quote_is_an_error() {
+        let (holdings, quotes, history) = market();
+        let set = rules(vec![Condition::PriceAbove { ticker: "ZZZ".to_string(), price: dec!(1) }]);
+        assert!(evaluate(&set, &holdings, &quotes, &history, date(2024, 6, 7)).is_err());
+    }
+
+    #[test]
+    fn test_load_rules_from_toml() {
+        let mut file = Builder::new().suffix(".toml").tempfile().unwrap();
+        write!(
+            file,
+            r#"
+[[rules]]
+name = "AAPL breakout"
+kind = "price-above"
+ticker = "AAPL"
+price = 200
+
+[[rules]]
+kind = "drawdown"
+percent = 15.5
+
+[notify]
+dedupe_minutes = 60
+
+[[notify.sinks]]
+kind = "webhook"
+url = "http://localhost:9000/hook"
+
+[[notify.sinks]]
+kind = "desktop"
+"#
+        )
+        .unwrap();
+
+        let set = RuleSet::load_from_file(file.path()).unwrap();
+        assert_eq!(set.rules.len(), 2);
+        assert_eq!(set.rules[0].label(), "AAPL breakout");
+        assert_eq!(
+            set.rules[1].condition,
+            Condition::Drawdown { ticker: None, percent: dec!(15.5), lookback_days: DEFAULT_LOOKBACK_DAYS }
+        );
+        assert_eq!(set.rules[1].label(), "portfolio 15.5% or more below its 365-day high");
+        assert_eq!(set.lookback_days(), Some(DEFAULT_LOOKBACK_DAYS));
+        let notify = set.notify.unwrap();
+        assert_eq!(notify.dedupe_minutes, 60);
+        assert_eq!(notify.retries, crate::notify::DEFAULT_RETRIES);
+        assert_eq!(notify.sinks.len(), 2);
+        assert_eq!(notify.sinks[1], crate::notify::Sink::Desktop);
+    }
+
+    #[test]
+    fn test_load_rejects_bad_rules() {
+        let mut file = Builder::new().suffix(".json").tempfile().unwrap();
+        write!(file, r#"{{"rules": [{{"kind": "daily-move", "percent": -2}}]}}"#).unwrap();
+        let err = RuleSet::load_from_file(file.path()).unwrap_err();
+        assert!(err.to_string().contains("rules[0]: percent must be positive"));
+
+        let mut file = Builder::new().suffix(".json").tempfile().unwrap();
+        write!(file, r#"{{"rules": [{{"kind": "moon", "ticker": "AAPL"}}]}}"#).unwrap();
+        assert!(RuleSet::load_from_file(file.path()).is_err());
+    }
+}
@@ -1,11 +1,43 @@
 use anyhow::{Context, Result};
-use serde::Deserialize;
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::sync::OnceLock;
 
 // For this demo, we'll use Alpha Vantage API (free tier)
 // In a real application, you might want to use a different service
 const API_KEY: &str = "demo"; // Use "demo" for demonstration, replace with real API key
 
+/// Where quotes come from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum PriceProvider {
+    /// Built-in demo prices, asking Alpha Vantage for other tickers
+    #[default]
+    Demo,
+    /// Alpha Vantage for every ticker
+    AlphaVantage,
+    /// Built-in demo prices only; never uses the network
+    Offline,
+}
+
+static PROVIDER: OnceLock<(PriceProvider, Option<String>)> = OnceLock::new();
+
+/// Choose the price provider and Alpha Vantage API key. Called once from
+/// `main` with the configured values.
+pub fn set_price_provider(provider: PriceProvider, api_key: Option<String>) {
+    let _ = PROVIDER.set((provider, api_key));
+}
+
+/// The configured provider and the API key to use with it.
+pub(crate) fn price_provider() -> (PriceProvider, &'static str) {
+    match PROVIDER.get() {
+        Some((provider, key)) => (*provider, key.as_deref().unwrap_or(API_KEY)),
+        None => (PriceProvider::Demo, API_KEY),
+    }
+}
+
 #[derive(Debug, Deserialize)]
 struct AlphaVantageResponse {
     #[serde(rename = "Global Quote")]
@@ -23,63 +55,90 @@ struct GlobalQuote {
 }
 
 // Mock price data for demonstration when API is not available
-fn get_mock_prices() -> HashMap<String, f64> {
+fn get_mock_prices() -> HashMap<String, Decimal> {
     let mut prices = HashMap::new();
     
     // Stock prices
-    prices.insert("AAPL".to_string(), 170.0);
-    prices.insert("TSLA".to_string(), 700.0);
-    prices.insert("GOOGL".to_string(), 2800.0);
-    prices.insert("MSFT".to_string(), 350.0);
-    prices.insert("AMZN".to_string(), 3200.0);
-    prices.insert("NVDA".to_string(), 450.0);
-    prices.insert("META".to_string(), 320.0);
-    prices.insert("BRK.B".to_string(), 325.0);
-    prices.insert("JPM".to_string(), 145.0);
-    prices.insert("V".to_string(), 240.0);
+    prices.insert("AAPL".to_string(), dec!(170.0));
+    prices.insert("TSLA".to_string(), dec!(700.0));
+    prices.insert("GOOGL".to_string(), dec!(2800.0));
+    prices.insert("MSFT".to_string(), dec!(350.0));
+    prices.insert("AMZN".to_string(), dec!(3200.0));
+    prices.insert("NVDA".to_string(), dec!(450.0));
+    prices.insert("META".to_string(), dec!(320.0));
+    prices.insert("BRK.B".to_string(), dec!(325.0));
+    prices.insert("JPM".to_string(), dec!(145.0));
+    prices.insert("V".to_string(), dec!(240.0));
     
     // Crypto prices
-    prices.insert("BTC-USD".to_string(), 95000.0);
-    prices.insert("ETH-USD".to_string(), 3800.0);
-    prices.insert("BNB-USD".to_string(), 680.0);
-    prices.insert("ADA-USD".to_string(), 1.2);
-    prices.insert("SOL-USD".to_string(), 180.0);
+    prices.insert("BTC-USD".to_string(), dec!(95000.0));
+    prices.insert("ETH-USD".to_string(), dec!(3800.0));
+    prices.insert("BNB-USD".to_string(), dec!(680.0));
+    prices.insert("ADA-USD".to_string(), dec!(1.2));
+    prices.insert("SOL-USD".to_string(), dec!(180.0));
+
+    // Index funds, used as benchmarks
+    prices.insert("SPY".to_string(), dec!(540.0));
+    prices.insert("VTI".to_string(), dec!(270.0));
+    prices.insert("QQQ".to_string(), dec!(480.0));
+    prices.insert("AGG".to_string(), dec!(98.0));
+    prices.insert("BND".to_string(), dec!(72.0));
+    prices.insert("GLD".to_string(), dec!(215.0));
     
     prices
 }
 
-pub async fn fetch_current_prices(tickers: &[String]) -> Result<HashMap<String, f64>> {
+pub async fn fetch_current_prices(tickers: &[String]) -> Result<HashMap<String, Decimal>> {
+    let (prices, failed) = fetch_prices_with_failures(tickers).await?;
+    for ticker in failed {
+        eprintln!("Warning: Could not fetch price for {}, using default", ticker);
+    }
+    Ok(prices)
+}
+
+/// Like `fetch_current_prices`, but instead of printing a warning it also
+/// returns the tickers whose quote could not be fetched and that were given
+/// the default price.
+pub async fn fetch_prices_with_failures(tickers: &[String]) -> Result<(HashMap<String, Decimal>, Vec<String>)> {
     let mut prices = HashMap::new();
-    let mock_prices = get_mock_prices();
+    let mut failed = Vec::new();
+    let (provider, api_key) = price_provider();
+    let mock_prices = match provider {
+        PriceProvider::AlphaVantage => HashMap::new(),
+        PriceProvider::Demo | PriceProvider::Offline => get_mock_prices(),
+    };
 
     // For demonstration purposes, we'll use mock data
     // In a real application, you would make actual API calls
     for ticker in tickers {
         if let Some(&price) = mock_prices.get(ticker) {
             prices.insert(ticker.clone(), price);
+        } else if provider == PriceProvider::Offline {
+            failed.push(ticker.clone());
+            prices.insert(ticker.clone(), dec!(100.0));
         } else {
             // Try to fetch real price if available
-            match fetch_real_price(ticker).await {
+            match fetch_real_price(ticker, api_key).await {
                 Ok(price) => {
                     prices.insert(ticker.clone(), price);
                 }
                 Err(_) => {
                     // Fallback to a default price for unknown tickers
-                    println!("Warning: Could not fetch price for {}, using default", ticker);
-                    prices.insert(ticker.clone(), 100.0);
+                    failed.push(ticker.clone());
+                    prices.insert(ticker.clone(), dec!(100.0));
                 }
             }
         }
     }
 
-    Ok(prices)
+    Ok((prices, failed))
 }
 
-async fn fetch_real_price(ticker: &str) -> Result<f64> {
+async fn fetch_real_price(ticker: &str, api_key: &str) -> Result<Decimal> {
     let client = reqwest::Client::new();
     let url = format!(
         "https://www.alphavantage.co/query?function=GLOBAL_QUOTE&symbol={}&apikey={}",
-        ticker, API_KEY
+        ticker, api_key
     );
 
     let response: AlphaVantageResponse = client
@@ -100,7 +159,8 @@ async fn fetch_real_price(ticker: &str) -> Result<f64> {
     }
 
     if let Some(quote) = response.global_quote {
-        let price: f64 = quote.price.parse()
+        // Quotes arrive as strings ("170.1200"), so they parse exactly
+        let price: Decimal = quote.price.parse()
             .context("Failed to parse price as number")?;
         Ok(price)
     } else {
@@ -121,8 +181,8 @@ mod tests {
         assert!(prices.contains_key("BTC-USD"));
         assert!(prices.contains_key("ETH-USD"));
         
-        assert_eq!(*prices.get("AAPL").unwrap(), 170.0);
-        assert_eq!(*prices.get("BTC-USD").unwrap(), 95000.0);
+        assert_eq!(*prices.get("AAPL").unwrap(), dec!(170.0));
+        assert_eq!(*prices.get("BTC-USD").unwrap(), dec!(95000.0));
     }
 
     #[tokio::test]
@@ -136,8 +196,8 @@ mod tests {
         assert_eq!(prices.len(), 2);
         assert!(prices.contains_key("AAPL"));
         assert!(prices.contains_key("TSLA"));
-        assert_eq!(*prices.get("AAPL").unwrap(), 170.0);
-        assert_eq!(*prices.get("TSLA").unwrap(), 700.0);
+        assert_eq!(*prices.get("AAPL").unwrap(), dec!(170.0));
+        assert_eq!(*prices.get("TSLA").unwrap(), dec!(700.0));
     }
 
     #[tokio::test]
@@ -150,7 +210,7 @@ mod tests {
         
         assert_eq!(prices.len(), 1);
         assert!(prices.contains_key("UNKNOWN_TICKER"));
-        assert_eq!(*prices.get("UNKNOWN_TICKER").unwrap(), 100.0); // Default fallback price
+        assert_eq!(*prices.get("UNKNOWN_TICKER").unwrap(), dec!(100.0)); // Default fallback price
     }
 
     #[tokio::test]
@@ -172,8 +232,18 @@ mod tests {
         let prices = result.unwrap();
         
         assert_eq!(prices.len(), 3);
-        assert_eq!(*prices.get("AAPL").unwrap(), 170.0);
-        assert_eq!(*prices.get("UNKNOWN").unwrap(), 100.0);
-        assert_eq!(*prices.get("BTC-USD").unwrap(), 95000.0);
+        assert_eq!(*prices.get("AAPL").unwrap(), dec!(170.0));
+        assert_eq!(*prices.get("UNKNOWN").unwrap(), dec!(100.0));
+        assert_eq!(*prices.get("BTC-USD").unwrap(), dec!(95000.0));
+    }
+
+    #[tokio::test]
+    async fn test_fetch_prices_with_failures_lists_fallbacks() {
+        let tickers = vec!["AAPL".to_string(), "UNKNOWN".to_string()];
+        let (prices, failed) = fetch_prices_with_failures(&tickers).await.unwrap();
+
+        assert_eq!(prices.len(), 2);
+        assert_eq!(*prices.get("UNKNOWN").unwrap(), dec!(100.0));
+        assert_eq!(failed, vec!["UNKNOWN".to_string()]);
     }
 }
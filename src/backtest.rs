@@ -0,0 +1,371 @@
+use anyhow::{bail, Context, Result};
+use chrono::{Datelike, NaiveDate};
+use rust_decimal::Decimal;
+use serde::Serialize;
+use std::collections::HashMap;
+
+use crate::history::{align, PriceSeries};
+use crate::money;
+use crate::risk;
+
+// Replays an investment strategy over historical closes. Trades happen at
+// the day's close in whole units of the asset's precision, so a little cash
+// can be left over; it stays in the account and counts towards its value.
+// There are no fees or taxes. Return, volatility and drawdown are measured
+// on time-weighted returns, so money paid in along the way does not count
+// as growth.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Strategy {
+    /// Invest everything on the first day and hold
+    LumpSum,
+    /// Invest equal amounts on the first trading day of every month
+    Dca,
+    /// Invest everything on the first day and rebalance to the weights periodically
+    Rebalance,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Frequency {
+    Monthly,
+    Quarterly,
+    Yearly,
+}
+
+impl Frequency {
+    /// Whether `date` falls in a later period than `previous`.
+    fn starts_period(self, previous: NaiveDate, date: NaiveDate) -> bool {
+        let key = |d: NaiveDate| match self {
+            Frequency::Monthly => (d.year(), d.month0()),
+            Frequency::Quarterly => (d.year(), d.month0() / 3),
+            Frequency::Yearly => (d.year(), 0),
+        };
+        key(previous) != key(date)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BacktestConfig {
+    pub strategy: Strategy,
+    /// Target weights that sum to 1.
+    pub weights: Vec<(String, f64)>,
+    /// Total amount invested; DCA spreads it over the months in the range.
+    pub amount: Decimal,
+    pub rebalance: Frequency,
+    pub currency: String,
+}
+
+/// The account on one trading day.
+#[derive(Debug, Clone, Serialize)]
+pub struct BacktestPoint {
+    pub date: NaiveDate,
+    pub invested: Decimal,
+    pub value: Decimal,
+}
+
+/// A position at the end of the backtest, laid out like a performance row.
+#[derive(Debug, Clone, Serialize)]
+pub struct BacktestRow {
+    pub ticker: String,
+    pub quantity: Decimal,
+    pub average_cost: Decimal,
+    pub last_price: Decimal,
+    pub value: Decimal,
+    pub gain_loss: Decimal,
+    pub return_percentage: Decimal,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BacktestResult {
+    pub strategy: Strategy,
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub rows: Vec<BacktestRow>,
+    pub cash: Decimal,
+    pub invested: Decimal,
+    pub final_value: Decimal,
+    pub total_gain_loss: Decimal,
+    pub total_return_percentage: Decimal,
+    /// Time-weighted figures as fractions (0.2 is 20%).
+    pub annual_return: f64,
+    pub volatility: f64,
+    pub max_drawdown: f64,
+    pub trades: usize,
+    pub values: Vec<BacktestPoint>,
+}
+
+struct Position {
+    quantity: Decimal,
+    cost: Decimal,
+}
+
+pub fn backtest(config: &BacktestConfig, history: &HashMap<String, PriceSeries>) -> Result<BacktestResult> {
+    if config.weights.is_empty() {
+        bail!("The backtest needs at least one ticker to invest in");
+    }
+    if config.amount <= Decimal::ZERO {
+        bail!("The amount to invest must be positive, got {}", config.amount);
+    }
+    let series: Vec<&PriceSeries> = config
+        .weights
+        .iter()
+        .map(|(t, _)| history.get(t).with_context(|| format!("No price history for {}", t)))
+        .collect::<Result<_>>()?;
+    let (dates, _) = align(&series);
+    if dates.len() < 2 {
+        bail!("Not enough price history: {} common trading day(s), need at least 2", dates.len());
+    }
+
+    let weights: Vec<Decimal> = config.weights.iter().map(|(_, w)| money::from_f64(*w)).collect();
+    let tickers: Vec<&str> = config.weights.iter().map(|(t, _)| t.as_str()).collect();
+    let mut positions: Vec<Position> =
+        tickers.iter().map(|_| Position { quantity: Decimal::ZERO, cost: Decimal::ZERO }).collect();
+
+    // DCA pays in one installment per month, the last one taking the
+    // rounding remainder so the installments add up to the amount
+    let months = 1 + dates.windows(2).filter(|w| Frequency::Monthly.starts_period(w[0], w[1])).count();
+    let installment = money::round_money(config.amount / Decimal::from(months), &config.currency);
+
+    let mut cash = Decimal::ZERO;
+    let mut invested = Decimal::ZERO;
+    let mut trades = 0;
+    let mut paid_in = 0;
+    let mut values = Vec::with_capacity(dates.len());
+    let mut index = Vec::with_capacity(dates.len());
+
+    for (day, &date) in dates.iter().enumerate() {
+        let prices: Vec<Decimal> = series.iter().map(|s| s[&date]).collect();
+        let new_period = |frequency: Frequency| day > 0 && frequency.starts_period(dates[day - 1], date);
+
+        let deposit = match config.strategy {
+            Strategy::LumpSum | Strategy::Rebalance if day == 0 => config.amount,
+            Strategy::Dca if day == 0 || new_period(Frequency::Monthly) => {
+                paid_in += 1;
+                if paid_in == months { config.amount - invested } else { installment }
+            }
+            _ => Decimal::ZERO,
+        };
+        if deposit > Decimal::ZERO {
+            cash += deposit;
+            invested += deposit;
+            // Put the new money to work at the target weights
+            for ((position, price), (weight, ticker)) in positions.iter_mut().zip(&prices).zip(weights.iter().zip(&tickers)) {
+                let quantity = money::floor_quantity(deposit * weight / price, ticker);
+                if quantity > Decimal::ZERO {
+                    position.quantity += quantity;
+                    position.cost += quantity * price;
+                    cash -= quantity * price;
+                    trades += 1;
+                }
+            }
+        } else if config.strategy == Strategy::Rebalance && new_period(config.rebalance) {
+            let total = cash + holdings_value(&positions, &prices);
+            let mut spent = Decimal::ZERO;
+            for ((position, price), (weight, ticker)) in positions.iter_mut().zip(&prices).zip(weights.iter().zip(&tickers)) {
+                let target = money::floor_quantity(total * weight / price, ticker);
+                if target > position.quantity {
+                    position.cost += (target - position.quantity) * price;
+                    trades += 1;
+                } else if target < position.quantity {
+                    position.cost = position.cost * target / position.quantity;
+                    trades += 1;
+                }
+                position.quantity = target;
+                spent += target * price;
+            }
+            cash = total - spent;
+        }
+
+        let value = cash + holdings_value(&positions, &prices);
+        // Growth of one unit invested at the start, ignoring deposits
+        let growth = match values.last() {
+            Some(BacktestPoint { value: previous, .. }) if !previous.is_zero() => {
+                money::to_f64((value - deposit) / previous)
+            }
+            _ => 1.0,
+        };
+        index.push(index.last().copied().unwrap_or(1.0) * growth);
+        values.push(BacktestPoint { date, invested, value });
+    }
+
+    let last_prices: Vec<Decimal> = series.iter().map(|s| s[&dates[dates.len() - 1]]).collect();
+    let rows: Vec<BacktestRow> = positions
+        .iter()
+        .zip(&last_prices)
+        .zip(&tickers)
+        .map(|((position, price), ticker)| {
+            let value = money::round_money(position.quantity * price, &config.currency);
+            let cost = money::round_money(position.cost, &config.currency);
+            let average_cost = if position.quantity.is_zero() {
+                Decimal::ZERO
+            } else {
+                money::round(position.cost / position.quantity, money::currency_decimals(&config.currency) + 4)
+            };
+            BacktestRow {
+                ticker: ticker.to_string(),
+                quantity: position.quantity,
+                average_cost,
+                last_price: *price,
+                value,
+                gain_loss: value - cost,
+                return_percentage: money::round_percent(money::percentage(value - cost, cost)),
+            }
+        })
+        .collect();
+
+    let cash = money::round_money(cash, &config.currency);
+    let final_value = rows.iter().map(|r| r.value).sum::<Decimal>() + cash;
+    let metrics = risk::metrics(&index, &[], 0.0);
+
+    Ok(BacktestResult {
+        strategy: config.strategy,
+        from: dates[0],
+        to: dates[dates.len() - 1],
+        rows,
+        cash,
+        invested,
+        final_value,
+        total_gain_loss: final_value - invested,
+        total_return_percentage: money::round_percent(money::percentage(final_value - invested, invested)),
+        annual_return: metrics.annual_return,
+        volatility: metrics.volatility,
+        max_drawdown: metrics.max_drawdown,
+        trades,
+        values,
+    })
+}
+
+fn holdings_value(positions: &[Position], prices: &[Decimal]) -> Decimal {
+    positions.iter().zip(prices).map(|(p, price)| p.quantity * price).sum()
+}
+
+/// The last point of every month, for printing value over time.
+pub fn month_ends(values: &[BacktestPoint]) -> Vec<&BacktestPoint> {
+    values
+        .iter()
+        .enumerate()
+        .filter(|(i, point)| {
+            values
+                .get(i + 1)
+                .is_none_or(|next| Frequency::Monthly.starts_period(point.date, next.date))
+        })
+        .map(|(_, point)| point)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use rust_decimal_macros::dec;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    /// One close every 10 days from 2024-01-01, so three per month.
+    fn series(closes: &[Decimal]) -> PriceSeries {
+        closes
+            .iter()
+            .enumerate()
+            .map(|(i, c)| (date(2024, 1, 1) + Duration::days(10 * i as i64), *c))
+            .collect()
+    }
+
+    fn config(strategy: Strategy, weights: &[(&str, f64)]) -> BacktestConfig {
+        BacktestConfig {
+            strategy,
+            weights: weights.iter().map(|(t, w)| (t.to_string(), *w)).collect(),
+            amount: dec!(1000),
+            rebalance: Frequency::Monthly,
+            currency: "USD".to_string(),
+        }
+    }
+
+    /// AAA doubles in January and halves again on the last day.
+    fn history() -> HashMap<String, PriceSeries> {
+        HashMap::from([
+            ("AAA".to_string(), series(&[dec!(10), dec!(20), dec!(20), dec!(20), dec!(20), dec!(10)])),
+            ("BBB".to_string(), series(&[dec!(10), dec!(10), dec!(10), dec!(10), dec!(10), dec!(10)])),
+        ])
+    }
+
+    #[test]
+    fn test_lump_sum_buys_and_holds() {
+        let result = backtest(&config(Strategy::LumpSum, &[("AAA", 1.0)]), &history()).unwrap();
+
+        assert_eq!(result.rows[0].quantity, dec!(100));
+        assert_eq!(result.invested, dec!(1000));
+        assert_eq!(result.final_value, dec!(1000));
+        assert_eq!(result.total_return_percentage, dec!(0));
+        assert_eq!(result.trades, 1);
+        // Doubled, then fell back by half
+        assert!((result.max_drawdown - 0.5).abs() < 1e-12);
+        assert_eq!(result.values[1].value, dec!(2000));
+    }
+
+    #[test]
+    fn test_dca_spreads_the_amount_over_months() {
+        // AAA doubles in January and halves just before February's installment
+        let history = HashMap::from([(
+            "AAA".to_string(),
+            series(&[dec!(10), dec!(20), dec!(20), dec!(20), dec!(10), dec!(10)]),
+        )]);
+        let result = backtest(&config(Strategy::Dca, &[("AAA", 1.0)]), &history).unwrap();
+
+        // 2024-01-01, 01-11, 01-21 | 01-31 | 02-10, 02-20: two months
+        let deposits: Vec<Decimal> = result.values.iter().map(|p| p.invested).collect();
+        assert_eq!(deposits, vec![dec!(500), dec!(500), dec!(500), dec!(500), dec!(1000), dec!(1000)]);
+        // 50 shares at 10 and 50 at 10 in February
+        assert_eq!(result.rows[0].quantity, dec!(100));
+        assert_eq!(result.rows[0].average_cost, dec!(10));
+        assert_eq!(result.final_value, dec!(1000));
+        // The account's value never drops, but the fall is not hidden by the deposit
+        assert!((result.max_drawdown - 0.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_rebalance_restores_target_weights() {
+        let mut config = config(Strategy::Rebalance, &[("AAA", 0.5), ("BBB", 0.5)]);
+        config.rebalance = Frequency::Monthly;
+        let result = backtest(&config, &history()).unwrap();
+
+        // 50 AAA + 50 BBB. AAA doubles to 1500 in total, so on 2024-02-10 it
+        // rebalances to 37.5 AAA at 20 and 75 BBB at 10; then AAA halves
+        assert_eq!(result.rows[0].quantity, dec!(37.5));
+        assert_eq!(result.rows[1].quantity, dec!(75));
+        assert_eq!(result.final_value, dec!(1125));
+        assert_eq!(result.total_gain_loss, dec!(125));
+        assert_eq!(result.trades, 4);
+
+        let hold = backtest(&self::config(Strategy::LumpSum, &[("AAA", 0.5), ("BBB", 0.5)]), &history()).unwrap();
+        assert_eq!(hold.final_value, dec!(1000));
+    }
+
+    #[test]
+    fn test_leftover_cash_counts_towards_value() {
+        let history = HashMap::from([("ETH-USD".to_string(), series(&[dec!(3), dec!(3)]))]);
+        let result = backtest(&config(Strategy::LumpSum, &[("ETH-USD", 1.0)]), &history).unwrap();
+
+        assert_eq!(result.rows[0].quantity, dec!(333.33333333));
+        assert_eq!(result.cash, dec!(0));
+        assert_eq!(result.final_value, dec!(1000));
+    }
+
+    #[test]
+    fn test_backtest_needs_history() {
+        assert!(backtest(&config(Strategy::LumpSum, &[("CCC", 1.0)]), &history()).is_err());
+        let mut no_money = config(Strategy::LumpSum, &[("AAA", 1.0)]);
+        no_money.amount = Decimal::ZERO;
+        assert!(backtest(&no_money, &history()).is_err());
+    }
+
+    #[test]
+    fn test_month_ends() {
+        let result = backtest(&config(Strategy::LumpSum, &[("AAA", 1.0)]), &history()).unwrap();
+        let dates: Vec<NaiveDate> = month_ends(&result.values).iter().map(|p| p.date).collect();
+        assert_eq!(dates, vec![date(2024, 1, 31), date(2024, 2, 20)]);
+    }
+}
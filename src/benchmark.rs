@@ -0,0 +1,402 @@
+use anyhow::{bail, Context, Result};
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::history::{align, PriceSeries, TRADING_DAYS};
+use crate::money;
+use crate::portfolio::{Holding, Portfolio, TransactionKind};
+use crate::stats;
+use crate::validation::DATE_FORMAT;
+
+// What the portfolio is measured against: a single ticker such as SPY, or a
+// weighted blend written `SPY:60,AGG:40`. A blend is treated as an index
+// rebalanced to its weights every day.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Benchmark {
+    /// Tickers with weights that sum to 1.
+    pub components: Vec<(String, f64)>,
+}
+
+impl Benchmark {
+    pub fn tickers(&self) -> Vec<String> {
+        self.components.iter().map(|(t, _)| t.clone()).collect()
+    }
+
+    /// Index level on each day every component traded, starting at 1.
+    pub fn index_series(&self, history: &HashMap<String, PriceSeries>) -> Result<PriceSeries> {
+        let series = self
+            .components
+            .iter()
+            .map(|(ticker, _)| history.get(ticker).with_context(|| format!("No price history for benchmark {}", ticker)))
+            .collect::<Result<Vec<_>>>()?;
+        let (dates, closes) = align(&series);
+
+        let mut level = 1.0;
+        let mut index = PriceSeries::new();
+        for (day, date) in dates.iter().enumerate() {
+            if day > 0 {
+                let change: f64 = self
+                    .components
+                    .iter()
+                    .zip(&closes)
+                    .map(|((_, weight), c)| weight * (c[day] / c[day - 1] - 1.0))
+                    .sum();
+                level *= 1.0 + change;
+            }
+            index.insert(*date, money::round(money::from_f64(level), 10));
+        }
+        Ok(index)
+    }
+}
+
+impl FromStr for Benchmark {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut components = Vec::new();
+        for part in s.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let (ticker, weight) = match part.split_once(':') {
+                Some((ticker, weight)) => {
+                    let weight: f64 = weight
+                        .trim()
+                        .trim_end_matches('%')
+                        .parse()
+                        .map_err(|_| format!("invalid weight in `{}`", part))?;
+                    (ticker.trim(), weight)
+                }
+                None => (part, 1.0),
+            };
+            if ticker.is_empty() || weight <= 0.0 {
+                return Err(format!("`{}` needs a ticker and a positive weight", part));
+            }
+            components.push((ticker.to_uppercase(), weight));
+        }
+        if components.is_empty() {
+            return Err("expected a ticker such as SPY or a blend such as SPY:60,AGG:40".to_string());
+        }
+
+        let total: f64 = components.iter().map(|(_, w)| w).sum();
+        for (_, weight) in &mut components {
+            *weight /= total;
+        }
+        Ok(Benchmark { components })
+    }
+}
+
+impl fmt::Display for Benchmark {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let [(ticker, _)] = self.components.as_slice() {
+            return write!(f, "{}", ticker);
+        }
+        let parts: Vec<String> = self
+            .components
+            .iter()
+            .map(|(ticker, weight)| format!("{}% {}", (weight * 100.0 * 10.0).round() / 10.0, ticker))
+            .collect();
+        write!(f, "{}", parts.join(" + "))
+    }
+}
+
+/// Money moving into (positive) or out of (negative) the portfolio.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CashFlow {
+    pub date: NaiveDate,
+    pub amount: Decimal,
+}
+
+/// The portfolio's external cash flows: deposits and withdrawals from the
+/// ledger, or its buys and sells if it records no deposits, or else the
+/// cost of each lot on its purchase date.
+pub fn cash_flows(portfolio: &Portfolio) -> Result<Vec<CashFlow>> {
+    let parse = |date: &str| {
+        NaiveDate::parse_from_str(date, DATE_FORMAT).with_context(|| format!("Invalid transaction date: {}", date))
+    };
+    let has_deposits = portfolio
+        .transactions
+        .iter()
+        .any(|t| matches!(t.kind, TransactionKind::Deposit | TransactionKind::Withdrawal));
+    let has_trades = portfolio
+        .transactions
+        .iter()
+        .any(|t| matches!(t.kind, TransactionKind::Buy | TransactionKind::Sell));
+
+    let mut flows = Vec::new();
+    if has_deposits || has_trades {
+        for t in &portfolio.transactions {
+            let amount = match (t.kind, has_deposits) {
+                (TransactionKind::Deposit, true) => t.amount(),
+                (TransactionKind::Withdrawal, true) => -t.amount(),
+                (TransactionKind::Buy, false) => t.amount() + t.fees,
+                (TransactionKind::Sell, false) => -(t.amount() - t.fees),
+                _ => continue,
+            };
+            flows.push(CashFlow { date: parse(&t.date)?, amount });
+        }
+    } else {
+        for h in &portfolio.holdings {
+            flows.push(CashFlow { date: h.purchase_date()?, amount: h.quantity * h.cost_basis });
+        }
+    }
+
+    flows.sort_by_key(|f| f.date);
+    Ok(flows)
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BenchmarkComparison {
+    pub benchmark: String,
+    pub since: NaiveDate,
+    /// Net amount paid in.
+    pub invested: Decimal,
+    pub portfolio_value: Decimal,
+    /// Gain on the net amount paid in, in percent.
+    pub portfolio_return: Decimal,
+    /// What the portfolio would be worth had every cash flow gone into
+    /// the benchmark instead.
+    pub benchmark_value: Decimal,
+    pub benchmark_return: Decimal,
+    pub excess_return: Decimal,
+    /// Annualized Jensen's alpha, as a fraction.
+    pub alpha: Option<f64>,
+    pub beta: Option<f64>,
+    /// Annualized standard deviation of daily return differences.
+    pub tracking_error: Option<f64>,
+}
+
+/// Compare the portfolio, currently worth `portfolio_value`, with putting
+/// the same cash flows into the benchmark. Alpha, beta and tracking error
+/// come from the daily time-weighted returns of the lots held.
+pub fn compare(
+    portfolio: &Portfolio,
+    portfolio_value: Decimal,
+    history: &HashMap<String, PriceSeries>,
+    benchmark: &Benchmark,
+    risk_free_rate: f64,
+) -> Result<BenchmarkComparison> {
+    let flows = cash_flows(portfolio)?;
+    let Some(first) = flows.first() else {
+        bail!("The portfolio has no purchases or deposits to compare");
+    };
+    let index = benchmark.index_series(history)?;
+    let Some((_, &last_level)) = index.iter().next_back() else {
+        bail!("No price history for benchmark {}", benchmark);
+    };
+
+    let currency = &portfolio.base_currency;
+    let mut invested = Decimal::ZERO;
+    let mut benchmark_value = Decimal::ZERO;
+    for flow in &flows {
+        // Flows on non-trading days go in at the next close
+        let level = index
+            .range(flow.date..)
+            .next()
+            .map(|(_, level)| *level)
+            .unwrap_or(last_level);
+        invested += flow.amount;
+        benchmark_value += flow.amount * last_level / level;
+    }
+    let invested = money::round_money(invested, currency);
+    let benchmark_value = money::round_money(benchmark_value, currency);
+
+    let portfolio_return = money::round_percent(money::percentage(portfolio_value - invested, invested));
+    let benchmark_return = money::round_percent(money::percentage(benchmark_value - invested, invested));
+
+    let (portfolio_returns, index_returns) = daily_returns(&portfolio.holdings, history, &index)?;
+    let rf = risk_free_rate / 100.0;
+    let beta = stats::beta(&portfolio_returns, &index_returns);
+    let alpha = beta.filter(|_| portfolio_returns.len() >= 2).map(|beta| {
+        let rp = stats::mean(&portfolio_returns) * TRADING_DAYS;
+        let rb = stats::mean(&index_returns) * TRADING_DAYS;
+        rp - (rf + beta * (rb - rf))
+    });
+    let differences: Vec<f64> = portfolio_returns.iter().zip(&index_returns).map(|(p, b)| p - b).collect();
+    let tracking_error = (differences.len() >= 2).then(|| stats::std_dev(&differences) * TRADING_DAYS.sqrt());
+
+    Ok(BenchmarkComparison {
+        benchmark: benchmark.to_string(),
+        since: first.date,
+        invested,
+        portfolio_value,
+        portfolio_return,
+        benchmark_value,
+        benchmark_return,
+        excess_return: portfolio_return - benchmark_return,
+        alpha,
+        beta,
+        tracking_error,
+    })
+}
+
+/// Daily time-weighted returns of the lots (each counted from its purchase
+/// date) next to the benchmark's, on the days the portfolio held anything.
+fn daily_returns(
+    holdings: &[Holding],
+    history: &HashMap<String, PriceSeries>,
+    index: &PriceSeries,
+) -> Result<(Vec<f64>, Vec<f64>)> {
+    let mut series: Vec<&PriceSeries> = holdings
+        .iter()
+        .map(|h| history.get(&h.ticker).with_context(|| format!("No price history for {}", h.ticker)))
+        .collect::<Result<_>>()?;
+    series.push(index);
+    let (dates, closes) = align(&series);
+    let (index_closes, lot_closes) = closes.split_last().expect("index column");
+    let purchased: Vec<NaiveDate> = holdings.iter().map(|h| h.purchase_date()).collect::<Result<_>>()?;
+    let quantities: Vec<f64> = holdings.iter().map(|h| money::to_f64(h.quantity)).collect();
+
+    let mut portfolio_returns = Vec::new();
+    let mut index_returns = Vec::new();
+    let mut previous_value = 0.0;
+    for day in 0..dates.len() {
+        let mut value = 0.0;
+        let mut added = 0.0;
+        for lot in 0..holdings.len() {
+            if purchased[lot] > dates[day] {
+                continue;
+            }
+            let lot_value = quantities[lot] * lot_closes[lot][day];
+            value += lot_value;
+            if day == 0 || purchased[lot] > dates[day - 1] {
+                added += lot_value;
+            }
+        }
+        if previous_value > 0.0 {
+            portfolio_returns.push((value - added) / previous_value - 1.0);
+            index_returns.push(index_closes[day] / index_closes[day - 1] - 1.0);
+        }
+        previous_value = value;
+    }
+
+    Ok((portfolio_returns, index_returns))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::portfolio::Transaction;
+    use chrono::Duration;
+    use rust_decimal_macros::dec;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    fn series(start: NaiveDate, closes: &[f64]) -> PriceSeries {
+        closes
+            .iter()
+            .enumerate()
+            .map(|(i, c)| (start + Duration::days(i as i64), money::from_f64(*c)))
+            .collect()
+    }
+
+    fn holding(ticker: &str, quantity: Decimal, cost_basis: Decimal, date: &str) -> Holding {
+        Holding {
+            ticker: ticker.to_string(),
+            quantity,
+            cost_basis,
+            date_purchased: date.to_string(),
+        }
+    }
+
+    fn transaction(date: &str, kind: TransactionKind, ticker: Option<&str>, quantity: Decimal, price: Decimal) -> Transaction {
+        Transaction {
+            date: date.to_string(),
+            kind,
+            ticker: ticker.map(str::to_string),
+            quantity,
+            price,
+            fees: Decimal::ZERO,
+            note: None,
+        }
+    }
+
+    #[test]
+    fn test_parse_ticker_and_blend() {
+        let single: Benchmark = "spy".parse().unwrap();
+        assert_eq!(single.components, vec![("SPY".to_string(), 1.0)]);
+        assert_eq!(single.to_string(), "SPY");
+
+        let blend: Benchmark = "SPY:60, AGG:40%".parse().unwrap();
+        assert_eq!(blend.tickers(), vec!["SPY", "AGG"]);
+        assert!((blend.components[0].1 - 0.6).abs() < 1e-12);
+        assert_eq!(blend.to_string(), "60% SPY + 40% AGG");
+
+        assert!("".parse::<Benchmark>().is_err());
+        assert!("SPY:-1".parse::<Benchmark>().is_err());
+        assert!("SPY:abc".parse::<Benchmark>().is_err());
+    }
+
+    #[test]
+    fn test_blend_index_rebalances_daily() {
+        let start = date(2024, 1, 1);
+        let history = HashMap::from([
+            ("A".to_string(), series(start, &[10.0, 11.0, 11.0])),
+            ("B".to_string(), series(start, &[50.0, 50.0, 45.0])),
+        ]);
+        let index = "A:50,B:50".parse::<Benchmark>().unwrap().index_series(&history).unwrap();
+        let levels: Vec<Decimal> = index.values().copied().collect();
+        assert_eq!(levels, vec![dec!(1), dec!(1.05), dec!(0.9975)]);
+    }
+
+    #[test]
+    fn test_cash_flows_prefer_deposits_then_trades_then_lots() {
+        let mut portfolio = Portfolio {
+            holdings: vec![holding("AAA", dec!(2), dec!(10), "2024-01-02")],
+            ..Default::default()
+        };
+        assert_eq!(cash_flows(&portfolio).unwrap(), vec![CashFlow { date: date(2024, 1, 2), amount: dec!(20) }]);
+
+        portfolio.transactions = vec![
+            transaction("2024-01-03", TransactionKind::Buy, Some("AAA"), dec!(2), dec!(10)),
+            transaction("2024-01-05", TransactionKind::Sell, Some("AAA"), dec!(1), dec!(12)),
+        ];
+        let amounts: Vec<Decimal> = cash_flows(&portfolio).unwrap().iter().map(|f| f.amount).collect();
+        assert_eq!(amounts, vec![dec!(20), dec!(-12)]);
+
+        portfolio.transactions.push(transaction("2024-01-01", TransactionKind::Deposit, None, Decimal::ZERO, dec!(100)));
+        portfolio.transactions.push(transaction("2024-01-04", TransactionKind::Withdrawal, None, Decimal::ZERO, dec!(30)));
+        let flows = cash_flows(&portfolio).unwrap();
+        assert_eq!(flows.iter().map(|f| f.amount).collect::<Vec<_>>(), vec![dec!(100), dec!(-30)]);
+        assert_eq!(flows[0].date, date(2024, 1, 1));
+    }
+
+    #[test]
+    fn test_compare_same_cash_flows() {
+        let start = date(2024, 1, 1);
+        let history = HashMap::from([
+            ("AAA".to_string(), series(start, &[10.0, 10.0, 12.0, 15.0])),
+            ("SPY".to_string(), series(start, &[100.0, 110.0, 121.0, 121.0])),
+        ]);
+        // 10 shares bought on day 0, 10 more on day 2
+        let portfolio = Portfolio {
+            holdings: vec![
+                holding("AAA", dec!(10), dec!(10), "2024-01-01"),
+                holding("AAA", dec!(10), dec!(12), "2024-01-03"),
+            ],
+            ..Default::default()
+        };
+
+        let comparison = compare(&portfolio, dec!(300), &history, &"SPY".parse().unwrap(), 0.0).unwrap();
+
+        assert_eq!(comparison.since, start);
+        assert_eq!(comparison.invested, dec!(220));
+        // 100 grows by 21%, the 120 put in on day 2 stays flat
+        assert_eq!(comparison.benchmark_value, dec!(241));
+        assert_eq!(comparison.portfolio_return, dec!(36.36));
+        assert_eq!(comparison.benchmark_return, dec!(9.55));
+        assert_eq!(comparison.excess_return, dec!(26.81));
+        assert!(comparison.tracking_error.unwrap() > 0.0);
+        assert!(comparison.alpha.is_some());
+    }
+
+    #[test]
+    fn test_compare_needs_cash_flows() {
+        let history = HashMap::from([("SPY".to_string(), series(date(2024, 1, 1), &[1.0, 2.0]))]);
+        assert!(compare(&Portfolio::default(), Decimal::ZERO, &history, &"SPY".parse().unwrap(), 0.0).is_err());
+    }
+}
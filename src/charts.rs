@@ -0,0 +1,220 @@
+use std::io::IsTerminal;
+
+// Text charts for the terminal. On a terminal they use Unicode block
+// characters in eighths and fill the window's width; when the output is
+// piped or redirected they fall back to plain ASCII at 80 columns (or
+// $COLUMNS), so logs and files stay readable.
+
+/// Bar heights in eighths of a cell, from one eighth to a full block.
+const VERTICAL_EIGHTHS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+/// Bar lengths in eighths of a cell.
+const HORIZONTAL_EIGHTHS: [char; 8] = ['▏', '▎', '▍', '▌', '▋', '▊', '▉', '█'];
+/// Sparkline levels without Unicode.
+const ASCII_LEVELS: [char; 8] = ['_', '.', '-', '~', '=', '+', '*', '#'];
+
+const DEFAULT_WIDTH: usize = 80;
+const MIN_WIDTH: usize = 30;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChartStyle {
+    pub width: usize,
+    pub unicode: bool,
+}
+
+impl ChartStyle {
+    /// Unicode at the terminal's width on a TTY, ASCII otherwise.
+    pub fn detect() -> ChartStyle {
+        let tty = std::io::stdout().is_terminal();
+        let width = tty
+            .then(|| ratatui::crossterm::terminal::size().ok().map(|(w, _)| w as usize))
+            .flatten()
+            .or_else(|| std::env::var("COLUMNS").ok().and_then(|c| c.parse().ok()))
+            .unwrap_or(DEFAULT_WIDTH);
+        ChartStyle { width: width.max(MIN_WIDTH), unicode: tty }
+    }
+
+    pub fn ascii(width: usize) -> ChartStyle {
+        ChartStyle { width: width.max(MIN_WIDTH), unicode: false }
+    }
+
+    pub fn unicode(width: usize) -> ChartStyle {
+        ChartStyle { width: width.max(MIN_WIDTH), unicode: true }
+    }
+}
+
+/// One bar: its label, length and the text printed after it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BarRow {
+    pub label: String,
+    pub value: f64,
+    pub text: String,
+}
+
+/// Horizontal bars scaled so the largest value fills the width left after
+/// the labels and texts. Negative values draw no bar.
+pub fn bar_chart(rows: &[BarRow], style: ChartStyle) -> Vec<String> {
+    let label_width = rows.iter().map(|r| r.label.chars().count()).max().unwrap_or(0);
+    let text_width = rows.iter().map(|r| r.text.chars().count()).max().unwrap_or(0);
+    let bar_width = style.width.saturating_sub(label_width + text_width + 2).max(1);
+    let max = rows.iter().map(|r| r.value).fold(0.0, f64::max);
+
+    rows.iter()
+        .map(|r| {
+            let fraction = if max > 0.0 { (r.value / max).clamp(0.0, 1.0) } else { 0.0 };
+            let bar = horizontal_bar(fraction * bar_width as f64, style.unicode);
+            let padding = bar_width - bar.chars().count();
+            format!("{:<lw$} {}{} {:>tw$}", r.label, bar, " ".repeat(padding), r.text, lw = label_width, tw = text_width)
+        })
+        .collect()
+}
+
+fn horizontal_bar(cells: f64, unicode: bool) -> String {
+    if !unicode {
+        return "#".repeat(cells.round() as usize);
+    }
+    let eighths = (cells * 8.0).round() as usize;
+    let mut bar = "█".repeat(eighths / 8);
+    if !eighths.is_multiple_of(8) {
+        bar.push(HORIZONTAL_EIGHTHS[eighths % 8 - 1]);
+    }
+    bar
+}
+
+/// At most `width` points of `values`, keeping the last point of each
+/// bucket so the chart always ends on the latest value.
+pub fn resample(values: &[f64], width: usize) -> Vec<f64> {
+    if values.len() <= width || width == 0 {
+        return values.to_vec();
+    }
+    (1..=width).map(|i| values[i * values.len() / width - 1]).collect()
+}
+
+/// One character per point, from the lowest to the highest value.
+pub fn sparkline(values: &[f64], width: usize, unicode: bool) -> String {
+    let values = resample(values, width);
+    let (min, max) = bounds(&values);
+    let levels = if unicode { VERTICAL_EIGHTHS } else { ASCII_LEVELS };
+    values
+        .iter()
+        .map(|v| {
+            let level = if max > min { ((v - min) / (max - min) * 7.0).round() as usize } else { 3 };
+            levels[level]
+        })
+        .collect()
+}
+
+/// An area chart `height` rows tall with the highest and lowest value on the
+/// axis and `start`/`end` labels under it.
+pub fn line_chart(values: &[f64], start: &str, end: &str, height: usize, style: ChartStyle) -> Vec<String> {
+    let height = height.max(2);
+    let (min, max) = bounds(values);
+    let labels = [format!("{:.2}", max), format!("{:.2}", min)];
+    let label_width = labels.iter().map(|l| l.len()).max().unwrap_or(0);
+    let plot_width = style.width.saturating_sub(label_width + 2).max(1);
+    let values = resample(values, plot_width);
+
+    // Height of each column in eighths; the lowest value still gets one
+    let total = height * 8;
+    let columns: Vec<usize> = values
+        .iter()
+        .map(|v| if max > min { 1 + ((v - min) / (max - min) * (total - 1) as f64).round() as usize } else { total / 2 })
+        .collect();
+
+    let (axis, corner, rule) = if style.unicode { ('┤', '└', '─') } else { ('|', '+', '-') };
+    let mut lines = Vec::with_capacity(height + 2);
+    for row in (0..height).rev() {
+        let label = match row {
+            r if r == height - 1 => labels[0].as_str(),
+            0 => labels[1].as_str(),
+            _ => "",
+        };
+        let cells: String = columns
+            .iter()
+            .map(|&eighths| {
+                let filled = eighths.saturating_sub(row * 8).min(8);
+                match (filled, style.unicode) {
+                    (0, _) => ' ',
+                    (f, true) => VERTICAL_EIGHTHS[f - 1],
+                    (f, false) if f >= 4 => '#',
+                    _ => ' ',
+                }
+            })
+            .collect();
+        lines.push(format!("{:>w$} {}{}", label, axis, cells.trim_end(), w = label_width));
+    }
+    lines.push(format!("{:>w$} {}{}", "", corner, rule.to_string().repeat(values.len()), w = label_width));
+
+    let gap = values.len().saturating_sub(start.len() + end.len()).max(1);
+    lines.push(format!("{:>w$}  {}{}{}", "", start, " ".repeat(gap), end, w = label_width));
+    lines
+}
+
+fn bounds(values: &[f64]) -> (f64, f64) {
+    let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    if values.is_empty() {
+        (0.0, 0.0)
+    } else {
+        (min, max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(label: &str, value: f64) -> BarRow {
+        BarRow { label: label.to_string(), value, text: format!("{:.1}%", value) }
+    }
+
+    #[test]
+    fn test_bar_chart_fills_width_for_largest() {
+        let lines = bar_chart(&[row("MSFT", 50.0), row("AAPL", 25.0), row("BTC-USD", 0.0)], ChartStyle::unicode(40));
+        assert!(lines.iter().all(|l| l.chars().count() == 40), "{:?}", lines);
+        // 40 - 7 (label) - 5 (text) - 2 spaces = 26 cells
+        assert!(lines[0].contains(&"█".repeat(26)));
+        assert!(lines[1].contains(&format!("{} ", "█".repeat(13))));
+        assert!(!lines[1].contains(&"█".repeat(14)));
+        assert!(lines[2].starts_with("BTC-USD  "));
+    }
+
+    #[test]
+    fn test_bar_chart_ascii() {
+        let lines = bar_chart(&[row("A", 10.0), row("B", 5.0)], ChartStyle::ascii(30));
+        // 30 - 1 (label) - 5 (text) - 2 spaces = 22 cells
+        assert_eq!(lines[0], format!("A {} 10.0%", "#".repeat(22)));
+        assert_eq!(lines[1], format!("B {}{}  5.0%", "#".repeat(11), " ".repeat(11)));
+    }
+
+    #[test]
+    fn test_resample_keeps_last_point() {
+        let values: Vec<f64> = (1..=10).map(f64::from).collect();
+        assert_eq!(resample(&values, 5), vec![2.0, 4.0, 6.0, 8.0, 10.0]);
+        assert_eq!(resample(&values, 20), values);
+    }
+
+    #[test]
+    fn test_sparkline_levels() {
+        assert_eq!(sparkline(&[1.0, 2.0, 3.0, 8.0], 10, true), "▁▂▃█");
+        assert_eq!(sparkline(&[1.0, 2.0, 3.0, 8.0], 10, false), "_.-#");
+        assert_eq!(sparkline(&[5.0, 5.0], 10, true), "▄▄");
+        assert_eq!(sparkline(&[1.0, 2.0, 3.0, 4.0], 2, true).chars().count(), 2);
+    }
+
+    #[test]
+    fn test_line_chart_layout() {
+        let values = [10.0, 12.0, 11.0, 20.0];
+        let lines = line_chart(&values, "2024-01-01", "2024-01-04", 3, ChartStyle::unicode(30));
+        assert_eq!(lines.len(), 5);
+        assert!(lines[0].starts_with("20.00 ┤"));
+        assert!(lines[2].starts_with("10.00 ┤"));
+        assert_eq!(lines[3], "      └────");
+        // The highest point reaches the top row, the lowest sits on the bottom one
+        assert!(lines[0].ends_with('█'));
+        assert_eq!(lines[2], "10.00 ┤▁▆▃█");
+        assert_eq!(lines[0], "20.00 ┤   █");
+
+        let ascii = line_chart(&values, "a", "b", 3, ChartStyle::ascii(30));
+        assert!(ascii.iter().all(|l| l.is_ascii()));
+    }
+}
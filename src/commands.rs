@@ -1,16 +1,64 @@
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
+use chrono::NaiveDate;
 use colored::*;
-use std::path::Path;
+use rust_decimal::Decimal;
+use std::collections::HashMap;
+use std::fs;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
-use crate::api::fetch_current_prices;
-use crate::portfolio::Portfolio;
+use crate::alerts::{self, RuleSet};
+use crate::api::{fetch_current_prices, fetch_prices_with_failures};
+use crate::backtest::{backtest, month_ends, BacktestConfig, Frequency, Strategy};
+use crate::benchmark::{compare, Benchmark, BenchmarkComparison};
+use crate::crypto;
+use crate::export::{build_sheets, write_export, ExportFormat};
+use crate::formats::{self, FileFormat};
+use crate::history::fetch_price_history;
+use crate::money;
+use crate::projection::{historical_returns, project, ProjectionConfig, PERCENTILES};
+use crate::portfolio::{parse_and_validate, CostBasisMethod, read_portfolio_text, write_file_atomic, HoldingWithPrice, Portfolio, WatchItem};
+use crate::schema::{self, CURRENT_SCHEMA_VERSION};
+use crate::storage::is_database_path;
+use crate::validation::Severity;
+use crate::charts::{bar_chart, line_chart, sparkline, BarRow, ChartStyle};
+use crate::history::{align, PriceSeries};
+use crate::live::Ticks;
+use crate::completions::CompletionShell;
+use crate::config::Settings;
+use crate::daemon::{DaemonConfig, LogFormat};
+use crate::reports::{
+    allocation_groups, allocation_report, balance_report, output_format, performance_report, watchlist_report,
+    AllocationGroup, AllocationReport, BalanceReport, OutputFormat, PerformanceReport,
+};
+use crate::risk::{correlation_matrix, positions, risk_report, value_series, RiskMetrics};
 
-pub async fn balance_command<P: AsRef<Path>>(portfolio_file: P) -> Result<()> {
+async fn load_with_prices<P: AsRef<Path>>(portfolio_file: P) -> Result<(Portfolio, Vec<HoldingWithPrice>)> {
     let portfolio = Portfolio::load_from_file(portfolio_file)?;
     let tickers = portfolio.get_tickers();
     let prices = fetch_current_prices(&tickers).await?;
     let holdings_with_prices = portfolio.holdings_with_prices(&prices);
+    Ok((portfolio, holdings_with_prices))
+}
+
+pub async fn balance_command<P: AsRef<Path>>(portfolio_file: P, watch: Option<Duration>) -> Result<()> {
+    let mut ticks = Ticks::new(portfolio_file.as_ref(), watch);
+    let mut previous: Option<BalanceReport> = None;
+
+    while let Some(tick) = ticks.next().await? {
+        let report = balance_report(&tick.holdings, &tick.portfolio.base_currency);
+        print_balances(&report, previous.as_ref());
+        previous = Some(report);
+    }
+
+    Ok(())
+}
 
+pub(crate) fn print_balances(report: &BalanceReport, previous: Option<&BalanceReport>) {
+    if output_format() == OutputFormat::Json {
+        return print_json(report);
+    }
     println!("{}", "--------------------------------------".cyan());
     println!("{:<8} | {:<8} | {:<13} | {:<10}", 
              "Ticker".bold(), 
@@ -19,65 +67,137 @@ pub async fn balance_command<P: AsRef<Path>>(portfolio_file: P) -> Result<()> {
              "Value".bold());
     println!("{}", "--------------------------------------".cyan());
 
-    let mut total_value = 0.0;
-
-    for holding_with_price in &holdings_with_prices {
-        let holding = &holding_with_price.holding;
-        let current_value = holding_with_price.current_value();
-        total_value += current_value;
-
-        println!("{:<8} | {:<8.2} | {:<13.2} | {:<10.2}",
-                 holding.ticker.yellow(),
-                 holding.quantity,
-                 holding_with_price.current_price,
-                 current_value);
+    for (index, row) in report.rows.iter().enumerate() {
+        let before = previous.and_then(|p| p.rows.get(index)).filter(|p| p.ticker == row.ticker);
+        let price = format!("{:.2}", money::round(row.current_price, 2));
+        let value = format!("{:.2}", row.value);
+        println!("{:<8} | {:<8} | {:<13} | {:<10}",
+                 row.ticker.yellow(),
+                 money::round_quantity(row.quantity, &row.ticker).normalize(),
+                 moved(price, row.current_price, before.map(|b| b.current_price)),
+                 moved(value, row.value, before.map(|b| b.value)));
     }
 
     println!("{}", "--------------------------------------".cyan());
-    println!("{}: {:.2}", "Total Portfolio Value".bold().green(), total_value);
-
-    Ok(())
+    let total = format!("{:.2}", report.total_value);
+    let total = match previous.map(|p| p.total_value) {
+        Some(before) if before != report.total_value => moved(total, report.total_value, Some(before)),
+        _ => total.normal(),
+    };
+    println!("{}: {}", "Total Portfolio Value".bold().green(), total);
 }
 
-pub async fn allocation_command<P: AsRef<Path>>(portfolio_file: P) -> Result<()> {
-    let portfolio = Portfolio::load_from_file(portfolio_file)?;
-    let tickers = portfolio.get_tickers();
-    let prices = fetch_current_prices(&tickers).await?;
-    let holdings_with_prices = portfolio.holdings_with_prices(&prices);
+/// Allocation per lot, or with `chart` as a bar chart per ticker or asset
+/// class.
+pub async fn allocation_command<P: AsRef<Path>>(
+    portfolio_file: P,
+    watch: Option<Duration>,
+    chart: Option<AllocationGroup>,
+) -> Result<()> {
+    let mut ticks = Ticks::new(portfolio_file.as_ref(), watch);
+    let mut previous: Option<AllocationReport> = None;
 
-    let total_value: f64 = holdings_with_prices.iter()
-        .map(|h| h.current_value())
-        .sum();
+    while let Some(tick) = ticks.next().await? {
+        let report = allocation_report(&tick.holdings, &tick.portfolio.base_currency);
+        print_allocation(&report, previous.as_ref(), chart);
+        previous = Some(report);
+    }
 
+    Ok(())
+}
+
+pub(crate) fn print_allocation(report: &AllocationReport, previous: Option<&AllocationReport>, chart: Option<AllocationGroup>) {
+    if output_format() == OutputFormat::Json {
+        return print_json(report);
+    }
     println!("{}", "--------------------------------------".cyan());
     println!("{}", "Asset Allocation".bold());
     println!("{}", "--------------------------------------".cyan());
 
-    for holding_with_price in &holdings_with_prices {
-        let holding = &holding_with_price.holding;
-        let current_value = holding_with_price.current_value();
-        let percentage = if total_value > 0.0 {
-            (current_value / total_value) * 100.0
-        } else {
-            0.0
-        };
+    if let Some(by) = chart {
+        let bars: Vec<BarRow> = allocation_groups(report, by)
+            .into_iter()
+            .map(|row| BarRow {
+                value: money::to_f64(row.percentage),
+                text: format!("{:.1}%", money::round(row.percentage, 1)),
+                label: row.ticker,
+            })
+            .collect();
+        for line in bar_chart(&bars, ChartStyle::detect()) {
+            println!("{}", line);
+        }
+    }
 
-        println!("{}: {:.1}%", 
-                 holding.ticker.yellow(), 
-                 percentage);
+    for (index, row) in report.rows.iter().enumerate().filter(|_| chart.is_none()) {
+        let before = previous.and_then(|p| p.rows.get(index)).filter(|p| p.ticker == row.ticker);
+        let percentage = money::round(row.percentage, 1);
+        println!("{}: {}", 
+                 row.ticker.yellow(), 
+                 moved(format!("{:.1}%", percentage), percentage, before.map(|b| money::round(b.percentage, 1))));
     }
 
     println!("{}", "--------------------------------------".cyan());
+    println!("{}: {:.4} ({} effective bets)",
+             "Concentration (HHI)".bold(),
+             report.concentration.herfindahl,
+             report.concentration.effective_bets);
+}
+
+/// Gains per holding. With a benchmark, also compares the portfolio with
+/// having put the same cash flows into the benchmark.
+pub async fn performance_command<P: AsRef<Path>>(
+    portfolio_file: P,
+    benchmark: Option<&Benchmark>,
+    risk_free_rate: f64,
+    watch: Option<Duration>,
+) -> Result<()> {
+    let mut ticks = Ticks::new(portfolio_file.as_ref(), watch);
+    let mut previous: Option<PerformanceReport> = None;
+
+    while let Some(tick) = ticks.next().await? {
+        let report = performance_report(&tick.holdings, &tick.portfolio.base_currency);
+        let comparison = match benchmark {
+            Some(benchmark) => {
+                let portfolio = &tick.portfolio;
+                let flows = crate::benchmark::cash_flows(portfolio)?;
+                let from = flows.first().map(|f| f.date).unwrap_or_else(|| chrono::Utc::now().date_naive());
+                let mut tickers = portfolio.get_tickers();
+                tickers.extend(benchmark.tickers());
+                let history = fetch_price_history(&tickers, from, chrono::Utc::now().date_naive()).await?;
+                Some(compare(portfolio, report.total_value, &history, benchmark, risk_free_rate)?)
+            }
+            None => None,
+        };
+
+        match &comparison {
+            // One JSON document, with the comparison alongside the report
+            Some(comparison) if output_format() == OutputFormat::Json => {
+                print_json(&PerformanceWithBenchmark { report: &report, benchmark: comparison });
+            }
+            _ => {
+                print_performance(&report, previous.as_ref());
+                if let Some(comparison) = &comparison {
+                    print_benchmark_comparison(comparison);
+                }
+            }
+        }
+        previous = Some(report);
+    }
 
     Ok(())
 }
 
-pub async fn performance_command<P: AsRef<Path>>(portfolio_file: P) -> Result<()> {
-    let portfolio = Portfolio::load_from_file(portfolio_file)?;
-    let tickers = portfolio.get_tickers();
-    let prices = fetch_current_prices(&tickers).await?;
-    let holdings_with_prices = portfolio.holdings_with_prices(&prices);
+#[derive(serde::Serialize)]
+struct PerformanceWithBenchmark<'a> {
+    #[serde(flatten)]
+    report: &'a PerformanceReport,
+    benchmark: &'a BenchmarkComparison,
+}
 
+pub(crate) fn print_performance(report: &PerformanceReport, previous: Option<&PerformanceReport>) {
+    if output_format() == OutputFormat::Json {
+        return print_json(report);
+    }
     println!("{}", "--------------------------------------".cyan());
     println!("{:<8} | {:<14} | {:<13} | {:<10} | {:<12}", 
              "Ticker".bold(), 
@@ -87,61 +207,976 @@ pub async fn performance_command<P: AsRef<Path>>(portfolio_file: P) -> Result<()
              "Gain/Loss".bold());
     println!("{}", "--------------------------------------".cyan());
 
-    let mut total_cost = 0.0;
-    let mut total_current_value = 0.0;
+    for (index, row) in report.rows.iter().enumerate() {
+        let before = previous.and_then(|p| p.rows.get(index)).filter(|p| p.ticker == row.ticker);
+        let return_percentage = money::round(row.return_percentage, 1);
+        let gain_loss = row.gain_loss;
 
-    for holding_with_price in &holdings_with_prices {
-        let holding = &holding_with_price.holding;
-        let return_percentage = holding_with_price.gain_loss_percentage();
-        let gain_loss = holding_with_price.gain_loss();
-        
-        total_cost += holding_with_price.total_cost();
-        total_current_value += holding_with_price.current_value();
-
-        let return_str = if return_percentage >= 0.0 {
+        let return_str = if return_percentage >= Decimal::ZERO {
             format!("+{:.1}%", return_percentage).green()
         } else {
             format!("{:.1}%", return_percentage).red()
         };
 
-        let gain_loss_str = if gain_loss >= 0.0 {
+        let gain_loss_str = if gain_loss >= Decimal::ZERO {
             format!("+{:.2}", gain_loss).green()
         } else {
             format!("{:.2}", gain_loss).red()
         };
 
-        println!("{:<8} | {:<14.2} | {:<13.2} | {:<10} | {:<12}",
-                 holding.ticker.yellow(),
-                 holding.cost_basis,
-                 holding_with_price.current_price,
+        let price = format!("{:.2}", money::round(row.current_price, 2));
+        println!("{:<8} | {:<14.2} | {:<13} | {:<10} | {:<12}",
+                 row.ticker.yellow(),
+                 money::round(row.cost_basis, 2),
+                 moved(price, row.current_price, before.map(|b| b.current_price)),
                  return_str,
                  gain_loss_str);
     }
 
     println!("{}", "--------------------------------------".cyan());
 
-    let total_return_percentage = if total_cost > 0.0 {
-        ((total_current_value - total_cost) / total_cost) * 100.0
-    } else {
-        0.0
-    };
-
-    let total_gain_loss = total_current_value - total_cost;
+    let total_return_percentage = money::round(report.total_return_percentage, 1);
+    let total_gain_loss = report.total_gain_loss;
 
-    let total_return_str = if total_return_percentage >= 0.0 {
+    let total_return_str = if total_return_percentage >= Decimal::ZERO {
         format!("Total Return: +{:.1}%", total_return_percentage).bold().green()
     } else {
         format!("Total Return: {:.1}%", total_return_percentage).bold().red()
     };
 
-    let total_gain_loss_str = if total_gain_loss >= 0.0 {
+    let total_gain_loss_str = if total_gain_loss >= Decimal::ZERO {
         format!("Total Gain/Loss: +{:.2}", total_gain_loss).bold().green()
     } else {
         format!("Total Gain/Loss: {:.2}", total_gain_loss).bold().red()
     };
 
-    println!("{}", total_return_str);
-    println!("{}", total_gain_loss_str);
+    println!("{}{}", total_return_str, change_marker(report.total_return_percentage, previous.map(|p| p.total_return_percentage)));
+    println!("{}{}", total_gain_loss_str, change_marker(total_gain_loss, previous.map(|p| p.total_gain_loss)));
+}
+
+/// A report as pretty-printed JSON, for `--output-format json`.
+fn print_json<T: serde::Serialize>(report: &T) {
+    println!("{}", serde_json::to_string_pretty(report).expect("reports serialize to JSON"));
+}
+
+/// In watch mode, a figure that moved since the previous refresh is shown
+/// green with ▲ when it went up and red with ▼ when it went down.
+fn moved(text: String, now: Decimal, before: Option<Decimal>) -> ColoredString {
+    match before {
+        Some(before) if now > before => format!("{} ▲", text).green().bold(),
+        Some(before) if now < before => format!("{} ▼", text).red().bold(),
+        _ => text.normal(),
+    }
+}
+
+/// ▲ or ▼ after a figure that is already coloured by its sign.
+fn change_marker(now: Decimal, before: Option<Decimal>) -> ColoredString {
+    match before {
+        Some(before) if now > before => " ▲".green().bold(),
+        Some(before) if now < before => " ▼".red().bold(),
+        _ => "".normal(),
+    }
+}
+
+fn print_benchmark_comparison(c: &BenchmarkComparison) {
+    let signed = |value: Decimal, suffix: &str| {
+        if value >= Decimal::ZERO {
+            format!("+{:.1}{}", money::round(value, 1), suffix).green()
+        } else {
+            format!("{:.1}{}", money::round(value, 1), suffix).red()
+        }
+    };
+    let optional = |value: Option<f64>, percent: bool| match value {
+        Some(v) if percent => format!("{:.1}%", v * 100.0),
+        Some(v) => format!("{:.2}", v),
+        None => "-".to_string(),
+    };
+
+    println!("{}", "--------------------------------------".cyan());
+    println!("{} {} {}", "Benchmark:".bold(), c.benchmark.yellow(), format!("(same cash flows since {})", c.since).dimmed());
+    println!("{}", "--------------------------------------".cyan());
+    println!("{:<15} {:.2}", "Invested", c.invested);
+    println!("{:<15} {:.2} ({})", "Portfolio", c.portfolio_value, signed(c.portfolio_return, "%"));
+    println!("{:<15} {:.2} ({})", "Benchmark", c.benchmark_value, signed(c.benchmark_return, "%"));
+    println!("{:<15} {}", "Excess Return", signed(c.excess_return, " pts"));
+    println!("{:<15} {}", "Alpha (annual)", optional(c.alpha, true));
+    println!("{:<15} {}", "Beta", optional(c.beta, false));
+    println!("{:<15} {}", "Tracking Error", optional(c.tracking_error, true));
+}
+
+/// Volatility, downside deviation, beta, Sharpe and Sortino ratios and
+/// maximum drawdown per holding and for the whole portfolio.
+pub async fn risk_command<P: AsRef<Path>>(
+    portfolio_file: P,
+    benchmark: &Benchmark,
+    risk_free_rate: f64,
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+) -> Result<()> {
+    let portfolio = Portfolio::load_from_file(portfolio_file)?;
+    let (from, to) = history_range(from, to);
+    let mut tickers = portfolio.get_tickers();
+    tickers.extend(benchmark.tickers());
+    let history = fetch_price_history(&tickers, from, to).await?;
+    let report = risk_report(&portfolio.holdings, &history, benchmark, risk_free_rate)?;
+
+    println!("{}", "--------------------------------------".cyan());
+    println!("{:<9} | {:<7} | {:<10} | {:<8} | {:<5} | {:<6} | {:<7} | {:<8}",
+             "Ticker".bold(),
+             "Weight".bold(),
+             "Volatility".bold(),
+             "Downside".bold(),
+             "Beta".bold(),
+             "Sharpe".bold(),
+             "Sortino".bold(),
+             "Max DD".bold());
+    println!("{}", "--------------------------------------".cyan());
+
+    for row in &report.rows {
+        print_risk_row(row.ticker.yellow(), Some(row.weight), &row.metrics);
+    }
+
+    println!("{}", "--------------------------------------".cyan());
+    print_risk_row("Portfolio".bold().green(), Some(100.0), &report.portfolio);
+    print_risk_row(report.benchmark.as_str().cyan(), None, &report.benchmark_metrics);
+    println!("{}", "--------------------------------------".cyan());
+    println!("{} {} to {} ({} trading days), risk-free rate {:.2}%",
+             "Period:".bold(),
+             report.from,
+             report.to,
+             report.observations,
+             report.risk_free_rate);
+
+    Ok(())
+}
+
+fn print_risk_row(label: ColoredString, weight: Option<f64>, m: &RiskMetrics) {
+    let ratio = |r: Option<f64>| r.map(|r| format!("{:.2}", r)).unwrap_or_else(|| "-".to_string());
+    println!("{:<9} | {:<7} | {:<10} | {:<8} | {:<5} | {:<6} | {:<7} | {:<8}",
+             label,
+             weight.map(|w| format!("{:.1}%", w)).unwrap_or_default(),
+             format!("{:.1}%", m.volatility * 100.0),
+             format!("{:.1}%", m.downside_deviation * 100.0),
+             ratio(m.beta),
+             ratio(m.sharpe),
+             ratio(m.sortino),
+             format!("-{:.1}%", m.max_drawdown * 100.0).red());
+}
+
+/// Pairwise correlation of daily returns as a colored heatmap, with the
+/// portfolio's concentration.
+pub async fn correlation_command<P: AsRef<Path>>(
+    portfolio_file: P,
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+) -> Result<()> {
+    let (portfolio, holdings_with_prices) = load_with_prices(portfolio_file).await?;
+    let mut tickers = portfolio.get_tickers();
+    tickers.sort();
+    tickers.dedup();
+    let (from, to) = history_range(from, to);
+    let history = fetch_price_history(&tickers, from, to).await?;
+    let matrix = correlation_matrix(&tickers, &history)?;
+    let concentration = crate::reports::concentration(&holdings_with_prices, &portfolio.base_currency);
+
+    println!("{}", "--------------------------------------".cyan());
+    print!("{:<8}", "");
+    for ticker in &matrix.tickers {
+        print!(" {:>8}", ticker.bold());
+    }
+    println!();
+    println!("{}", "--------------------------------------".cyan());
+
+    for (i, ticker) in matrix.tickers.iter().enumerate() {
+        print!("{:<8}", ticker.yellow());
+        for (j, value) in matrix.values[i].iter().enumerate() {
+            print!(" {}", heatmap_cell(*value, i == j));
+        }
+        println!();
+    }
+
+    println!("{}", "--------------------------------------".cyan());
+    if let Some(average) = matrix.average() {
+        println!("{}: {:.2}", "Average Correlation".bold(), average);
+    }
+    if let Some((a, b, c)) = matrix.highest_pair() {
+        println!("{}: {} / {} ({:.2})", "Most Correlated".bold(), a, b, c);
+    }
+    println!("{}: {:.4} ({} effective bets)",
+             "Concentration (HHI)".bold(),
+             concentration.herfindahl,
+             concentration.effective_bets);
+    println!("{} {} to {} ({} trading days)", "Period:".bold(), matrix.from, matrix.to, matrix.observations);
+
+    Ok(())
+}
+
+/// Line chart of the portfolio's value (the current positions over the
+/// price history), followed by a sparkline per ticker; or with `ticker`,
+/// the chart of that ticker's price.
+pub async fn chart_command<P: AsRef<Path>>(
+    portfolio_file: P,
+    ticker: Option<&str>,
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+    height: usize,
+) -> Result<()> {
+    let portfolio = Portfolio::load_from_file(portfolio_file)?;
+    let (from, to) = history_range(from, to);
+    let style = ChartStyle::detect();
+
+    let (title, tickers) = match ticker {
+        Some(ticker) => (format!("{} price", ticker.to_uppercase()), vec![ticker.to_uppercase()]),
+        None => {
+            let positions = positions(&portfolio.holdings);
+            if positions.is_empty() {
+                bail!("The portfolio has no holdings to chart");
+            }
+            ("Portfolio value".to_string(), positions.keys().cloned().collect())
+        }
+    };
+    let history = fetch_price_history(&tickers, from, to).await?;
+    let series: Vec<&PriceSeries> = tickers
+        .iter()
+        .map(|t| history.get(t).with_context(|| format!("No price history for {}", t)))
+        .collect::<Result<_>>()?;
+    let (dates, closes) = align(&series);
+    let values = match ticker {
+        Some(_) => closes[0].clone(),
+        None => value_series(&positions(&portfolio.holdings).into_values().collect::<Vec<_>>(), &closes),
+    };
+    let (Some(first_date), Some(last_date)) = (dates.first(), dates.last()) else {
+        bail!("No price history between {} and {}", from, to);
+    };
+
+    println!("{}", "--------------------------------------".cyan());
+    println!("{} {} to {}", title.bold(), first_date, last_date);
+    println!("{}", "--------------------------------------".cyan());
+    for line in line_chart(&values, &first_date.to_string(), &last_date.to_string(), height, style) {
+        println!("{}", line);
+    }
+
+    let change = |values: &[f64]| match (values.first(), values.last()) {
+        (Some(&first), Some(&last)) if first > 0.0 => {
+            let change = (last / first - 1.0) * 100.0;
+            let text = format!("{:+.1}%", change);
+            if change >= 0.0 { text.green() } else { text.red() }
+        }
+        _ => "-".normal(),
+    };
+    println!("{}", "--------------------------------------".cyan());
+    println!("{} {:.2}  {} {:.2}  {} {}",
+             "Start".bold(), values[0],
+             "End".bold(), values[values.len() - 1],
+             "Change".bold(), change(&values));
+
+    if ticker.is_none() && tickers.len() > 1 {
+        println!("{}", "--------------------------------------".cyan());
+        let label_width = tickers.iter().map(|t| t.len()).max().unwrap_or(0);
+        // Room for the label, the last close and the change
+        let width = style.width.saturating_sub(label_width + 22).max(10);
+        for (ticker, closes) in tickers.iter().zip(&closes) {
+            println!("{:<lw$} {} {:>10.2} {:>8}",
+                     ticker.yellow(),
+                     sparkline(closes, width, style.unicode),
+                     closes[closes.len() - 1],
+                     change(closes),
+                     lw = label_width);
+        }
+    }
+
+    Ok(())
+}
+
+/// Red for assets that move together, blue for ones that offset each other.
+fn heatmap_cell(value: Option<f64>, diagonal: bool) -> ColoredString {
+    let Some(c) = value else {
+        return format!("{:>8}", "-").dimmed();
+    };
+    let text = format!("{:>8.2}", c);
+    if diagonal {
+        text.dimmed()
+    } else if c >= 0.7 {
+        text.white().on_red()
+    } else if c >= 0.4 {
+        text.black().on_yellow()
+    } else if c > -0.4 {
+        text.normal()
+    } else {
+        text.white().on_blue()
+    }
+}
+
+/// Monte Carlo projection of the portfolio's value at each horizon, from
+/// the current holdings' price history.
+pub async fn project_command<P: AsRef<Path>>(
+    portfolio_file: P,
+    config: ProjectionConfig,
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+) -> Result<()> {
+    let (portfolio, holdings_with_prices) = load_with_prices(portfolio_file).await?;
+    let (from, to) = history_range(from, to);
+    let history = fetch_price_history(&portfolio.get_tickers(), from, to).await?;
+    let returns = historical_returns(&portfolio.holdings, &history)?;
+    let starting_value: Decimal = holdings_with_prices.iter().map(|h| h.current_value()).sum();
+    let projection = project(money::to_f64(starting_value), &returns, &config)?;
+    let currency = portfolio.base_currency.as_str();
+    let amount = |value: f64| money::round_money(money::from_f64(value), currency);
+
+    println!("{}", "--------------------------------------".cyan());
+    print!("{:<7} | {:<12}", "Years".bold(), "Contributed".bold());
+    for p in PERCENTILES {
+        print!(" | {:<12}", format!("P{}", p).bold());
+    }
+    println!(" | {:<8}", "Depleted".bold());
+    println!("{}", "--------------------------------------".cyan());
+
+    for outcome in &projection.outcomes {
+        print!("{:<7} | {:<12.2}", outcome.years.to_string().yellow(), amount(outcome.net_contributions));
+        for (p, value) in PERCENTILES.iter().zip(&outcome.percentiles) {
+            let cell = format!("{:<12.2}", amount(*value));
+            if *p == 50.0 {
+                print!(" | {}", cell.bold());
+            } else {
+                print!(" | {}", cell);
+            }
+        }
+        println!(" | {:<8}", format!("{:.1}%", outcome.depleted * 100.0));
+    }
+
+    println!("{}", "--------------------------------------".cyan());
+    println!("{}: {:.2}", "Starting Value".bold().green(), money::round_money(starting_value, currency));
+    println!("{} {} {:?} paths from {} daily returns ({} to {}), seed {}",
+             "Simulated:".bold(),
+             projection.paths,
+             projection.method,
+             returns.len(),
+             from,
+             to,
+             projection.seed);
+
+    Ok(())
+}
+
+/// Replay a strategy over historical prices and report it the way
+/// `performance` reports the live portfolio. Without `weights` it invests
+/// in the current holdings at their current weights; without `amount` it
+/// invests what the holdings cost.
+pub async fn backtest_command<P: AsRef<Path>>(
+    portfolio_file: P,
+    strategy: Strategy,
+    weights: Option<&Benchmark>,
+    amount: Option<Decimal>,
+    rebalance: Frequency,
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+) -> Result<()> {
+    let (portfolio, holdings_with_prices) = load_with_prices(portfolio_file).await?;
+    let currency = portfolio.base_currency.as_str();
+    let live = performance_report(&holdings_with_prices, currency);
+    let weights = match weights {
+        Some(weights) => weights.components.clone(),
+        None => current_weights(&holdings_with_prices)?,
+    };
+    let config = BacktestConfig {
+        strategy,
+        weights,
+        amount: amount.unwrap_or(live.total_cost),
+        rebalance,
+        currency: currency.to_string(),
+    };
+    let (from, to) = history_range(from, to);
+    let tickers: Vec<String> = config.weights.iter().map(|(t, _)| t.clone()).collect();
+    let history = fetch_price_history(&tickers, from, to).await?;
+    let result = backtest(&config, &history)?;
+
+    let signed_percent = |value: Decimal| {
+        let value = money::round(value, 1);
+        if value >= Decimal::ZERO {
+            format!("+{:.1}%", value).green()
+        } else {
+            format!("{:.1}%", value).red()
+        }
+    };
+    let signed_amount = |value: Decimal| {
+        if value >= Decimal::ZERO {
+            format!("+{:.2}", value).green()
+        } else {
+            format!("{:.2}", value).red()
+        }
+    };
+
+    println!("{}", "--------------------------------------".cyan());
+    println!("{:<12} | {:<12} | {:<12}", "Month End".bold(), "Invested".bold(), "Value".bold());
+    println!("{}", "--------------------------------------".cyan());
+    for point in month_ends(&result.values) {
+        println!("{:<12} | {:<12.2} | {:<12.2}",
+                 point.date.to_string().yellow(),
+                 money::round_money(point.invested, currency),
+                 money::round_money(point.value, currency));
+    }
+
+    println!("{}", "--------------------------------------".cyan());
+    println!("{:<8} | {:<14} | {:<13} | {:<10} | {:<12}",
+             "Ticker".bold(),
+             "Average Cost".bold(),
+             "Last Price".bold(),
+             "Return %".bold(),
+             "Gain/Loss".bold());
+    println!("{}", "--------------------------------------".cyan());
+    for row in &result.rows {
+        println!("{:<8} | {:<14.2} | {:<13.2} | {:<10} | {:<12}",
+                 row.ticker.yellow(),
+                 money::round(row.average_cost, 2),
+                 money::round(row.last_price, 2),
+                 signed_percent(row.return_percentage),
+                 signed_amount(row.gain_loss));
+    }
+    if !result.cash.is_zero() {
+        println!("{:<8} | {:<14} | {:<13} | {:<10} | {:<12.2}", "Cash".yellow(), "", "", "", result.cash);
+    }
+
+    println!("{}", "--------------------------------------".cyan());
+    println!("{} {}", "Total Return:".bold(), signed_percent(result.total_return_percentage).bold());
+    println!("{} {}", "Total Gain/Loss:".bold(), signed_amount(result.total_gain_loss).bold());
+    println!("{:<16} {:.2} -> {:.2}", "Invested", result.invested, result.final_value);
+    println!("{:<16} {:.1}% a year, {:.1}% volatility",
+             "Time-weighted",
+             result.annual_return * 100.0,
+             result.volatility * 100.0);
+    println!("{:<16} {}", "Max Drawdown", format!("-{:.1}%", result.max_drawdown * 100.0).red());
+    println!("{:<16} {}", "Live Portfolio", signed_percent(live.total_return_percentage));
+    println!("{} {} to {}, {} trade(s)", "Period:".bold(), result.from, result.to, result.trades);
+
+    Ok(())
+}
+
+/// Weight of each ticker in the portfolio's current value.
+fn current_weights(holdings: &[HoldingWithPrice]) -> Result<Vec<(String, f64)>> {
+    let mut values: std::collections::BTreeMap<String, Decimal> = std::collections::BTreeMap::new();
+    for h in holdings {
+        *values.entry(h.holding.ticker.clone()).or_default() += h.current_value();
+    }
+    let total: Decimal = values.values().sum();
+    if total <= Decimal::ZERO {
+        bail!("The portfolio has no value to take weights from; pass --weights");
+    }
+    Ok(values.into_iter().map(|(ticker, value)| (ticker, money::to_f64(value / total))).collect())
+}
+
+/// Evaluate alert rules against fresh quotes, and with `notify` deliver
+/// the fired ones to the rules file's sinks. Returns whether any rule
+/// fired, so the caller can exit with a non-zero status for cron and
+/// monitoring.
+pub async fn alerts_check_command<P: AsRef<Path>>(
+    portfolio_file: P,
+    rules_file: &Path,
+    json: bool,
+    notify: bool,
+) -> Result<bool> {
+    let portfolio = Portfolio::load_from_file(portfolio_file)?;
+    let rules = RuleSet::load_from_file(rules_file)?;
+    let report = alerts::check(&portfolio, &rules).await?;
+    for ticker in &report.unquoted {
+        eprintln!("{}: Could not fetch price for {}", "warning".yellow().bold(), ticker);
+    }
 
+    let delivery = match (&rules.notify, notify && !report.fired.is_empty()) {
+        (Some(settings), true) => {
+            Some(crate::notify::notify_alerts(&report.fired, settings, &settings.state_path(rules_file)).await?)
+        }
+        (None, true) => bail!("{} has no [notify] section to deliver alerts with", rules_file.display()),
+        _ => None,
+    };
+    // Failed sinks go to stderr so they never mix with the JSON output
+    for err in delivery.iter().flat_map(|d| &d.errors) {
+        eprintln!("{}: {:#}", "warning".yellow().bold(), err);
+    }
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(!report.fired.is_empty());
+    }
+
+    println!("{}", "--------------------------------------".cyan());
+    println!("{} {} rule(s) at {}", "Checked".bold(), report.rules, report.checked_at.format("%Y-%m-%d %H:%M UTC"));
+    println!("{}", "--------------------------------------".cyan());
+    for alert in &report.fired {
+        println!("{} {}: {}", "ALERT".red().bold(), alert.rule.yellow(), alert.message);
+    }
+    if report.fired.is_empty() {
+        println!("{}", "No alerts".green());
+    } else {
+        println!("{}", "--------------------------------------".cyan());
+        println!("{}", format!("{} alert(s) fired", report.fired.len()).bold().red());
+    }
+    if let Some(delivery) = delivery {
+        println!("{} {} alert(s), {} already sent recently",
+                 "Notified".bold(),
+                 delivery.sent,
+                 delivery.suppressed);
+    }
+
+    Ok(!report.fired.is_empty())
+}
+
+/// List the rules in an alert rules file.
+pub fn alerts_list_command(rules_file: &Path) -> Result<()> {
+    let rules = RuleSet::load_from_file(rules_file)?;
+
+    println!("{}", "--------------------------------------".cyan());
+    println!("{:<4} | {:<13} | {}", "#".bold(), "Kind".bold(), "Rule".bold());
+    println!("{}", "--------------------------------------".cyan());
+    for (index, rule) in rules.rules.iter().enumerate() {
+        println!("{:<4} | {:<13} | {}", index, alerts::kind_name(&rule.condition).yellow(), rule.label());
+    }
+
+    Ok(())
+}
+
+/// Serve the web dashboard and JSON endpoints for `portfolio_files` until
+/// Ctrl-C.
+pub async fn serve_command(portfolio_files: &[PathBuf], bind: SocketAddr, token: Option<String>) -> Result<()> {
+    if token.as_deref().is_some_and(|t| t.trim().is_empty()) {
+        bail!("The token must not be empty");
+    }
+    crate::server::serve(portfolio_files, crate::server::ServeOptions { bind, token }).await
+}
+
+/// Export portfolio and quote metrics for Prometheus until Ctrl-C.
+pub async fn metrics_command(portfolio_files: &[PathBuf], bind: SocketAddr, refresh: Duration) -> Result<()> {
+    crate::metrics::serve(portfolio_files, bind, refresh).await
+}
+
+/// Run the jobs in a schedule file until SIGINT or SIGTERM, or each one
+/// once with `once`.
+pub async fn daemon_command(schedule_file: &Path, log_format: LogFormat, once: bool) -> Result<()> {
+    let config = DaemonConfig::load_from_file(schedule_file)?;
+    crate::daemon::init_logging(log_format);
+    crate::daemon::run(config, once).await
+}
+
+/// Full-screen dashboard, refreshing quotes every `refresh_seconds`.
+pub async fn dashboard_command<P: AsRef<Path>>(portfolio_file: P, refresh_seconds: u64) -> Result<()> {
+    if refresh_seconds == 0 {
+        bail!("The refresh interval must be at least one second");
+    }
+    crate::dashboard::run(portfolio_file.as_ref(), std::time::Duration::from_secs(refresh_seconds)).await
+}
+
+/// Prompt for commands against one portfolio until `exit` or Ctrl-D.
+pub async fn shell_command(portfolio_file: &Path, method: CostBasisMethod) -> Result<()> {
+    crate::shell::run(portfolio_file, method).await
+}
+
+/// A change to the watchlist, made before it is shown.
+#[derive(Debug, Clone)]
+pub enum WatchChange {
+    Add { ticker: String, target_price: Option<Decimal>, note: Option<String> },
+    Remove { ticker: String },
+}
+
+/// Show the watchlist against current quotes, from the same price provider
+/// as `balance`. Adding a ticker records today's quote so later runs can
+/// show how far it has moved since.
+pub async fn watch_command<P: AsRef<Path>>(portfolio_file: P, change: Option<WatchChange>) -> Result<()> {
+    let portfolio_file = portfolio_file.as_ref();
+    let mut portfolio = Portfolio::load_from_file(portfolio_file)?;
+    let mut tickers: Vec<String> = portfolio.watchlist.iter().map(|w| w.ticker.clone()).collect();
+    let mut quotes = None;
+
+    match change {
+        Some(WatchChange::Add { ticker, target_price, note }) => {
+            let ticker = ticker.trim().to_uppercase();
+            if target_price.is_some_and(|t| t <= Decimal::ZERO) {
+                bail!("Target price for {} must be positive", ticker);
+            }
+            // One fetch for the new ticker and the table below
+            tickers.push(ticker.clone());
+            let fetched = watch_quotes(&tickers).await?;
+            portfolio.watch(WatchItem {
+                ticker: ticker.clone(),
+                added: crate::validation::local_today().format(crate::validation::DATE_FORMAT).to_string(),
+                price_added: fetched.get(&ticker).copied(),
+                target_price,
+                note,
+            });
+            portfolio.save_to_file(portfolio_file)?;
+            println!("{} {}", "Watching".green(), ticker);
+            quotes = Some(fetched);
+        }
+        Some(WatchChange::Remove { ticker }) => {
+            let ticker = ticker.trim().to_uppercase();
+            if !portfolio.unwatch(&ticker) {
+                bail!("{} is not on the watchlist", ticker);
+            }
+            portfolio.save_to_file(portfolio_file)?;
+            println!("{} {}", "Removed".green(), ticker);
+        }
+        None => {}
+    }
+
+    if portfolio.watchlist.is_empty() {
+        println!("The watchlist is empty; add a ticker with --add");
+        return Ok(());
+    }
+
+    let quotes = match quotes {
+        Some(quotes) => quotes,
+        None => watch_quotes(&tickers).await?,
+    };
+    let rows = watchlist_report(&portfolio.watchlist, &quotes);
+    let percent = |p: Option<Decimal>| p.map(|p| format!("{:+.2}%", p)).unwrap_or_else(|| "-".to_string());
+
+    println!("{}", "--------------------------------------".cyan());
+    println!("{:<8} | {:<10} | {:<10} | {:<11} | {:<10} | {:<10}",
+             "Ticker".bold(),
+             "Added".bold(),
+             "Quote".bold(),
+             "Since Added".bold(),
+             "Target".bold(),
+             "To Target".bold());
+    println!("{}", "--------------------------------------".cyan());
+
+    for row in &rows {
+        let target = row.target_price.map(|t| format!("{:.2}", money::round(t, 2))).unwrap_or_else(|| "-".to_string());
+        let distance = percent(row.distance_to_target);
+        let quote = if row.quote.is_zero() { "-".to_string() } else { format!("{:.2}", money::round(row.quote, 2)) };
+        println!("{:<8} | {:<10} | {:<10} | {:<11} | {:<10} | {:<10}",
+                 row.ticker.yellow(),
+                 row.added,
+                 quote,
+                 percent(row.change_since_added),
+                 target,
+                 if row.at_target { distance.green() } else { distance.normal() });
+        if let Some(note) = &row.note {
+            println!("{:<8}   {}", "", note.dimmed());
+        }
+    }
+
+    println!("{}", "--------------------------------------".cyan());
+    let at_target = rows.iter().filter(|r| r.at_target).count();
+    if at_target > 0 {
+        println!("{}", format!("{} ticker(s) at or below target", at_target).bold().green());
+    }
+
+    Ok(())
+}
+
+/// Quotes for the watchlist. Tickers without one are left out, so a failed
+/// quote is never recorded as the price when added.
+async fn watch_quotes(tickers: &[String]) -> Result<HashMap<String, Decimal>> {
+    let (mut quotes, failed) = fetch_prices_with_failures(tickers).await?;
+    for ticker in &failed {
+        eprintln!("Warning: Could not fetch price for {}", ticker);
+        quotes.remove(ticker);
+    }
+    Ok(quotes)
+}
+
+/// Default history window: the year up to today.
+fn history_range(from: Option<NaiveDate>, to: Option<NaiveDate>) -> (NaiveDate, NaiveDate) {
+    let to = to.unwrap_or_else(|| chrono::Utc::now().date_naive());
+    let from = from.unwrap_or_else(|| to - chrono::Duration::days(365));
+    (from, to)
+}
+
+pub async fn export_command<P: AsRef<Path>>(
+    portfolio_file: P,
+    output: &Path,
+    format: Option<ExportFormat>,
+) -> Result<()> {
+    let portfolio = Portfolio::load_from_file(portfolio_file)?;
+    let prices = fetch_current_prices(&portfolio.get_tickers()).await?;
+    let holdings_with_prices = portfolio.holdings_with_prices(&prices);
+    let format = format.unwrap_or_else(|| ExportFormat::from_path(output));
+    let sheets = build_sheets(&holdings_with_prices, &portfolio.transactions, &portfolio.base_currency);
+    let written = write_export(&sheets, output, format)?;
+
+    println!("{}", "--------------------------------------".cyan());
+    println!("{}", "Export".bold());
+    println!("{}", "--------------------------------------".cyan());
+    for path in &written {
+        println!("{} {}", "Wrote".green(), path.display());
+    }
+    println!("{}", "--------------------------------------".cyan());
+
+    Ok(())
+}
+
+pub fn encrypt_command(portfolio_file: &Path, output: Option<&Path>, new_passphrase_file: Option<&Path>) -> Result<()> {
+    refuse_database("encrypt", portfolio_file)?;
+    if let Some(output) = output {
+        refuse_database("encrypt", output)?;
+    }
+    let plaintext = fs::read(portfolio_file)
+        .with_context(|| format!("Failed to read portfolio file: {:?}", portfolio_file))?;
+    if crypto::is_encrypted(&plaintext) {
+        bail!("{:?} is already encrypted; use `rekey` to change the passphrase", portfolio_file);
+    }
+
+    let passphrase = crypto::new_passphrase(new_passphrase_file)?;
+    let encrypted = crypto::encrypt(&plaintext, &passphrase)?;
+    let target = output.unwrap_or(portfolio_file);
+    write_file_atomic(target, &encrypted)?;
+
+    println!("{} {}", "Encrypted".green(), target.display());
+    Ok(())
+}
+
+pub fn decrypt_command(portfolio_file: &Path, output: Option<&Path>) -> Result<()> {
+    refuse_database("decrypt", portfolio_file)?;
+    if let Some(output) = output {
+        refuse_database("decrypt", output)?;
+    }
+    let data = fs::read(portfolio_file)
+        .with_context(|| format!("Failed to read portfolio file: {:?}", portfolio_file))?;
+    if !crypto::is_encrypted(&data) {
+        bail!("{:?} is not encrypted", portfolio_file);
+    }
+
+    let plaintext = crypto::decrypt(&data, &crypto::passphrase()?)?;
+    let target = output.unwrap_or(portfolio_file);
+    write_file_atomic(target, &plaintext)?;
+
+    println!("{} {}", "Decrypted".green(), target.display());
+    Ok(())
+}
+
+pub fn rekey_command(portfolio_file: &Path, new_passphrase_file: Option<&Path>) -> Result<()> {
+    refuse_database("rekey", portfolio_file)?;
+    let data = fs::read(portfolio_file)
+        .with_context(|| format!("Failed to read portfolio file: {:?}", portfolio_file))?;
+    if !crypto::is_encrypted(&data) {
+        bail!("{:?} is not encrypted; use `encrypt` first", portfolio_file);
+    }
+
+    let plaintext = crypto::decrypt(&data, &crypto::passphrase()?)?;
+    let passphrase = crypto::new_passphrase(new_passphrase_file)?;
+    let encrypted = crypto::encrypt(&plaintext, &passphrase)?;
+    write_file_atomic(portfolio_file, &encrypted)?;
+
+    println!("{} {}", "Re-encrypted".green(), portfolio_file.display());
+    Ok(())
+}
+
+/// Upgrade a portfolio file to the current schema version in place, keeping
+/// the original next to it as `<file>.v<N>.bak`.
+pub fn migrate_command(portfolio_file: &Path, dry_run: bool) -> Result<()> {
+    refuse_database("migrate", portfolio_file)?;
+    let content = read_portfolio_text(portfolio_file)?;
+    let format = formats::input_format_for(portfolio_file);
+    let (value, _) = formats::parse(&content, format)?;
+    let version = schema::detect_version(&value)?;
+
+    if version == CURRENT_SCHEMA_VERSION {
+        println!("{} is already at schema version {}", portfolio_file.display(), version);
+        return Ok(());
+    }
+
+    // Parse fully before touching anything so a broken file is never rewritten
+    let mut portfolio = Portfolio::from_str_with_format(&content, format)?;
+
+    if dry_run {
+        println!("{} would be migrated from schema version {} to {}",
+                 portfolio_file.display(), version, CURRENT_SCHEMA_VERSION);
+        return Ok(());
+    }
+
+    let backup = backup_path(portfolio_file, version);
+    fs::copy(portfolio_file, &backup)
+        .with_context(|| format!("Failed to write backup: {:?}", backup))?;
+    portfolio.save_to_file(portfolio_file)?;
+
+    println!("{} {} from schema version {} to {}",
+             "Migrated".green(), portfolio_file.display(), version, CURRENT_SCHEMA_VERSION);
+    println!("{} {}", "Backup".cyan(), backup.display());
+    Ok(())
+}
+
+/// Databases have no schema versions or source lines and are not encrypted
+/// as a whole, so `validate`, `migrate`, `encrypt`, `decrypt` and `rekey` only
+/// work on portfolio files.
+fn refuse_database(command: &str, path: &Path) -> Result<()> {
+    if is_database_path(path) {
+        bail!("{} applies to portfolio files, not databases: {}", command, path.display());
+    }
+    Ok(())
+}
+
+/// `<file>.v1.bak`, or `<file>.v1.2.bak`, `<file>.v1.3.bak`, ... if a backup
+/// from an earlier run already exists.
+fn backup_path(portfolio_file: &Path, version: u32) -> std::path::PathBuf {
+    let name = portfolio_file
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let mut candidate = portfolio_file.with_file_name(format!("{}.v{}.bak", name, version));
+    let mut n = 2;
+    while candidate.exists() {
+        candidate = portfolio_file.with_file_name(format!("{}.v{}.{}.bak", name, version, n));
+        n += 1;
+    }
+    candidate
+}
+
+/// Check a portfolio file and print every problem found. Returns whether the
+/// file is free of errors (warnings alone still pass).
+pub fn validate_command(portfolio_file: &Path) -> Result<bool> {
+    refuse_database("validate", portfolio_file)?;
+    let content = read_portfolio_text(portfolio_file)?;
+
+    println!("{}", "--------------------------------------".cyan());
+    println!("{} {}", "Validating".bold(), portfolio_file.display());
+    println!("{}", "--------------------------------------".cyan());
+
+    let report = match parse_and_validate(&content, formats::input_format_for(portfolio_file)) {
+        Ok((_, report)) => report,
+        Err(err) => {
+            println!("{}: {:#}", "error".red().bold(), err);
+            return Ok(false);
+        }
+    };
+
+    for issue in &report.issues {
+        let severity = match issue.severity {
+            Severity::Error => "error".red().bold(),
+            Severity::Warning => "warning".yellow().bold(),
+        };
+        println!("{}: {}: {}", severity, issue.location.to_string().cyan(), issue.message);
+        if let Some(hint) = &issue.hint {
+            println!("    {} {}", "hint:".dimmed(), hint);
+        }
+    }
+
+    if !report.issues.is_empty() {
+        println!("{}", "--------------------------------------".cyan());
+    }
+    let summary = format!("{} error(s), {} warning(s)", report.error_count(), report.warning_count());
+    if report.has_errors() {
+        println!("{}", summary.red().bold());
+    } else {
+        println!("{}", summary.green().bold());
+    }
+
+    Ok(!report.has_errors())
+}
+
+/// Rewrite a portfolio file in another format. Encrypted input produces
+/// encrypted output under the same passphrase. A database path as the output
+/// creates (or replaces the contents of) a SQLite portfolio instead.
+pub fn convert_command(input: &Path, output: &Path, to: Option<FileFormat>) -> Result<()> {
+    let encrypted = !is_database_path(input)
+        && crypto::is_encrypted(&fs::read(input).with_context(|| format!("Failed to read portfolio file: {:?}", input))?);
+
+    if is_database_path(output) {
+        if let Some(format) = to {
+            bail!("{} is a database and cannot be written as {}", output.display(), format.name());
+        }
+        if encrypted {
+            bail!("{} is encrypted, but SQLite databases cannot be; decrypt it first", input.display());
+        }
+        Portfolio::load_from_file(input)?.save_to_file(output)?;
+        println!("{} {} -> {} (SQLite)", "Converted".green(), input.display(), output.display());
+        return Ok(());
+    }
+    let mut portfolio = Portfolio::load_from_file(input)?;
+    let format = to.unwrap_or_else(|| FileFormat::from_path(output));
+
+    portfolio.save_to_file_as(output, format, encrypted)?;
+
+    println!("{} {} -> {} ({}{})",
+             "Converted".green(),
+             input.display(),
+             output.display(),
+             format.name(),
+             if encrypted { ", encrypted" } else { "" });
+    Ok(())
+}
+
+/// Show the settings in effect and the config file and profile they came
+/// from.
+pub fn config_command(settings: &Settings) {
+    crate::config::print_settings(settings);
+}
+
+/// Print the completion script for `shell`; it calls back into this
+/// binary for candidates.
+pub fn completions_command(shell: CompletionShell) -> Result<()> {
+    let binary = std::env::current_exe().unwrap_or_else(|_| PathBuf::from("portfolio_rs"));
+    crate::completions::write_registration(shell, &binary, &mut std::io::stdout())
+}
+
+/// Print the manual page, or write one per subcommand into `dir`.
+pub fn manpage_command(cli: clap::Command, dir: Option<&Path>) -> Result<()> {
+    crate::completions::write_manpages(cli, dir, &mut std::io::stdout())?;
+    if let Some(dir) = dir {
+        println!("{} {}", "Wrote manual pages to".green(), dir.display());
+    }
+    Ok(())
+}
+
+/// Copy a portfolio file into a SQLite database, replacing the holdings and
+/// ledger already stored there.
+#[cfg(feature = "sqlite")]
+pub fn db_import_command(source: &Path, database: &Path) -> Result<()> {
+    use crate::storage::PortfolioStore;
+
+    let mut portfolio = Portfolio::load_from_file(source)?;
+    let mut store = crate::sqlite::SqliteStore::create(database)?;
+    store.save(&mut portfolio)?;
+
+    println!("{} {} holding(s) and {} transaction(s) from {} into {}",
+             "Imported".green(),
+             portfolio.holdings.len(),
+             portfolio.transactions.len(),
+             source.display(),
+             database.display());
+    Ok(())
+}
+
+/// Write the holdings and ledger of a SQLite database to a portfolio file.
+#[cfg(feature = "sqlite")]
+pub fn db_export_command(database: &Path, destination: &Path, to: Option<FileFormat>) -> Result<()> {
+    use crate::storage::PortfolioStore;
+
+    let mut portfolio = crate::sqlite::SqliteStore::open(database)?.load()?;
+    let format = to.unwrap_or_else(|| FileFormat::from_path(destination));
+    portfolio.save_to_file_as(destination, format, false)?;
+
+    println!("{} {} -> {} ({})",
+             "Exported".green(),
+             database.display(),
+             destination.display(),
+             format.name());
+    Ok(())
+}
+
+/// List lots straight from the database's index, e.g. all AAPL lots bought
+/// in 2021, without loading the whole portfolio.
+#[cfg(feature = "sqlite")]
+pub fn db_lots_command(database: &Path, query: &crate::sqlite::LedgerQuery) -> Result<()> {
+    let store = crate::sqlite::SqliteStore::open(database)?;
+    let lots = store.lots(query)?;
+
+    println!("{}", "--------------------------------------".cyan());
+    println!("{:<8} | {:<10} | {:<10} | {:<10}",
+             "Ticker".bold(),
+             "Date".bold(),
+             "Quantity".bold(),
+             "Cost Basis".bold());
+    println!("{}", "--------------------------------------".cyan());
+
+    for lot in &lots {
+        println!("{:<8} | {:<10} | {:<10} | {:<10.2}",
+                 lot.ticker.yellow(),
+                 lot.date_purchased,
+                 money::round_quantity(lot.quantity, &lot.ticker).normalize(),
+                 money::round(lot.cost_basis, 2));
+    }
+
+    println!("{}", "--------------------------------------".cyan());
+    println!("{}: {}", "Lots".bold(), lots.len());
     Ok(())
 }
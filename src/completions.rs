@@ -0,0 +1,201 @@
+use anyhow::{Context, Result};
+use clap_complete::env::{self, EnvCompleter};
+use clap_complete::CompletionCandidate;
+use std::ffi::{OsStr, OsString};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::config::Settings;
+use crate::crypto;
+use crate::portfolio::Portfolio;
+use crate::storage::is_database_path;
+
+// Completions are generated by the binary itself: the script a shell sources
+// calls back into `portfolio_rs` with PORTFOLIO_COMPLETE set, and clap works
+// out the candidates from the same `Cli` definition used for parsing. That
+// keeps them in step with the commands and lets ticker arguments complete
+// from the portfolio file named on the command line.
+
+/// Environment variable that switches the binary into completion mode.
+pub const COMPLETE_VAR: &str = "PORTFOLIO_COMPLETE";
+
+const BIN: &str = "portfolio_rs";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum CompletionShell {
+    Bash,
+    Zsh,
+    Fish,
+    Powershell,
+}
+
+impl CompletionShell {
+    fn completer(self) -> &'static dyn EnvCompleter {
+        match self {
+            CompletionShell::Bash => &env::Bash,
+            CompletionShell::Zsh => &env::Zsh,
+            CompletionShell::Fish => &env::Fish,
+            CompletionShell::Powershell => &env::Powershell,
+        }
+    }
+}
+
+/// Write the script that registers completions for `shell`. `completer` is
+/// the binary the script runs to get candidates.
+pub fn write_registration(shell: CompletionShell, completer: &Path, out: &mut dyn Write) -> Result<()> {
+    shell
+        .completer()
+        .write_registration(COMPLETE_VAR, BIN, BIN, &completer.to_string_lossy(), out)
+        .context("Failed to write the completion script")
+}
+
+/// Every ticker a portfolio mentions: holdings, transactions and the
+/// watchlist. Empty when the file cannot be read without help, so an
+/// encrypted file never prompts for a passphrase in the middle of a
+/// completion.
+pub fn portfolio_tickers(path: &Path) -> Vec<String> {
+    if !is_database_path(path) {
+        match std::fs::read(path) {
+            Ok(bytes) if !crypto::is_encrypted(&bytes) => {}
+            _ => return Vec::new(),
+        }
+    }
+    let Ok(portfolio) = Portfolio::load_from_file(path) else {
+        return Vec::new();
+    };
+
+    let mut tickers: Vec<String> = portfolio.holdings.iter().map(|h| h.ticker.clone()).collect();
+    tickers.extend(portfolio.transactions.iter().filter_map(|t| t.ticker.clone()));
+    tickers.extend(portfolio.watchlist.iter().map(|w| w.ticker.clone()));
+    tickers.sort();
+    tickers.dedup();
+    tickers
+}
+
+/// The first argument on the line being completed that names an existing
+/// file, taken to be the portfolio (or database) the command reads.
+fn portfolio_in_args(args: &[OsString]) -> Option<PathBuf> {
+    // The shell passes the words being completed after `--`
+    let words = args.iter().position(|a| a == "--").map(|i| &args[i + 1..]).unwrap_or(args);
+    words.iter().skip(1).map(PathBuf::from).find(|path| path.is_file())
+}
+
+fn ticker_candidates(current: &OsStr, args: &[OsString], configured: Option<PathBuf>) -> Vec<CompletionCandidate> {
+    let Some(path) = portfolio_in_args(args).or(configured) else {
+        return Vec::new();
+    };
+    let prefix = current.to_string_lossy().to_uppercase();
+    portfolio_tickers(&path)
+        .into_iter()
+        .filter(|ticker| ticker.to_uppercase().starts_with(&prefix))
+        .map(CompletionCandidate::new)
+        .collect()
+}
+
+/// Completer for ticker arguments (`--ticker`, `watch --add`, ...). Without
+/// a file on the command line, the configured portfolio is used.
+pub fn complete_ticker(current: &OsStr) -> Vec<CompletionCandidate> {
+    let args: Vec<OsString> = std::env::args_os().collect();
+    let configured = Settings::load(None, None).ok().and_then(|settings| settings.portfolio);
+    ticker_candidates(current, &args, configured)
+}
+
+/// Render the manual page for `cmd` to `out`, or with `dir`, write one page
+/// per command and subcommand there (`portfolio_rs.1`,
+/// `portfolio_rs-balances.1`, ...).
+pub fn write_manpages(cmd: clap::Command, dir: Option<&Path>, out: &mut dyn Write) -> Result<()> {
+    match dir {
+        Some(dir) => {
+            std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {:?}", dir))?;
+            clap_mangen::generate_to(cmd, dir).with_context(|| format!("Failed to write manual pages to {:?}", dir))
+        }
+        None => clap_mangen::Man::new(cmd).render(out).context("Failed to render the manual page"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    const PORTFOLIO: &str = r#"{
+        "schema_version": 3,
+        "holdings": [
+            {"ticker": "AAPL", "quantity": 10, "cost_basis": 150, "date_purchased": "2024-01-01"},
+            {"ticker": "AMZN", "quantity": 1, "cost_basis": 120, "date_purchased": "2024-01-01"}
+        ],
+        "transactions": [{"date": "2024-02-01", "kind": "dividend", "ticker": "MSFT", "price": 3}],
+        "watchlist": [{"ticker": "TSLA", "added": "2024-01-01"}]
+    }"#;
+
+    fn args(words: &[&str]) -> Vec<OsString> {
+        words.iter().map(OsString::from).collect()
+    }
+
+    #[test]
+    fn test_portfolio_tickers() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("portfolio.json");
+        fs::write(&path, PORTFOLIO).unwrap();
+
+        assert_eq!(portfolio_tickers(&path), vec!["AAPL", "AMZN", "MSFT", "TSLA"]);
+        assert!(portfolio_tickers(&dir.path().join("missing.json")).is_empty());
+
+        let encrypted = dir.path().join("secret.json");
+        let fast = crypto::KdfParams { memory_kib: 64, iterations: 1, parallelism: 1 };
+        fs::write(&encrypted, crypto::encrypt_with_params(PORTFOLIO.as_bytes(), "passphrase", fast).unwrap()).unwrap();
+        assert!(portfolio_tickers(&encrypted).is_empty());
+    }
+
+    #[test]
+    fn test_ticker_candidates_use_portfolio_on_command_line() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("portfolio.json");
+        fs::write(&path, PORTFOLIO).unwrap();
+        let path = path.to_str().unwrap();
+
+        let line = args(&["portfolio_rs", "--", "portfolio_rs", "chart", path, "--ticker", "a"]);
+        let names: Vec<String> = ticker_candidates(OsStr::new("a"), &line, None)
+            .iter()
+            .map(|c| c.get_value().to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(names, vec!["AAPL", "AMZN"]);
+
+        // No portfolio typed yet: the configured one, if any
+        let line = args(&["portfolio_rs", "--", "portfolio_rs", "chart", "--ticker", ""]);
+        assert!(ticker_candidates(OsStr::new(""), &line, None).is_empty());
+        assert_eq!(ticker_candidates(OsStr::new("t"), &line, Some(PathBuf::from(path))).len(), 1);
+    }
+
+    #[test]
+    fn test_registration_scripts() {
+        for shell in [CompletionShell::Bash, CompletionShell::Zsh, CompletionShell::Fish, CompletionShell::Powershell] {
+            let mut script = Vec::new();
+            write_registration(shell, Path::new("/usr/local/bin/portfolio_rs"), &mut script).unwrap();
+            let script = String::from_utf8(script).unwrap();
+            assert!(script.contains(COMPLETE_VAR), "{:?}", shell);
+            assert!(script.contains("/usr/local/bin/portfolio_rs"), "{:?}", shell);
+        }
+    }
+
+    #[test]
+    fn test_manpages() {
+        let cmd = || {
+            clap::Command::new("portfolio_rs")
+                .about("Personal Investment Portfolio CLI Tracker")
+                .subcommand(clap::Command::new("balances").about("Show current balances"))
+        };
+
+        let mut page = Vec::new();
+        write_manpages(cmd(), None, &mut page).unwrap();
+        let page = String::from_utf8(page).unwrap();
+        assert!(page.starts_with(".ie"), "{}", page);
+        assert!(page.contains("balances"));
+
+        let dir = tempdir().unwrap();
+        write_manpages(cmd(), Some(&dir.path().join("man")), &mut Vec::new()).unwrap();
+        assert!(dir.path().join("man/portfolio_rs.1").exists());
+        assert!(dir.path().join("man/portfolio_rs-balances.1").exists());
+    }
+}
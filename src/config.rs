@@ -0,0 +1,411 @@
+use anyhow::{bail, Context, Result};
+use clap::ValueEnum;
+use colored::*;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::api::PriceProvider;
+use crate::formats::{self, FileFormat};
+use crate::portfolio::CostBasisMethod;
+use crate::reports::OutputFormat;
+
+// Defaults for every command, so `portfolio_rs balances` works without a
+// path. The config file (TOML, JSON or YAML) holds top-level settings and
+// named profiles that override them:
+//
+//     portfolio = "~/finance/portfolio.json"
+//     base_currency = "EUR"
+//     default_profile = "personal"
+//
+//     [profiles.work]
+//     portfolio = "/srv/shared/team.db"
+//     price_provider = "alpha-vantage"
+//
+// Each setting is taken from, in order: a command-line flag, a PORTFOLIO_*
+// environment variable, the selected profile, the top level of the file,
+// and finally the built-in default.
+
+/// Path of the config file, instead of the default location.
+pub const CONFIG_ENV: &str = "PORTFOLIO_CONFIG";
+/// Profile to use, instead of the file's `default_profile`.
+pub const PROFILE_ENV: &str = "PORTFOLIO_PROFILE";
+
+/// Environment variable for each setting, by its name in the file.
+pub const SETTING_ENVS: [(&str, &str); 7] = [
+    ("portfolio", "PORTFOLIO_FILE"),
+    ("base_currency", "PORTFOLIO_BASE_CURRENCY"),
+    ("price_provider", "PORTFOLIO_PRICE_PROVIDER"),
+    ("api_key", "PORTFOLIO_API_KEY"),
+    ("output_format", "PORTFOLIO_OUTPUT_FORMAT"),
+    ("color", "PORTFOLIO_COLOR"),
+    ("cost_basis", "PORTFOLIO_COST_BASIS"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorChoice {
+    /// Colour when writing to a terminal and NO_COLOR is not set
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+/// One layer of settings: the top level of the file, a profile, or the
+/// environment.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Layer {
+    portfolio: Option<PathBuf>,
+    base_currency: Option<String>,
+    price_provider: Option<PriceProvider>,
+    api_key: Option<String>,
+    output_format: Option<OutputFormat>,
+    color: Option<ColorChoice>,
+    cost_basis: Option<CostBasisMethod>,
+}
+
+impl Layer {
+    /// Settings in `over` replace those here.
+    fn merge(self, over: Layer) -> Layer {
+        Layer {
+            portfolio: over.portfolio.or(self.portfolio),
+            base_currency: over.base_currency.or(self.base_currency),
+            price_provider: over.price_provider.or(self.price_provider),
+            api_key: over.api_key.or(self.api_key),
+            output_format: over.output_format.or(self.output_format),
+            color: over.color.or(self.color),
+            cost_basis: over.cost_basis.or(self.cost_basis),
+        }
+    }
+
+    /// Paths in a config file are relative to the file.
+    fn relative_to(mut self, base: &Path, home: Option<&Path>) -> Layer {
+        self.portfolio = self.portfolio.map(|path| expand_path(&path, base, home));
+        self
+    }
+}
+
+fn expand_path(path: &Path, base: &Path, home: Option<&Path>) -> PathBuf {
+    match (path.strip_prefix("~"), home) {
+        (Ok(rest), Some(home)) => home.join(rest),
+        _ => base.join(path),
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+struct ConfigFile {
+    defaults: Layer,
+    default_profile: Option<String>,
+    profiles: BTreeMap<String, Layer>,
+}
+
+impl ConfigFile {
+    fn parse(content: &str, path: &Path) -> Result<ConfigFile> {
+        let format = FileFormat::from_path(path);
+        if format == FileFormat::Csv {
+            bail!("The config file must be TOML, JSON or YAML, not CSV");
+        }
+        let (value, _) = formats::parse(content, format)?;
+        let Value::Object(mut map) = value else {
+            bail!("The config file {:?} must be a table of settings", path);
+        };
+
+        let default_profile = match map.remove("default_profile") {
+            None => None,
+            Some(Value::String(name)) => Some(name),
+            Some(other) => bail!("default_profile must be a profile name, not {}", other),
+        };
+        let profiles: BTreeMap<String, Layer> = match map.remove("profiles") {
+            None => BTreeMap::new(),
+            Some(profiles) => from_value(profiles, "profiles", path)?,
+        };
+        let defaults = from_value(Value::Object(map), "settings", path)?;
+        Ok(ConfigFile { defaults, default_profile, profiles })
+    }
+}
+
+fn from_value<T: DeserializeOwned>(value: Value, what: &str, path: &Path) -> Result<T> {
+    serde_json::from_value(value).with_context(|| format!("Invalid {} in {:?}", what, path))
+}
+
+/// The settings in effect for this run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Settings {
+    /// The config file read, if there was one.
+    pub file: Option<PathBuf>,
+    pub profile: Option<String>,
+    pub portfolio: Option<PathBuf>,
+    pub base_currency: Option<String>,
+    pub price_provider: PriceProvider,
+    pub api_key: Option<String>,
+    pub output_format: OutputFormat,
+    pub color: ColorChoice,
+    pub cost_basis: CostBasisMethod,
+}
+
+impl Settings {
+    /// Read the config file (`config`, $PORTFOLIO_CONFIG, or the default
+    /// location if it exists) and the environment, with `profile` (or
+    /// $PORTFOLIO_PROFILE) selecting a profile.
+    pub fn load(config: Option<&Path>, profile: Option<&str>) -> Result<Settings> {
+        Settings::resolve(config, profile, &|name: &str| std::env::var(name).ok().filter(|v| !v.is_empty()))
+    }
+
+    fn resolve(config: Option<&Path>, profile: Option<&str>, env: &dyn Fn(&str) -> Option<String>) -> Result<Settings> {
+        let home = env("HOME").map(PathBuf::from);
+        let (path, explicit) = match config.map(Path::to_path_buf).or_else(|| env(CONFIG_ENV).map(PathBuf::from)) {
+            Some(path) => (Some(path), true),
+            None => (default_path(env), false),
+        };
+
+        let file = match &path {
+            Some(path) if explicit || path.exists() => {
+                let content = std::fs::read_to_string(path).with_context(|| format!("Failed to read config file: {:?}", path))?;
+                let base = path.parent().unwrap_or(Path::new(""));
+                let mut file = ConfigFile::parse(&content, path)?;
+                file.defaults = file.defaults.relative_to(base, home.as_deref());
+                for layer in file.profiles.values_mut() {
+                    *layer = std::mem::take(layer).relative_to(base, home.as_deref());
+                }
+                Some(file)
+            }
+            _ => None,
+        };
+        let file_path = file.as_ref().and(path);
+        let file = file.unwrap_or_default();
+
+        let profile = profile.map(str::to_string).or_else(|| env(PROFILE_ENV)).or(file.default_profile.clone());
+        let mut layer = file.defaults.clone();
+        if let Some(name) = &profile {
+            let Some(selected) = file.profiles.get(name) else {
+                let known: Vec<&str> = file.profiles.keys().map(String::as_str).collect();
+                match &file_path {
+                    Some(path) if !known.is_empty() => {
+                        bail!("No profile '{}' in {:?} (profiles: {})", name, path, known.join(", "))
+                    }
+                    Some(path) => bail!("No profile '{}': {:?} defines no profiles", name, path),
+                    None => bail!("No profile '{}': there is no config file (set {} or create one)", name, CONFIG_ENV),
+                }
+            };
+            layer = layer.merge(selected.clone());
+        }
+        layer = layer.merge(env_layer(env)?);
+
+        if let Some(currency) = &mut layer.base_currency {
+            *currency = currency.to_uppercase();
+            if currency.len() != 3 || !currency.chars().all(|c| c.is_ascii_alphabetic()) {
+                bail!("Invalid base_currency '{}': use a three-letter ISO 4217 code such as USD or EUR", currency);
+            }
+        }
+
+        Ok(Settings {
+            file: file_path,
+            profile,
+            portfolio: layer.portfolio,
+            base_currency: layer.base_currency,
+            price_provider: layer.price_provider.unwrap_or_default(),
+            api_key: layer.api_key,
+            output_format: layer.output_format.unwrap_or_default(),
+            color: layer.color.unwrap_or_default(),
+            cost_basis: layer.cost_basis.unwrap_or_default(),
+        })
+    }
+
+    /// The portfolio file to use: the one given on the command line, or the
+    /// configured default.
+    pub fn portfolio_file(&self, given: Option<PathBuf>) -> Result<PathBuf> {
+        given.or_else(|| self.portfolio.clone()).with_context(|| {
+            format!("No portfolio file given. Pass one, set `portfolio` in the config file, or set {}", SETTING_ENVS[0].1)
+        })
+    }
+
+    /// Like `portfolio_file`, for commands taking several files.
+    pub fn portfolio_files(&self, given: Vec<PathBuf>) -> Result<Vec<PathBuf>> {
+        match given.is_empty() {
+            true => Ok(vec![self.portfolio_file(None)?]),
+            false => Ok(given),
+        }
+    }
+
+    /// Hand the settings to the modules that use them. Call once, before
+    /// running a command.
+    pub fn apply(&self) {
+        if let Some(currency) = &self.base_currency {
+            crate::schema::set_default_base_currency(currency.clone());
+        }
+        crate::api::set_price_provider(self.price_provider, self.api_key.clone());
+        crate::reports::set_output_format(self.output_format);
+        match self.color {
+            ColorChoice::Auto => {}
+            ColorChoice::Always => colored::control::set_override(true),
+            ColorChoice::Never => colored::control::set_override(false),
+        }
+    }
+}
+
+/// `$XDG_CONFIG_HOME/portfolio_rs/config.toml`, or under `~/.config`.
+fn default_path(env: &dyn Fn(&str) -> Option<String>) -> Option<PathBuf> {
+    let dir = env("XDG_CONFIG_HOME").map(PathBuf::from).or_else(|| env("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(dir.join("portfolio_rs").join("config.toml"))
+}
+
+fn env_layer(env: &dyn Fn(&str) -> Option<String>) -> Result<Layer> {
+    fn parse<T: ValueEnum>(env: &dyn Fn(&str) -> Option<String>, name: &str) -> Result<Option<T>> {
+        let Some(value) = env(name) else {
+            return Ok(None);
+        };
+        T::from_str(&value, true).map(Some).map_err(|_| {
+            let expected: Vec<String> = T::value_variants()
+                .iter()
+                .filter_map(|v| v.to_possible_value().map(|p| p.get_name().to_string()))
+                .collect();
+            anyhow::anyhow!("Invalid {}='{}' (expected {})", name, value, expected.join(", "))
+        })
+    }
+    let var = |setting: &str| SETTING_ENVS.iter().find(|(s, _)| *s == setting).map(|(_, var)| *var).expect("known setting");
+
+    Ok(Layer {
+        portfolio: env(var("portfolio")).map(PathBuf::from),
+        base_currency: env(var("base_currency")),
+        price_provider: parse(env, var("price_provider"))?,
+        api_key: env(var("api_key")),
+        output_format: parse(env, var("output_format"))?,
+        color: parse(env, var("color"))?,
+        cost_basis: parse(env, var("cost_basis"))?,
+    })
+}
+
+/// Show where the settings come from and what they are.
+pub fn print_settings(settings: &Settings) {
+    let show = |value: Option<String>| value.unwrap_or_else(|| "(not set)".dimmed().to_string());
+    println!("{}", "--------------------------------------".cyan());
+    println!("{}: {}", "Config file".bold(), show(settings.file.as_ref().map(|p| p.display().to_string())));
+    println!("{}: {}", "Profile".bold(), show(settings.profile.clone()));
+    println!("{}", "--------------------------------------".cyan());
+    println!("{:<15} {}", "portfolio", show(settings.portfolio.as_ref().map(|p| p.display().to_string())));
+    println!("{:<15} {}", "base_currency", show(settings.base_currency.clone()));
+    println!("{:<15} {}", "price_provider", name(settings.price_provider));
+    println!("{:<15} {}", "api_key", show(settings.api_key.as_ref().map(|_| "(set)".to_string())));
+    println!("{:<15} {}", "output_format", name(settings.output_format));
+    println!("{:<15} {}", "color", name(settings.color));
+    println!("{:<15} {}", "cost_basis", name(settings.cost_basis));
+}
+
+fn name<T: ValueEnum>(value: T) -> String {
+    value.to_possible_value().map(|p| p.get_name().to_string()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::fs;
+    use tempfile::tempdir;
+
+    const CONFIG: &str = r#"
+        portfolio = "portfolio.json"
+        base_currency = "eur"
+        output_format = "json"
+        default_profile = "personal"
+
+        [profiles.personal]
+        cost_basis = "hifo"
+
+        [profiles.work]
+        portfolio = "~/work/team.db"
+        price_provider = "alpha-vantage"
+        color = "never"
+    "#;
+
+    fn resolve(config: &Path, profile: Option<&str>, vars: &[(&str, &str)]) -> Result<Settings> {
+        let vars: HashMap<String, String> = vars.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        Settings::resolve(Some(config), profile, &|name| vars.get(name).cloned())
+    }
+
+    #[test]
+    fn test_defaults_and_default_profile() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        fs::write(&path, CONFIG).unwrap();
+
+        let settings = resolve(&path, None, &[("HOME", "/home/me")]).unwrap();
+        assert_eq!(settings.profile.as_deref(), Some("personal"));
+        assert_eq!(settings.portfolio, Some(dir.path().join("portfolio.json")));
+        assert_eq!(settings.base_currency.as_deref(), Some("EUR"));
+        assert_eq!(settings.output_format, OutputFormat::Json);
+        assert_eq!(settings.cost_basis, CostBasisMethod::Hifo);
+        assert_eq!(settings.price_provider, PriceProvider::Demo);
+        assert_eq!(settings.color, ColorChoice::Auto);
+    }
+
+    #[test]
+    fn test_profile_then_environment_override() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        fs::write(&path, CONFIG).unwrap();
+
+        let settings = resolve(&path, Some("work"), &[("HOME", "/home/me")]).unwrap();
+        assert_eq!(settings.portfolio, Some(PathBuf::from("/home/me/work/team.db")));
+        assert_eq!(settings.price_provider, PriceProvider::AlphaVantage);
+        assert_eq!(settings.color, ColorChoice::Never);
+        // Not in the profile: the top-level setting
+        assert_eq!(settings.output_format, OutputFormat::Json);
+        assert_eq!(settings.cost_basis, CostBasisMethod::Fifo);
+
+        let settings = resolve(&path, None, &[
+            ("PORTFOLIO_PROFILE", "work"),
+            ("PORTFOLIO_FILE", "other.json"),
+            ("PORTFOLIO_OUTPUT_FORMAT", "TABLE"),
+            ("PORTFOLIO_COST_BASIS", "lifo"),
+        ]).unwrap();
+        assert_eq!(settings.profile.as_deref(), Some("work"));
+        assert_eq!(settings.portfolio, Some(PathBuf::from("other.json")));
+        assert_eq!(settings.output_format, OutputFormat::Table);
+        assert_eq!(settings.cost_basis, CostBasisMethod::Lifo);
+        assert_eq!(settings.price_provider, PriceProvider::AlphaVantage);
+    }
+
+    #[test]
+    fn test_errors() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        fs::write(&path, CONFIG).unwrap();
+        let error = |result: Result<Settings>| format!("{:#}", result.unwrap_err());
+
+        assert!(error(resolve(&path, Some("holiday"), &[])).contains("profiles: personal, work"));
+        assert!(error(resolve(&path, None, &[("PORTFOLIO_OUTPUT_FORMAT", "xml")])).contains("expected table, json"));
+        assert!(error(resolve(&dir.path().join("missing.toml"), None, &[])).contains("Failed to read config file"));
+
+        fs::write(&path, "portfolo = \"typo.json\"").unwrap();
+        assert!(error(resolve(&path, None, &[])).contains("unknown field `portfolo`"));
+        fs::write(&path, "base_currency = \"euro\"").unwrap();
+        assert!(error(resolve(&path, None, &[])).contains("Invalid base_currency"));
+    }
+
+    #[test]
+    fn test_no_config_file() {
+        let dir = tempdir().unwrap();
+        let home = dir.path().to_str().unwrap();
+        let vars: HashMap<&str, &str> = HashMap::from([("HOME", home)]);
+        let settings = Settings::resolve(None, None, &|name| vars.get(name).map(|v| v.to_string())).unwrap();
+        assert_eq!(settings.file, None);
+        assert_eq!(settings.portfolio, None);
+        assert!(settings.portfolio_file(None).unwrap_err().to_string().contains("No portfolio file given"));
+        assert_eq!(settings.portfolio_file(Some(PathBuf::from("p.json"))).unwrap(), PathBuf::from("p.json"));
+
+        let error = Settings::resolve(None, Some("work"), &|name| vars.get(name).map(|v| v.to_string())).unwrap_err();
+        assert!(error.to_string().contains("there is no config file"));
+
+        // The default location is used when it exists
+        let default = dir.path().join(".config/portfolio_rs/config.toml");
+        fs::create_dir_all(default.parent().unwrap()).unwrap();
+        fs::write(&default, "portfolio = \"p.json\"").unwrap();
+        let settings = Settings::resolve(None, None, &|name| vars.get(name).map(|v| v.to_string())).unwrap();
+        assert_eq!(settings.file, Some(default));
+        assert_eq!(settings.portfolio, Some(dir.path().join(".config/portfolio_rs/p.json")));
+    }
+}
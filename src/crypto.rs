@@ -0,0 +1,358 @@
+use anyhow::{anyhow, bail, Context, Result};
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use serde::{Deserialize, Serialize};
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use zeroize::Zeroizing;
+
+// Encrypted portfolio files are a small JSON document wrapping the original
+// file contents. The passphrase is stretched with Argon2id and the contents
+// are sealed with XChaCha20-Poly1305, so a wrong passphrase or any edit to
+// the file is detected instead of producing garbage. Passphrases and derived
+// keys are wiped from memory once dropped.
+
+pub const ENCRYPTED_FORMAT: &str = "portfolio-encrypted";
+pub const ENCRYPTED_VERSION: u32 = 1;
+
+/// Environment variable holding the passphrase itself.
+pub const PASSPHRASE_ENV: &str = "PORTFOLIO_PASSPHRASE";
+/// Environment variable pointing at a file whose contents are the passphrase.
+pub const PASSPHRASE_FILE_ENV: &str = "PORTFOLIO_PASSPHRASE_FILE";
+/// Environment variable holding the new passphrase for `encrypt` and `rekey`.
+pub const NEW_PASSPHRASE_ENV: &str = "PORTFOLIO_NEW_PASSPHRASE";
+
+const KEY_LEN: usize = 32;
+const SALT_LEN: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KdfParams {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        // OWASP's recommended Argon2id baseline
+        KdfParams {
+            memory_kib: 19 * 1024,
+            iterations: 2,
+            parallelism: 1,
+        }
+    }
+}
+
+impl KdfParams {
+    /// The most expensive parameters a file may ask for: four times the
+    /// defaults. The header is read before the MAC can be checked, so without
+    /// a limit a crafted file could make Argon2 allocate gigabytes or run for
+    /// minutes.
+    pub fn max() -> Self {
+        let default = KdfParams::default();
+        KdfParams {
+            memory_kib: default.memory_kib * 4,
+            iterations: default.iterations * 4,
+            parallelism: default.parallelism * 4,
+        }
+    }
+
+    fn check(&self) -> Result<()> {
+        let max = KdfParams::max();
+        if self.memory_kib > max.memory_kib || self.iterations > max.iterations || self.parallelism > max.parallelism {
+            bail!(
+                "Key derivation parameters m={} t={} p={} exceed the limit of m={} t={} p={}",
+                self.memory_kib,
+                self.iterations,
+                self.parallelism,
+                max.memory_kib,
+                max.iterations,
+                max.parallelism
+            );
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct KdfHeader {
+    algorithm: String,
+    #[serde(flatten)]
+    params: KdfParams,
+    salt: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct EncryptedFile {
+    format: String,
+    version: u32,
+    kdf: KdfHeader,
+    cipher: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+impl EncryptedFile {
+    /// Everything except the ciphertext is authenticated as associated data,
+    /// so tampering with the KDF parameters or salt fails decryption too.
+    fn associated_data(&self) -> Vec<u8> {
+        format!(
+            "{}:v{}:{}:m={}:t={}:p={}:{}:{}",
+            self.format,
+            self.version,
+            self.kdf.algorithm,
+            self.kdf.params.memory_kib,
+            self.kdf.params.iterations,
+            self.kdf.params.parallelism,
+            self.kdf.salt,
+            self.cipher
+        )
+        .into_bytes()
+    }
+}
+
+fn parse_encrypted(data: &[u8]) -> Option<EncryptedFile> {
+    let file: EncryptedFile = serde_json::from_slice(data).ok()?;
+    (file.format == ENCRYPTED_FORMAT).then_some(file)
+}
+
+/// Whether the bytes are an encrypted portfolio file rather than plaintext.
+pub fn is_encrypted(data: &[u8]) -> bool {
+    parse_encrypted(data).is_some()
+}
+
+fn derive_key(passphrase: &str, salt: &[u8], params: KdfParams) -> Result<Zeroizing<[u8; KEY_LEN]>> {
+    params.check()?;
+    let params = Params::new(params.memory_kib, params.iterations, params.parallelism, Some(KEY_LEN))
+        .map_err(|e| anyhow!("Invalid key derivation parameters: {}", e))?;
+    let mut key = Zeroizing::new([0u8; KEY_LEN]);
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), salt, key.as_mut())
+        .map_err(|e| anyhow!("Key derivation failed: {}", e))?;
+    Ok(key)
+}
+
+pub fn encrypt(plaintext: &[u8], passphrase: &str) -> Result<Vec<u8>> {
+    encrypt_with_params(plaintext, passphrase, KdfParams::default())
+}
+
+pub fn encrypt_with_params(plaintext: &[u8], passphrase: &str, params: KdfParams) -> Result<Vec<u8>> {
+    if passphrase.is_empty() {
+        bail!("Refusing to encrypt with an empty passphrase");
+    }
+
+    let mut salt = [0u8; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+
+    let mut file = EncryptedFile {
+        format: ENCRYPTED_FORMAT.to_string(),
+        version: ENCRYPTED_VERSION,
+        kdf: KdfHeader {
+            algorithm: "argon2id".to_string(),
+            params,
+            salt: BASE64.encode(salt),
+        },
+        cipher: "xchacha20poly1305".to_string(),
+        nonce: BASE64.encode(nonce),
+        ciphertext: String::new(),
+    };
+
+    let key = derive_key(passphrase, &salt, params)?;
+    let cipher = XChaCha20Poly1305::new(&(*key).into());
+    let aad = file.associated_data();
+    let ciphertext = cipher
+        .encrypt(&nonce, Payload { msg: plaintext, aad: &aad })
+        .map_err(|_| anyhow!("Encryption failed"))?;
+    file.ciphertext = BASE64.encode(ciphertext);
+
+    let mut out = serde_json::to_vec_pretty(&file)?;
+    out.push(b'\n');
+    Ok(out)
+}
+
+pub fn decrypt(data: &[u8], passphrase: &str) -> Result<Vec<u8>> {
+    let file = parse_encrypted(data).context("Not an encrypted portfolio file")?;
+
+    if file.version != ENCRYPTED_VERSION {
+        bail!("Unsupported encrypted file version {}", file.version);
+    }
+    if file.kdf.algorithm != "argon2id" || file.cipher != "xchacha20poly1305" {
+        bail!("Unsupported encryption scheme {} / {}", file.kdf.algorithm, file.cipher);
+    }
+
+    let salt = BASE64.decode(&file.kdf.salt).context("Corrupt salt in encrypted file")?;
+    let nonce = BASE64.decode(&file.nonce).context("Corrupt nonce in encrypted file")?;
+    let ciphertext = BASE64.decode(&file.ciphertext).context("Corrupt ciphertext in encrypted file")?;
+    if nonce.len() != 24 {
+        bail!("Corrupt nonce in encrypted file");
+    }
+
+    let key = derive_key(passphrase, &salt, file.kdf.params)?;
+    let cipher = XChaCha20Poly1305::new(&(*key).into());
+    let aad = file.associated_data();
+    cipher
+        .decrypt(XNonce::from_slice(&nonce), Payload { msg: &ciphertext, aad: &aad })
+        .map_err(|_| anyhow!("Wrong passphrase or the encrypted file has been modified"))
+}
+
+// ---------------------------------------------------------------------------
+// Passphrase sources
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PassphraseSource {
+    /// Environment variables, falling back to an interactive prompt
+    Default,
+    /// A file containing the passphrase (trailing newline ignored)
+    File(PathBuf),
+}
+
+static SOURCE: OnceLock<PassphraseSource> = OnceLock::new();
+// Remember the passphrase once entered so the prompt appears at most once
+// per process, even when a command loads several files.
+static CACHED: Mutex<Option<Zeroizing<String>>> = Mutex::new(None);
+
+/// Choose where passphrases come from. Called once from `main` based on the
+/// command line; later calls are ignored.
+pub fn set_passphrase_source(source: PassphraseSource) {
+    let _ = SOURCE.set(source);
+}
+
+fn read_passphrase_file(path: &Path) -> Result<Zeroizing<String>> {
+    let content = Zeroizing::new(
+        std::fs::read_to_string(path).with_context(|| format!("Failed to read passphrase file: {:?}", path))?,
+    );
+    let passphrase = Zeroizing::new(content.trim_end_matches(['\r', '\n']).to_string());
+    if passphrase.is_empty() {
+        bail!("Passphrase file {:?} is empty", path);
+    }
+    Ok(passphrase)
+}
+
+fn prompt(message: &str) -> Result<Zeroizing<String>> {
+    if !std::io::stdin().is_terminal() {
+        bail!(
+            "A passphrase is required but no terminal is available; set {} or {}",
+            PASSPHRASE_ENV,
+            PASSPHRASE_FILE_ENV
+        );
+    }
+    rpassword::prompt_password(message)
+        .map(Zeroizing::new)
+        .context("Failed to read passphrase")
+}
+
+/// The passphrase for opening existing encrypted files.
+pub fn passphrase() -> Result<Zeroizing<String>> {
+    let mut cached = CACHED.lock().unwrap();
+    if let Some(passphrase) = cached.as_ref() {
+        return Ok(passphrase.clone());
+    }
+
+    let passphrase = match SOURCE.get().unwrap_or(&PassphraseSource::Default) {
+        PassphraseSource::File(path) => read_passphrase_file(path)?,
+        PassphraseSource::Default => {
+            if let Ok(value) = std::env::var(PASSPHRASE_ENV) {
+                Zeroizing::new(value)
+            } else if let Ok(path) = std::env::var(PASSPHRASE_FILE_ENV) {
+                read_passphrase_file(Path::new(&path))?
+            } else {
+                prompt("Portfolio passphrase: ")?
+            }
+        }
+    };
+
+    *cached = Some(passphrase.clone());
+    Ok(passphrase)
+}
+
+/// A new passphrase for `encrypt` and `rekey`, asked twice when prompting.
+pub fn new_passphrase(file: Option<&Path>) -> Result<Zeroizing<String>> {
+    if let Some(path) = file {
+        return read_passphrase_file(path);
+    }
+    if let Ok(value) = std::env::var(NEW_PASSPHRASE_ENV) {
+        return Ok(Zeroizing::new(value));
+    }
+
+    let first = prompt("New passphrase: ")?;
+    let second = prompt("Repeat new passphrase: ")?;
+    if first != second {
+        bail!("Passphrases do not match");
+    }
+    if first.is_empty() {
+        bail!("Passphrase must not be empty");
+    }
+    Ok(first)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Small parameters keep the tests fast; the format records them anyway.
+    const FAST: KdfParams = KdfParams { memory_kib: 64, iterations: 1, parallelism: 1 };
+
+    #[test]
+    fn test_round_trip() {
+        let data = encrypt_with_params(b"[{\"ticker\":\"AAPL\"}]", "secret", FAST).unwrap();
+        assert!(is_encrypted(&data));
+        assert_eq!(decrypt(&data, "secret").unwrap(), b"[{\"ticker\":\"AAPL\"}]");
+    }
+
+    #[test]
+    fn test_wrong_passphrase_fails() {
+        let data = encrypt_with_params(b"[]", "secret", FAST).unwrap();
+        assert!(decrypt(&data, "guess").is_err());
+    }
+
+    #[test]
+    fn test_tampered_header_fails() {
+        let data = encrypt_with_params(b"[]", "secret", FAST).unwrap();
+        let mut file: serde_json::Value = serde_json::from_slice(&data).unwrap();
+        file["kdf"]["iterations"] = serde_json::json!(2);
+        let tampered = serde_json::to_vec(&file).unwrap();
+        assert!(decrypt(&tampered, "secret").is_err());
+    }
+
+    #[test]
+    fn test_oversized_kdf_params_rejected() {
+        let data = encrypt_with_params(b"[]", "secret", FAST).unwrap();
+        let mut file: serde_json::Value = serde_json::from_slice(&data).unwrap();
+        file["kdf"]["memory_kib"] = serde_json::json!(4 * 1024 * 1024);
+        let err = decrypt(&serde_json::to_vec(&file).unwrap(), "secret").unwrap_err();
+        assert!(err.to_string().contains("exceed the limit"), "{}", err);
+
+        let mut file: serde_json::Value = serde_json::from_slice(&data).unwrap();
+        file["kdf"]["iterations"] = serde_json::json!(u32::MAX);
+        assert!(decrypt(&serde_json::to_vec(&file).unwrap(), "secret").is_err());
+
+        assert!(KdfParams::max().check().is_ok());
+        assert!(KdfParams::default().check().is_ok());
+    }
+
+    #[test]
+    fn test_plaintext_is_not_encrypted() {
+        assert!(!is_encrypted(b"[]"));
+        assert!(!is_encrypted(b"{\"format\": \"something-else\"}"));
+        assert!(decrypt(b"[]", "secret").is_err());
+    }
+
+    #[test]
+    fn test_empty_passphrase_rejected() {
+        assert!(encrypt_with_params(b"[]", "", FAST).is_err());
+    }
+
+    #[test]
+    fn test_fresh_salt_and_nonce_each_time() {
+        let a = encrypt_with_params(b"[]", "secret", FAST).unwrap();
+        let b = encrypt_with_params(b"[]", "secret", FAST).unwrap();
+        assert_ne!(a, b);
+    }
+}
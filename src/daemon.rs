@@ -0,0 +1,586 @@
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Datelike, Local, NaiveTime, TimeZone, Utc, Weekday};
+use serde::Deserialize;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing::{error, info, warn};
+
+use crate::alerts::{self, RuleSet};
+use crate::api::fetch_prices_with_failures;
+use crate::export::{build_sheets, write_export, ExportFormat};
+use crate::formats::{self, FileFormat};
+use crate::live::parse_interval;
+use crate::portfolio::{Portfolio, Snapshot};
+use crate::storage::is_database_path;
+
+// Long-running scheduler. A schedule file lists jobs, each run either
+// `every` some interval or `at` a wall-clock time on chosen days:
+//
+//     portfolio = "portfolio.db"
+//     timezone = "America/New_York"
+//
+//     [[jobs]]
+//     kind = "snapshot"
+//     at = "16:05"
+//     days = ["mon", "tue", "wed", "thu", "fri"]
+//
+//     [[jobs]]
+//     kind = "alerts"
+//     every = "5m"
+//     rules = "alerts.toml"
+//
+// Paths are relative to the schedule file. A job that fails is logged and
+// tried again at its next run; it never stops the daemon. SIGINT or SIGTERM
+// lets the job that is running finish, then exits.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum LogFormat {
+    /// One human-readable line per event
+    #[default]
+    Text,
+    /// One JSON object per event, for log collectors
+    Json,
+}
+
+/// Send log events to stdout in the chosen format.
+pub fn init_logging(format: LogFormat) {
+    let builder = tracing_subscriber::fmt().with_target(false);
+    // A second init (as in tests) keeps the first subscriber
+    let _ = match format {
+        LogFormat::Text => builder.try_init(),
+        LogFormat::Json => builder.json().flatten_event(true).try_init(),
+    };
+}
+
+/// The schedule file as written.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawConfig {
+    portfolio: PathBuf,
+    #[serde(default)]
+    timezone: Option<String>,
+    #[serde(default)]
+    jobs: Vec<RawJob>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct RawJob {
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    every: Option<String>,
+    #[serde(default)]
+    at: Option<String>,
+    #[serde(default)]
+    days: Option<Vec<String>>,
+    #[serde(flatten)]
+    job: Job,
+}
+
+/// What a job does.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum Job {
+    /// Value the portfolio at fresh quotes and store a snapshot in a SQLite
+    /// database (default: the portfolio, if it is one).
+    Snapshot {
+        #[serde(default)]
+        database: Option<PathBuf>,
+    },
+    /// Fetch quotes for every held and watched ticker into a SQLite
+    /// database's quote cache (default: the portfolio, if it is one).
+    Quotes {
+        #[serde(default)]
+        database: Option<PathBuf>,
+    },
+    /// Check alert rules, delivering fired alerts through the rules file's
+    /// `notify` section unless `notify` is false.
+    Alerts {
+        rules: PathBuf,
+        #[serde(default = "default_notify")]
+        notify: bool,
+    },
+    /// Export the portfolio to `output` (`.xlsx`, `.ods`, or a directory of
+    /// CSV files); `{date}` in the path is replaced with the run's date.
+    Report { output: String },
+}
+
+fn default_notify() -> bool {
+    true
+}
+
+impl Job {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Job::Snapshot { .. } => "snapshot",
+            Job::Quotes { .. } => "quotes",
+            Job::Alerts { .. } => "alerts",
+            Job::Report { .. } => "report",
+        }
+    }
+}
+
+/// When a job runs.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Schedule {
+    Every(Duration),
+    At { time: NaiveTime, days: Vec<Weekday> },
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Schedule::Every(interval) => write!(f, "every {}s", interval.as_secs()),
+            Schedule::At { time, days } if days.len() == 7 => write!(f, "daily at {}", time.format("%H:%M")),
+            Schedule::At { time, days } => {
+                let days: Vec<String> = days.iter().map(|d| d.to_string()).collect();
+                write!(f, "at {} on {}", time.format("%H:%M"), days.join(","))
+            }
+        }
+    }
+}
+
+/// The timezone `at` times are in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Zone {
+    Local,
+    Named(chrono_tz::Tz),
+}
+
+impl Schedule {
+    /// The first run strictly after `now`.
+    pub fn next_after(&self, now: DateTime<Utc>, zone: Zone) -> DateTime<Utc> {
+        match self {
+            Schedule::Every(interval) => now + chrono::Duration::from_std(*interval).unwrap_or(chrono::Duration::MAX),
+            Schedule::At { time, days } => match zone {
+                Zone::Local => next_at(now.with_timezone(&Local), *time, days),
+                Zone::Named(tz) => next_at(now.with_timezone(&tz), *time, days),
+            },
+        }
+    }
+}
+
+fn next_at<Tz: TimeZone>(now: DateTime<Tz>, time: NaiveTime, days: &[Weekday]) -> DateTime<Utc> {
+    let zone = now.timezone();
+    // Eight days always include the same weekday next week
+    for offset in 0..=7 {
+        let date = now.date_naive() + chrono::Days::new(offset);
+        if !days.contains(&date.weekday()) {
+            continue;
+        }
+        // A time skipped by a daylight saving change does not happen that day
+        if let Some(run) = zone.from_local_datetime(&date.and_time(time)).earliest() {
+            if run > now {
+                return run.with_timezone(&Utc);
+            }
+        }
+    }
+    now.with_timezone(&Utc) + chrono::Duration::days(1)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScheduledJob {
+    pub name: String,
+    pub schedule: Schedule,
+    pub job: Job,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DaemonConfig {
+    pub portfolio: PathBuf,
+    pub zone: Zone,
+    pub jobs: Vec<ScheduledJob>,
+}
+
+impl DaemonConfig {
+    /// Load a schedule from a JSON, TOML or YAML file (by extension).
+    pub fn load_from_file(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path).with_context(|| format!("Failed to read schedule: {:?}", path))?;
+        let format = FileFormat::from_path(path);
+        if format == FileFormat::Csv {
+            bail!("The schedule must be JSON, TOML or YAML, not CSV");
+        }
+        let (value, _) = formats::parse(&content, format)?;
+        let raw: RawConfig = serde_json::from_value(value).with_context(|| format!("Invalid schedule in {:?}", path))?;
+        let base = path.parent().unwrap_or(Path::new(""));
+        DaemonConfig::from_raw(raw, base)
+    }
+
+    fn from_raw(raw: RawConfig, base: &Path) -> Result<Self> {
+        let zone = match raw.timezone.as_deref() {
+            None | Some("local") => Zone::Local,
+            Some(name) => Zone::Named(name.parse().map_err(|_| anyhow::anyhow!("Unknown timezone '{}' (use e.g. America/New_York)", name))?),
+        };
+        if raw.jobs.is_empty() {
+            bail!("The schedule has no jobs");
+        }
+        let portfolio = base.join(&raw.portfolio);
+
+        let mut jobs = Vec::new();
+        for (index, raw_job) in raw.jobs.into_iter().enumerate() {
+            let context = || format!("jobs[{}]", index);
+            let schedule = match (&raw_job.every, &raw_job.at) {
+                (Some(every), None) => {
+                    if raw_job.days.is_some() {
+                        bail!("{}: days only apply to `at` schedules", context());
+                    }
+                    Schedule::Every(parse_interval(every).map_err(|e| anyhow::anyhow!("{}: {}", context(), e))?)
+                }
+                (None, Some(at)) => Schedule::At {
+                    time: NaiveTime::parse_from_str(at, "%H:%M")
+                        .with_context(|| format!("{}: invalid time '{}' (expected HH:MM)", context(), at))?,
+                    days: parse_days(raw_job.days.as_deref()).with_context(context)?,
+                },
+                _ => bail!("{}: give exactly one of `every` or `at`", context()),
+            };
+            let job = resolve_paths(raw_job.job, base, &portfolio).with_context(context)?;
+            let name = raw_job.name.unwrap_or_else(|| job.kind().to_string());
+            jobs.push(ScheduledJob { name, schedule, job });
+        }
+
+        Ok(DaemonConfig { portfolio, zone, jobs })
+    }
+}
+
+/// Weekday names, or every day when none are given.
+fn parse_days(days: Option<&[String]>) -> Result<Vec<Weekday>> {
+    let Some(days) = days else {
+        return Ok(vec![Weekday::Mon, Weekday::Tue, Weekday::Wed, Weekday::Thu, Weekday::Fri, Weekday::Sat, Weekday::Sun]);
+    };
+    if days.is_empty() {
+        bail!("days must not be empty");
+    }
+    days.iter()
+        .map(|day| day.parse::<Weekday>().map_err(|_| anyhow::anyhow!("unknown day '{}' (use mon, tue, ...)", day)))
+        .collect()
+}
+
+fn resolve_paths(job: Job, base: &Path, portfolio: &Path) -> Result<Job> {
+    let database = |database: Option<PathBuf>, kind: &str| -> Result<Option<PathBuf>> {
+        match database {
+            Some(database) => Ok(Some(base.join(database))),
+            None if is_database_path(portfolio) => Ok(Some(portfolio.to_path_buf())),
+            None => bail!("a {} job needs `database` unless the portfolio is a SQLite file", kind),
+        }
+    };
+    Ok(match job {
+        Job::Snapshot { database: db } => Job::Snapshot { database: database(db, "snapshot")? },
+        Job::Quotes { database: db } => Job::Quotes { database: database(db, "quotes")? },
+        Job::Alerts { rules, notify } => Job::Alerts { rules: base.join(rules), notify },
+        Job::Report { output } => Job::Report { output: base.join(output).to_string_lossy().into_owned() },
+    })
+}
+
+/// Run one job now. Returns a one-line summary for the log.
+pub async fn run_job(job: &Job, portfolio_file: &Path, now: DateTime<Utc>) -> Result<String> {
+    match job {
+        Job::Snapshot { database } => {
+            let portfolio = Portfolio::load_from_file(portfolio_file)?;
+            let prices = fetch_all_quotes(&portfolio.get_tickers()).await?;
+            let snapshot = Snapshot::capture(&portfolio.holdings_with_prices(&prices), now);
+            let database = database.as_deref().expect("resolved when loading");
+            record_snapshot(database, &snapshot)?;
+            Ok(format!("Recorded a snapshot worth {} in {}", snapshot.total_value.round_dp(2), database.display()))
+        }
+        Job::Quotes { database } => {
+            let portfolio = Portfolio::load_from_file(portfolio_file)?;
+            let mut tickers = portfolio.get_tickers();
+            tickers.extend(portfolio.watchlist.iter().map(|w| w.ticker.clone()));
+            tickers.sort();
+            tickers.dedup();
+            let (mut prices, failed) = fetch_prices_with_failures(&tickers).await?;
+            for ticker in &failed {
+                warn!(ticker = %ticker, "Could not fetch quote; keeping the cached one");
+                prices.remove(ticker);
+            }
+            let database = database.as_deref().expect("resolved when loading");
+            store_quotes(database, &prices, now)?;
+            Ok(format!("Cached {} quote(s) in {}", prices.len(), database.display()))
+        }
+        Job::Alerts { rules, notify } => {
+            let portfolio = Portfolio::load_from_file(portfolio_file)?;
+            let rule_set = RuleSet::load_from_file(rules)?;
+            let report = alerts::check(&portfolio, &rule_set).await?;
+            for ticker in &report.unquoted {
+                warn!(ticker = %ticker, "Could not fetch quote");
+            }
+            for alert in &report.fired {
+                warn!(rule = %alert.rule, ticker = alert.ticker.as_deref().unwrap_or(""), "{}", alert.message);
+            }
+            let mut summary = format!("{} of {} rule(s) fired", report.fired.len(), report.rules);
+            if let (true, false, Some(settings)) = (*notify, report.fired.is_empty(), &rule_set.notify) {
+                let delivery = crate::notify::notify_alerts(&report.fired, settings, &settings.state_path(rules)).await?;
+                for err in &delivery.errors {
+                    warn!("Alert delivery failed: {:#}", err);
+                }
+                summary.push_str(&format!(", {} delivered", delivery.sent));
+            }
+            Ok(summary)
+        }
+        Job::Report { output } => {
+            let portfolio = Portfolio::load_from_file(portfolio_file)?;
+            let prices = fetch_all_quotes(&portfolio.get_tickers()).await?;
+            let holdings = portfolio.holdings_with_prices(&prices);
+            let output = PathBuf::from(output.replace("{date}", &now.with_timezone(&Local).format("%Y-%m-%d").to_string()));
+            if let Some(parent) = output.parent().filter(|p| !p.as_os_str().is_empty()) {
+                std::fs::create_dir_all(parent).with_context(|| format!("Failed to create {:?}", parent))?;
+            }
+            let sheets = build_sheets(&holdings, &portfolio.transactions, &portfolio.base_currency);
+            let written = write_export(&sheets, &output, ExportFormat::from_path(&output))?;
+            Ok(format!("Wrote {} file(s) to {}", written.len(), output.display()))
+        }
+    }
+}
+
+/// Fetch quotes for a job that values the portfolio. Fails when any quote
+/// is missing, so no snapshot or report is made at a default price; the
+/// job runs again at its next time.
+async fn fetch_all_quotes(tickers: &[String]) -> Result<std::collections::HashMap<String, rust_decimal::Decimal>> {
+    let (prices, failed) = fetch_prices_with_failures(tickers).await?;
+    if !failed.is_empty() {
+        bail!("Could not fetch quotes for {}", failed.join(", "));
+    }
+    Ok(prices)
+}
+
+#[cfg(feature = "sqlite")]
+fn record_snapshot(database: &Path, snapshot: &Snapshot) -> Result<()> {
+    crate::sqlite::SqliteStore::create(database)?.record_snapshot(snapshot)
+}
+
+#[cfg(feature = "sqlite")]
+fn store_quotes(database: &Path, prices: &std::collections::HashMap<String, rust_decimal::Decimal>, now: DateTime<Utc>) -> Result<()> {
+    crate::sqlite::SqliteStore::create(database)?.store_quotes(prices, now)
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn record_snapshot(database: &Path, _snapshot: &Snapshot) -> Result<()> {
+    bail!("{:?} is a SQLite database, but this build has no SQLite support (enable the `sqlite` feature)", database)
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn store_quotes(database: &Path, _prices: &std::collections::HashMap<String, rust_decimal::Decimal>, _now: DateTime<Utc>) -> Result<()> {
+    bail!("{:?} is a SQLite database, but this build has no SQLite support (enable the `sqlite` feature)", database)
+}
+
+/// Resolves on SIGINT (Ctrl-C) or, on Unix, SIGTERM.
+async fn shutdown_signal() -> &'static str {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => tokio::select! {
+                _ = tokio::signal::ctrl_c() => "SIGINT",
+                _ = terminate.recv() => "SIGTERM",
+            },
+            Err(_) => {
+                let _ = tokio::signal::ctrl_c().await;
+                "SIGINT"
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+        "Ctrl-C"
+    }
+}
+
+async fn run_logged(scheduled: &ScheduledJob, portfolio: &Path) {
+    info!(job = %scheduled.name, kind = scheduled.job.kind(), "Job started");
+    match run_job(&scheduled.job, portfolio, Utc::now()).await {
+        Ok(summary) => info!(job = %scheduled.name, kind = scheduled.job.kind(), "{}", summary),
+        Err(err) => error!(job = %scheduled.name, kind = scheduled.job.kind(), "Job failed: {:#}", err),
+    }
+}
+
+/// Run the schedule until SIGINT or SIGTERM, or with `once`, run every job
+/// one time and return.
+pub async fn run(config: DaemonConfig, once: bool) -> Result<()> {
+    // Fail on a portfolio that does not load before waiting for any job
+    Portfolio::load_from_file(&config.portfolio)?;
+
+    if once {
+        for scheduled in &config.jobs {
+            run_logged(scheduled, &config.portfolio).await;
+        }
+        return Ok(());
+    }
+
+    let now = Utc::now();
+    let mut next: Vec<DateTime<Utc>> = config.jobs.iter().map(|j| j.schedule.next_after(now, config.zone)).collect();
+    for (scheduled, when) in config.jobs.iter().zip(&next) {
+        info!(job = %scheduled.name, kind = scheduled.job.kind(), schedule = %scheduled.schedule,
+              next_run = %when.to_rfc3339(), "Job scheduled");
+    }
+    info!(jobs = config.jobs.len(), portfolio = %config.portfolio.display(), "Daemon started");
+
+    // Pinned once, so a signal that arrives while a job runs is seen as soon
+    // as the job is done
+    let shutdown = shutdown_signal();
+    tokio::pin!(shutdown);
+    loop {
+        let (index, due) = next
+            .iter()
+            .copied()
+            .enumerate()
+            .min_by_key(|(_, when)| *when)
+            .expect("the schedule has jobs");
+        let wait = (due - Utc::now()).to_std().unwrap_or_default();
+        tokio::select! {
+            signal = &mut shutdown => {
+                info!(signal, "Shutting down");
+                return Ok(());
+            }
+            _ = tokio::time::sleep(wait) => {}
+        }
+
+        let scheduled = &config.jobs[index];
+        run_logged(scheduled, &config.portfolio).await;
+        // Never before the slot that just ran, even if the clock is early
+        next[index] = scheduled.schedule.next_after(Utc::now().max(due), config.zone);
+        info!(job = %scheduled.name, next_run = %next[index].to_rfc3339(), "Job scheduled");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn utc(text: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(text).unwrap().with_timezone(&Utc)
+    }
+
+    fn load(text: &str) -> Result<DaemonConfig> {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("schedule.toml");
+        fs::write(&path, text).unwrap();
+        DaemonConfig::load_from_file(&path)
+    }
+
+    #[test]
+    fn test_load_schedule() {
+        let config = load(r#"
+            portfolio = "portfolio.db"
+            timezone = "America/New_York"
+
+            [[jobs]]
+            kind = "snapshot"
+            at = "16:05"
+            days = ["mon", "tue", "wed", "thu", "fri"]
+
+            [[jobs]]
+            name = "price alerts"
+            kind = "alerts"
+            every = "5m"
+            rules = "alerts.toml"
+            notify = false
+
+            [[jobs]]
+            kind = "report"
+            at = "18:00"
+            days = ["fri"]
+            output = "reports/weekly-{date}.xlsx"
+        "#).unwrap();
+
+        assert_eq!(config.zone, Zone::Named(chrono_tz::America::New_York));
+        assert_eq!(config.jobs.len(), 3);
+        assert_eq!(config.jobs[0].name, "snapshot");
+        assert_eq!(config.jobs[0].schedule.to_string(), "at 16:05 on Mon,Tue,Wed,Thu,Fri");
+        // The snapshot goes into the portfolio's own database
+        assert!(matches!(&config.jobs[0].job, Job::Snapshot { database: Some(db) } if db.ends_with("portfolio.db")));
+        assert_eq!(config.jobs[1].name, "price alerts");
+        assert_eq!(config.jobs[1].schedule, Schedule::Every(Duration::from_secs(300)));
+        assert!(matches!(&config.jobs[1].job, Job::Alerts { notify: false, .. }));
+    }
+
+    #[test]
+    fn test_schedule_errors() {
+        let error = |text: &str| format!("{:#}", load(text).unwrap_err());
+        assert!(error("portfolio = \"p.json\"").contains("no jobs"));
+        assert!(error("portfolio = \"p.json\"\ntimezone = \"Mars/Base\"\n[[jobs]]\nkind = \"quotes\"\nevery = \"1h\"\ndatabase = \"q.db\"")
+            .contains("Unknown timezone"));
+        assert!(error("portfolio = \"p.json\"\n[[jobs]]\nkind = \"snapshot\"\nevery = \"1h\"").contains("needs `database`"));
+        assert!(error("portfolio = \"p.db\"\n[[jobs]]\nkind = \"snapshot\"").contains("exactly one of"));
+        assert!(error("portfolio = \"p.db\"\n[[jobs]]\nkind = \"snapshot\"\nat = \"25:00\"").contains("invalid time"));
+        assert!(error("portfolio = \"p.db\"\n[[jobs]]\nkind = \"snapshot\"\nat = \"16:00\"\ndays = [\"someday\"]").contains("unknown day"));
+        assert!(error("portfolio = \"p.db\"\n[[jobs]]\nkind = \"backup\"\nevery = \"1h\"").contains("Invalid schedule"));
+    }
+
+    #[test]
+    fn test_next_run_at_market_close() {
+        let weekdays = vec![Weekday::Mon, Weekday::Tue, Weekday::Wed, Weekday::Thu, Weekday::Fri];
+        let close = Schedule::At { time: NaiveTime::from_hms_opt(16, 5, 0).unwrap(), days: weekdays };
+        let new_york = Zone::Named(chrono_tz::America::New_York);
+
+        // Wednesday 2024-07-10, 12:00 in New York (EDT, UTC-4)
+        assert_eq!(close.next_after(utc("2024-07-10T16:00:00Z"), new_york), utc("2024-07-10T20:05:00Z"));
+        // Just after the close it is the next day's
+        assert_eq!(close.next_after(utc("2024-07-10T20:05:00Z"), new_york), utc("2024-07-11T20:05:00Z"));
+        // Friday evening skips the weekend
+        assert_eq!(close.next_after(utc("2024-07-12T22:00:00Z"), new_york), utc("2024-07-15T20:05:00Z"));
+        // In winter New York is UTC-5
+        assert_eq!(close.next_after(utc("2024-01-10T16:00:00Z"), new_york), utc("2024-01-10T21:05:00Z"));
+
+        let every = Schedule::Every(Duration::from_secs(900));
+        assert_eq!(every.next_after(utc("2024-07-10T16:00:00Z"), new_york), utc("2024-07-10T16:15:00Z"));
+    }
+
+    #[test]
+    fn test_time_skipped_by_dst_moves_to_next_day() {
+        let daily = Schedule::At { time: NaiveTime::from_hms_opt(2, 30, 0).unwrap(), days: parse_days(None).unwrap() };
+        // 2024-03-10 02:30 does not exist in New York
+        let next = daily.next_after(utc("2024-03-10T05:00:00Z"), Zone::Named(chrono_tz::America::New_York));
+        assert_eq!(next, utc("2024-03-11T06:30:00Z"));
+    }
+
+    const PORTFOLIO: &str = r#"[{"ticker": "AAPL", "quantity": 10, "cost_basis": 150, "date_purchased": "2024-01-01"}]"#;
+
+    #[tokio::test]
+    async fn test_report_job_writes_dated_export() {
+        let dir = tempdir().unwrap();
+        let portfolio = dir.path().join("portfolio.json");
+        fs::write(&portfolio, PORTFOLIO).unwrap();
+        let output = dir.path().join("reports/weekly-{date}.xlsx").to_string_lossy().into_owned();
+
+        let now = Utc::now();
+        let summary = run_job(&Job::Report { output }, &portfolio, now).await.unwrap();
+        let expected = dir.path().join(format!("reports/weekly-{}.xlsx", now.with_timezone(&Local).format("%Y-%m-%d")));
+        assert!(expected.exists(), "{}", summary);
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn test_snapshot_and_quote_jobs_fill_database() {
+        let dir = tempdir().unwrap();
+        let portfolio = dir.path().join("portfolio.json");
+        fs::write(&portfolio, PORTFOLIO).unwrap();
+        let database = dir.path().join("history.db");
+
+        let now = Utc::now();
+        run_job(&Job::Snapshot { database: Some(database.clone()) }, &portfolio, now).await.unwrap();
+        run_job(&Job::Quotes { database: Some(database.clone()) }, &portfolio, now).await.unwrap();
+
+        let store = crate::sqlite::SqliteStore::open(&database).unwrap();
+        let snapshots = store.snapshots(None, None).unwrap();
+        assert_eq!(snapshots.len(), 1);
+        assert_eq!(snapshots[0].total_value, rust_decimal_macros::dec!(1700));
+        assert!(store.cached_quotes(&["AAPL".to_string()]).unwrap().contains_key("AAPL"));
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn test_snapshot_job_fails_without_every_quote() {
+        let dir = tempdir().unwrap();
+        let portfolio = dir.path().join("portfolio.json");
+        fs::write(&portfolio, r#"[{"ticker": "ZZZQ", "quantity": 1, "cost_basis": 10, "date_purchased": "2024-01-01"}]"#).unwrap();
+        let database = dir.path().join("history.db");
+
+        let error = run_job(&Job::Snapshot { database: Some(database.clone()) }, &portfolio, Utc::now()).await.unwrap_err();
+        assert!(error.to_string().contains("ZZZQ"), "{}", error);
+        assert!(!database.exists());
+    }
+}
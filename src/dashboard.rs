@@ -0,0 +1,711 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Direction, Layout, Rect};
+use ratatui::style::{Modifier, Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Bar, BarChart, BarGroup, Block, Cell, Paragraph, Row, Sparkline, Table, TableState};
+use ratatui::{DefaultTerminal, Frame};
+use rust_decimal::Decimal;
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::api::fetch_prices_with_failures;
+use crate::history::{align, fetch_price_history, PriceSeries};
+use crate::money;
+use crate::portfolio::{HoldingWithPrice, Portfolio};
+use crate::reports::{concentration, lot_rows, position_rows, transaction_rows};
+use crate::risk::{positions, value_series};
+
+// Full-screen dashboard. All of the state lives in `Dashboard`, which only
+// knows about key codes and prices; the event loop at the bottom feeds it
+// keys and fresh quotes and redraws it. Figures come from the same report
+// builders as the one-shot commands.
+
+/// Days of price history behind the value sparkline.
+pub const HISTORY_DAYS: i64 = 90;
+
+/// How often the event loop wakes up to check for keys and the refresh timer.
+const TICK: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    Ticker,
+    Value,
+    GainLoss,
+    Return,
+    Weight,
+}
+
+impl SortKey {
+    pub fn next(self) -> SortKey {
+        match self {
+            SortKey::Ticker => SortKey::Value,
+            SortKey::Value => SortKey::GainLoss,
+            SortKey::GainLoss => SortKey::Return,
+            SortKey::Return => SortKey::Weight,
+            SortKey::Weight => SortKey::Ticker,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            SortKey::Ticker => "ticker",
+            SortKey::Value => "value",
+            SortKey::GainLoss => "gain/loss",
+            SortKey::Return => "return",
+            SortKey::Weight => "weight",
+        }
+    }
+}
+
+/// One position in the holdings table, with lots merged.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DashboardRow {
+    pub ticker: String,
+    pub lots: usize,
+    pub quantity: Decimal,
+    pub price: Decimal,
+    pub value: Decimal,
+    pub cost: Decimal,
+    pub gain_loss: Decimal,
+    pub return_percentage: Decimal,
+    pub weight: Decimal,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    None,
+    Quit,
+    Refresh,
+}
+
+pub struct Dashboard {
+    portfolio: Portfolio,
+    holdings: Vec<HoldingWithPrice>,
+    rows: Vec<DashboardRow>,
+    sort: SortKey,
+    descending: bool,
+    selected: usize,
+    /// Ticker whose lots and transactions are open, if any.
+    detail: Option<String>,
+    /// Daily portfolio values, followed by one value per refresh.
+    values: Vec<f64>,
+    refreshed_at: DateTime<Local>,
+    status: Option<String>,
+}
+
+impl Dashboard {
+    pub fn new(portfolio: Portfolio, prices: &HashMap<String, Decimal>, history: Vec<f64>) -> Dashboard {
+        let mut dashboard = Dashboard {
+            portfolio,
+            holdings: Vec::new(),
+            rows: Vec::new(),
+            sort: SortKey::Value,
+            descending: true,
+            selected: 0,
+            detail: None,
+            values: history,
+            refreshed_at: Local::now(),
+            status: None,
+        };
+        dashboard.update_prices(prices);
+        dashboard
+    }
+
+    /// Reprice the holdings, keeping the same ticker selected.
+    pub fn update_prices(&mut self, prices: &HashMap<String, Decimal>) {
+        let selected = self.selected_ticker().map(str::to_string);
+        self.holdings = self.portfolio.holdings_with_prices(prices);
+        self.rows = dashboard_rows(&self.holdings, &self.portfolio.base_currency);
+        self.sort_rows(selected.as_deref());
+        self.values.push(money::to_f64(self.total_value()));
+        self.refreshed_at = Local::now();
+        self.status = None;
+    }
+
+    /// Take a refresh's quotes. A ticker whose quote failed keeps the price
+    /// it had and is named in the status line.
+    pub fn refresh_prices(&mut self, mut prices: HashMap<String, Decimal>, failed: &[String]) {
+        for ticker in failed {
+            match self.holdings.iter().find(|h| h.holding.ticker == *ticker) {
+                Some(last) => prices.insert(ticker.clone(), last.current_price),
+                None => prices.remove(ticker),
+            };
+        }
+        self.update_prices(&prices);
+        if !failed.is_empty() {
+            self.set_status(format!("No quote for {}", failed.join(", ")));
+        }
+    }
+
+    pub fn set_status(&mut self, status: String) {
+        self.status = Some(status);
+    }
+
+    pub fn rows(&self) -> &[DashboardRow] {
+        &self.rows
+    }
+
+    pub fn selected_ticker(&self) -> Option<&str> {
+        self.rows.get(self.selected).map(|r| r.ticker.as_str())
+    }
+
+    pub fn detail(&self) -> Option<&str> {
+        self.detail.as_deref()
+    }
+
+    pub fn total_value(&self) -> Decimal {
+        self.rows.iter().map(|r| r.value).sum()
+    }
+
+    pub fn total_cost(&self) -> Decimal {
+        self.rows.iter().map(|r| r.cost).sum()
+    }
+
+    pub fn handle_key(&mut self, key: KeyCode) -> Action {
+        if self.detail.is_some() {
+            return match key {
+                KeyCode::Char('q') => Action::Quit,
+                KeyCode::Esc | KeyCode::Backspace | KeyCode::Left | KeyCode::Char('h') => {
+                    self.detail = None;
+                    Action::None
+                }
+                KeyCode::Char('r') => Action::Refresh,
+                _ => Action::None,
+            };
+        }
+
+        match key {
+            KeyCode::Char('q') | KeyCode::Esc => return Action::Quit,
+            KeyCode::Char('r') => return Action::Refresh,
+            KeyCode::Down | KeyCode::Char('j') => {
+                self.selected = (self.selected + 1).min(self.rows.len().saturating_sub(1));
+            }
+            KeyCode::Up | KeyCode::Char('k') => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Home | KeyCode::Char('g') => self.selected = 0,
+            KeyCode::End | KeyCode::Char('G') => self.selected = self.rows.len().saturating_sub(1),
+            KeyCode::Char('s') => {
+                let selected = self.selected_ticker().map(str::to_string);
+                self.sort = self.sort.next();
+                // Text sorts A to Z, figures largest first
+                self.descending = self.sort != SortKey::Ticker;
+                self.sort_rows(selected.as_deref());
+            }
+            KeyCode::Char('o') => {
+                let selected = self.selected_ticker().map(str::to_string);
+                self.descending = !self.descending;
+                self.sort_rows(selected.as_deref());
+            }
+            KeyCode::Enter | KeyCode::Right | KeyCode::Char('l') => {
+                self.detail = self.selected_ticker().map(str::to_string);
+            }
+            _ => {}
+        }
+        Action::None
+    }
+
+    fn sort_rows(&mut self, keep_selected: Option<&str>) {
+        let (sort, descending) = (self.sort, self.descending);
+        self.rows.sort_by(|a, b| {
+            let order = match sort {
+                SortKey::Ticker => a.ticker.cmp(&b.ticker),
+                SortKey::Value => a.value.cmp(&b.value),
+                SortKey::GainLoss => a.gain_loss.cmp(&b.gain_loss),
+                SortKey::Return => a.return_percentage.cmp(&b.return_percentage),
+                SortKey::Weight => a.weight.cmp(&b.weight),
+            };
+            let order = if descending { order.reverse() } else { order };
+            // Ties fall back to the ticker so the order never flickers
+            order.then_with(|| a.ticker.cmp(&b.ticker))
+        });
+        self.selected = keep_selected
+            .and_then(|t| self.rows.iter().position(|r| r.ticker == t))
+            .unwrap_or(0)
+            .min(self.rows.len().saturating_sub(1));
+    }
+
+    pub fn draw(&self, frame: &mut Frame) {
+        let [header, body, footer] = Layout::vertical([
+            Constraint::Length(3),
+            Constraint::Min(8),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+
+        self.draw_header(frame, header);
+        match &self.detail {
+            Some(ticker) => self.draw_detail(frame, body, ticker),
+            None => self.draw_overview(frame, body),
+        }
+
+        let help = match self.detail {
+            Some(_) => " Esc back · r refresh · q quit",
+            None => " ↑↓ select · Enter lots · s sort · o order · r refresh · q quit",
+        };
+        let footer_line = match &self.status {
+            Some(status) => Line::from(vec![Span::raw(help), Span::raw("  "), Span::styled(status.clone(), Style::new().red())]),
+            None => Line::from(help).dark_gray(),
+        };
+        frame.render_widget(Paragraph::new(footer_line), footer);
+    }
+
+    fn draw_header(&self, frame: &mut Frame, area: Rect) {
+        let currency = &self.portfolio.base_currency;
+        let value = self.total_value();
+        let gain = value - self.total_cost();
+        let name = self.portfolio.metadata.name.clone().unwrap_or_else(|| "Portfolio".to_string());
+        let line = Line::from(vec![
+            Span::styled(name, Style::new().bold()),
+            Span::raw("  Value "),
+            Span::styled(format!("{:.2} {}", value, currency), Style::new().green().bold()),
+            Span::raw("  Gain/Loss "),
+            Span::styled(format!("{:+.2}", gain), gain_style(gain)),
+            Span::raw(format!(" ({:+.2}%)", money::round_percent(money::percentage(gain, self.total_cost())))),
+            Span::raw(format!("  Updated {}", self.refreshed_at.format("%H:%M:%S"))).dark_gray(),
+        ]);
+        frame.render_widget(Paragraph::new(line).block(Block::bordered().cyan()), area);
+    }
+
+    fn draw_overview(&self, frame: &mut Frame, area: Rect) {
+        let [top, bottom] = Layout::vertical([Constraint::Min(6), Constraint::Length(10)]).areas(area);
+        let [table_area, allocation_area] =
+            Layout::horizontal([Constraint::Percentage(62), Constraint::Percentage(38)]).areas(top);
+        let [performance_area, sparkline_area] =
+            Layout::horizontal([Constraint::Length(36), Constraint::Min(20)]).areas(bottom);
+
+        self.draw_holdings(frame, table_area);
+        self.draw_allocation(frame, allocation_area);
+        self.draw_performance(frame, performance_area);
+        self.draw_sparkline(frame, sparkline_area);
+    }
+
+    fn draw_holdings(&self, frame: &mut Frame, area: Rect) {
+        let header = Row::new(["Ticker", "Quantity", "Price", "Value", "Gain/Loss", "Return", "Weight"])
+            .style(Style::new().bold());
+        let rows = self.rows.iter().map(|r| {
+            Row::new(vec![
+                Cell::from(r.ticker.clone()).yellow(),
+                Cell::from(money::round_quantity(r.quantity, &r.ticker).normalize().to_string()),
+                Cell::from(format!("{:.2}", money::round(r.price, 2))),
+                Cell::from(format!("{:.2}", r.value)),
+                Cell::from(format!("{:+.2}", r.gain_loss)).style(gain_style(r.gain_loss)),
+                Cell::from(format!("{:+.2}%", r.return_percentage)).style(gain_style(r.return_percentage)),
+                Cell::from(format!("{:.1}%", money::round(r.weight, 1))),
+            ])
+        });
+        let widths = [
+            Constraint::Length(9),
+            Constraint::Length(10),
+            Constraint::Length(10),
+            Constraint::Length(12),
+            Constraint::Length(12),
+            Constraint::Length(9),
+            Constraint::Length(7),
+        ];
+        let order = if self.descending { "▼" } else { "▲" };
+        let table = Table::new(rows, widths)
+            .header(header)
+            .block(Block::bordered().title(format!(" Holdings · by {} {} ", self.sort.label(), order)))
+            .row_highlight_style(Style::new().add_modifier(Modifier::REVERSED))
+            .highlight_symbol("› ");
+        let mut state = TableState::default().with_selected(Some(self.selected));
+        frame.render_stateful_widget(table, area, &mut state);
+    }
+
+    fn draw_allocation(&self, frame: &mut Frame, area: Rect) {
+        let mut by_weight: Vec<&DashboardRow> = self.rows.iter().collect();
+        by_weight.sort_by(|a, b| b.weight.cmp(&a.weight).then_with(|| a.ticker.cmp(&b.ticker)));
+        let bars: Vec<Bar> = by_weight
+            .iter()
+            .map(|r| {
+                Bar::default()
+                    // Hundredths of a percent, so small weights still show
+                    .value(money::to_f64(r.weight * Decimal::ONE_HUNDRED).max(0.0) as u64)
+                    .label(Line::from(r.ticker.clone()))
+                    .text_value(format!("{:.1}%", money::round(r.weight, 1)))
+            })
+            .collect();
+        let chart = BarChart::default()
+            .block(Block::bordered().title(" Allocation "))
+            .direction(Direction::Horizontal)
+            .bar_width(1)
+            .bar_gap(0)
+            .bar_style(Style::new().cyan())
+            .value_style(Style::new().black().on_cyan())
+            .data(BarGroup::default().bars(&bars));
+        frame.render_widget(chart, area);
+    }
+
+    fn draw_performance(&self, frame: &mut Frame, area: Rect) {
+        let value = self.total_value();
+        let cost = self.total_cost();
+        let gain = value - cost;
+        let concentration = concentration(&self.holdings, &self.portfolio.base_currency);
+        let best = self.rows.iter().max_by(|a, b| a.return_percentage.cmp(&b.return_percentage));
+        let worst = self.rows.iter().min_by(|a, b| a.return_percentage.cmp(&b.return_percentage));
+
+        let mut lines = vec![
+            Line::from(format!("Total cost   {:.2}", cost)),
+            Line::from(format!("Total value  {:.2}", value)),
+            Line::from(vec![Span::raw("Gain/Loss    "), Span::styled(format!("{:+.2}", gain), gain_style(gain))]),
+            Line::from(vec![
+                Span::raw("Return       "),
+                Span::styled(
+                    format!("{:+.2}%", money::round_percent(money::percentage(gain, cost))),
+                    gain_style(gain),
+                ),
+            ]),
+            Line::from(format!("Eff. bets    {}", concentration.effective_bets)),
+        ];
+        if let (Some(best), Some(worst)) = (best, worst) {
+            lines.push(Line::from(format!("Best         {} {:+.2}%", best.ticker, best.return_percentage)));
+            lines.push(Line::from(format!("Worst        {} {:+.2}%", worst.ticker, worst.return_percentage)));
+        }
+        frame.render_widget(Paragraph::new(lines).block(Block::bordered().title(" Performance ")), area);
+    }
+
+    fn draw_sparkline(&self, frame: &mut Frame, area: Rect) {
+        // One column per value, most recent on the right
+        let width = area.width.saturating_sub(2) as usize;
+        let shown = &self.values[self.values.len().saturating_sub(width)..];
+        let change = match (shown.first(), shown.last()) {
+            (Some(&first), Some(&last)) if first > 0.0 => format!(" {:+.2}%", (last / first - 1.0) * 100.0),
+            _ => String::new(),
+        };
+        let data = sparkline_data(shown);
+        let sparkline = Sparkline::default()
+            .block(Block::bordered().title(format!(" Value, last {} days{} ", HISTORY_DAYS, change)))
+            .data(&data)
+            .style(Style::new().green());
+        frame.render_widget(sparkline, area);
+    }
+
+    fn draw_detail(&self, frame: &mut Frame, area: Rect, ticker: &str) {
+        let currency = &self.portfolio.base_currency;
+        let holdings: Vec<HoldingWithPrice> =
+            self.holdings.iter().filter(|h| h.holding.ticker == ticker).cloned().collect();
+        let transactions: Vec<_> = transaction_rows(&self.holdings, &self.portfolio.transactions, currency)
+            .into_iter()
+            .filter(|t| t.ticker == ticker)
+            .collect();
+        let position = position_rows(&holdings, currency).into_iter().next();
+
+        let [summary_area, lots_area, transactions_area] = Layout::vertical([
+            Constraint::Length(4),
+            Constraint::Min(4),
+            Constraint::Min(4),
+        ])
+        .areas(area);
+
+        let summary = match &position {
+            Some(p) => {
+                let gain = p.current_value - p.total_cost;
+                vec![
+                    Line::from(format!("{} lot(s), {} units at an average cost of {:.2}",
+                                       p.lots, p.quantity.normalize(), money::round(p.average_cost, 2))),
+                    Line::from(vec![
+                        Span::raw(format!("Price {:.2}  Value {:.2}  Gain/Loss ", money::round(p.current_price, 2), p.current_value)),
+                        Span::styled(format!("{:+.2}", gain), gain_style(gain)),
+                    ]),
+                ]
+            }
+            None => vec![Line::from("No lots")],
+        };
+        frame.render_widget(
+            Paragraph::new(summary).block(Block::bordered().title(format!(" {} ", ticker)).yellow()),
+            summary_area,
+        );
+
+        let price = position.as_ref().map(|p| p.current_price).unwrap_or_default();
+        let lots = lot_rows(&holdings, currency).into_iter().map(|lot| {
+            let value = money::round_money(lot.quantity * price, currency);
+            let gain = value - lot.total_cost;
+            Row::new(vec![
+                Cell::from(lot.date_purchased),
+                Cell::from(money::round_quantity(lot.quantity, &lot.ticker).normalize().to_string()),
+                Cell::from(format!("{:.2}", money::round(lot.cost_basis, 2))),
+                Cell::from(format!("{:.2}", lot.total_cost)),
+                Cell::from(format!("{:.2}", value)),
+                Cell::from(format!("{:+.2}", gain)).style(gain_style(gain)),
+            ])
+        });
+        let lots = Table::new(lots, [Constraint::Length(12); 6])
+            .header(Row::new(["Purchased", "Quantity", "Cost Basis", "Cost", "Value", "Gain/Loss"]).bold())
+            .block(Block::bordered().title(" Lots "));
+        frame.render_widget(lots, lots_area);
+
+        let transactions = transactions.into_iter().map(|t| {
+            Row::new(vec![
+                t.date,
+                t.kind,
+                money::round_quantity(t.quantity, ticker).normalize().to_string(),
+                format!("{:.2}", money::round(t.price, 2)),
+                format!("{:.2}", t.amount),
+            ])
+        });
+        let transactions = Table::new(transactions, [Constraint::Length(12); 5])
+            .header(Row::new(["Date", "Kind", "Quantity", "Price", "Amount"]).bold())
+            .block(Block::bordered().title(" Transactions "));
+        frame.render_widget(transactions, transactions_area);
+    }
+}
+
+fn gain_style(amount: Decimal) -> Style {
+    if amount.is_sign_negative() && !amount.is_zero() {
+        Style::new().red()
+    } else {
+        Style::new().green()
+    }
+}
+
+/// Merge lots into positions and add each position's weight.
+pub fn dashboard_rows(holdings: &[HoldingWithPrice], currency: &str) -> Vec<DashboardRow> {
+    let positions = position_rows(holdings, currency);
+    let total: Decimal = positions.iter().map(|p| p.current_value).sum();
+    positions
+        .into_iter()
+        .map(|p| {
+            let gain_loss = p.current_value - p.total_cost;
+            DashboardRow {
+                ticker: p.ticker,
+                lots: p.lots,
+                quantity: p.quantity,
+                price: p.current_price,
+                value: p.current_value,
+                cost: p.total_cost,
+                gain_loss,
+                return_percentage: money::round_percent(money::percentage(gain_loss, p.total_cost)),
+                weight: money::round_percent(money::percentage(p.current_value, total)),
+            }
+        })
+        .collect()
+}
+
+/// Scale values to 0..=100 above their minimum, so that small moves of a
+/// large portfolio are still visible.
+pub fn sparkline_data(values: &[f64]) -> Vec<u64> {
+    let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let range = max - min;
+    values
+        .iter()
+        .map(|v| if range > 0.0 { (1.0 + (v - min) / range * 99.0).round() as u64 } else { 50 })
+        .collect()
+}
+
+/// Daily value of the current positions over the last `HISTORY_DAYS`.
+async fn value_history(portfolio: &Portfolio) -> Result<Vec<f64>> {
+    let positions = positions(&portfolio.holdings);
+    let tickers: Vec<String> = positions.keys().cloned().collect();
+    let to = chrono::Utc::now().date_naive();
+    let history = fetch_price_history(&tickers, to - chrono::Duration::days(HISTORY_DAYS), to).await?;
+    let series: Vec<&PriceSeries> = tickers
+        .iter()
+        .map(|t| history.get(t).with_context(|| format!("No price history for {}", t)))
+        .collect::<Result<_>>()?;
+    let (_, closes) = align(&series);
+    let quantities: Vec<f64> = positions.values().copied().collect();
+    let mut values = value_series(&quantities, &closes);
+    // The live quote is added by the first refresh
+    values.pop();
+    Ok(values)
+}
+
+/// Run the dashboard until the user quits, refreshing quotes every `refresh`.
+pub async fn run(portfolio_file: &Path, refresh: Duration) -> Result<()> {
+    let portfolio = Portfolio::load_from_file(portfolio_file)?;
+    let (mut prices, failed) = fetch_prices_with_failures(&portfolio.get_tickers()).await?;
+    for ticker in &failed {
+        prices.remove(ticker);
+    }
+    let history = value_history(&portfolio).await.unwrap_or_default();
+    let mut dashboard = Dashboard::new(portfolio, &prices, history);
+    if !failed.is_empty() {
+        dashboard.set_status(format!("No quote for {}", failed.join(", ")));
+    }
+
+    let mut terminal = ratatui::try_init().context("Failed to start the dashboard (is this a terminal?)")?;
+    let result = event_loop(&mut terminal, &mut dashboard, refresh).await;
+    ratatui::restore();
+    result
+}
+
+async fn event_loop(terminal: &mut DefaultTerminal, dashboard: &mut Dashboard, refresh: Duration) -> Result<()> {
+    let mut last_refresh = Instant::now();
+    loop {
+        terminal.draw(|frame| dashboard.draw(frame))?;
+
+        let mut action = Action::None;
+        if event::poll(TICK)? {
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press {
+                    action = dashboard.handle_key(key.code);
+                }
+            }
+        }
+        if action == Action::None && last_refresh.elapsed() >= refresh {
+            action = Action::Refresh;
+        }
+
+        match action {
+            Action::Quit => return Ok(()),
+            Action::Refresh => {
+                let tickers = dashboard.portfolio.get_tickers();
+                // Printing would garble the screen; problems go to the status line
+                match fetch_prices_with_failures(&tickers).await {
+                    Ok((prices, failed)) => dashboard.refresh_prices(prices, &failed),
+                    Err(err) => dashboard.set_status(format!("Refresh failed: {:#}", err)),
+                }
+                last_refresh = Instant::now();
+            }
+            Action::None => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::portfolio::Holding;
+    use ratatui::backend::TestBackend;
+    use ratatui::Terminal;
+    use rust_decimal_macros::dec;
+
+    fn holding(ticker: &str, quantity: Decimal, cost_basis: Decimal, date: &str) -> Holding {
+        Holding {
+            ticker: ticker.to_string(),
+            quantity,
+            cost_basis,
+            date_purchased: date.to_string(),
+        }
+    }
+
+    fn dashboard() -> Dashboard {
+        let portfolio = Portfolio {
+            holdings: vec![
+                holding("AAPL", dec!(10), dec!(150), "2023-01-01"),
+                holding("TSLA", dec!(5), dec!(200), "2023-02-01"),
+                holding("AAPL", dec!(10), dec!(130), "2023-03-01"),
+                holding("BTC-USD", dec!(0.1), dec!(50000), "2023-04-01"),
+            ],
+            ..Default::default()
+        };
+        let prices = HashMap::from([
+            ("AAPL".to_string(), dec!(170)),
+            ("TSLA".to_string(), dec!(180)),
+            ("BTC-USD".to_string(), dec!(45000)),
+        ]);
+        Dashboard::new(portfolio, &prices, vec![6000.0, 6100.0])
+    }
+
+    fn tickers(dashboard: &Dashboard) -> Vec<&str> {
+        dashboard.rows().iter().map(|r| r.ticker.as_str()).collect()
+    }
+
+    #[test]
+    fn test_rows_merge_lots_and_weights() {
+        let dashboard = dashboard();
+        // AAPL 3400, TSLA 900, BTC 4500 of 8800
+        assert_eq!(tickers(&dashboard), vec!["BTC-USD", "AAPL", "TSLA"]);
+        let aapl = &dashboard.rows()[1];
+        assert_eq!(aapl.lots, 2);
+        assert_eq!(aapl.gain_loss, dec!(600));
+        assert_eq!(aapl.weight, dec!(38.64));
+        assert_eq!(dashboard.total_value(), dec!(8800));
+    }
+
+    #[test]
+    fn test_sorting_keeps_selection() {
+        let mut dashboard = dashboard();
+        dashboard.handle_key(KeyCode::Down);
+        assert_eq!(dashboard.selected_ticker(), Some("AAPL"));
+
+        dashboard.handle_key(KeyCode::Char('s')); // gain/loss, largest first
+        assert_eq!(tickers(&dashboard), vec!["AAPL", "TSLA", "BTC-USD"]);
+        assert_eq!(dashboard.selected_ticker(), Some("AAPL"));
+
+        dashboard.handle_key(KeyCode::Char('o'));
+        assert_eq!(tickers(&dashboard), vec!["BTC-USD", "TSLA", "AAPL"]);
+        assert_eq!(dashboard.selected_ticker(), Some("AAPL"));
+
+        for _ in 0..3 {
+            dashboard.handle_key(KeyCode::Char('s'));
+        }
+        assert_eq!(tickers(&dashboard), vec!["AAPL", "BTC-USD", "TSLA"]);
+    }
+
+    #[test]
+    fn test_failed_quote_keeps_last_price() {
+        let mut dashboard = dashboard();
+        let prices = HashMap::from([
+            ("AAPL".to_string(), dec!(170)),
+            ("TSLA".to_string(), dec!(100)),
+            ("BTC-USD".to_string(), dec!(45000)),
+        ]);
+        dashboard.refresh_prices(prices, &["TSLA".to_string()]);
+        // TSLA stays at 180 rather than the default price
+        assert_eq!(dashboard.total_value(), dec!(8800));
+        assert_eq!(dashboard.status.as_deref(), Some("No quote for TSLA"));
+    }
+
+    #[test]
+    fn test_keys_open_and_close_detail() {
+        let mut dashboard = dashboard();
+        dashboard.handle_key(KeyCode::End);
+        assert_eq!(dashboard.handle_key(KeyCode::Enter), Action::None);
+        assert_eq!(dashboard.detail(), Some("TSLA"));
+        // Esc leaves the detail view before it quits
+        assert_eq!(dashboard.handle_key(KeyCode::Esc), Action::None);
+        assert_eq!(dashboard.detail(), None);
+        assert_eq!(dashboard.handle_key(KeyCode::Char('r')), Action::Refresh);
+        assert_eq!(dashboard.handle_key(KeyCode::Esc), Action::Quit);
+    }
+
+    #[test]
+    fn test_update_prices_appends_value() {
+        let mut dashboard = dashboard();
+        assert_eq!(dashboard.values, vec![6000.0, 6100.0, 8800.0]);
+        let prices = HashMap::from([
+            ("AAPL".to_string(), dec!(100)),
+            ("TSLA".to_string(), dec!(100)),
+            ("BTC-USD".to_string(), dec!(10000)),
+        ]);
+        dashboard.update_prices(&prices);
+        assert_eq!(dashboard.values.last(), Some(&3500.0));
+        assert_eq!(tickers(&dashboard), vec!["AAPL", "BTC-USD", "TSLA"]);
+    }
+
+    #[test]
+    fn test_sparkline_data_scales_above_minimum() {
+        assert_eq!(sparkline_data(&[100.0, 150.0, 200.0]), vec![1, 51, 100]);
+        assert_eq!(sparkline_data(&[5.0, 5.0]), vec![50, 50]);
+        assert!(sparkline_data(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_draws_overview_and_detail() {
+        let mut dashboard = dashboard();
+        let mut terminal = Terminal::new(TestBackend::new(120, 30)).unwrap();
+        let screen = |terminal: &Terminal<TestBackend>| {
+            let buffer = terminal.backend().buffer();
+            buffer.content().iter().map(|c| c.symbol()).collect::<String>()
+        };
+
+        terminal.draw(|frame| dashboard.draw(frame)).unwrap();
+        let overview = screen(&terminal);
+        assert!(overview.contains("Holdings · by value ▼"));
+        assert!(overview.contains("8800.00 USD"));
+        assert!(overview.contains("Allocation"));
+        assert!(overview.contains("51.1%"));
+
+        dashboard.handle_key(KeyCode::Down);
+        dashboard.handle_key(KeyCode::Enter);
+        terminal.draw(|frame| dashboard.draw(frame)).unwrap();
+        let detail = screen(&terminal);
+        assert!(detail.contains("2 lot(s)"));
+        assert!(detail.contains("2023-03-01"));
+        assert!(detail.contains("Transactions"));
+    }
+}
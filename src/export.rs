@@ -0,0 +1,680 @@
+use anyhow::{bail, Context, Result};
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+use rust_xlsxwriter::{utility::column_number_to_name, ExcelDateTime, Format, Formula, Workbook};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::money;
+use crate::portfolio::{HoldingWithPrice, Transaction};
+use crate::reports;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ExportFormat {
+    /// Excel workbook with one sheet per table
+    Xlsx,
+    /// OpenDocument spreadsheet with one sheet per table
+    Ods,
+    /// One CSV file per table, written into a directory
+    Csv,
+}
+
+impl ExportFormat {
+    /// Guess the format from the output path: `.xlsx` and `.ods` map to
+    /// workbooks, anything else is treated as a directory of CSV files.
+    pub fn from_path(path: &Path) -> ExportFormat {
+        match path.extension().and_then(|e| e.to_str()).map(|e| e.to_ascii_lowercase()) {
+            Some(ext) if ext == "xlsx" => ExportFormat::Xlsx,
+            Some(ext) if ext == "ods" => ExportFormat::Ods,
+            _ => ExportFormat::Csv,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnKind {
+    Text,
+    Date,
+    Count,
+    Quantity,
+    Money,
+    /// Stored as a percentage (12.5 means 12.5%), written as a fraction
+    /// with a percent number format in workbooks.
+    Percent,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Total {
+    None,
+    Sum,
+    /// Total of one column divided by the total of another, e.g. the
+    /// portfolio return is total gain / total cost.
+    Ratio { numerator: usize, denominator: usize },
+}
+
+#[derive(Debug, Clone)]
+pub struct Column {
+    pub header: &'static str,
+    pub kind: ColumnKind,
+    pub total: Total,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Cell {
+    Text(String),
+    Number(Decimal),
+}
+
+/// A single table, independent of the file format it ends up in.
+#[derive(Debug, Clone)]
+pub struct Sheet {
+    pub name: &'static str,
+    pub columns: Vec<Column>,
+    pub rows: Vec<Vec<Cell>>,
+}
+
+fn column(header: &'static str, kind: ColumnKind, total: Total) -> Column {
+    Column { header, kind, total }
+}
+
+impl Sheet {
+    fn has_totals(&self) -> bool {
+        self.columns.iter().any(|c| c.total != Total::None)
+    }
+
+    fn column_sum(&self, col: usize) -> Decimal {
+        self.rows
+            .iter()
+            .map(|row| match &row[col] {
+                Cell::Number(n) => *n,
+                Cell::Text(_) => Decimal::ZERO,
+            })
+            .sum()
+    }
+
+    /// The computed value of a totals cell, used for CSV output and as the
+    /// cached result of spreadsheet formulas.
+    fn total_value(&self, col: usize) -> Option<Decimal> {
+        match self.columns[col].total {
+            Total::None => None,
+            Total::Sum => Some(self.column_sum(col)),
+            Total::Ratio { numerator, denominator } => {
+                let den = self.column_sum(denominator);
+                let ratio = if den.is_zero() { Decimal::ZERO } else { self.column_sum(numerator) / den };
+                // Ratios of percent columns are stored as percentages too
+                Some(match self.columns[col].kind {
+                    ColumnKind::Percent => ratio * Decimal::ONE_HUNDRED,
+                    _ => ratio,
+                })
+            }
+        }
+    }
+}
+
+/// Build every exported table from the priced holdings.
+pub fn build_sheets(holdings: &[HoldingWithPrice], transactions: &[Transaction], currency: &str) -> Vec<Sheet> {
+    let positions = reports::position_rows(holdings, currency);
+    let lots = reports::lot_rows(holdings, currency);
+    let transactions = reports::transaction_rows(holdings, transactions, currency);
+    let performance = reports::performance_report(holdings, currency);
+    let allocation = reports::allocation_report(holdings, currency);
+
+    vec![
+        Sheet {
+            name: "Holdings",
+            columns: vec![
+                column("Ticker", ColumnKind::Text, Total::None),
+                column("Lots", ColumnKind::Count, Total::None),
+                column("Quantity", ColumnKind::Quantity, Total::None),
+                column("Average Cost", ColumnKind::Money, Total::None),
+                column("Current Price", ColumnKind::Money, Total::None),
+                column("Total Cost", ColumnKind::Money, Total::Sum),
+                column("Market Value", ColumnKind::Money, Total::Sum),
+            ],
+            rows: positions
+                .into_iter()
+                .map(|p| vec![
+                    Cell::Text(p.ticker),
+                    Cell::Number(Decimal::from(p.lots)),
+                    Cell::Number(p.quantity),
+                    Cell::Number(p.average_cost),
+                    Cell::Number(p.current_price),
+                    Cell::Number(p.total_cost),
+                    Cell::Number(p.current_value),
+                ])
+                .collect(),
+        },
+        Sheet {
+            name: "Lots",
+            columns: vec![
+                column("Ticker", ColumnKind::Text, Total::None),
+                column("Date Purchased", ColumnKind::Date, Total::None),
+                column("Quantity", ColumnKind::Quantity, Total::None),
+                column("Cost Basis", ColumnKind::Money, Total::None),
+                column("Total Cost", ColumnKind::Money, Total::Sum),
+            ],
+            rows: lots
+                .into_iter()
+                .map(|l| vec![
+                    Cell::Text(l.ticker),
+                    Cell::Text(l.date_purchased),
+                    Cell::Number(l.quantity),
+                    Cell::Number(l.cost_basis),
+                    Cell::Number(l.total_cost),
+                ])
+                .collect(),
+        },
+        Sheet {
+            name: "Transactions",
+            columns: vec![
+                column("Date", ColumnKind::Date, Total::None),
+                column("Type", ColumnKind::Text, Total::None),
+                column("Ticker", ColumnKind::Text, Total::None),
+                column("Quantity", ColumnKind::Quantity, Total::None),
+                column("Price", ColumnKind::Money, Total::None),
+                column("Amount", ColumnKind::Money, Total::Sum),
+            ],
+            rows: transactions
+                .into_iter()
+                .map(|t| vec![
+                    Cell::Text(t.date),
+                    Cell::Text(t.kind),
+                    Cell::Text(t.ticker),
+                    Cell::Number(t.quantity),
+                    Cell::Number(t.price),
+                    Cell::Number(t.amount),
+                ])
+                .collect(),
+        },
+        Sheet {
+            name: "Performance",
+            columns: vec![
+                column("Ticker", ColumnKind::Text, Total::None),
+                column("Purchase Price", ColumnKind::Money, Total::None),
+                column("Current Price", ColumnKind::Money, Total::None),
+                column("Total Cost", ColumnKind::Money, Total::Sum),
+                column("Current Value", ColumnKind::Money, Total::Sum),
+                column("Gain/Loss", ColumnKind::Money, Total::Sum),
+                column("Return %", ColumnKind::Percent, Total::Ratio { numerator: 5, denominator: 3 }),
+            ],
+            rows: performance
+                .rows
+                .into_iter()
+                .map(|r| vec![
+                    Cell::Text(r.ticker),
+                    Cell::Number(r.cost_basis),
+                    Cell::Number(r.current_price),
+                    Cell::Number(r.total_cost),
+                    Cell::Number(r.current_value),
+                    Cell::Number(r.gain_loss),
+                    Cell::Number(r.return_percentage),
+                ])
+                .collect(),
+        },
+        Sheet {
+            name: "Allocation",
+            columns: vec![
+                column("Ticker", ColumnKind::Text, Total::None),
+                column("Value", ColumnKind::Money, Total::Sum),
+                column("Weight", ColumnKind::Percent, Total::Sum),
+            ],
+            rows: allocation
+                .rows
+                .into_iter()
+                .map(|r| vec![
+                    Cell::Text(r.ticker),
+                    Cell::Number(r.value),
+                    Cell::Number(r.percentage),
+                ])
+                .collect(),
+        },
+    ]
+}
+
+/// Write the sheets in the requested format. Returns the files that were
+/// created so the caller can report them.
+pub fn write_export(sheets: &[Sheet], output: &Path, format: ExportFormat) -> Result<Vec<PathBuf>> {
+    match format {
+        ExportFormat::Xlsx => {
+            write_xlsx(sheets, output)?;
+            Ok(vec![output.to_path_buf()])
+        }
+        ExportFormat::Ods => {
+            write_ods(sheets, output)?;
+            Ok(vec![output.to_path_buf()])
+        }
+        ExportFormat::Csv => write_csv_dir(sheets, output),
+    }
+}
+
+fn parse_date(value: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d").ok()
+}
+
+// ---------------------------------------------------------------------------
+// CSV
+// ---------------------------------------------------------------------------
+
+pub fn write_csv_dir(sheets: &[Sheet], dir: &Path) -> Result<Vec<PathBuf>> {
+    if dir.is_file() {
+        bail!("CSV export needs a directory, but {:?} is a file", dir);
+    }
+    fs::create_dir_all(dir)
+        .with_context(|| format!("Failed to create export directory: {:?}", dir))?;
+
+    let mut written = Vec::new();
+    for sheet in sheets {
+        let path = dir.join(format!("{}.csv", sheet.name.to_lowercase()));
+        let file = File::create(&path)
+            .with_context(|| format!("Failed to create CSV file: {:?}", path))?;
+        write_csv(sheet, file)?;
+        written.push(path);
+    }
+
+    Ok(written)
+}
+
+pub fn write_csv<W: Write>(sheet: &Sheet, writer: W) -> Result<()> {
+    let mut csv = csv::Writer::from_writer(writer);
+    csv.write_record(sheet.columns.iter().map(|c| c.header))?;
+
+    for row in &sheet.rows {
+        let record: Vec<String> = row
+            .iter()
+            .zip(&sheet.columns)
+            .map(|(cell, col)| csv_value(cell, col.kind))
+            .collect();
+        csv.write_record(&record)?;
+    }
+
+    if sheet.has_totals() {
+        let record: Vec<String> = (0..sheet.columns.len())
+            .map(|col| match sheet.total_value(col) {
+                Some(value) => csv_value(&Cell::Number(value), sheet.columns[col].kind),
+                None if col == 0 => "TOTAL".to_string(),
+                None => String::new(),
+            })
+            .collect();
+        csv.write_record(&record)?;
+    }
+
+    csv.flush()?;
+    Ok(())
+}
+
+fn csv_value(cell: &Cell, kind: ColumnKind) -> String {
+    match cell {
+        Cell::Text(text) => text.clone(),
+        Cell::Number(n) => match kind {
+            ColumnKind::Money | ColumnKind::Percent => format!("{:.2}", money::round(*n, 2)),
+            _ => n.to_string(),
+        },
+    }
+}
+
+// ---------------------------------------------------------------------------
+// XLSX
+// ---------------------------------------------------------------------------
+
+pub fn write_xlsx(sheets: &[Sheet], path: &Path) -> Result<()> {
+    let header = Format::new().set_bold();
+    let money = Format::new().set_num_format("#,##0.00");
+    let quantity = Format::new().set_num_format("#,##0.00######");
+    let count = Format::new().set_num_format("0");
+    let percent = Format::new().set_num_format("0.00%");
+    let date = Format::new().set_num_format("yyyy-mm-dd");
+
+    let number_format = |kind: ColumnKind, bold: bool| -> Format {
+        let format = match kind {
+            ColumnKind::Money => money.clone(),
+            ColumnKind::Quantity => quantity.clone(),
+            ColumnKind::Count => count.clone(),
+            ColumnKind::Percent => percent.clone(),
+            ColumnKind::Date => date.clone(),
+            ColumnKind::Text => Format::new(),
+        };
+        if bold { format.set_bold() } else { format }
+    };
+
+    let mut workbook = Workbook::new();
+
+    for sheet in sheets {
+        let worksheet = workbook.add_worksheet();
+        worksheet.set_name(sheet.name)?;
+
+        for (col, column) in sheet.columns.iter().enumerate() {
+            worksheet.write_string_with_format(0, col as u16, column.header, &header)?;
+        }
+
+        for (i, row) in sheet.rows.iter().enumerate() {
+            let r = i as u32 + 1;
+            for (col, cell) in row.iter().enumerate() {
+                let c = col as u16;
+                let kind = sheet.columns[col].kind;
+                match cell {
+                    Cell::Text(text) if kind == ColumnKind::Date && parse_date(text).is_some() => {
+                        let value = ExcelDateTime::parse_from_str(text)?;
+                        worksheet.write_date_with_format(r, c, &value, &date)?;
+                    }
+                    Cell::Text(text) => {
+                        worksheet.write_string(r, c, text)?;
+                    }
+                    Cell::Number(n) => {
+                        let value = money::to_f64(*n);
+                        let value = if kind == ColumnKind::Percent { value / 100.0 } else { value };
+                        worksheet.write_number_with_format(r, c, value, &number_format(kind, false))?;
+                    }
+                }
+            }
+        }
+
+        if sheet.has_totals() {
+            let first = 2; // 1-based spreadsheet row of the first data row
+            let last = sheet.rows.len() as u32 + 1;
+            let total_row = last;
+            let total_ref = |col: usize| format!("{}{}", column_number_to_name(col as u16), total_row + 1);
+
+            worksheet.write_string_with_format(total_row, 0, "TOTAL", &header)?;
+            for (col, column) in sheet.columns.iter().enumerate() {
+                let Some(value) = sheet.total_value(col) else { continue };
+                let cached = if column.kind == ColumnKind::Percent { value / Decimal::ONE_HUNDRED } else { value };
+                let letter = column_number_to_name(col as u16);
+                let formula = match column.total {
+                    Total::None => continue,
+                    Total::Sum if sheet.rows.is_empty() => "=0".to_string(),
+                    Total::Sum => format!("=SUM({letter}{first}:{letter}{last})"),
+                    Total::Ratio { numerator, denominator } => format!(
+                        "=IF({den}=0,0,{num}/{den})",
+                        num = total_ref(numerator),
+                        den = total_ref(denominator)
+                    ),
+                };
+                let formula = Formula::new(formula).set_result(cached.to_string());
+                worksheet.write_formula_with_format(total_row, col as u16, formula, &number_format(column.kind, true))?;
+            }
+        }
+
+        worksheet.set_freeze_panes(1, 0)?;
+        worksheet.autofit();
+    }
+
+    workbook
+        .save(path)
+        .with_context(|| format!("Failed to write XLSX file: {:?}", path))?;
+
+    Ok(())
+}
+
+// ---------------------------------------------------------------------------
+// ODS
+// ---------------------------------------------------------------------------
+
+const ODS_MIMETYPE: &str = "application/vnd.oasis.opendocument.spreadsheet";
+
+const ODS_MANIFEST: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<manifest:manifest xmlns:manifest="urn:oasis:names:tc:opendocument:xmlns:manifest:1.0" manifest:version="1.3">
+ <manifest:file-entry manifest:full-path="/" manifest:media-type="application/vnd.oasis.opendocument.spreadsheet"/>
+ <manifest:file-entry manifest:full-path="content.xml" manifest:media-type="text/xml"/>
+</manifest:manifest>
+"#;
+
+const ODS_STYLES: &str = r#"<office:automatic-styles>
+  <number:number-style style:name="N_COUNT"><number:number number:decimal-places="0"/></number:number-style>
+  <number:number-style style:name="N_MONEY"><number:number number:decimal-places="2" number:min-decimal-places="2" number:grouping="true"/></number:number-style>
+  <number:number-style style:name="N_QTY"><number:number number:decimal-places="8" number:min-decimal-places="2" number:grouping="true"/></number:number-style>
+  <number:percentage-style style:name="N_PCT"><number:number number:decimal-places="2" number:min-decimal-places="2"/><number:text>%</number:text></number:percentage-style>
+  <number:date-style style:name="N_DATE"><number:year number:style="long"/><number:text>-</number:text><number:month number:style="long"/><number:text>-</number:text><number:day number:style="long"/></number:date-style>
+  <style:style style:name="ce_header" style:family="table-cell"><style:text-properties fo:font-weight="bold"/></style:style>
+  <style:style style:name="ce_count" style:family="table-cell" style:data-style-name="N_COUNT"/>
+  <style:style style:name="ce_money" style:family="table-cell" style:data-style-name="N_MONEY"/>
+  <style:style style:name="ce_money_total" style:family="table-cell" style:data-style-name="N_MONEY"><style:text-properties fo:font-weight="bold"/></style:style>
+  <style:style style:name="ce_qty" style:family="table-cell" style:data-style-name="N_QTY"/>
+  <style:style style:name="ce_pct" style:family="table-cell" style:data-style-name="N_PCT"/>
+  <style:style style:name="ce_pct_total" style:family="table-cell" style:data-style-name="N_PCT"><style:text-properties fo:font-weight="bold"/></style:style>
+  <style:style style:name="ce_date" style:family="table-cell" style:data-style-name="N_DATE"/>
+ </office:automatic-styles>"#;
+
+pub fn write_ods(sheets: &[Sheet], path: &Path) -> Result<()> {
+    use zip::write::SimpleFileOptions;
+    use zip::CompressionMethod;
+
+    let file = File::create(path).with_context(|| format!("Failed to create ODS file: {:?}", path))?;
+    let mut zip = zip::ZipWriter::new(file);
+
+    // The mimetype entry must come first and be stored uncompressed so that
+    // tools can sniff the file type from a fixed offset.
+    let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+    let deflated = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    zip.start_file("mimetype", stored)?;
+    zip.write_all(ODS_MIMETYPE.as_bytes())?;
+    zip.start_file("META-INF/manifest.xml", deflated)?;
+    zip.write_all(ODS_MANIFEST.as_bytes())?;
+    zip.start_file("content.xml", deflated)?;
+    zip.write_all(ods_content(sheets).as_bytes())?;
+    zip.finish()
+        .with_context(|| format!("Failed to write ODS file: {:?}", path))?;
+
+    Ok(())
+}
+
+fn ods_content(sheets: &[Sheet]) -> String {
+    let mut xml = String::new();
+    xml.push_str(r#"<?xml version="1.0" encoding="UTF-8"?>
+<office:document-content xmlns:office="urn:oasis:names:tc:opendocument:xmlns:office:1.0" xmlns:style="urn:oasis:names:tc:opendocument:xmlns:style:1.0" xmlns:text="urn:oasis:names:tc:opendocument:xmlns:text:1.0" xmlns:table="urn:oasis:names:tc:opendocument:xmlns:table:1.0" xmlns:number="urn:oasis:names:tc:opendocument:xmlns:datastyle:1.0" xmlns:fo="urn:oasis:names:tc:opendocument:xmlns:xsl-fo-compatible:1.0" xmlns:of="urn:oasis:names:tc:opendocument:xmlns:of:1.2" office:version="1.3">
+ "#);
+    xml.push_str(ODS_STYLES);
+    xml.push_str("\n <office:body>\n  <office:spreadsheet>\n");
+
+    for sheet in sheets {
+        xml.push_str(&format!("   <table:table table:name=\"{}\">\n", xml_escape(sheet.name)));
+
+        xml.push_str("    <table:table-row>");
+        for column in &sheet.columns {
+            xml.push_str(&ods_string_cell(column.header, Some("ce_header")));
+        }
+        xml.push_str("</table:table-row>\n");
+
+        for row in &sheet.rows {
+            xml.push_str("    <table:table-row>");
+            for (cell, column) in row.iter().zip(&sheet.columns) {
+                xml.push_str(&ods_cell(cell, column.kind));
+            }
+            xml.push_str("</table:table-row>\n");
+        }
+
+        if sheet.has_totals() {
+            let first = 2;
+            let last = sheet.rows.len() + 1;
+            let total_row = last + 1;
+            let cell_ref = |col: usize, row: usize| {
+                format!("[.{}{}]", column_number_to_name(col as u16), row)
+            };
+
+            xml.push_str("    <table:table-row>");
+            for (col, column) in sheet.columns.iter().enumerate() {
+                let Some(value) = sheet.total_value(col) else {
+                    if col == 0 {
+                        xml.push_str(&ods_string_cell("TOTAL", Some("ce_header")));
+                    } else {
+                        xml.push_str("<table:table-cell/>");
+                    }
+                    continue;
+                };
+                let formula = match column.total {
+                    Total::None => unreachable!(),
+                    Total::Sum if sheet.rows.is_empty() => "of:=0".to_string(),
+                    Total::Sum => format!(
+                        "of:=SUM([.{l}{first}:.{l}{last}])",
+                        l = column_number_to_name(col as u16)
+                    ),
+                    Total::Ratio { numerator, denominator } => format!(
+                        "of:=IF({den}=0;0;{num}/{den})",
+                        num = cell_ref(numerator, total_row),
+                        den = cell_ref(denominator, total_row)
+                    ),
+                };
+                let (value_type, style, stored) = match column.kind {
+                    ColumnKind::Percent => ("percentage", "ce_pct_total", value / Decimal::ONE_HUNDRED),
+                    _ => ("float", "ce_money_total", value),
+                };
+                xml.push_str(&format!(
+                    "<table:table-cell table:style-name=\"{}\" table:formula=\"{}\" office:value-type=\"{}\" office:value=\"{}\"/>",
+                    style,
+                    xml_escape(&formula),
+                    value_type,
+                    stored
+                ));
+            }
+            xml.push_str("</table:table-row>\n");
+        }
+
+        xml.push_str("   </table:table>\n");
+    }
+
+    xml.push_str("  </office:spreadsheet>\n </office:body>\n</office:document-content>\n");
+    xml
+}
+
+fn ods_string_cell(text: &str, style: Option<&str>) -> String {
+    let style = style
+        .map(|s| format!(" table:style-name=\"{}\"", s))
+        .unwrap_or_default();
+    format!(
+        "<table:table-cell{} office:value-type=\"string\"><text:p>{}</text:p></table:table-cell>",
+        style,
+        xml_escape(text)
+    )
+}
+
+fn ods_cell(cell: &Cell, kind: ColumnKind) -> String {
+    match cell {
+        Cell::Text(text) if kind == ColumnKind::Date && parse_date(text).is_some() => format!(
+            "<table:table-cell table:style-name=\"ce_date\" office:value-type=\"date\" office:date-value=\"{0}\"><text:p>{0}</text:p></table:table-cell>",
+            xml_escape(text)
+        ),
+        Cell::Text(text) => ods_string_cell(text, None),
+        Cell::Number(n) => {
+            let (value_type, style, value) = match kind {
+                ColumnKind::Percent => ("percentage", "ce_pct", n / Decimal::ONE_HUNDRED),
+                ColumnKind::Quantity => ("float", "ce_qty", *n),
+                ColumnKind::Count => ("float", "ce_count", *n),
+                _ => ("float", "ce_money", *n),
+            };
+            format!(
+                "<table:table-cell table:style-name=\"{}\" office:value-type=\"{}\" office:value=\"{}\"/>",
+                style, value_type, value
+            )
+        }
+    }
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::portfolio::Holding;
+    use rust_decimal_macros::dec;
+    use std::io::Read;
+    use tempfile::tempdir;
+
+    fn sample() -> Vec<HoldingWithPrice> {
+        vec![
+            HoldingWithPrice {
+                holding: Holding {
+                    ticker: "AAPL".to_string(),
+                    quantity: dec!(10.0),
+                    cost_basis: dec!(150.0),
+                    date_purchased: "2023-01-01".to_string(),
+                },
+                current_price: dec!(170.0),
+            },
+            HoldingWithPrice {
+                holding: Holding {
+                    ticker: "BTC-USD".to_string(),
+                    quantity: dec!(0.5),
+                    cost_basis: dec!(45000.0),
+                    date_purchased: "2024-01-15".to_string(),
+                },
+                current_price: dec!(95000.0),
+            },
+        ]
+    }
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(ExportFormat::from_path(Path::new("out.xlsx")), ExportFormat::Xlsx);
+        assert_eq!(ExportFormat::from_path(Path::new("out.ODS")), ExportFormat::Ods);
+        assert_eq!(ExportFormat::from_path(Path::new("exports")), ExportFormat::Csv);
+    }
+
+    #[test]
+    fn test_build_sheets_names() {
+        let sheets = build_sheets(&sample(), &[], "USD");
+        let names: Vec<&str> = sheets.iter().map(|s| s.name).collect();
+        assert_eq!(names, vec!["Holdings", "Lots", "Transactions", "Performance", "Allocation"]);
+    }
+
+    #[test]
+    fn test_performance_ratio_total() {
+        let sheets = build_sheets(&sample(), &[], "USD");
+        let performance = sheets.iter().find(|s| s.name == "Performance").unwrap();
+        // gain = 200 + 25000, cost = 1500 + 22500
+        assert_eq!(performance.total_value(6).unwrap(), dec!(105));
+    }
+
+    #[test]
+    fn test_csv_includes_totals_row() {
+        let sheets = build_sheets(&sample(), &[], "USD");
+        let allocation = sheets.iter().find(|s| s.name == "Allocation").unwrap();
+        let mut buffer = Vec::new();
+        write_csv(allocation, &mut buffer).unwrap();
+        let text = String::from_utf8(buffer).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+
+        assert_eq!(lines[0], "Ticker,Value,Weight");
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[3], "TOTAL,49200.00,100.00");
+    }
+
+    #[test]
+    fn test_write_csv_dir_creates_one_file_per_sheet() {
+        let dir = tempdir().unwrap();
+        let out = dir.path().join("export");
+        let files = write_export(&build_sheets(&sample(), &[], "USD"), &out, ExportFormat::Csv).unwrap();
+        assert_eq!(files.len(), 5);
+        assert!(out.join("holdings.csv").exists());
+        assert!(out.join("transactions.csv").exists());
+    }
+
+    #[test]
+    fn test_write_xlsx() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("portfolio.xlsx");
+        write_export(&build_sheets(&sample(), &[], "USD"), &path, ExportFormat::Xlsx).unwrap();
+        assert!(fs::metadata(&path).unwrap().len() > 0);
+    }
+
+    #[test]
+    fn test_write_ods_layout() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("portfolio.ods");
+        write_export(&build_sheets(&sample(), &[], "USD"), &path, ExportFormat::Ods).unwrap();
+
+        let mut archive = zip::ZipArchive::new(File::open(&path).unwrap()).unwrap();
+        assert_eq!(archive.by_index(0).unwrap().name(), "mimetype");
+
+        let mut content = String::new();
+        archive.by_name("content.xml").unwrap().read_to_string(&mut content).unwrap();
+        assert!(content.contains("table:name=\"Lots\""));
+        assert!(content.contains("of:=SUM([.E2:.E3])"));
+        assert!(content.contains("office:date-value=\"2024-01-15\""));
+    }
+}
@@ -0,0 +1,435 @@
+use anyhow::{bail, Context, Result};
+use rust_decimal::Decimal;
+use serde_json::{Map, Number, Value};
+use std::path::Path;
+use std::sync::OnceLock;
+
+use crate::schema::PortfolioDocument;
+use crate::validation::{json_entry_lines, EntryLines};
+
+// Portfolio files can be written as JSON, TOML, YAML or CSV. Every format is
+// parsed into a `serde_json::Value` first, so schema migration and validation
+// work the same way no matter how the file was written.
+//
+// Amounts are `Decimal`s, and JSON and CSV keep every digit of them: numbers
+// go through serde_json's arbitrary precision mode, never through f64. TOML
+// and YAML numbers are 64-bit floats by nature.
+
+/// Name under which serde_json hands an exact number to serializers other
+/// than its own.
+const NUMBER_TOKEN: &str = "$serde_json::private::Number";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum FileFormat {
+    Json,
+    Toml,
+    Yaml,
+    /// Holdings only: ticker,quantity,cost_basis,date_purchased
+    Csv,
+}
+
+impl FileFormat {
+    /// Pick the format from the file extension, defaulting to JSON.
+    pub fn from_path(path: &Path) -> FileFormat {
+        match path.extension().and_then(|e| e.to_str()).map(|e| e.to_ascii_lowercase()).as_deref() {
+            Some("toml") => FileFormat::Toml,
+            Some("yaml") | Some("yml") => FileFormat::Yaml,
+            Some("csv") => FileFormat::Csv,
+            _ => FileFormat::Json,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            FileFormat::Json => "JSON",
+            FileFormat::Toml => "TOML",
+            FileFormat::Yaml => "YAML",
+            FileFormat::Csv => "CSV",
+        }
+    }
+}
+
+static INPUT_FORMAT: OnceLock<FileFormat> = OnceLock::new();
+
+/// Force the format of portfolio files instead of guessing from the
+/// extension. Called once from `main` for `--input-format`.
+pub fn set_input_format(format: FileFormat) {
+    let _ = INPUT_FORMAT.set(format);
+}
+
+/// The format to read (and write back) the portfolio file at `path` in.
+pub fn input_format_for(path: &Path) -> FileFormat {
+    INPUT_FORMAT.get().copied().unwrap_or_else(|| FileFormat::from_path(path))
+}
+
+/// Parse a portfolio file into its raw (not yet migrated) value, along with
+/// the source line of each holding and transaction where it can be found.
+pub fn parse(content: &str, format: FileFormat) -> Result<(Value, EntryLines)> {
+    match format {
+        FileFormat::Json => {
+            let value = serde_json::from_str(content)
+                .with_context(|| "Failed to parse portfolio JSON")?;
+            Ok((value, json_entry_lines(content)))
+        }
+        FileFormat::Toml => {
+            let table: toml::Table = content
+                .parse()
+                .with_context(|| "Failed to parse portfolio TOML")?;
+            Ok((toml_to_json(toml::Value::Table(table)), toml_entry_lines(content)))
+        }
+        FileFormat::Yaml => {
+            let value = serde_yaml::from_str(content)
+                .with_context(|| "Failed to parse portfolio YAML")?;
+            Ok((value, yaml_entry_lines(content)))
+        }
+        FileFormat::Csv => parse_csv(content),
+    }
+}
+
+/// Write a document in the given format.
+pub fn render(document: &PortfolioDocument, format: FileFormat) -> Result<String> {
+    let mut text = match format {
+        FileFormat::Json => serde_json::to_string_pretty(document)?,
+        FileFormat::Toml => toml::Value::try_from(document)
+            .map(toml_numbers)
+            .and_then(|value| toml::to_string_pretty(&value))
+            .with_context(|| "Failed to write portfolio TOML")?,
+        FileFormat::Yaml => serde_yaml::to_value(document)
+            .map(yaml_numbers)
+            .and_then(|value| serde_yaml::to_string(&value))
+            .with_context(|| "Failed to write portfolio YAML")?,
+        FileFormat::Csv => render_csv(document)?,
+    };
+    if !text.ends_with('\n') {
+        text.push('\n');
+    }
+    Ok(text)
+}
+
+fn toml_to_json(value: toml::Value) -> Value {
+    match value {
+        toml::Value::String(s) => Value::String(s),
+        toml::Value::Integer(i) => Value::Number(i.into()),
+        toml::Value::Float(f) => Number::from_f64(f).map(Value::Number).unwrap_or(Value::Null),
+        toml::Value::Boolean(b) => Value::Bool(b),
+        // Unquoted TOML dates (date_purchased = 2024-06-01) become plain strings
+        toml::Value::Datetime(d) => Value::String(d.to_string()),
+        toml::Value::Array(items) => Value::Array(items.into_iter().map(toml_to_json).collect()),
+        toml::Value::Table(table) => Value::Object(
+            table.into_iter().map(|(k, v)| (k, toml_to_json(v))).collect(),
+        ),
+    }
+}
+
+/// Turn the exact numbers serde_json passes through as one-key tables back
+/// into TOML numbers.
+fn toml_numbers(value: toml::Value) -> toml::Value {
+    match value {
+        toml::Value::Table(table) => {
+            if let (1, Some(toml::Value::String(number))) = (table.len(), table.get(NUMBER_TOKEN)) {
+                return match number.parse::<i64>() {
+                    Ok(integer) => toml::Value::Integer(integer),
+                    Err(_) => number
+                        .parse::<f64>()
+                        .map(toml::Value::Float)
+                        .unwrap_or_else(|_| toml::Value::String(number.clone())),
+                };
+            }
+            toml::Value::Table(table.into_iter().map(|(k, v)| (k, toml_numbers(v))).collect())
+        }
+        toml::Value::Array(items) => toml::Value::Array(items.into_iter().map(toml_numbers).collect()),
+        other => other,
+    }
+}
+
+/// The same for YAML.
+fn yaml_numbers(value: serde_yaml::Value) -> serde_yaml::Value {
+    match value {
+        serde_yaml::Value::Mapping(mapping) => {
+            if let (1, Some(serde_yaml::Value::String(number))) = (mapping.len(), mapping.get(NUMBER_TOKEN)) {
+                return number
+                    .parse::<serde_yaml::Number>()
+                    .map(serde_yaml::Value::Number)
+                    .unwrap_or_else(|_| serde_yaml::Value::String(number.clone()));
+            }
+            serde_yaml::Value::Mapping(mapping.into_iter().map(|(k, v)| (k, yaml_numbers(v))).collect())
+        }
+        serde_yaml::Value::Sequence(items) => serde_yaml::Value::Sequence(items.into_iter().map(yaml_numbers).collect()),
+        other => other,
+    }
+}
+
+fn toml_entry_lines(content: &str) -> EntryLines {
+    let mut lines = EntryLines::default();
+    for (i, line) in content.lines().enumerate() {
+        match line.trim() {
+            "[[holdings]]" => lines.holdings.push(i + 1),
+            "[[transactions]]" => lines.transactions.push(i + 1),
+            "[[watchlist]]" => lines.watchlist.push(i + 1),
+            _ => {}
+        }
+    }
+    lines
+}
+
+/// Line of each `- ` item under the top-level `holdings:`, `transactions:`
+/// and `watchlist:` keys. A bare top-level list is the holdings list.
+fn yaml_entry_lines(content: &str) -> EntryLines {
+    #[derive(PartialEq)]
+    enum Section { Holdings, Transactions, Watchlist, Other }
+
+    let mut lines = EntryLines::default();
+    let mut section = Section::Holdings;
+    let mut item_indent: Option<usize> = None;
+
+    for (i, line) in content.lines().enumerate() {
+        let trimmed = line.trim_start();
+        if trimmed.is_empty() || trimmed.starts_with('#') || trimmed == "---" {
+            continue;
+        }
+        let indent = line.len() - trimmed.len();
+
+        if trimmed == "-" || trimmed.starts_with("- ") {
+            if item_indent.is_none() {
+                item_indent = Some(indent);
+            }
+            if item_indent == Some(indent) {
+                match section {
+                    Section::Holdings => lines.holdings.push(i + 1),
+                    Section::Transactions => lines.transactions.push(i + 1),
+                    Section::Watchlist => lines.watchlist.push(i + 1),
+                    Section::Other => {}
+                }
+            }
+        } else if indent == 0 {
+            let key = trimmed.split(':').next().unwrap_or("").trim().trim_matches('"');
+            section = match key {
+                "holdings" => Section::Holdings,
+                "transactions" => Section::Transactions,
+                "watchlist" => Section::Watchlist,
+                _ => Section::Other,
+            };
+            item_indent = None;
+        }
+    }
+
+    lines
+}
+
+pub const CSV_COLUMNS: [&str; 4] = ["ticker", "quantity", "cost_basis", "date_purchased"];
+
+fn parse_csv(content: &str) -> Result<(Value, EntryLines)> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(content.as_bytes());
+    let headers: Vec<String> = reader
+        .headers()
+        .with_context(|| "Failed to parse portfolio CSV header")?
+        .iter()
+        .map(|h| h.to_lowercase())
+        .collect();
+
+    let mut holdings = Vec::new();
+    let mut lines = EntryLines::default();
+
+    for record in reader.records() {
+        let record = record.with_context(|| "Failed to parse portfolio CSV")?;
+        lines.holdings.push(record.position().map(|p| p.line() as usize).unwrap_or(0));
+
+        let mut entry = Map::new();
+        for (header, field) in headers.iter().zip(record.iter()) {
+            if field.is_empty() {
+                continue;
+            }
+            // Numbers that do not parse stay strings so validation can point at them
+            let value = match header.as_str() {
+                "quantity" | "cost_basis" => field
+                    .parse::<Decimal>()
+                    .ok()
+                    .and_then(|amount| amount.to_string().parse::<Number>().ok())
+                    .map(Value::Number)
+                    .unwrap_or_else(|| Value::String(field.to_string())),
+                _ => Value::String(field.to_string()),
+            };
+            entry.insert(header.clone(), value);
+        }
+        holdings.push(Value::Object(entry));
+    }
+
+    Ok((Value::Array(holdings), lines))
+}
+
+fn render_csv(document: &PortfolioDocument) -> Result<String> {
+    if !document.transactions.is_empty() {
+        bail!(
+            "CSV portfolio files only hold holdings; {} transaction(s) would be lost (use JSON, TOML or YAML)",
+            document.transactions.len()
+        );
+    }
+    if !document.watchlist.is_empty() {
+        bail!(
+            "CSV portfolio files only hold holdings; {} watchlist entr(ies) would be lost (use JSON, TOML or YAML)",
+            document.watchlist.len()
+        );
+    }
+    // A CSV file is read back in the default currency
+    let default_currency = crate::schema::default_base_currency();
+    if document.base_currency != default_currency {
+        bail!(
+            "CSV portfolio files have no base currency; {} would be read back as {} (use JSON, TOML or YAML)",
+            document.base_currency,
+            default_currency
+        );
+    }
+
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record(CSV_COLUMNS)?;
+    for holding in &document.holdings {
+        writer.write_record([
+            holding.ticker.clone(),
+            holding.quantity.to_string(),
+            holding.cost_basis.to_string(),
+            holding.date_purchased.clone(),
+        ])?;
+    }
+
+    Ok(String::from_utf8(writer.into_inner()?)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::portfolio::Portfolio;
+    use rust_decimal_macros::dec;
+    use serde_json::json;
+
+    fn sample_document() -> PortfolioDocument {
+        Portfolio::from_str_with_format(
+            r#"{
+                "schema_version": 2,
+                "base_currency": "USD",
+                "holdings": [
+                    {"ticker": "AAPL", "quantity": 10, "cost_basis": 150, "date_purchased": "2024-06-01"},
+                    {"ticker": "BTC-USD", "quantity": 0.5, "cost_basis": 45000, "date_purchased": "2024-01-15"}
+                ]
+            }"#,
+            FileFormat::Json,
+        )
+        .unwrap()
+        .to_document()
+    }
+
+    #[test]
+    fn test_from_path() {
+        assert_eq!(FileFormat::from_path(Path::new("p.toml")), FileFormat::Toml);
+        assert_eq!(FileFormat::from_path(Path::new("p.YML")), FileFormat::Yaml);
+        assert_eq!(FileFormat::from_path(Path::new("p.csv")), FileFormat::Csv);
+        assert_eq!(FileFormat::from_path(Path::new("p.json")), FileFormat::Json);
+        assert_eq!(FileFormat::from_path(Path::new("portfolio")), FileFormat::Json);
+    }
+
+    #[test]
+    fn test_round_trip_every_format() {
+        let document = sample_document();
+        for format in [FileFormat::Json, FileFormat::Toml, FileFormat::Yaml, FileFormat::Csv] {
+            let text = render(&document, format).unwrap();
+            let portfolio = Portfolio::from_str_with_format(&text, format)
+                .unwrap_or_else(|e| panic!("{:?}: {:#}\n{}", format, e, text));
+            assert_eq!(portfolio.holdings.len(), 2, "{:?}", format);
+            assert_eq!(portfolio.holdings[1].ticker, "BTC-USD");
+            assert_eq!(portfolio.holdings[1].quantity, dec!(0.5));
+            assert_eq!(portfolio.holdings[1].date_purchased, "2024-01-15");
+        }
+    }
+
+    #[test]
+    fn test_json_and_csv_keep_every_digit() {
+        let json_text = r#"{"schema_version": 3, "holdings": [
+            {"ticker": "ETH-USD", "quantity": 1.234567890123456789, "cost_basis": 0.1, "date_purchased": "2024-01-15"},
+            {"ticker": "AAPL", "quantity": 123456789012.345678, "cost_basis": 0.2, "date_purchased": "2024-06-01"}
+        ]}"#;
+        let csv_text = "ticker,quantity,cost_basis,date_purchased\nETH-USD,1.234567890123456789,0.1,2024-01-15\nAAPL,123456789012.345678,0.2,2024-06-01\n";
+        for (text, format) in [(json_text, FileFormat::Json), (csv_text, FileFormat::Csv)] {
+            let portfolio = Portfolio::from_str_with_format(text, format).unwrap();
+            assert_eq!(portfolio.holdings[0].quantity, dec!(1.234567890123456789), "{:?}", format);
+            assert_eq!(portfolio.holdings[1].quantity, dec!(123456789012.345678), "{:?}", format);
+            assert_eq!(portfolio.holdings[0].cost_basis + portfolio.holdings[1].cost_basis, dec!(0.3));
+
+            let rendered = render(&portfolio.to_document(), format).unwrap();
+            assert!(rendered.contains("1.234567890123456789"), "{:?}: {}", format, rendered);
+            let again = Portfolio::from_str_with_format(&rendered, format).unwrap();
+            assert_eq!(again.holdings[1].quantity, dec!(123456789012.345678), "{:?}", format);
+        }
+    }
+
+    #[test]
+    fn test_toml_unquoted_dates_and_lines() {
+        let text = r#"schema_version = 2
+
+[[holdings]]
+ticker = "AAPL"
+quantity = 10
+cost_basis = 150.0
+date_purchased = 2024-06-01
+
+[[holdings]]
+ticker = "MSFT"
+quantity = 15
+cost_basis = 300.0
+date_purchased = "2024-03-20"
+"#;
+        let (value, lines) = parse(text, FileFormat::Toml).unwrap();
+        assert_eq!(value["holdings"][0]["date_purchased"], json!("2024-06-01"));
+        assert_eq!(lines.holdings, vec![3, 9]);
+    }
+
+    #[test]
+    fn test_yaml_lines() {
+        let text = "schema_version: 2\nholdings:\n- ticker: AAPL\n  quantity: 10\n- ticker: MSFT\n  quantity: 1\ntransactions:\n  - date: 2024-01-01\n    kind: deposit\nwatchlist:\n- ticker: NVDA\n";
+        let (value, lines) = parse(text, FileFormat::Yaml).unwrap();
+        assert_eq!(value["transactions"][0]["date"], json!("2024-01-01"));
+        assert_eq!(lines.holdings, vec![3, 5]);
+        assert_eq!(lines.transactions, vec![8]);
+        assert_eq!(lines.watchlist, vec![11]);
+    }
+
+    #[test]
+    fn test_yaml_bare_list_is_holdings() {
+        let lines = yaml_entry_lines("- ticker: AAPL\n  quantity: 1\n- ticker: TSLA\n");
+        assert_eq!(lines.holdings, vec![1, 3]);
+    }
+
+    #[test]
+    fn test_csv_bad_number_kept_for_validation() {
+        let text = "ticker,quantity,cost_basis,date_purchased\nAAPL,ten,150,2024-06-01\n";
+        let (value, lines) = parse(text, FileFormat::Csv).unwrap();
+        assert_eq!(value[0]["quantity"], json!("ten"));
+        assert_eq!(lines.holdings, vec![2]);
+        assert!(Portfolio::from_str_with_format(text, FileFormat::Csv).is_err());
+    }
+
+    #[test]
+    fn test_csv_refuses_to_drop_transactions() {
+        let mut document = sample_document();
+        document.transactions.push(crate::portfolio::Transaction {
+            date: "2024-01-01".to_string(),
+            kind: crate::portfolio::TransactionKind::Deposit,
+            ticker: None,
+            quantity: rust_decimal::Decimal::ZERO,
+            price: dec!(100),
+            fees: rust_decimal::Decimal::ZERO,
+            note: None,
+        });
+        assert!(render(&document, FileFormat::Csv).is_err());
+        assert!(render(&document, FileFormat::Toml).is_ok());
+    }
+
+    #[test]
+    fn test_csv_refuses_to_drop_base_currency() {
+        let mut document = sample_document();
+        document.base_currency = "EUR".to_string();
+        let error = render(&document, FileFormat::Csv).unwrap_err().to_string();
+        assert!(error.contains("EUR would be read back as USD"), "{}", error);
+        assert!(render(&document, FileFormat::Yaml).is_ok());
+    }
+}
@@ -0,0 +1,319 @@
+use anyhow::{Context, Result};
+use chrono::{Datelike, Duration, NaiveDate, Weekday};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::api::{fetch_prices_with_failures, price_provider, PriceProvider};
+use crate::money;
+
+// Daily closing prices for the analytics commands (risk, correlation,
+// projections, backtests). Like the quote API this is a demo: well-known
+// tickers get a synthetic but deterministic history that ends at today's
+// mock quote, so results are reproducible and the same day always has the
+// same close. Other tickers are looked up on Alpha Vantage, following the
+// configured price provider: `alpha-vantage` looks up every ticker there and
+// `offline` none.
+
+/// Trading days per year, used to annualize daily statistics.
+pub const TRADING_DAYS: f64 = 252.0;
+
+/// Closing price per trading day.
+pub type PriceSeries = BTreeMap<NaiveDate, Decimal>;
+
+/// How a synthetic history moves: expected annual return, sensitivity to
+/// the shared market factor and annual volatility of its own moves.
+#[derive(Debug, Clone, Copy)]
+struct Profile {
+    drift: f64,
+    beta: f64,
+    volatility: f64,
+}
+
+const MARKET_VOLATILITY: f64 = 0.16;
+
+fn mock_profile(ticker: &str) -> Option<Profile> {
+    let (drift, beta, volatility) = match ticker {
+        "AAPL" => (0.15, 1.1, 0.18),
+        "TSLA" => (0.25, 1.8, 0.45),
+        "GOOGL" => (0.12, 1.05, 0.20),
+        "MSFT" => (0.14, 1.0, 0.16),
+        "AMZN" => (0.13, 1.2, 0.22),
+        "NVDA" => (0.35, 1.7, 0.35),
+        "META" => (0.18, 1.3, 0.28),
+        "BRK.B" => (0.09, 0.8, 0.10),
+        "JPM" => (0.08, 1.1, 0.15),
+        "V" => (0.10, 0.9, 0.14),
+        "BTC-USD" => (0.40, 1.6, 0.60),
+        "ETH-USD" => (0.35, 1.8, 0.70),
+        "BNB-USD" => (0.30, 1.5, 0.65),
+        "ADA-USD" => (0.10, 1.9, 0.85),
+        "SOL-USD" => (0.45, 2.0, 0.90),
+        // Index funds used as benchmarks
+        "SPY" => (0.10, 1.0, 0.01),
+        "VTI" => (0.10, 1.02, 0.015),
+        "QQQ" => (0.13, 1.2, 0.06),
+        "AGG" | "BND" => (0.03, 0.05, 0.05),
+        "GLD" => (0.06, 0.1, 0.14),
+        _ => return None,
+    };
+    Some(Profile { drift, beta, volatility })
+}
+
+pub fn is_trading_day(date: NaiveDate) -> bool {
+    !matches!(date.weekday(), Weekday::Sat | Weekday::Sun)
+}
+
+/// Daily closes between `from` and `to` (inclusive) for each ticker. Fails
+/// for a ticker that has neither a demo profile nor a real history, rather
+/// than computing statistics from made-up prices.
+pub async fn fetch_price_history(
+    tickers: &[String],
+    from: NaiveDate,
+    to: NaiveDate,
+) -> Result<HashMap<String, PriceSeries>> {
+    if from > to {
+        anyhow::bail!("History start {} is after its end {}", from, to);
+    }
+    let today = chrono::Utc::now().date_naive();
+    let to = to.min(today);
+    let (provider, api_key) = price_provider();
+    let profile = |ticker: &str| match provider {
+        PriceProvider::AlphaVantage => None,
+        PriceProvider::Demo | PriceProvider::Offline => mock_profile(ticker),
+    };
+
+    // Only synthetic histories need today's quote to end at
+    let anchored: Vec<String> = tickers.iter().filter(|t| profile(t).is_some()).cloned().collect();
+    let (latest, failed) = fetch_prices_with_failures(&anchored).await?;
+    if !failed.is_empty() {
+        anyhow::bail!("Could not fetch quotes for {}", failed.join(", "));
+    }
+
+    let mut history = HashMap::new();
+    for ticker in tickers {
+        if history.contains_key(ticker) {
+            continue;
+        }
+        let series = match profile(ticker) {
+            Some(profile) => synthetic_series(ticker, profile, latest[ticker], today, from, to),
+            None => {
+                let real = match provider {
+                    PriceProvider::Offline => Err(anyhow::anyhow!("The offline price provider has no history for {}", ticker)),
+                    _ => fetch_real_history(ticker, api_key).await,
+                };
+                let series = real.with_context(|| format!("Could not fetch price history for {}", ticker))?;
+                series.range(from..=to).map(|(d, p)| (*d, *p)).collect()
+            }
+        };
+        history.insert(ticker.clone(), series);
+    }
+
+    Ok(history)
+}
+
+/// Walk back from `anchor_price` on `anchor`, undoing each day's return.
+/// Each day's return depends only on the ticker and the date, so a day's
+/// close is the same whatever range is asked for.
+fn synthetic_series(
+    ticker: &str,
+    profile: Profile,
+    anchor_price: Decimal,
+    anchor: NaiveDate,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> PriceSeries {
+    let daily_drift = (profile.drift
+        - 0.5 * (profile.beta * profile.beta * MARKET_VOLATILITY * MARKET_VOLATILITY + profile.volatility * profile.volatility))
+        / TRADING_DAYS;
+    let market_scale = profile.beta * MARKET_VOLATILITY / TRADING_DAYS.sqrt();
+    let own_scale = profile.volatility / TRADING_DAYS.sqrt();
+    let seed = ticker_seed(ticker);
+    let decimals = if money::is_crypto(ticker) { 6 } else { 4 };
+
+    let mut series = PriceSeries::new();
+    let mut price = money::to_f64(anchor_price);
+    let mut date = anchor;
+    while date >= from {
+        if is_trading_day(date) {
+            if date <= to {
+                series.insert(date, money::round(money::from_f64(price), decimals));
+            }
+            let day = date.num_days_from_ce() as u64;
+            let log_return = daily_drift
+                + market_scale * normal(MARKET_SEED, day)
+                + own_scale * normal(seed, day);
+            price /= log_return.exp();
+        }
+        date -= Duration::days(1);
+    }
+    series
+}
+
+const MARKET_SEED: u64 = 0x6d61_726b_6574;
+
+fn ticker_seed(ticker: &str) -> u64 {
+    // FNV-1a: stable across runs and platforms, unlike the std hasher
+    ticker.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, b| (hash ^ b as u64).wrapping_mul(0x0100_0000_01b3))
+}
+
+fn splitmix64(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+fn uniform(seed: u64, n: u64) -> f64 {
+    // 53 random bits in (0, 1]
+    ((splitmix64(seed ^ splitmix64(n)) >> 11) as f64 + 1.0) / (1u64 << 53) as f64
+}
+
+/// Standard normal draw for (seed, n), via Box-Muller.
+fn normal(seed: u64, n: u64) -> f64 {
+    let u1 = uniform(seed, 2 * n);
+    let u2 = uniform(seed, 2 * n + 1);
+    (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()
+}
+
+#[derive(Debug, Deserialize)]
+struct DailySeriesResponse {
+    #[serde(rename = "Time Series (Daily)")]
+    series: Option<BTreeMap<String, DailyBar>>,
+    #[serde(rename = "Error Message")]
+    error_message: Option<String>,
+    #[serde(rename = "Note")]
+    note: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DailyBar {
+    #[serde(rename = "4. close")]
+    close: String,
+}
+
+async fn fetch_real_history(ticker: &str, api_key: &str) -> Result<PriceSeries> {
+    let client = reqwest::Client::new();
+    let url = format!(
+        "https://www.alphavantage.co/query?function=TIME_SERIES_DAILY&outputsize=full&symbol={}&apikey={}",
+        ticker, api_key
+    );
+
+    let response: DailySeriesResponse = client
+        .get(&url)
+        .send()
+        .await
+        .context("Failed to make API request")?
+        .json()
+        .await
+        .context("Failed to parse API response")?;
+
+    if let Some(error) = response.error_message {
+        anyhow::bail!("API Error: {}", error);
+    }
+    if let Some(note) = response.note {
+        anyhow::bail!("API Note: {}", note);
+    }
+
+    let bars = response
+        .series
+        .with_context(|| format!("No price history found for ticker: {}", ticker))?;
+    bars.into_iter()
+        .map(|(date, bar)| {
+            let date = NaiveDate::parse_from_str(&date, "%Y-%m-%d")
+                .with_context(|| format!("Invalid date in price history: {}", date))?;
+            let close: Decimal = bar.close.parse().context("Failed to parse price as number")?;
+            Ok((date, close))
+        })
+        .collect()
+}
+
+/// Closing prices of several series on the dates they all have, as floats
+/// for the statistics. Returns the dates and one column per series.
+pub fn align(series: &[&PriceSeries]) -> (Vec<NaiveDate>, Vec<Vec<f64>>) {
+    let Some(first) = series.first() else {
+        return (Vec::new(), Vec::new());
+    };
+    let dates: Vec<NaiveDate> = first
+        .keys()
+        .filter(|date| series.iter().all(|s| s.contains_key(date)))
+        .copied()
+        .collect();
+    let columns = series
+        .iter()
+        .map(|s| dates.iter().map(|d| money::to_f64(s[d])).collect())
+        .collect();
+    (dates, columns)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn test_synthetic_series_ends_at_anchor_and_skips_weekends() {
+        let profile = mock_profile("AAPL").unwrap();
+        let anchor = date(2024, 6, 7); // a Friday
+        let series = synthetic_series("AAPL", profile, dec!(170), anchor, date(2024, 6, 1), anchor);
+
+        assert_eq!(series[&anchor], dec!(170));
+        assert_eq!(series.len(), 5);
+        assert!(series.keys().all(|d| is_trading_day(*d)));
+    }
+
+    #[test]
+    fn test_synthetic_series_is_deterministic_across_ranges() {
+        let profile = mock_profile("TSLA").unwrap();
+        let anchor = date(2024, 6, 7);
+        let long = synthetic_series("TSLA", profile, dec!(700), anchor, date(2023, 1, 1), anchor);
+        let short = synthetic_series("TSLA", profile, dec!(700), anchor, date(2024, 1, 1), date(2024, 3, 1));
+
+        assert!(!short.is_empty());
+        for (day, close) in &short {
+            assert_eq!(long[day], *close);
+        }
+    }
+
+    #[test]
+    fn test_normal_draws_look_standard() {
+        let draws: Vec<f64> = (0..20_000).map(|n| normal(42, n)).collect();
+        let mean = draws.iter().sum::<f64>() / draws.len() as f64;
+        let var = draws.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / draws.len() as f64;
+        assert!(mean.abs() < 0.03, "mean {}", mean);
+        assert!((var - 1.0).abs() < 0.05, "variance {}", var);
+    }
+
+    #[test]
+    fn test_align_uses_common_dates() {
+        let a: PriceSeries = [(date(2024, 1, 2), dec!(1)), (date(2024, 1, 3), dec!(2))].into();
+        let b: PriceSeries = [(date(2024, 1, 3), dec!(5)), (date(2024, 1, 4), dec!(6))].into();
+
+        let (dates, columns) = align(&[&a, &b]);
+        assert_eq!(dates, vec![date(2024, 1, 3)]);
+        assert_eq!(columns, vec![vec![2.0], vec![5.0]]);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_price_history_known_tickers() {
+        let to = chrono::Utc::now().date_naive();
+        let from = to - Duration::days(30);
+        let history = fetch_price_history(&["AAPL".to_string(), "SPY".to_string()], from, to).await.unwrap();
+
+        assert_eq!(history.len(), 2);
+        assert!(history["AAPL"].len() >= 15);
+        assert_eq!(history["AAPL"].keys().collect::<Vec<_>>(), history["SPY"].keys().collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn test_unknown_ticker_fails_on_its_history() {
+        let to = chrono::Utc::now().date_naive();
+        let from = to - Duration::days(30);
+        let error = fetch_price_history(&["AAPL".to_string(), "ZZZQ".to_string()], from, to).await.unwrap_err();
+        assert_eq!(error.to_string(), "Could not fetch price history for ZZZQ");
+    }
+}
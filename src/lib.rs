@@ -2,3 +2,31 @@
 pub mod portfolio;
 pub mod api;
 pub mod commands;
+pub mod money;
+pub mod stats;
+pub mod history;
+pub mod risk;
+pub mod benchmark;
+pub mod projection;
+pub mod backtest;
+pub mod alerts;
+pub mod notify;
+pub mod dashboard;
+pub mod live;
+pub mod shell;
+pub mod completions;
+pub mod config;
+pub mod charts;
+pub mod server;
+pub mod rest;
+pub mod metrics;
+pub mod daemon;
+pub mod reports;
+pub mod export;
+pub mod crypto;
+pub mod schema;
+pub mod validation;
+pub mod formats;
+pub mod storage;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
@@ -0,0 +1,221 @@
+use anyhow::Result;
+use chrono::Local;
+use colored::*;
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::api::fetch_current_prices;
+use crate::portfolio::{HoldingWithPrice, Portfolio};
+
+// Watch mode for the report commands. `Ticks` hands the command a freshly
+// priced portfolio once, or with an interval over and over: after each
+// report it waits for the interval to pass or for the portfolio file to
+// change on disk, clears the screen and prices the portfolio again. Ctrl-C
+// ends the loop.
+
+/// How often the portfolio file's modification time is checked.
+const FILE_POLL: Duration = Duration::from_millis(500);
+
+/// Parse an interval such as `30s`, `5m`, `1h` or `45` (seconds).
+pub fn parse_interval(text: &str) -> Result<Duration, String> {
+    let text = text.trim();
+    let split = text.find(|c: char| !c.is_ascii_digit()).unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("invalid interval '{}' (expected e.g. 30s, 5m or 1h)", text))?;
+    let seconds = match unit.trim() {
+        "" | "s" | "sec" | "secs" => number,
+        "m" | "min" | "mins" => number * 60,
+        "h" | "hr" | "hrs" => number * 3600,
+        other => return Err(format!("unknown interval unit '{}' (use s, m or h)", other)),
+    };
+    if seconds == 0 {
+        return Err("the interval must be at least one second".to_string());
+    }
+    Ok(Duration::from_secs(seconds))
+}
+
+/// Why the next report was drawn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trigger {
+    First,
+    Interval,
+    FileChanged,
+}
+
+pub struct Tick {
+    pub portfolio: Portfolio,
+    pub holdings: Vec<HoldingWithPrice>,
+    pub trigger: Trigger,
+}
+
+pub struct Ticks {
+    path: PathBuf,
+    interval: Option<Duration>,
+    portfolio: Option<Portfolio>,
+    modified: Option<SystemTime>,
+    /// Shown under the next report, e.g. a reload that failed.
+    problem: Option<String>,
+}
+
+impl Ticks {
+    /// With no interval the portfolio is priced exactly once.
+    pub fn new(path: &Path, interval: Option<Duration>) -> Ticks {
+        Ticks {
+            path: path.to_path_buf(),
+            interval,
+            portfolio: None,
+            modified: None,
+            problem: None,
+        }
+    }
+
+    pub fn watching(&self) -> bool {
+        self.interval.is_some()
+    }
+
+    /// The next priced portfolio, or `None` once the loop is over.
+    pub async fn next(&mut self) -> Result<Option<Tick>> {
+        let trigger = match (&self.portfolio, self.interval) {
+            (None, _) => Trigger::First,
+            (Some(_), None) => return Ok(None),
+            (Some(_), Some(interval)) => {
+                self.print_footer(interval);
+                tokio::select! {
+                    _ = tokio::time::sleep(interval) => Trigger::Interval,
+                    _ = self.file_changed() => Trigger::FileChanged,
+                    _ = tokio::signal::ctrl_c() => return Ok(None),
+                }
+            }
+        };
+
+        self.reload(trigger)?;
+        let portfolio = self.portfolio.clone().expect("portfolio is loaded");
+        let prices = fetch_current_prices(&portfolio.get_tickers()).await?;
+        let holdings = portfolio.holdings_with_prices(&prices);
+        if self.watching() && std::io::stdout().is_terminal() {
+            // Clear the screen and move to the top left, so the report is
+            // redrawn in place
+            print!("\x1b[2J\x1b[H");
+        }
+        Ok(Some(Tick { portfolio, holdings, trigger }))
+    }
+
+    /// Load the portfolio the first time and whenever the file changed.
+    /// A reload that fails keeps the last good portfolio on screen.
+    fn reload(&mut self, trigger: Trigger) -> Result<()> {
+        let modified = modified_time(&self.path);
+        if self.portfolio.is_some() && modified == self.modified {
+            return Ok(());
+        }
+        self.modified = modified;
+        match Portfolio::load_from_file(&self.path) {
+            Ok(portfolio) => {
+                self.portfolio = Some(portfolio);
+                self.problem = None;
+            }
+            Err(err) if trigger != Trigger::First => {
+                self.problem = Some(format!("Could not reload {}: {:#}", self.path.display(), err));
+            }
+            Err(err) => return Err(err),
+        }
+        Ok(())
+    }
+
+    async fn file_changed(&self) {
+        loop {
+            tokio::time::sleep(FILE_POLL).await;
+            if modified_time(&self.path) != self.modified {
+                return;
+            }
+        }
+    }
+
+    fn print_footer(&self, interval: Duration) {
+        println!();
+        if let Some(problem) = &self.problem {
+            println!("{}", problem.red());
+        }
+        println!("{}", format!("Updated {} · refreshing every {}s or when {} changes · Ctrl-C to stop",
+                               Local::now().format("%H:%M:%S"),
+                               interval.as_secs(),
+                               self.path.display()).dimmed());
+        let _ = std::io::stdout().flush();
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_parse_interval_units() {
+        assert_eq!(parse_interval("45"), Ok(Duration::from_secs(45)));
+        assert_eq!(parse_interval("30s"), Ok(Duration::from_secs(30)));
+        assert_eq!(parse_interval("5m"), Ok(Duration::from_secs(300)));
+        assert_eq!(parse_interval("1h"), Ok(Duration::from_secs(3600)));
+        assert!(parse_interval("0s").is_err());
+        assert!(parse_interval("5d").is_err());
+        assert!(parse_interval("soon").is_err());
+    }
+
+    const PORTFOLIO: &str = r#"[{"ticker": "AAPL", "quantity": 10, "cost_basis": 150, "date_purchased": "2024-01-01"}]"#;
+
+    #[tokio::test]
+    async fn test_single_tick_without_interval() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("portfolio.json");
+        fs::write(&path, PORTFOLIO).unwrap();
+
+        let mut ticks = Ticks::new(&path, None);
+        let tick = ticks.next().await.unwrap().unwrap();
+        assert_eq!(tick.trigger, Trigger::First);
+        assert_eq!(tick.holdings.len(), 1);
+        assert!(ticks.next().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_file_change_reloads_before_interval() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("portfolio.json");
+        fs::write(&path, PORTFOLIO).unwrap();
+
+        let mut ticks = Ticks::new(&path, Some(Duration::from_secs(3600)));
+        ticks.next().await.unwrap().unwrap();
+
+        // Make sure the modification time moves even on coarse filesystems
+        let later = SystemTime::now() + Duration::from_secs(5);
+        let two_lots = PORTFOLIO.replace("}]", r#"}, {"ticker": "MSFT", "quantity": 1, "cost_basis": 300, "date_purchased": "2024-01-01"}]"#);
+        fs::write(&path, two_lots).unwrap();
+        fs::File::options().write(true).open(&path).unwrap().set_modified(later).unwrap();
+
+        let tick = tokio::time::timeout(Duration::from_secs(10), ticks.next()).await.unwrap().unwrap().unwrap();
+        assert_eq!(tick.trigger, Trigger::FileChanged);
+        assert_eq!(tick.holdings.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_broken_reload_keeps_last_portfolio() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("portfolio.json");
+        fs::write(&path, PORTFOLIO).unwrap();
+
+        let mut ticks = Ticks::new(&path, Some(Duration::from_secs(3600)));
+        ticks.next().await.unwrap().unwrap();
+
+        fs::write(&path, "not json").unwrap();
+        let later = SystemTime::now() + Duration::from_secs(5);
+        fs::File::options().write(true).open(&path).unwrap().set_modified(later).unwrap();
+        ticks.reload(Trigger::FileChanged).unwrap();
+        assert_eq!(ticks.portfolio.as_ref().unwrap().holdings.len(), 1);
+        assert!(ticks.problem.as_deref().unwrap().contains("Could not reload"));
+    }
+}
@@ -1,16 +1,33 @@
 use anyhow::Result;
-use clap::{Parser, Subcommand};
+use clap::{CommandFactory, Parser, Subcommand};
+use clap_complete::{ArgValueCompleter, CompleteEnv};
 use std::path::PathBuf;
 
-// mod declares that these modules exist and makes them part of our crate
-// The compiler looks for portfolio.rs, commands.rs, api.rs (or portfolio/mod.rs, etc.)
-mod portfolio;
-mod commands;
-mod api;
-
+// The modules live in the library crate (src/lib.rs), so the binary only
+// needs `use` to reach them instead of compiling its own copy with `mod`
 // use brings specific items from modules into scope so we don't need full paths
 // Without this, we'd have to write commands::balance_command() everywhere
-use commands::{balance_command, allocation_command, performance_command};
+use portfolio::commands::{
+    balance_command, allocation_command, performance_command, export_command,
+    encrypt_command, decrypt_command, rekey_command, migrate_command, validate_command,
+    convert_command, risk_command, correlation_command, project_command,
+    backtest_command, alerts_check_command, alerts_list_command, watch_command, WatchChange,
+    dashboard_command, chart_command, serve_command, metrics_command, daemon_command, shell_command,
+    completions_command, manpage_command, config_command,
+};
+use portfolio::config::{ColorChoice, Settings};
+use portfolio::completions::{complete_ticker, CompletionShell, COMPLETE_VAR};
+use portfolio::formats::{set_input_format, FileFormat};
+use portfolio::daemon::LogFormat;
+use portfolio::live::parse_interval;
+use portfolio::reports::{AllocationGroup, OutputFormat};
+#[cfg(feature = "sqlite")]
+use portfolio::commands::{db_import_command, db_export_command, db_lots_command};
+use portfolio::crypto::{set_passphrase_source, PassphraseSource};
+use portfolio::backtest::{Frequency, Strategy};
+use portfolio::benchmark::Benchmark;
+use portfolio::export::ExportFormat;
+use portfolio::projection::{ProjectionConfig, ProjectionMethod};
 
 // #[derive(Parser)] - attribute that auto-generates Parser trait implementation
 // Saves us from writing boilerplate code for command line parsing
@@ -23,6 +40,32 @@ struct Cli {
     // #[command(subcommand)] tells clap this field contains subcommands
     #[command(subcommand)]
     command: Commands,
+
+    /// Read the passphrase for encrypted portfolio files from this file
+    /// (otherwise $PORTFOLIO_PASSPHRASE, $PORTFOLIO_PASSPHRASE_FILE or a prompt)
+    #[arg(long, global = true)]
+    passphrase_file: Option<PathBuf>,
+
+    /// Format of the portfolio file (guessed from the extension when omitted)
+    #[arg(long, global = true, value_enum)]
+    input_format: Option<FileFormat>,
+
+    /// Config file with defaults and profiles
+    /// (otherwise $PORTFOLIO_CONFIG or ~/.config/portfolio_rs/config.toml)
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+
+    /// Use this profile from the config file (otherwise $PORTFOLIO_PROFILE or default_profile)
+    #[arg(long, global = true)]
+    profile: Option<String>,
+
+    /// How reports are printed (otherwise $PORTFOLIO_OUTPUT_FORMAT or the config file)
+    #[arg(long, global = true, value_enum)]
+    output_format: Option<OutputFormat>,
+
+    /// When to colour output (otherwise $PORTFOLIO_COLOR or the config file)
+    #[arg(long, global = true, value_enum)]
+    color: Option<ColorChoice>,
 }
 
 // #[derive(Subcommand)] - auto-generates Subcommand trait for this enum
@@ -30,18 +73,344 @@ struct Cli {
 enum Commands {
     /// Show current balances for all holdings
     Balances {
-        /// Path to the portfolio JSON file
-        portfolio_file: PathBuf,
+        /// Path to the portfolio file (default: from the config file)
+        portfolio_file: Option<PathBuf>,
+        /// Redraw every interval (30s, 5m, 1h) and when the file changes
+        #[arg(long, value_name = "INTERVAL", value_parser = parse_interval)]
+        watch: Option<std::time::Duration>,
     },
     /// Show asset allocation percentages
     Allocation {
-        /// Path to the portfolio JSON file
-        portfolio_file: PathBuf,
+        /// Path to the portfolio file (default: from the config file)
+        portfolio_file: Option<PathBuf>,
+        /// Draw a bar chart per ticker (default) or per asset class
+        #[arg(long, value_enum, value_name = "BY", num_args = 0..=1, default_missing_value = "ticker")]
+        chart: Option<AllocationGroup>,
+        /// Redraw every interval (30s, 5m, 1h) and when the file changes
+        #[arg(long, value_name = "INTERVAL", value_parser = parse_interval)]
+        watch: Option<std::time::Duration>,
     },
     /// Show performance metrics for all holdings
     Performance {
-        /// Path to the portfolio JSON file
-        portfolio_file: PathBuf,
+        /// Path to the portfolio file (default: from the config file)
+        portfolio_file: Option<PathBuf>,
+        /// Compare with a ticker (SPY) or weighted blend (SPY:60,AGG:40)
+        #[arg(short, long)]
+        benchmark: Option<Benchmark>,
+        /// Annual risk-free rate in percent, used for alpha
+        #[arg(long, default_value_t = 0.0)]
+        risk_free_rate: f64,
+        /// Redraw every interval (30s, 5m, 1h) and when the file changes
+        #[arg(long, value_name = "INTERVAL", value_parser = parse_interval)]
+        watch: Option<std::time::Duration>,
+    },
+    /// Chart the portfolio's value, or one ticker's price, over time
+    Chart {
+        /// Path to the portfolio file (default: from the config file)
+        portfolio_file: Option<PathBuf>,
+        /// Chart this ticker's price instead of the portfolio's value
+        #[arg(short, long, add = ArgValueCompleter::new(complete_ticker))]
+        ticker: Option<String>,
+        /// First day (default: one year before --to)
+        #[arg(long)]
+        from: Option<chrono::NaiveDate>,
+        /// Last day (default: today)
+        #[arg(long)]
+        to: Option<chrono::NaiveDate>,
+        /// Chart height in rows
+        #[arg(long, default_value_t = 12)]
+        height: usize,
+    },
+    /// Show volatility, beta, Sharpe and Sortino ratios and drawdowns from price history
+    Risk {
+        /// Path to the portfolio file (default: from the config file)
+        portfolio_file: Option<PathBuf>,
+        /// Ticker (SPY) or weighted blend (SPY:60,AGG:40) to measure beta against
+        #[arg(short, long, default_value = "SPY")]
+        benchmark: Benchmark,
+        /// Annual risk-free rate in percent, e.g. 4.5
+        #[arg(long, default_value_t = 0.0)]
+        risk_free_rate: f64,
+        /// First day of price history (default: one year before --to)
+        #[arg(long)]
+        from: Option<chrono::NaiveDate>,
+        /// Last day of price history (default: today)
+        #[arg(long)]
+        to: Option<chrono::NaiveDate>,
+    },
+    /// Show how the holdings' daily returns move together, as a heatmap
+    Correlation {
+        /// Path to the portfolio file (default: from the config file)
+        portfolio_file: Option<PathBuf>,
+        /// First day of price history (default: one year before --to)
+        #[arg(long)]
+        from: Option<chrono::NaiveDate>,
+        /// Last day of price history (default: today)
+        #[arg(long)]
+        to: Option<chrono::NaiveDate>,
+    },
+    /// Simulate future portfolio values and show percentile outcomes (Monte Carlo)
+    Project {
+        /// Path to the portfolio file (default: from the config file)
+        portfolio_file: Option<PathBuf>,
+        /// How to draw future returns from the price history
+        #[arg(short, long, value_enum, default_value_t = ProjectionMethod::Bootstrap)]
+        method: ProjectionMethod,
+        /// Number of simulated paths
+        #[arg(long, default_value_t = 5000)]
+        paths: usize,
+        /// Horizons in years, comma separated
+        #[arg(long, value_delimiter = ',', default_value = "1,5,10")]
+        years: Vec<u32>,
+        /// Amount added at the end of every month
+        #[arg(long, default_value_t = 0.0)]
+        contribute: f64,
+        /// Amount taken out at the end of every month
+        #[arg(long, default_value_t = 0.0)]
+        withdraw: f64,
+        /// Seed for the random generator; the same seed gives the same result
+        #[arg(long)]
+        seed: Option<u64>,
+        /// First day of price history (default: one year before --to)
+        #[arg(long)]
+        from: Option<chrono::NaiveDate>,
+        /// Last day of price history (default: today)
+        #[arg(long)]
+        to: Option<chrono::NaiveDate>,
+    },
+    /// Replay a strategy (lump sum, monthly DCA or rebalancing) over historical prices
+    Backtest {
+        /// Path to the portfolio file (default: from the config file)
+        portfolio_file: Option<PathBuf>,
+        /// Strategy to replay
+        #[arg(short, long, value_enum, default_value_t = Strategy::LumpSum)]
+        strategy: Strategy,
+        /// Target weights such as AAPL:60,AGG:40 (default: the current holdings' weights)
+        #[arg(short, long)]
+        weights: Option<Benchmark>,
+        /// Total amount to invest (default: what the current holdings cost)
+        #[arg(long)]
+        amount: Option<rust_decimal::Decimal>,
+        /// How often the rebalance strategy trades back to the weights
+        #[arg(long, value_enum, default_value_t = Frequency::Quarterly)]
+        rebalance: Frequency,
+        /// First day of the backtest (default: one year before --to)
+        #[arg(long)]
+        from: Option<chrono::NaiveDate>,
+        /// Last day of the backtest (default: today)
+        #[arg(long)]
+        to: Option<chrono::NaiveDate>,
+    },
+    /// Check price, move, weight and drawdown alert rules
+    Alerts {
+        #[command(subcommand)]
+        command: AlertsCommands,
+    },
+    /// Full-screen dashboard of holdings, allocation and performance
+    Dashboard {
+        /// Path to the portfolio file (default: from the config file)
+        portfolio_file: Option<PathBuf>,
+        /// Seconds between quote refreshes
+        #[arg(long, default_value_t = 60)]
+        refresh: u64,
+    },
+    /// Interactive prompt for reports and bookkeeping, with quotes cached for the session
+    Shell {
+        /// Path to the portfolio file (default: from the config file)
+        portfolio_file: Option<PathBuf>,
+    },
+    /// Serve a web dashboard and JSON endpoints on localhost
+    Serve {
+        /// Portfolio files to serve, selectable in the dashboard by file name (default: from the config file)
+        portfolio_files: Vec<PathBuf>,
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        bind: std::net::SocketAddr,
+        /// Require this token on every request
+        #[arg(long)]
+        token: Option<String>,
+    },
+    /// Export portfolio values and quote health as Prometheus metrics
+    Metrics {
+        /// Portfolio files to export, labelled by file name (default: from the config file)
+        portfolio_files: Vec<PathBuf>,
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1:9187")]
+        bind: std::net::SocketAddr,
+        /// How often to refresh quotes (e.g. 30s, 5m)
+        #[arg(long, default_value = "60s", value_parser = parse_interval)]
+        refresh: std::time::Duration,
+    },
+    /// Run scheduled snapshots, quote refreshes, alert checks and reports
+    Daemon {
+        /// Schedule file (JSON, TOML or YAML)
+        schedule_file: PathBuf,
+        /// Log line format
+        #[arg(long, value_enum, default_value_t = LogFormat::Text)]
+        log_format: LogFormat,
+        /// Run every job once and exit
+        #[arg(long)]
+        once: bool,
+    },
+    /// Show watched tickers against current quotes and target prices
+    Watch {
+        /// Path to the portfolio file (default: from the config file)
+        portfolio_file: Option<PathBuf>,
+        /// Start watching a ticker, recording today's quote
+        #[arg(long, value_name = "TICKER", conflicts_with = "remove", add = ArgValueCompleter::new(complete_ticker))]
+        add: Option<String>,
+        /// Price you would like to buy at (with --add)
+        #[arg(long, requires = "add")]
+        target: Option<rust_decimal::Decimal>,
+        /// Free-form note kept with the ticker (with --add)
+        #[arg(long, requires = "add")]
+        note: Option<String>,
+        /// Stop watching a ticker
+        #[arg(long, value_name = "TICKER", add = ArgValueCompleter::new(complete_ticker))]
+        remove: Option<String>,
+    },
+    /// Export holdings, lots, transactions, performance and allocation to spreadsheets
+    Export {
+        /// Path to the portfolio file (default: from the config file)
+        portfolio_file: Option<PathBuf>,
+        /// Output file (.xlsx, .ods) or directory for CSV files
+        #[arg(short, long)]
+        output: PathBuf,
+        /// Output format (guessed from the output path when omitted)
+        #[arg(short, long, value_enum)]
+        format: Option<ExportFormat>,
+    },
+    /// Encrypt a portfolio file with a passphrase
+    Encrypt {
+        /// Path to the portfolio file (default: from the config file)
+        portfolio_file: Option<PathBuf>,
+        /// Write the encrypted file here instead of replacing the original
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Read the new passphrase from this file (otherwise $PORTFOLIO_NEW_PASSPHRASE or a prompt)
+        #[arg(long)]
+        new_passphrase_file: Option<PathBuf>,
+    },
+    /// Decrypt an encrypted portfolio file back to plaintext
+    Decrypt {
+        /// Path to the encrypted portfolio file (default: from the config file)
+        portfolio_file: Option<PathBuf>,
+        /// Write the plaintext file here instead of replacing the original
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Change the passphrase of an encrypted portfolio file
+    Rekey {
+        /// Path to the encrypted portfolio file (default: from the config file)
+        portfolio_file: Option<PathBuf>,
+        /// Read the new passphrase from this file (otherwise $PORTFOLIO_NEW_PASSPHRASE or a prompt)
+        #[arg(long)]
+        new_passphrase_file: Option<PathBuf>,
+    },
+    /// Upgrade a portfolio file to the current schema version (keeps a backup)
+    Migrate {
+        /// Path to the portfolio file (default: from the config file)
+        portfolio_file: Option<PathBuf>,
+        /// Only report what would change
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Check a portfolio file and report every problem with its location
+    Validate {
+        /// Path to the portfolio file (default: from the config file)
+        portfolio_file: Option<PathBuf>,
+    },
+    /// Convert a portfolio file between JSON, TOML, YAML and CSV
+    Convert {
+        /// Portfolio file to read
+        input: PathBuf,
+        /// Portfolio file to write
+        output: PathBuf,
+        /// Output format (guessed from the output extension when omitted)
+        #[arg(long, value_enum)]
+        to: Option<FileFormat>,
+    },
+    /// Show the settings in effect: config file, profile and defaults
+    Config,
+    /// Print a shell completion script (completes tickers from the portfolio file too)
+    Completions {
+        /// Shell to complete in
+        #[arg(value_enum)]
+        shell: CompletionShell,
+    },
+    /// Print the manual page, or write one per command into a directory
+    Manpage {
+        /// Write portfolio_rs.1 and a page per subcommand here instead
+        #[arg(long)]
+        dir: Option<PathBuf>,
+    },
+    /// Manage the SQLite storage backend
+    #[cfg(feature = "sqlite")]
+    Db {
+        #[command(subcommand)]
+        command: DbCommands,
+    },
+}
+
+#[derive(Subcommand)]
+enum AlertsCommands {
+    /// Evaluate the rules against fresh quotes; exits with status 1 when any fire
+    Check {
+        /// Path to the portfolio file (default: from the config file)
+        portfolio_file: Option<PathBuf>,
+        /// Alert rules file (JSON, TOML or YAML)
+        #[arg(short, long)]
+        rules: PathBuf,
+        /// Print the result as JSON
+        #[arg(long)]
+        json: bool,
+        /// Deliver fired alerts to the sinks in the rules file's [notify] section
+        #[arg(long)]
+        notify: bool,
+    },
+    /// List the rules in a rules file
+    List {
+        /// Alert rules file (JSON, TOML or YAML)
+        rules: PathBuf,
+    },
+}
+
+#[cfg(feature = "sqlite")]
+#[derive(Subcommand)]
+enum DbCommands {
+    /// Load a portfolio file into a SQLite database (replaces its holdings and transactions)
+    Import {
+        /// Portfolio file to read
+        source: PathBuf,
+        /// SQLite database to write
+        database: PathBuf,
+    },
+    /// Write a SQLite database's holdings and transactions to a portfolio file
+    Export {
+        /// SQLite database to read
+        database: PathBuf,
+        /// Portfolio file to write
+        destination: PathBuf,
+        /// Output format (guessed from the destination extension when omitted)
+        #[arg(long, value_enum)]
+        to: Option<FileFormat>,
+    },
+    /// List lots from the database, filtered by ticker and purchase date
+    Lots {
+        /// SQLite database to read
+        database: PathBuf,
+        /// Only lots of this ticker
+        #[arg(long, add = ArgValueCompleter::new(complete_ticker))]
+        ticker: Option<String>,
+        /// Only lots bought in this year (shorthand for --from/--to)
+        #[arg(long, conflicts_with_all = ["from", "to"], value_parser = clap::value_parser!(i32).range(1..=9999))]
+        year: Option<i32>,
+        /// Only lots bought on or after this date (YYYY-MM-DD)
+        #[arg(long)]
+        from: Option<chrono::NaiveDate>,
+        /// Only lots bought on or before this date (YYYY-MM-DD)
+        #[arg(long)]
+        to: Option<chrono::NaiveDate>,
     },
 }
 
@@ -49,19 +418,190 @@ enum Commands {
 // Without this, we'd need to manually set up the Tokio async runtime
 #[tokio::main]
 async fn main() -> Result<()> {
+    // Answers the shell and exits when called from a completion script
+    CompleteEnv::with_factory(Cli::command).var(COMPLETE_VAR).complete();
     let cli = Cli::parse();
 
+    if let Some(path) = cli.passphrase_file {
+        set_passphrase_source(PassphraseSource::File(path));
+    }
+    if let Some(format) = cli.input_format {
+        set_input_format(format);
+    }
+
+    let mut settings = Settings::load(cli.config.as_deref(), cli.profile.as_deref())?;
+    settings.output_format = cli.output_format.unwrap_or(settings.output_format);
+    settings.color = cli.color.unwrap_or(settings.color);
+    settings.apply();
+
     match cli.command {
-        Commands::Balances { portfolio_file } => {
-            balance_command(&portfolio_file).await?;
+        Commands::Balances { portfolio_file, watch } => {
+            let portfolio_file = settings.portfolio_file(portfolio_file)?;
+            balance_command(&portfolio_file, watch).await?;
+        }
+        Commands::Allocation { portfolio_file, chart, watch } => {
+            let portfolio_file = settings.portfolio_file(portfolio_file)?;
+            allocation_command(&portfolio_file, watch, chart).await?;
+        }
+        Commands::Performance { portfolio_file, benchmark, risk_free_rate, watch } => {
+            let portfolio_file = settings.portfolio_file(portfolio_file)?;
+            performance_command(&portfolio_file, benchmark.as_ref(), risk_free_rate, watch).await?;
+        }
+        Commands::Chart { portfolio_file, ticker, from, to, height } => {
+            let portfolio_file = settings.portfolio_file(portfolio_file)?;
+            chart_command(&portfolio_file, ticker.as_deref(), from, to, height).await?;
+        }
+        Commands::Risk { portfolio_file, benchmark, risk_free_rate, from, to } => {
+            let portfolio_file = settings.portfolio_file(portfolio_file)?;
+            risk_command(&portfolio_file, &benchmark, risk_free_rate, from, to).await?;
+        }
+        Commands::Correlation { portfolio_file, from, to } => {
+            let portfolio_file = settings.portfolio_file(portfolio_file)?;
+            correlation_command(&portfolio_file, from, to).await?;
+        }
+        Commands::Project { portfolio_file, method, paths, years, contribute, withdraw, seed, from, to } => {
+            let portfolio_file = settings.portfolio_file(portfolio_file)?;
+            let config = ProjectionConfig {
+                method,
+                paths,
+                horizons: years,
+                monthly_cash_flow: contribute - withdraw,
+                // Without a seed, pick one and print it so the run can be repeated
+                seed: seed.unwrap_or_else(rand::random),
+            };
+            project_command(&portfolio_file, config, from, to).await?;
+        }
+        Commands::Backtest { portfolio_file, strategy, weights, amount, rebalance, from, to } => {
+            let portfolio_file = settings.portfolio_file(portfolio_file)?;
+            backtest_command(&portfolio_file, strategy, weights.as_ref(), amount, rebalance, from, to).await?;
+        }
+        Commands::Alerts { command } => match command {
+            AlertsCommands::Check { portfolio_file, rules, json, notify } => {
+                let portfolio_file = settings.portfolio_file(portfolio_file)?;
+                if alerts_check_command(&portfolio_file, &rules, json, notify).await? {
+                    std::process::exit(1);
+                }
+            }
+            AlertsCommands::List { rules } => {
+                alerts_list_command(&rules)?;
+            }
+        },
+        Commands::Dashboard { portfolio_file, refresh } => {
+            let portfolio_file = settings.portfolio_file(portfolio_file)?;
+            dashboard_command(&portfolio_file, refresh).await?;
+        }
+        Commands::Shell { portfolio_file } => {
+            let portfolio_file = settings.portfolio_file(portfolio_file)?;
+            shell_command(&portfolio_file, settings.cost_basis).await?;
+        }
+        Commands::Serve { portfolio_files, bind, token } => {
+            serve_command(&settings.portfolio_files(portfolio_files)?, bind, token).await?;
+        }
+        Commands::Metrics { portfolio_files, bind, refresh } => {
+            metrics_command(&settings.portfolio_files(portfolio_files)?, bind, refresh).await?;
         }
-        Commands::Allocation { portfolio_file } => {
-            allocation_command(&portfolio_file).await?;
+        Commands::Daemon { schedule_file, log_format, once } => {
+            daemon_command(&schedule_file, log_format, once).await?;
         }
-        Commands::Performance { portfolio_file } => {
-            performance_command(&portfolio_file).await?;
+        Commands::Watch { portfolio_file, add, target, note, remove } => {
+            let portfolio_file = settings.portfolio_file(portfolio_file)?;
+            let change = match (add, remove) {
+                (Some(ticker), _) => Some(WatchChange::Add { ticker, target_price: target, note }),
+                (None, Some(ticker)) => Some(WatchChange::Remove { ticker }),
+                (None, None) => None,
+            };
+            watch_command(&portfolio_file, change).await?;
+        }
+        Commands::Export { portfolio_file, output, format } => {
+            let portfolio_file = settings.portfolio_file(portfolio_file)?;
+            export_command(&portfolio_file, &output, format).await?;
+        }
+        Commands::Encrypt { portfolio_file, output, new_passphrase_file } => {
+            let portfolio_file = settings.portfolio_file(portfolio_file)?;
+            encrypt_command(&portfolio_file, output.as_deref(), new_passphrase_file.as_deref())?;
+        }
+        Commands::Decrypt { portfolio_file, output } => {
+            let portfolio_file = settings.portfolio_file(portfolio_file)?;
+            decrypt_command(&portfolio_file, output.as_deref())?;
+        }
+        Commands::Rekey { portfolio_file, new_passphrase_file } => {
+            let portfolio_file = settings.portfolio_file(portfolio_file)?;
+            rekey_command(&portfolio_file, new_passphrase_file.as_deref())?;
+        }
+        Commands::Migrate { portfolio_file, dry_run } => {
+            let portfolio_file = settings.portfolio_file(portfolio_file)?;
+            migrate_command(&portfolio_file, dry_run)?;
+        }
+        Commands::Convert { input, output, to } => {
+            convert_command(&input, &output, to)?;
+        }
+        Commands::Config => {
+            config_command(&settings);
+        }
+        Commands::Completions { shell } => {
+            completions_command(shell)?;
+        }
+        Commands::Manpage { dir } => {
+            manpage_command(Cli::command(), dir.as_deref())?;
+        }
+        #[cfg(feature = "sqlite")]
+        Commands::Db { command } => match command {
+            DbCommands::Import { source, database } => {
+                db_import_command(&source, &database)?;
+            }
+            DbCommands::Export { database, destination, to } => {
+                db_export_command(&database, &destination, to)?;
+            }
+            DbCommands::Lots { database, ticker, year, from, to } => {
+                let (from, to) = match year {
+                    Some(year) => {
+                        let day = |month, day| {
+                            chrono::NaiveDate::from_ymd_opt(year, month, day)
+                                .ok_or_else(|| anyhow::anyhow!("Invalid year {}", year))
+                        };
+                        (Some(day(1, 1)?), Some(day(12, 31)?))
+                    }
+                    None => (from, to),
+                };
+                let query = portfolio::sqlite::LedgerQuery { ticker, from, to };
+                db_lots_command(&database, &query)?;
+            }
+        },
+        Commands::Validate { portfolio_file } => {
+            let portfolio_file = settings.portfolio_file(portfolio_file)?;
+            if !validate_command(&portfolio_file)? {
+                std::process::exit(1);
+            }
         }
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_global_options_next_to_subcommand_options() {
+        Cli::command().debug_assert();
+        // Subcommands with an `output` of their own next to the global options
+        for line in [
+            vec!["portfolio_rs", "convert", "in.json", "out.toml"],
+            vec!["portfolio_rs", "--output-format", "json", "export", "p.json", "--output", "out.xlsx"],
+            vec!["portfolio_rs", "decrypt", "p.json", "-o", "plain.json", "--output-format", "table"],
+        ] {
+            Cli::try_parse_from(&line).unwrap_or_else(|e| panic!("{:?}: {}", line, e));
+        }
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn test_db_lots_year_must_be_a_real_year() {
+        let lots = |year: &str| Cli::try_parse_from(["portfolio_rs", "db", "lots", "ledger.db", "--year", year]);
+        assert!(lots("2021").is_ok());
+        for year in ["0", "-5", "10000", "2147483647"] {
+            assert!(lots(year).is_err(), "{}", year);
+        }
+    }
+}
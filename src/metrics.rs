@@ -0,0 +1,404 @@
+use anyhow::{Context, Result};
+use axum::extract::State;
+use axum::http::header;
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::Router;
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::api::fetch_prices_with_failures;
+use crate::money;
+use crate::portfolio::Portfolio;
+use crate::reports::position_rows;
+
+// Prometheus exporter. `metrics` refreshes quotes for every served
+// portfolio in the background and answers GET /metrics in the Prometheus
+// text format. A scrape never waits for the price provider: it reads the
+// portfolio files again and values them at the last quotes, and
+// `portfolio_quote_age_seconds` says how old those are.
+//
+// A quote that fails to fetch keeps its previous price and age (or the
+// provider's default price, with no age, if it never succeeded), and counts
+// towards `portfolio_price_fetch_errors_total`.
+
+/// Upper bounds, in seconds, of the fetch latency histogram buckets.
+const LATENCY_BUCKETS: [f64; 8] = [0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+/// How a quote was last fetched.
+#[derive(Debug, Clone, PartialEq)]
+struct QuoteSample {
+    price: Decimal,
+    /// When the provider last returned a real quote; `None` if it never has.
+    fetched_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Default)]
+struct FetchStats {
+    fetches: u64,
+    errors: BTreeMap<String, u64>,
+    /// Fetches per latency bucket (not cumulative), plus one for +Inf.
+    buckets: [u64; LATENCY_BUCKETS.len() + 1],
+    latency_sum: f64,
+}
+
+impl FetchStats {
+    fn observe(&mut self, latency: Duration, failed: &[String]) {
+        let seconds = latency.as_secs_f64();
+        let bucket = LATENCY_BUCKETS.iter().position(|&bound| seconds <= bound).unwrap_or(LATENCY_BUCKETS.len());
+        self.buckets[bucket] += 1;
+        self.latency_sum += seconds;
+        self.fetches += 1;
+        for ticker in failed {
+            *self.errors.entry(ticker.clone()).or_default() += 1;
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Quotes {
+    samples: HashMap<String, QuoteSample>,
+    stats: FetchStats,
+    refreshed_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Clone)]
+pub struct Exporter {
+    /// Portfolio name (file stem) and file.
+    portfolios: Arc<Vec<(String, PathBuf)>>,
+    quotes: Arc<Mutex<Quotes>>,
+}
+
+impl Exporter {
+    pub fn new(files: &[PathBuf]) -> Result<Exporter> {
+        let mut portfolios: Vec<(String, PathBuf)> = Vec::new();
+        for file in files {
+            let name = file
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .with_context(|| format!("Cannot name portfolio {}", file.display()))?
+                .to_string();
+            if portfolios.iter().any(|(other, _)| *other == name) {
+                anyhow::bail!("Two portfolio files are named '{}'; rename one of them", name);
+            }
+            portfolios.push((name, file.clone()));
+        }
+        Ok(Exporter { portfolios: Arc::new(portfolios), quotes: Arc::default() })
+    }
+
+    /// Every ticker held in any portfolio that currently loads.
+    fn tickers(&self) -> Vec<String> {
+        let tickers: BTreeSet<String> = self
+            .portfolios
+            .iter()
+            .filter_map(|(_, file)| Portfolio::load_from_file(file).ok())
+            .flat_map(|portfolio| portfolio.get_tickers())
+            .collect();
+        tickers.into_iter().collect()
+    }
+
+    /// Fetch fresh quotes for all held tickers and record how it went.
+    pub async fn refresh(&self) -> Result<()> {
+        let tickers = self.tickers();
+        let started = Instant::now();
+        let result = fetch_prices_with_failures(&tickers).await;
+        let latency = started.elapsed();
+        let now = Utc::now();
+
+        let mut quotes = self.quotes.lock().expect("quotes lock");
+        let (prices, failed) = match result {
+            Ok(fetched) => fetched,
+            Err(err) => {
+                // The whole fetch failed: every ticker counts as an error
+                quotes.stats.observe(latency, &tickers);
+                return Err(err);
+            }
+        };
+        quotes.stats.observe(latency, &failed);
+        quotes.refreshed_at = Some(now);
+        for (ticker, price) in prices {
+            let ok = !failed.contains(&ticker);
+            match quotes.samples.get_mut(&ticker) {
+                Some(sample) if ok => *sample = QuoteSample { price, fetched_at: Some(now) },
+                Some(_) => {}
+                None => {
+                    quotes.samples.insert(ticker, QuoteSample { price, fetched_at: ok.then_some(now) });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// All metrics in the Prometheus text exposition format.
+    pub fn render(&self, now: DateTime<Utc>) -> String {
+        let quotes = self.quotes.lock().expect("quotes lock");
+        let prices: HashMap<String, Decimal> = quotes.samples.iter().map(|(t, s)| (t.clone(), s.price)).collect();
+        let mut out = Metrics::default();
+
+        for (name, file) in self.portfolios.iter() {
+            let portfolio = match Portfolio::load_from_file(file) {
+                Ok(portfolio) => portfolio,
+                Err(_) => {
+                    out.sample("portfolio_up", "Whether the portfolio file loads", "gauge", &[("portfolio", name)], 0.0);
+                    continue;
+                }
+            };
+            out.sample("portfolio_up", "Whether the portfolio file loads", "gauge", &[("portfolio", name)], 1.0);
+
+            let currency = portfolio.base_currency.as_str();
+            let rows = position_rows(&portfolio.holdings_with_prices(&prices), currency);
+            let total_value: Decimal = rows.iter().map(|r| r.current_value).sum();
+            let total_cost: Decimal = rows.iter().map(|r| r.total_cost).sum();
+            let labels = [("portfolio", name.as_str()), ("currency", currency)];
+            out.sample("portfolio_value", "Market value of the portfolio", "gauge", &labels, money::to_f64(total_value));
+            out.sample("portfolio_cost", "Cost basis of the portfolio", "gauge", &labels, money::to_f64(total_cost));
+            out.sample("portfolio_unrealized_gain", "Market value minus cost basis", "gauge", &labels,
+                       money::to_f64(total_value - total_cost));
+
+            for row in &rows {
+                let labels = [("portfolio", name.as_str()), ("ticker", row.ticker.as_str()), ("currency", currency)];
+                let weight = if total_value > Decimal::ZERO { row.current_value / total_value } else { Decimal::ZERO };
+                out.sample("portfolio_holding_quantity", "Quantity held, lots merged", "gauge", &labels[..2],
+                           money::to_f64(row.quantity));
+                out.sample("portfolio_holding_value", "Market value of the holding", "gauge", &labels,
+                           money::to_f64(row.current_value));
+                out.sample("portfolio_holding_weight", "Share of the portfolio's value, from 0 to 1", "gauge", &labels[..2],
+                           money::to_f64(weight));
+                out.sample("portfolio_holding_unrealized_gain", "Market value minus cost basis of the holding", "gauge",
+                           &labels, money::to_f64(row.current_value - row.total_cost));
+            }
+        }
+
+        let mut tickers: Vec<(&String, &QuoteSample)> = quotes.samples.iter().collect();
+        tickers.sort_by(|a, b| a.0.cmp(b.0));
+        for (ticker, sample) in tickers {
+            out.sample("portfolio_quote_price", "Last quote", "gauge", &[("ticker", ticker)], money::to_f64(sample.price));
+            if let Some(fetched_at) = sample.fetched_at {
+                let age = (now - fetched_at).num_milliseconds().max(0) as f64 / 1000.0;
+                out.sample("portfolio_quote_age_seconds", "Seconds since the quote was fetched", "gauge",
+                           &[("ticker", ticker)], age);
+            }
+        }
+
+        let stats = &quotes.stats;
+        out.sample("portfolio_price_fetches_total", "Quote refreshes", "counter", &[], stats.fetches as f64);
+        for (ticker, errors) in &stats.errors {
+            out.sample("portfolio_price_fetch_errors_total", "Quotes that could not be fetched", "counter",
+                       &[("ticker", ticker)], *errors as f64);
+        }
+        out.histogram("portfolio_price_fetch_duration_seconds", "Time taken to refresh all quotes", stats);
+        if let Some(refreshed_at) = quotes.refreshed_at {
+            out.sample("portfolio_last_refresh_timestamp_seconds", "Unix time of the last quote refresh", "gauge", &[],
+                       refreshed_at.timestamp() as f64);
+        }
+
+        out.text()
+    }
+}
+
+/// Collects samples per metric, so each metric's HELP and TYPE lines and
+/// all of its samples come out as one group, as the format requires.
+#[derive(Default)]
+struct Metrics {
+    families: Vec<Family>,
+}
+
+struct Family {
+    name: &'static str,
+    help: String,
+    kind: &'static str,
+    samples: String,
+}
+
+impl Metrics {
+    fn family(&mut self, name: &'static str, help: &str, kind: &'static str) -> &mut String {
+        let index = match self.families.iter().position(|f| f.name == name) {
+            Some(index) => index,
+            None => {
+                self.families.push(Family { name, help: help.to_string(), kind, samples: String::new() });
+                self.families.len() - 1
+            }
+        };
+        &mut self.families[index].samples
+    }
+
+    fn sample(&mut self, name: &'static str, help: &str, kind: &'static str, labels: &[(&str, &str)], value: f64) {
+        let samples = self.family(name, help, kind);
+        let _ = writeln!(samples, "{}{} {}", name, label_set(labels), value);
+    }
+
+    fn histogram(&mut self, name: &'static str, help: &str, stats: &FetchStats) {
+        let samples = self.family(name, help, "histogram");
+        let mut cumulative = 0;
+        for (bound, count) in LATENCY_BUCKETS.iter().zip(stats.buckets) {
+            cumulative += count;
+            let _ = writeln!(samples, "{}_bucket{{le=\"{}\"}} {}", name, bound, cumulative);
+        }
+        let _ = writeln!(samples, "{}_bucket{{le=\"+Inf\"}} {}", name, stats.fetches);
+        let _ = writeln!(samples, "{}_sum {}", name, stats.latency_sum);
+        let _ = writeln!(samples, "{}_count {}", name, stats.fetches);
+    }
+
+    fn text(&self) -> String {
+        let mut text = String::new();
+        for family in &self.families {
+            let _ = writeln!(text, "# HELP {} {}", family.name, family.help);
+            let _ = writeln!(text, "# TYPE {} {}", family.name, family.kind);
+            text.push_str(&family.samples);
+        }
+        text
+    }
+}
+
+fn label_set(labels: &[(&str, &str)]) -> String {
+    if labels.is_empty() {
+        return String::new();
+    }
+    let pairs: Vec<String> = labels
+        .iter()
+        .map(|(key, value)| {
+            let value = value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n");
+            format!("{}=\"{}\"", key, value)
+        })
+        .collect();
+    format!("{{{}}}", pairs.join(","))
+}
+
+pub fn router(exporter: Exporter) -> Router {
+    Router::new()
+        .route("/metrics", get(scrape))
+        .route("/", get(|| async { "Portfolio metrics exporter: see /metrics\n" }))
+        .with_state(exporter)
+}
+
+async fn scrape(State(exporter): State<Exporter>) -> impl IntoResponse {
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], exporter.render(Utc::now()))
+}
+
+/// Serve /metrics, refreshing quotes every `refresh`, until Ctrl-C.
+pub async fn serve(files: &[PathBuf], bind: SocketAddr, refresh: Duration) -> Result<()> {
+    let exporter = Exporter::new(files)?;
+    for (_, file) in exporter.portfolios.iter() {
+        Portfolio::load_from_file(file)?;
+    }
+    exporter.refresh().await?;
+
+    let listener = tokio::net::TcpListener::bind(bind)
+        .await
+        .with_context(|| format!("Failed to listen on {}", bind))?;
+    println!("Serving metrics for {} portfolio(s) at http://{}/metrics", files.len(), listener.local_addr()?);
+    println!("Refreshing quotes every {}s; press Ctrl-C to stop", refresh.as_secs());
+
+    let background = exporter.clone();
+    let refresher = tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(refresh);
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            if let Err(err) = background.refresh().await {
+                eprintln!("Quote refresh failed: {:#}", err);
+            }
+        }
+    });
+
+    let result = axum::serve(listener, router(exporter))
+        .with_graceful_shutdown(async {
+            let _ = tokio::signal::ctrl_c().await;
+        })
+        .await
+        .context("The metrics server failed");
+    refresher.abort();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    const PORTFOLIO: &str = r#"[
+        {"ticker": "AAPL", "quantity": 10, "cost_basis": 150, "date_purchased": "2024-01-01"},
+        {"ticker": "AAPL", "quantity": 5, "cost_basis": 160, "date_purchased": "2024-02-01"},
+        {"ticker": "MSFT", "quantity": 5, "cost_basis": 300, "date_purchased": "2024-01-01"}
+    ]"#;
+
+    fn value(text: &str, series: &str) -> f64 {
+        let line = text.lines().find(|l| l.starts_with(series) && l[series.len()..].starts_with(' ')).unwrap_or_else(|| panic!("no {} in\n{}", series, text));
+        line[series.len() + 1..].parse().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_render_portfolio_metrics() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("main.json");
+        fs::write(&path, PORTFOLIO).unwrap();
+        let exporter = Exporter::new(&[path]).unwrap();
+        exporter.refresh().await.unwrap();
+
+        let later = Utc::now() + chrono::Duration::seconds(30);
+        let text = exporter.render(later);
+        // AAPL 15 x 170 = 2550, MSFT 5 x 350 = 1750
+        assert_eq!(value(&text, r#"portfolio_value{portfolio="main",currency="USD"}"#), 4300.0);
+        assert_eq!(value(&text, r#"portfolio_unrealized_gain{portfolio="main",currency="USD"}"#), 4300.0 - 2300.0 - 1500.0);
+        assert_eq!(value(&text, r#"portfolio_holding_quantity{portfolio="main",ticker="AAPL"}"#), 15.0);
+        assert_eq!(value(&text, r#"portfolio_holding_value{portfolio="main",ticker="MSFT",currency="USD"}"#), 1750.0);
+        let weight = value(&text, r#"portfolio_holding_weight{portfolio="main",ticker="AAPL"}"#);
+        assert!((weight - 2550.0 / 4300.0).abs() < 1e-9);
+        assert!(value(&text, r#"portfolio_quote_age_seconds{ticker="AAPL"}"#) >= 29.0);
+        assert_eq!(value(&text, "portfolio_price_fetches_total"), 1.0);
+        assert_eq!(value(&text, r#"portfolio_price_fetch_duration_seconds_bucket{le="+Inf"}"#), 1.0);
+        assert_eq!(text.matches("# TYPE portfolio_holding_value gauge").count(), 1);
+        // Each metric's samples form one group
+        let values: Vec<usize> = text.lines().enumerate().filter(|(_, l)| l.starts_with("portfolio_holding_value{")).map(|(i, _)| i).collect();
+        assert_eq!(values, vec![values[0], values[0] + 1]);
+    }
+
+    #[tokio::test]
+    async fn test_broken_portfolio_reports_down() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("main.json");
+        fs::write(&path, PORTFOLIO).unwrap();
+        let exporter = Exporter::new(std::slice::from_ref(&path)).unwrap();
+        exporter.refresh().await.unwrap();
+
+        fs::write(&path, "not json").unwrap();
+        let text = exporter.render(Utc::now());
+        assert_eq!(value(&text, r#"portfolio_up{portfolio="main"}"#), 0.0);
+        assert!(!text.contains("portfolio_value{"));
+        // Quotes fetched earlier are still exported
+        assert_eq!(value(&text, r#"portfolio_quote_price{ticker="AAPL"}"#), 170.0);
+    }
+
+    #[test]
+    fn test_fetch_stats_buckets_and_errors() {
+        let mut stats = FetchStats::default();
+        stats.observe(Duration::from_millis(80), &[]);
+        stats.observe(Duration::from_secs(20), &["XYZ".to_string()]);
+        assert_eq!(stats.buckets[1], 1);
+        assert_eq!(stats.buckets[LATENCY_BUCKETS.len()], 1);
+        assert_eq!(stats.errors["XYZ"], 1);
+
+        let mut out = Metrics::default();
+        out.histogram("fetch", "help", &stats);
+        let text = out.text();
+        assert!(text.starts_with("# HELP fetch help\n# TYPE fetch histogram\n"));
+        assert!(text.contains("fetch_bucket{le=\"0.05\"} 0\n"));
+        assert!(text.contains("fetch_bucket{le=\"0.1\"} 1\n"));
+        assert!(text.contains("fetch_bucket{le=\"10\"} 1\n"));
+        assert!(text.contains("fetch_bucket{le=\"+Inf\"} 2\n"));
+        assert!(text.contains("fetch_count 2\n"));
+    }
+
+    #[test]
+    fn test_label_values_are_escaped() {
+        assert_eq!(label_set(&[]), "");
+        assert_eq!(label_set(&[("a", "x\"y\\z\n")]), r#"{a="x\"y\\z\n"}"#);
+    }
+}
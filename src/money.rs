@@ -0,0 +1,128 @@
+use rust_decimal::prelude::*;
+
+// Money and quantities are fixed-point decimals so that totals add up to
+// the cent. Values are kept exact while computing and rounded only where a
+// figure is reported, using the precision of the currency (for money) or
+// the asset (for quantities). Every rounding goes through this module.
+
+/// Round half away from zero, the way statements and brokers round
+/// (2.345 -> 2.35, -2.345 -> -2.35).
+pub const ROUNDING: RoundingStrategy = RoundingStrategy::MidpointAwayFromZero;
+
+/// Decimal places reported for percentages (returns, weights).
+pub const PERCENT_DECIMALS: u32 = 2;
+
+/// Minor units of an ISO 4217 currency: 0 for the yen, 3 for the dinars,
+/// 2 for everything else.
+pub fn currency_decimals(currency: &str) -> u32 {
+    match currency.to_ascii_uppercase().as_str() {
+        "JPY" | "KRW" | "CLP" | "ISK" | "VND" | "HUF" | "TWD" | "UGX" | "XAF" | "XOF" => 0,
+        "BHD" | "JOD" | "KWD" | "OMR" | "TND" | "IQD" | "LYD" => 3,
+        _ => 2,
+    }
+}
+
+/// Decimal places a quantity of the asset is tracked to: crypto pairs
+/// (`BTC-USD`) to 8 places like satoshis, shares and funds to 6 places
+/// for fractional shares.
+pub fn asset_decimals(ticker: &str) -> u32 {
+    if is_crypto(ticker) {
+        8
+    } else {
+        6
+    }
+}
+
+pub fn is_crypto(ticker: &str) -> bool {
+    ticker.to_ascii_uppercase().ends_with("-USD")
+}
+
+pub fn round(value: Decimal, decimals: u32) -> Decimal {
+    value.round_dp_with_strategy(decimals, ROUNDING)
+}
+
+/// Round an amount to the currency's minor unit.
+pub fn round_money(amount: Decimal, currency: &str) -> Decimal {
+    round(amount, currency_decimals(currency))
+}
+
+/// Round a quantity to the asset's precision.
+pub fn round_quantity(quantity: Decimal, ticker: &str) -> Decimal {
+    round(quantity, asset_decimals(ticker))
+}
+
+/// Round a quantity down to the asset's precision, so that buying it never
+/// costs more than the cash set aside for it.
+pub fn floor_quantity(quantity: Decimal, ticker: &str) -> Decimal {
+    quantity.round_dp_with_strategy(asset_decimals(ticker), RoundingStrategy::ToZero)
+}
+
+pub fn round_percent(percentage: Decimal) -> Decimal {
+    round(percentage, PERCENT_DECIMALS)
+}
+
+/// `part` as a percentage of `whole`, or zero when there is no whole.
+pub fn percentage(part: Decimal, whole: Decimal) -> Decimal {
+    if whole.is_zero() {
+        Decimal::ZERO
+    } else {
+        part / whole * Decimal::ONE_HUNDRED
+    }
+}
+
+/// For statistics (volatility, correlation, simulations) that work in
+/// floating point. Never feed the result back into stored amounts.
+pub fn to_f64(value: Decimal) -> f64 {
+    value.to_f64().unwrap_or(0.0)
+}
+
+/// Convert a floating-point figure (such as a quote from a JSON API) using
+/// its shortest decimal representation, so 0.1 becomes exactly 0.1.
+pub fn from_f64(value: f64) -> Decimal {
+    value
+        .to_string()
+        .parse()
+        .or_else(|_| Decimal::from_f64(value).ok_or(()))
+        .unwrap_or(Decimal::ZERO)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_cents_add_up_exactly() {
+        let total: Decimal = std::iter::repeat_n(dec!(0.1), 10).sum();
+        assert_eq!(total, dec!(1.0));
+        assert_ne!((0..10).map(|_| 0.1_f64).sum::<f64>(), 1.0);
+    }
+
+    #[test]
+    fn test_round_money_per_currency() {
+        assert_eq!(round_money(dec!(2.345), "USD"), dec!(2.35));
+        assert_eq!(round_money(dec!(-2.345), "EUR"), dec!(-2.35));
+        assert_eq!(round_money(dec!(1234.5), "JPY"), dec!(1235));
+        assert_eq!(round_money(dec!(1.2345), "KWD"), dec!(1.235));
+    }
+
+    #[test]
+    fn test_round_quantity_per_asset() {
+        assert_eq!(round_quantity(dec!(0.123456789), "BTC-USD"), dec!(0.12345679));
+        assert_eq!(round_quantity(dec!(0.1234567), "AAPL"), dec!(0.123457));
+        assert_eq!(floor_quantity(dec!(0.1234567), "AAPL"), dec!(0.123456));
+    }
+
+    #[test]
+    fn test_percentage_of_zero_whole() {
+        assert_eq!(percentage(dec!(5), Decimal::ZERO), Decimal::ZERO);
+        assert_eq!(percentage(dec!(1), dec!(4)), dec!(25));
+    }
+
+    #[test]
+    fn test_from_f64_uses_shortest_representation() {
+        assert_eq!(from_f64(0.1), dec!(0.1));
+        assert_eq!(from_f64(170.0), dec!(170));
+        assert_eq!(from_f64(1e-10), dec!(0.0000000001));
+    }
+}
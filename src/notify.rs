@@ -0,0 +1,589 @@
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use lettre::message::{Mailbox, Message};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Tokio1Executor};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use tokio::io::AsyncWriteExt;
+
+use crate::alerts::Alert;
+use crate::portfolio::write_file_atomic;
+
+// Delivery of alerts (and scheduled reports) to the places people look:
+// an HTTP webhook, email over SMTP, a local command or a desktop
+// notification. Sinks are configured in the `notify` section of the alert
+// rules file. Each delivery is retried with a growing delay, and alerts
+// already sent within the dedupe window are held back, so a cron job that
+// runs every few minutes does not send the same alert every time.
+
+pub const DEFAULT_DEDUPE_MINUTES: i64 = 24 * 60;
+pub const DEFAULT_RETRIES: u32 = 3;
+pub const DEFAULT_RETRY_DELAY_MS: u64 = 1000;
+const SEND_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+fn default_dedupe_minutes() -> i64 {
+    DEFAULT_DEDUPE_MINUTES
+}
+
+fn default_retries() -> u32 {
+    DEFAULT_RETRIES
+}
+
+fn default_retry_delay_ms() -> u64 {
+    DEFAULT_RETRY_DELAY_MS
+}
+
+fn default_smtp_port() -> u16 {
+    587
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpSecurity {
+    /// Plain connection, for a relay on the same machine.
+    None,
+    /// Upgrade with STARTTLS (port 587).
+    #[default]
+    Starttls,
+    /// TLS from the start (port 465).
+    Tls,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum Sink {
+    /// POST the notification as JSON.
+    Webhook {
+        url: String,
+        #[serde(default)]
+        headers: BTreeMap<String, String>,
+    },
+    /// Send a plain-text email.
+    Email {
+        host: String,
+        #[serde(default = "default_smtp_port")]
+        port: u16,
+        #[serde(default)]
+        security: SmtpSecurity,
+        from: String,
+        to: Vec<String>,
+        #[serde(default)]
+        username: Option<String>,
+        /// Environment variable holding the SMTP password, so it stays out
+        /// of the file.
+        #[serde(default)]
+        password_env: Option<String>,
+    },
+    /// Run a program with the notification as JSON on stdin and its title
+    /// and body in `PORTFOLIO_NOTIFY_TITLE` and `PORTFOLIO_NOTIFY_BODY`.
+    Command {
+        program: String,
+        #[serde(default)]
+        args: Vec<String>,
+    },
+    /// Desktop notification through `notify-send` (Linux) or `osascript`
+    /// (macOS).
+    Desktop,
+}
+
+impl Sink {
+    pub fn name(&self) -> String {
+        match self {
+            Sink::Webhook { url, .. } => format!("webhook {}", url),
+            Sink::Email { to, .. } => format!("email to {}", to.join(", ")),
+            Sink::Command { program, .. } => format!("command {}", program),
+            Sink::Desktop => "desktop".to_string(),
+        }
+    }
+
+    pub async fn send(&self, notification: &Notification) -> Result<()> {
+        match self {
+            Sink::Webhook { url, headers } => send_webhook(url, headers, notification).await,
+            Sink::Email { host, port, security, from, to, username, password_env } => {
+                let credentials = match username {
+                    Some(username) => {
+                        let password = match password_env {
+                            Some(var) => std::env::var(var)
+                                .with_context(|| format!("SMTP password variable {} is not set", var))?,
+                            None => String::new(),
+                        };
+                        Some(Credentials::new(username.clone(), password))
+                    }
+                    None => None,
+                };
+                send_email(host, *port, *security, from, to, credentials, notification).await
+            }
+            Sink::Command { program, args } => run_command(program, args, notification).await,
+            Sink::Desktop => {
+                let (program, args) = desktop_command(notification);
+                run_command(program, &args, notification).await
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NotifySettings {
+    #[serde(default)]
+    pub sinks: Vec<Sink>,
+    /// Minutes during which an alert that was sent is not sent again.
+    #[serde(default = "default_dedupe_minutes")]
+    pub dedupe_minutes: i64,
+    /// Attempts after the first one failed.
+    #[serde(default = "default_retries")]
+    pub retries: u32,
+    /// Delay before the first retry; doubled for every further retry.
+    #[serde(default = "default_retry_delay_ms")]
+    pub retry_delay_ms: u64,
+    /// Where sent alerts are recorded (default: next to the rules file,
+    /// as `<name>.sent.json`).
+    #[serde(default)]
+    pub state_file: Option<PathBuf>,
+}
+
+impl NotifySettings {
+    pub fn state_path(&self, rules_file: &Path) -> PathBuf {
+        self.state_file.clone().unwrap_or_else(|| rules_file.with_extension("sent.json"))
+    }
+}
+
+/// What gets delivered: a title and plain-text body for people, and the
+/// alerts themselves for programs.
+#[derive(Debug, Clone, Serialize)]
+pub struct Notification {
+    pub title: String,
+    pub body: String,
+    pub sent_at: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub alerts: Vec<Alert>,
+}
+
+impl Notification {
+    pub fn message(title: impl Into<String>, body: impl Into<String>) -> Self {
+        Notification { title: title.into(), body: body.into(), sent_at: Utc::now(), alerts: Vec::new() }
+    }
+
+    pub fn for_alerts(alerts: Vec<Alert>) -> Self {
+        let title = match alerts.as_slice() {
+            [alert] => format!("Portfolio alert: {}", alert.rule),
+            _ => format!("Portfolio: {} alerts", alerts.len()),
+        };
+        let body = alerts.iter().map(|a| format!("{}: {}", a.rule, a.message)).collect::<Vec<_>>().join("\n");
+        Notification { title, body, sent_at: Utc::now(), alerts }
+    }
+}
+
+/// Send to one sink, retrying with a doubling delay.
+pub async fn send_with_retries(sink: &Sink, notification: &Notification, settings: &NotifySettings) -> Result<()> {
+    let mut delay = std::time::Duration::from_millis(settings.retry_delay_ms);
+    let mut attempt = 0;
+    loop {
+        match sink.send(notification).await {
+            Ok(()) => return Ok(()),
+            Err(err) if attempt >= settings.retries => {
+                return Err(err.context(format!("{} failed after {} attempt(s)", sink.name(), attempt + 1)));
+            }
+            Err(_) => {
+                attempt += 1;
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+            }
+        }
+    }
+}
+
+/// Send to every sink. Returns the errors of the sinks that failed.
+pub async fn deliver(notification: &Notification, settings: &NotifySettings) -> Vec<anyhow::Error> {
+    let mut errors = Vec::new();
+    for sink in &settings.sinks {
+        if let Err(err) = send_with_retries(sink, notification, settings).await {
+            errors.push(err);
+        }
+    }
+    errors
+}
+
+#[derive(Debug, Default)]
+pub struct Delivery {
+    pub sent: usize,
+    /// Alerts held back because they were sent within the dedupe window.
+    pub suppressed: usize,
+    pub errors: Vec<anyhow::Error>,
+}
+
+/// Deliver the alerts that were not already sent within the dedupe window,
+/// recording them in the state file once at least one sink accepted them.
+pub async fn notify_alerts(alerts: &[Alert], settings: &NotifySettings, state_path: &Path) -> Result<Delivery> {
+    let now = Utc::now();
+    let mut log = SentLog::load(state_path)?;
+    let window = Duration::minutes(settings.dedupe_minutes);
+    let fresh: Vec<Alert> = alerts.iter().filter(|a| !log.sent_within(a, now, window)).cloned().collect();
+    let mut delivery = Delivery { suppressed: alerts.len() - fresh.len(), ..Delivery::default() };
+    if fresh.is_empty() || settings.sinks.is_empty() {
+        return Ok(delivery);
+    }
+
+    delivery.errors = deliver(&Notification::for_alerts(fresh.clone()), settings).await;
+    if delivery.errors.len() < settings.sinks.len() {
+        for alert in &fresh {
+            log.record(alert, now);
+        }
+        log.forget_before(now - window);
+        log.save(state_path)?;
+        delivery.sent = fresh.len();
+    }
+    Ok(delivery)
+}
+
+/// When each alert was last sent, keyed by rule, kind and ticker.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SentLog {
+    sent: BTreeMap<String, DateTime<Utc>>,
+}
+
+impl SentLog {
+    pub fn load(path: &Path) -> Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content)
+                .with_context(|| format!("Failed to parse sent alerts: {:?}", path)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(SentLog::default()),
+            Err(err) => Err(err).with_context(|| format!("Failed to read sent alerts: {:?}", path)),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        write_file_atomic(path, serde_json::to_string_pretty(self)?.as_bytes())
+    }
+
+    fn key(alert: &Alert) -> String {
+        format!("{}|{}|{}", alert.kind, alert.rule, alert.ticker.as_deref().unwrap_or(""))
+    }
+
+    pub fn sent_within(&self, alert: &Alert, now: DateTime<Utc>, window: Duration) -> bool {
+        self.sent.get(&Self::key(alert)).is_some_and(|at| now - *at < window)
+    }
+
+    pub fn record(&mut self, alert: &Alert, at: DateTime<Utc>) {
+        self.sent.insert(Self::key(alert), at);
+    }
+
+    fn forget_before(&mut self, cutoff: DateTime<Utc>) {
+        self.sent.retain(|_, at| *at >= cutoff);
+    }
+}
+
+async fn send_webhook(url: &str, headers: &BTreeMap<String, String>, notification: &Notification) -> Result<()> {
+    let client = reqwest::Client::builder().timeout(SEND_TIMEOUT).build()?;
+    let mut request = client.post(url).json(notification);
+    for (name, value) in headers {
+        request = request.header(name, value);
+    }
+    request
+        .send()
+        .await
+        .with_context(|| format!("Failed to reach webhook {}", url))?
+        .error_for_status()
+        .with_context(|| format!("Webhook {} rejected the notification", url))?;
+    Ok(())
+}
+
+async fn send_email(
+    host: &str,
+    port: u16,
+    security: SmtpSecurity,
+    from: &str,
+    to: &[String],
+    credentials: Option<Credentials>,
+    notification: &Notification,
+) -> Result<()> {
+    if to.is_empty() {
+        bail!("Email sink has no recipients");
+    }
+    let mut message = Message::builder()
+        .from(from.parse::<Mailbox>().with_context(|| format!("Invalid sender address: {}", from))?)
+        .subject(&notification.title);
+    for recipient in to {
+        message = message.to(recipient.parse::<Mailbox>().with_context(|| format!("Invalid recipient address: {}", recipient))?);
+    }
+    let message = message.body(notification.body.clone())?;
+
+    let mut transport = match security {
+        SmtpSecurity::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host),
+        SmtpSecurity::Starttls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)?,
+        SmtpSecurity::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(host)?,
+    }
+    .port(port)
+    .timeout(Some(SEND_TIMEOUT));
+    if let Some(credentials) = credentials {
+        transport = transport.credentials(credentials);
+    }
+    transport
+        .build()
+        .send(message)
+        .await
+        .with_context(|| format!("Failed to send email through {}:{}", host, port))?;
+    Ok(())
+}
+
+async fn run_command(program: &str, args: &[String], notification: &Notification) -> Result<()> {
+    let mut child = tokio::process::Command::new(program)
+        .args(args)
+        .env("PORTFOLIO_NOTIFY_TITLE", &notification.title)
+        .env("PORTFOLIO_NOTIFY_BODY", &notification.body)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()
+        .with_context(|| format!("Failed to run {}", program))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // A program that does not read its input is fine
+        let _ = stdin.write_all(&serde_json::to_vec(notification)?).await;
+    }
+    let status = tokio::time::timeout(SEND_TIMEOUT, child.wait())
+        .await
+        .with_context(|| format!("{} did not finish in time", program))??;
+    if !status.success() {
+        bail!("{} exited with {}", program, status);
+    }
+    Ok(())
+}
+
+fn desktop_command(notification: &Notification) -> (&'static str, Vec<String>) {
+    if cfg!(target_os = "macos") {
+        let quote = |s: &str| format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""));
+        let script = format!("display notification {} with title {}", quote(&notification.body), quote(&notification.title));
+        ("osascript", vec!["-e".to_string(), script])
+    } else {
+        ("notify-send", vec![notification.title.clone(), notification.body.clone()])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+    use std::sync::{Arc, Mutex};
+    use tempfile::tempdir;
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
+    use tokio::net::TcpListener;
+
+    fn alert(rule: &str, ticker: &str) -> Alert {
+        Alert {
+            rule: rule.to_string(),
+            kind: "price-above",
+            ticker: Some(ticker.to_string()),
+            value: dec!(210),
+            threshold: dec!(200),
+            message: format!("{} is at 210, at or above 200", ticker),
+        }
+    }
+
+    fn settings(sinks: Vec<Sink>) -> NotifySettings {
+        NotifySettings {
+            sinks,
+            dedupe_minutes: DEFAULT_DEDUPE_MINUTES,
+            retries: 2,
+            retry_delay_ms: 1,
+            state_file: None,
+        }
+    }
+
+    /// Minimal HTTP server: answers each request with the next status in
+    /// `statuses` (200 once they run out) and records the request bodies.
+    async fn http_listener(statuses: Vec<u16>) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let bodies = Arc::new(Mutex::new(Vec::new()));
+        let recorded = bodies.clone();
+        tokio::spawn(async move {
+            let mut statuses = statuses.into_iter();
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut reader = BufReader::new(stream);
+                let mut length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).await.unwrap();
+                    if let Some(value) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                        length = value.trim().parse().unwrap();
+                    }
+                    if line == "\r\n" {
+                        break;
+                    }
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).await.unwrap();
+                recorded.lock().unwrap().push(String::from_utf8(body).unwrap());
+                let status = statuses.next().unwrap_or(200);
+                let response = format!("HTTP/1.1 {} X\r\ncontent-length: 0\r\nconnection: close\r\n\r\n", status);
+                reader.into_inner().write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        (url, bodies)
+    }
+
+    /// SMTP stand-in that accepts one message and records its data.
+    async fn smtp_listener() -> (u16, Arc<Mutex<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let data = Arc::new(Mutex::new(String::new()));
+        let recorded = data.clone();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (read, mut write) = stream.into_split();
+            let mut reader = BufReader::new(read);
+            write.write_all(b"220 localhost ESMTP stand-in\r\n").await.unwrap();
+            let mut in_data = false;
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).await.unwrap() == 0 {
+                    break;
+                }
+                if in_data {
+                    if line == ".\r\n" {
+                        in_data = false;
+                        write.write_all(b"250 OK queued\r\n").await.unwrap();
+                    } else {
+                        recorded.lock().unwrap().push_str(&line);
+                    }
+                    continue;
+                }
+                let command = line.to_ascii_uppercase();
+                let reply: &[u8] = if command.starts_with("EHLO") || command.starts_with("HELO") {
+                    b"250 localhost\r\n"
+                } else if command.starts_with("DATA") {
+                    in_data = true;
+                    b"354 End data with <CR><LF>.<CR><LF>\r\n"
+                } else if command.starts_with("QUIT") {
+                    write.write_all(b"221 Bye\r\n").await.unwrap();
+                    break;
+                } else {
+                    b"250 OK\r\n"
+                };
+                write.write_all(reply).await.unwrap();
+            }
+        });
+        (port, data)
+    }
+
+    #[tokio::test]
+    async fn test_webhook_posts_json_and_retries() {
+        let (url, bodies) = http_listener(vec![503]).await;
+        let sink = Sink::Webhook { url, headers: BTreeMap::new() };
+        let notification = Notification::for_alerts(vec![alert("Breakout", "AAPL")]);
+
+        send_with_retries(&sink, &notification, &settings(vec![])).await.unwrap();
+
+        let bodies = bodies.lock().unwrap();
+        assert_eq!(bodies.len(), 2);
+        let payload: serde_json::Value = serde_json::from_str(&bodies[1]).unwrap();
+        assert_eq!(payload["title"], "Portfolio alert: Breakout");
+        assert_eq!(payload["alerts"][0]["ticker"], "AAPL");
+        assert_eq!(payload["alerts"][0]["value"], 210.0);
+    }
+
+    #[tokio::test]
+    async fn test_webhook_gives_up_after_retries() {
+        let (url, bodies) = http_listener(vec![500, 500, 500, 500]).await;
+        let sink = Sink::Webhook { url, headers: BTreeMap::new() };
+
+        let err = send_with_retries(&sink, &Notification::message("t", "b"), &settings(vec![])).await.unwrap_err();
+
+        assert!(err.to_string().contains("failed after 3 attempt(s)"));
+        assert_eq!(bodies.lock().unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_email_through_smtp_stand_in() {
+        let (port, data) = smtp_listener().await;
+        let sink = Sink::Email {
+            host: "127.0.0.1".to_string(),
+            port,
+            security: SmtpSecurity::None,
+            from: "Portfolio <portfolio@localhost>".to_string(),
+            to: vec!["me@localhost".to_string()],
+            username: None,
+            password_env: None,
+        };
+
+        sink.send(&Notification::for_alerts(vec![alert("Breakout", "AAPL")])).await.unwrap();
+
+        let data = data.lock().unwrap();
+        assert!(data.contains("Subject: Portfolio alert: Breakout"));
+        assert!(data.contains("To: me@localhost"));
+        assert!(data.contains("AAPL is at 210, at or above 200"));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_command_gets_json_on_stdin() {
+        let dir = tempdir().unwrap();
+        let out = dir.path().join("out.json");
+        let sink = Sink::Command {
+            program: "sh".to_string(),
+            args: vec!["-c".to_string(), format!("cat > {}", out.display())],
+        };
+
+        sink.send(&Notification::message("Weekly report", "All good")).await.unwrap();
+
+        let payload: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&out).unwrap()).unwrap();
+        assert_eq!(payload["title"], "Weekly report");
+        assert!(payload.get("alerts").is_none());
+
+        let failing = Sink::Command { program: "false".to_string(), args: vec![] };
+        assert!(failing.send(&Notification::message("t", "b")).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_notify_alerts_dedupes_within_window() {
+        let dir = tempdir().unwrap();
+        let state = dir.path().join("alerts.sent.json");
+        let (url, bodies) = http_listener(vec![]).await;
+        let settings = settings(vec![Sink::Webhook { url, headers: BTreeMap::new() }]);
+
+        let first = notify_alerts(&[alert("Breakout", "AAPL")], &settings, &state).await.unwrap();
+        assert_eq!((first.sent, first.suppressed), (1, 0));
+
+        let second = notify_alerts(&[alert("Breakout", "AAPL"), alert("Breakout", "MSFT")], &settings, &state)
+            .await
+            .unwrap();
+        assert_eq!((second.sent, second.suppressed), (1, 1));
+
+        let bodies = bodies.lock().unwrap();
+        assert_eq!(bodies.len(), 2);
+        assert!(bodies[1].contains("MSFT") && !bodies[1].contains("AAPL"));
+    }
+
+    #[tokio::test]
+    async fn test_failed_delivery_is_not_recorded() {
+        let dir = tempdir().unwrap();
+        let state = dir.path().join("alerts.sent.json");
+        let mut settings = settings(vec![Sink::Command { program: "/nonexistent/notifier".to_string(), args: vec![] }]);
+        settings.retries = 0;
+
+        let delivery = notify_alerts(&[alert("Breakout", "AAPL")], &settings, &state).await.unwrap();
+
+        assert_eq!(delivery.sent, 0);
+        assert_eq!(delivery.errors.len(), 1);
+        assert!(!state.exists());
+    }
+
+    #[test]
+    fn test_sent_log_window() {
+        let now = Utc::now();
+        let mut log = SentLog::default();
+        log.record(&alert("Breakout", "AAPL"), now - Duration::hours(2));
+
+        assert!(log.sent_within(&alert("Breakout", "AAPL"), now, Duration::hours(3)));
+        assert!(!log.sent_within(&alert("Breakout", "AAPL"), now, Duration::hours(1)));
+        assert!(!log.sent_within(&alert("Breakout", "MSFT"), now, Duration::hours(3)));
+    }
+
+    #[test]
+    fn test_state_path_defaults_next_to_rules() {
+        let settings = settings(vec![]);
+        assert_eq!(settings.state_path(Path::new("/tmp/alerts.toml")), PathBuf::from("/tmp/alerts.sent.json"));
+    }
+}
@@ -1,69 +1,370 @@
 use anyhow::{Context, Result};
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
 use std::fs;
+use std::io::Write;
 use std::path::Path;
+use utoipa::ToSchema;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+use crate::crypto;
+use crate::formats::{self, FileFormat};
+use crate::money;
+use crate::schema::{self, Metadata, PortfolioDocument, CURRENT_SCHEMA_VERSION};
+use crate::storage;
+use crate::validation::{self, ValidationReport};
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct Holding {
     pub ticker: String,
-    pub quantity: f64,
-    pub cost_basis: f64,
+    pub quantity: Decimal,
+    pub cost_basis: Decimal,
     pub date_purchased: String,
 }
 
+impl Holding {
+    pub fn purchase_date(&self) -> Result<NaiveDate> {
+        NaiveDate::parse_from_str(&self.date_purchased, validation::DATE_FORMAT)
+            .with_context(|| format!("Invalid purchase date for {}: {}", self.ticker, self.date_purchased))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum TransactionKind {
+    Buy,
+    Sell,
+    Dividend,
+    Deposit,
+    Withdrawal,
+    Fee,
+}
+
+impl TransactionKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            TransactionKind::Buy => "BUY",
+            TransactionKind::Sell => "SELL",
+            TransactionKind::Dividend => "DIVIDEND",
+            TransactionKind::Deposit => "DEPOSIT",
+            TransactionKind::Withdrawal => "WITHDRAWAL",
+            TransactionKind::Fee => "FEE",
+        }
+    }
+}
+
+/// Which lots a sale takes from first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum CostBasisMethod {
+    /// Oldest lots first
+    #[default]
+    Fifo,
+    /// Newest lots first
+    Lifo,
+    /// Most expensive lots first, realizing the smallest gain
+    Hifo,
+}
+
+/// An entry in the portfolio's ledger. Cash movements (deposits,
+/// withdrawals, fees) have no ticker and use `price` as the amount.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct Transaction {
+    pub date: String,
+    pub kind: TransactionKind,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ticker: Option<String>,
+    #[serde(default)]
+    pub quantity: Decimal,
+    #[serde(default)]
+    pub price: Decimal,
+    #[serde(default)]
+    pub fees: Decimal,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+impl Transaction {
+    /// Gross amount of the transaction, before fees.
+    pub fn amount(&self) -> Decimal {
+        match self.ticker {
+            Some(_) => self.quantity * self.price,
+            None => self.price,
+        }
+    }
+}
+
+/// A ticker followed without holding it. `price_added` is the quote when it
+/// was added, so the watchlist can show how far it has moved since.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WatchItem {
+    pub ticker: String,
+    pub added: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub price_added: Option<Decimal>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target_price: Option<Decimal>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+/// Value of every position at a point in time.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct Snapshot {
+    pub taken_at: chrono::DateTime<chrono::Utc>,
+    pub total_value: Decimal,
+    pub positions: Vec<SnapshotPosition>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct SnapshotPosition {
+    pub ticker: String,
+    pub quantity: Decimal,
+    pub price: Decimal,
+    pub value: Decimal,
+}
+
+impl Snapshot {
+    /// Capture the current value of each ticker, merging lots.
+    pub fn capture(holdings: &[HoldingWithPrice], taken_at: chrono::DateTime<chrono::Utc>) -> Snapshot {
+        let mut positions: Vec<SnapshotPosition> = Vec::new();
+        for h in holdings {
+            match positions.iter_mut().find(|p| p.ticker == h.holding.ticker) {
+                Some(position) => {
+                    position.quantity += h.holding.quantity;
+                    position.value += h.current_value();
+                }
+                None => positions.push(SnapshotPosition {
+                    ticker: h.holding.ticker.clone(),
+                    quantity: h.holding.quantity,
+                    price: h.current_price,
+                    value: h.current_value(),
+                }),
+            }
+        }
+        positions.sort_by(|a, b| a.ticker.cmp(&b.ticker));
+        let total_value = positions.iter().map(|p| p.value).sum();
+
+        Snapshot { taken_at, total_value, positions }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct HoldingWithPrice {
     pub holding: Holding,
-    pub current_price: f64,
+    pub current_price: Decimal,
 }
 
+// These are exact; reports round them to the portfolio's currency.
 impl HoldingWithPrice {
-    pub fn current_value(&self) -> f64 {
+    pub fn current_value(&self) -> Decimal {
         self.holding.quantity * self.current_price
     }
 
-    pub fn total_cost(&self) -> f64 {
+    pub fn total_cost(&self) -> Decimal {
         self.holding.quantity * self.holding.cost_basis
     }
 
-    pub fn gain_loss(&self) -> f64 {
+    pub fn gain_loss(&self) -> Decimal {
         self.current_value() - self.total_cost()
     }
 
-    pub fn gain_loss_percentage(&self) -> f64 {
-        if self.total_cost() == 0.0 {
-            0.0
-        } else {
-            (self.gain_loss() / self.total_cost()) * 100.0
-        }
+    pub fn gain_loss_percentage(&self) -> Decimal {
+        money::percentage(self.gain_loss(), self.total_cost())
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Portfolio {
     pub holdings: Vec<Holding>,
+    pub transactions: Vec<Transaction>,
+    pub watchlist: Vec<WatchItem>,
+    pub base_currency: String,
+    pub metadata: Metadata,
+}
+
+impl Default for Portfolio {
+    fn default() -> Self {
+        Portfolio {
+            holdings: Vec::new(),
+            transactions: Vec::new(),
+            watchlist: Vec::new(),
+            base_currency: schema::default_base_currency(),
+            metadata: Metadata::default(),
+        }
+    }
 }
 
 impl Portfolio {
+    /// Load a portfolio file of any schema version. Older layouts are
+    /// migrated in memory; use `migrate` to upgrade the file itself.
     pub fn load_from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let content = fs::read_to_string(&path)
-            .with_context(|| format!("Failed to read portfolio file: {:?}", path.as_ref()))?;
-        
-        let holdings: Vec<Holding> = serde_json::from_str(&content)
+        storage::open(path.as_ref())?.load()
+    }
+
+    pub fn from_json_str(content: &str) -> Result<Self> {
+        Self::from_str_with_format(content, FileFormat::Json)
+    }
+
+    /// Parse, migrate and validate a portfolio. Validation errors fail the
+    /// load with a `ValidationReport` listing every problem; warnings are
+    /// only shown by the `validate` command.
+    pub fn from_str_with_format(content: &str, format: FileFormat) -> Result<Self> {
+        let (value, report) = parse_and_validate(content, format)?;
+        if report.has_errors() {
+            return Err(report.into());
+        }
+
+        let document: PortfolioDocument = serde_json::from_value(value)
             .with_context(|| "Failed to parse portfolio JSON")?;
 
-        Ok(Portfolio { holdings })
+        Ok(Portfolio::from(document))
+    }
+
+    pub fn to_json_string(&self) -> Result<String> {
+        self.to_string_with_format(FileFormat::Json)
+    }
+
+    pub fn to_string_with_format(&self, format: FileFormat) -> Result<String> {
+        formats::render(&self.to_document(), format)
+    }
+
+    pub fn to_document(&self) -> PortfolioDocument {
+        PortfolioDocument {
+            schema_version: CURRENT_SCHEMA_VERSION,
+            metadata: self.metadata.clone(),
+            base_currency: self.base_currency.clone(),
+            holdings: self.holdings.clone(),
+            transactions: self.transactions.clone(),
+            watchlist: self.watchlist.clone(),
+        }
+    }
+
+    /// Write the portfolio in the current schema version to whichever store
+    /// the path names (see `storage::create`).
+    pub fn save_to_file<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        storage::create(path.as_ref())?.save(self)
+    }
+
+    pub fn save_to_file_as(&mut self, path: &Path, format: FileFormat, encrypt: bool) -> Result<()> {
+        let now = chrono::Utc::now();
+        self.metadata.created_at.get_or_insert(now);
+        self.metadata.updated_at = Some(now);
+
+        let text = self.to_string_with_format(format)?;
+        let bytes = if encrypt {
+            crypto::encrypt(text.as_bytes(), &crypto::passphrase()?)?
+        } else {
+            text.into_bytes()
+        };
+
+        write_file_atomic(path, &bytes)
     }
 
     pub fn get_tickers(&self) -> Vec<String> {
         self.holdings.iter().map(|h| h.ticker.clone()).collect()
     }
 
-    pub fn holdings_with_prices(&self, prices: &std::collections::HashMap<String, f64>) -> Vec<HoldingWithPrice> {
+    /// Add a ticker to the watchlist. Adding a ticker that is already watched
+    /// updates its target and note but keeps when, and at what price, it was
+    /// first added.
+    pub fn watch(&mut self, item: WatchItem) {
+        match self.watchlist.iter_mut().find(|w| w.ticker == item.ticker) {
+            Some(existing) => {
+                if item.target_price.is_some() {
+                    existing.target_price = item.target_price;
+                }
+                if item.note.is_some() {
+                    existing.note = item.note;
+                }
+            }
+            None => self.watchlist.push(item),
+        }
+    }
+
+    /// Drop a ticker from the watchlist. Returns whether it was there.
+    pub fn unwatch(&mut self, ticker: &str) -> bool {
+        let before = self.watchlist.len();
+        self.watchlist.retain(|w| w.ticker != ticker);
+        self.watchlist.len() != before
+    }
+
+    /// Record a purchase as a new lot and a buy transaction.
+    pub fn buy(&mut self, ticker: &str, quantity: Decimal, price: Decimal, date: NaiveDate) {
+        let date = date.format(validation::DATE_FORMAT).to_string();
+        self.holdings.push(Holding {
+            ticker: ticker.to_string(),
+            quantity,
+            cost_basis: price,
+            date_purchased: date.clone(),
+        });
+        self.transactions.push(Transaction {
+            date,
+            kind: TransactionKind::Buy,
+            ticker: Some(ticker.to_string()),
+            quantity,
+            price,
+            fees: Decimal::ZERO,
+            note: None,
+        });
+    }
+
+    /// Sell from a ticker's lots in the order `method` picks, and record a
+    /// sell transaction. Returns what was taken from each lot; a lot sold in
+    /// full is removed.
+    pub fn sell(
+        &mut self,
+        ticker: &str,
+        quantity: Decimal,
+        price: Decimal,
+        date: NaiveDate,
+        method: CostBasisMethod,
+    ) -> Result<Vec<Holding>> {
+        let held: Decimal = self.holdings.iter().filter(|h| h.ticker == ticker).map(|h| h.quantity).sum();
+        if quantity > held {
+            anyhow::bail!("Cannot sell {} {}: only {} held", quantity.normalize(), ticker, held.normalize());
+        }
+
+        let mut lots: Vec<usize> = (0..self.holdings.len()).filter(|&i| self.holdings[i].ticker == ticker).collect();
+        // Dates are validated on load; stable sorts keep file order for ties
+        let holdings = &self.holdings;
+        match method {
+            CostBasisMethod::Fifo => lots.sort_by(|&a, &b| holdings[a].date_purchased.cmp(&holdings[b].date_purchased)),
+            CostBasisMethod::Lifo => lots.sort_by(|&a, &b| holdings[b].date_purchased.cmp(&holdings[a].date_purchased)),
+            CostBasisMethod::Hifo => lots.sort_by(|&a, &b| holdings[b].cost_basis.cmp(&holdings[a].cost_basis)),
+        }
+
+        let mut remaining = quantity;
+        let mut sold = Vec::new();
+        for index in lots {
+            if remaining.is_zero() {
+                break;
+            }
+            let lot = &mut self.holdings[index];
+            let taken = remaining.min(lot.quantity);
+            lot.quantity -= taken;
+            remaining -= taken;
+            sold.push(Holding { quantity: taken, ..lot.clone() });
+        }
+        self.holdings.retain(|h| !h.quantity.is_zero());
+
+        self.transactions.push(Transaction {
+            date: date.format(validation::DATE_FORMAT).to_string(),
+            kind: TransactionKind::Sell,
+            ticker: Some(ticker.to_string()),
+            quantity,
+            price,
+            fees: Decimal::ZERO,
+            note: None,
+        });
+        Ok(sold)
+    }
+
+    pub fn holdings_with_prices(&self, prices: &std::collections::HashMap<String, Decimal>) -> Vec<HoldingWithPrice> {
         self.holdings
             .iter()
             .map(|holding| {
-                let current_price = *prices.get(&holding.ticker).unwrap_or(&0.0);
+                let current_price = prices.get(&holding.ticker).copied().unwrap_or_default();
                 HoldingWithPrice {
                     holding: holding.clone(),
                     current_price,
@@ -73,9 +374,70 @@ impl Portfolio {
     }
 }
 
+impl From<PortfolioDocument> for Portfolio {
+    fn from(document: PortfolioDocument) -> Self {
+        Portfolio {
+            holdings: document.holdings,
+            transactions: document.transactions,
+            watchlist: document.watchlist,
+            base_currency: document.base_currency,
+            metadata: document.metadata,
+        }
+    }
+}
+
+/// Parse and migrate a portfolio file's text and check it for problems.
+/// Fails only when the text is not a readable portfolio at all (syntax
+/// errors, unknown schema version).
+pub fn parse_and_validate(content: &str, format: FileFormat) -> Result<(serde_json::Value, ValidationReport)> {
+    let (value, lines) = formats::parse(content, format)?;
+    let (value, _) = schema::migrate(value)?;
+    let report = validation::validate_document(&value, &lines);
+    Ok((value, report))
+}
+
+/// Read a portfolio file as text, transparently decrypting encrypted files.
+pub fn read_portfolio_text<P: AsRef<Path>>(path: P) -> Result<String> {
+    let path = path.as_ref();
+    let bytes = fs::read(path)
+        .with_context(|| format!("Failed to read portfolio file: {:?}", path))?;
+
+    let bytes = if crypto::is_encrypted(&bytes) {
+        crypto::decrypt(&bytes, &crypto::passphrase()?)
+            .with_context(|| format!("Failed to decrypt portfolio file: {:?}", path))?
+    } else {
+        bytes
+    };
+
+    String::from_utf8(bytes)
+        .with_context(|| format!("Portfolio file is not valid UTF-8: {:?}", path))
+}
+
+/// Replace a file's contents without leaving a half-written file behind if
+/// the process dies mid-write: write a sibling temp file, then rename it.
+pub fn write_file_atomic(path: &Path, contents: &[u8]) -> Result<()> {
+    let file_name = path
+        .file_name()
+        .with_context(|| format!("Not a file path: {:?}", path))?;
+    let mut tmp_name = file_name.to_os_string();
+    tmp_name.push(".tmp");
+    let tmp_path = path.with_file_name(tmp_name);
+
+    let mut file = fs::File::create(&tmp_path)
+        .with_context(|| format!("Failed to create temporary file: {:?}", tmp_path))?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    drop(file);
+
+    fs::rename(&tmp_path, path)
+        .with_context(|| format!("Failed to replace file: {:?}", path))?;
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rust_decimal_macros::dec;
     use std::collections::HashMap;
     use std::fs;
     use tempfile::tempdir;
@@ -83,8 +445,8 @@ mod tests {
     fn create_test_holding() -> Holding {
         Holding {
             ticker: "AAPL".to_string(),
-            quantity: 10.0,
-            cost_basis: 150.0,
+            quantity: dec!(10.0),
+            cost_basis: dec!(150.0),
             date_purchased: "2023-01-01".to_string(),
         }
     }
@@ -94,17 +456,18 @@ mod tests {
             holdings: vec![
                 Holding {
                     ticker: "AAPL".to_string(),
-                    quantity: 10.0,
-                    cost_basis: 150.0,
+                    quantity: dec!(10.0),
+                    cost_basis: dec!(150.0),
                     date_purchased: "2023-01-01".to_string(),
                 },
                 Holding {
                     ticker: "TSLA".to_string(),
-                    quantity: 5.0,
-                    cost_basis: 200.0,
+                    quantity: dec!(5.0),
+                    cost_basis: dec!(200.0),
                     date_purchased: "2023-02-01".to_string(),
                 },
             ],
+            ..Default::default()
         }
     }
 
@@ -113,10 +476,10 @@ mod tests {
         let holding = create_test_holding();
         let holding_with_price = HoldingWithPrice {
             holding,
-            current_price: 175.0,
+            current_price: dec!(175.0),
         };
 
-        assert_eq!(holding_with_price.current_value(), 1750.0); // 10 * 175
+        assert_eq!(holding_with_price.current_value(), dec!(1750)); // 10 * 175
     }
 
     #[test]
@@ -124,10 +487,10 @@ mod tests {
         let holding = create_test_holding();
         let holding_with_price = HoldingWithPrice {
             holding,
-            current_price: 175.0,
+            current_price: dec!(175.0),
         };
 
-        assert_eq!(holding_with_price.total_cost(), 1500.0); // 10 * 150
+        assert_eq!(holding_with_price.total_cost(), dec!(1500)); // 10 * 150
     }
 
     #[test]
@@ -135,10 +498,10 @@ mod tests {
         let holding = create_test_holding();
         let holding_with_price = HoldingWithPrice {
             holding,
-            current_price: 175.0,
+            current_price: dec!(175.0),
         };
 
-        assert_eq!(holding_with_price.gain_loss(), 250.0); // 1750 - 1500
+        assert_eq!(holding_with_price.gain_loss(), dec!(250)); // 1750 - 1500
     }
 
     #[test]
@@ -146,23 +509,24 @@ mod tests {
         let holding = create_test_holding();
         let holding_with_price = HoldingWithPrice {
             holding,
-            current_price: 175.0,
+            current_price: dec!(175.0),
         };
 
-        let expected_percentage = (250.0 / 1500.0) * 100.0;
-        assert!((holding_with_price.gain_loss_percentage() - expected_percentage).abs() < 0.001);
+        let expected_percentage = dec!(250) / dec!(1500) * dec!(100);
+        assert_eq!(holding_with_price.gain_loss_percentage(), expected_percentage);
+        assert_eq!(money::round_percent(expected_percentage), dec!(16.67));
     }
 
     #[test]
     fn test_holding_with_price_gain_loss_percentage_zero_cost() {
         let mut holding = create_test_holding();
-        holding.cost_basis = 0.0;
+        holding.cost_basis = Decimal::ZERO;
         let holding_with_price = HoldingWithPrice {
             holding,
-            current_price: 175.0,
+            current_price: dec!(175.0),
         };
 
-        assert_eq!(holding_with_price.gain_loss_percentage(), 0.0);
+        assert_eq!(holding_with_price.gain_loss_percentage(), Decimal::ZERO);
     }
 
     #[test]
@@ -179,8 +543,8 @@ mod tests {
     fn test_portfolio_holdings_with_prices() {
         let portfolio = create_test_portfolio();
         let mut prices = HashMap::new();
-        prices.insert("AAPL".to_string(), 175.0);
-        prices.insert("TSLA".to_string(), 250.0);
+        prices.insert("AAPL".to_string(), dec!(175));
+        prices.insert("TSLA".to_string(), dec!(250));
 
         let holdings_with_prices = portfolio.holdings_with_prices(&prices);
 
@@ -189,19 +553,19 @@ mod tests {
         let aapl_holding = holdings_with_prices.iter()
             .find(|h| h.holding.ticker == "AAPL")
             .unwrap();
-        assert_eq!(aapl_holding.current_price, 175.0);
+        assert_eq!(aapl_holding.current_price, dec!(175));
         
         let tsla_holding = holdings_with_prices.iter()
             .find(|h| h.holding.ticker == "TSLA")
             .unwrap();
-        assert_eq!(tsla_holding.current_price, 250.0);
+        assert_eq!(tsla_holding.current_price, dec!(250));
     }
 
     #[test]
     fn test_portfolio_holdings_with_prices_missing_ticker() {
         let portfolio = create_test_portfolio();
         let mut prices = HashMap::new();
-        prices.insert("AAPL".to_string(), 175.0);
+        prices.insert("AAPL".to_string(), dec!(175));
         // TSLA price is missing
 
         let holdings_with_prices = portfolio.holdings_with_prices(&prices);
@@ -209,7 +573,7 @@ mod tests {
         let tsla_holding = holdings_with_prices.iter()
             .find(|h| h.holding.ticker == "TSLA")
             .unwrap();
-        assert_eq!(tsla_holding.current_price, 0.0); // Should default to 0.0
+        assert_eq!(tsla_holding.current_price, Decimal::ZERO); // Should default to zero
     }
 
     #[test]
@@ -238,9 +602,9 @@ mod tests {
 
         assert_eq!(portfolio.holdings.len(), 2);
         assert_eq!(portfolio.holdings[0].ticker, "AAPL");
-        assert_eq!(portfolio.holdings[0].quantity, 10.0);
+        assert_eq!(portfolio.holdings[0].quantity, dec!(10));
         assert_eq!(portfolio.holdings[1].ticker, "TSLA");
-        assert_eq!(portfolio.holdings[1].quantity, 5.0);
+        assert_eq!(portfolio.holdings[1].quantity, dec!(5));
     }
 
     #[test]
@@ -254,6 +618,219 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_portfolio_load_from_file_envelope() {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("envelope_portfolio.json");
+
+        let test_data = r#"{
+            "schema_version": 2,
+            "metadata": { "name": "Retirement" },
+            "base_currency": "EUR",
+            "holdings": [
+                {
+                    "ticker": "AAPL",
+                    "quantity": 10.0,
+                    "cost_basis": 150.0,
+                    "date_purchased": "2023-01-01"
+                }
+            ],
+            "transactions": [
+                { "date": "2023-01-01", "kind": "deposit", "price": 2000.0 },
+                { "date": "2023-01-01", "kind": "buy", "ticker": "AAPL", "quantity": 10.0, "price": 150.0 }
+            ]
+        }"#;
+
+        fs::write(&file_path, test_data).unwrap();
+
+        let portfolio = Portfolio::load_from_file(&file_path).unwrap();
+
+        assert_eq!(portfolio.base_currency, "EUR");
+        assert_eq!(portfolio.metadata.name.as_deref(), Some("Retirement"));
+        assert_eq!(portfolio.holdings.len(), 1);
+        assert_eq!(portfolio.transactions.len(), 2);
+        assert_eq!(portfolio.transactions[0].amount(), dec!(2000));
+        assert_eq!(portfolio.transactions[1].amount(), dec!(1500));
+    }
+
+    #[test]
+    fn test_portfolio_save_round_trip_upgrades_legacy_file() {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("legacy_portfolio.json");
+        fs::write(&file_path, r#"[{"ticker": "AAPL", "quantity": 10, "cost_basis": 150, "date_purchased": "2023-01-01"}]"#).unwrap();
+
+        let mut portfolio = Portfolio::load_from_file(&file_path).unwrap();
+        portfolio.save_to_file(&file_path).unwrap();
+
+        let raw: serde_json::Value = serde_json::from_str(&fs::read_to_string(&file_path).unwrap()).unwrap();
+        assert_eq!(raw["schema_version"], CURRENT_SCHEMA_VERSION);
+        assert!(raw["metadata"]["updated_at"].is_string());
+
+        let reloaded = Portfolio::load_from_file(&file_path).unwrap();
+        assert_eq!(reloaded.holdings.len(), 1);
+        assert_eq!(reloaded.holdings[0].quantity, dec!(10));
+    }
+
+    #[test]
+    fn test_watchlist_add_update_remove_round_trip() {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("portfolio.json");
+        let mut portfolio = create_test_portfolio();
+
+        portfolio.watch(WatchItem {
+            ticker: "NVDA".to_string(),
+            added: "2024-06-01".to_string(),
+            price_added: Some(dec!(120)),
+            target_price: Some(dec!(100)),
+            note: Some("wait for a pullback".to_string()),
+        });
+        portfolio.watch(WatchItem {
+            ticker: "NVDA".to_string(),
+            added: "2024-07-01".to_string(),
+            price_added: Some(dec!(130)),
+            target_price: Some(dec!(110)),
+            note: None,
+        });
+        assert_eq!(portfolio.watchlist.len(), 1);
+        assert_eq!(portfolio.watchlist[0].added, "2024-06-01");
+        assert_eq!(portfolio.watchlist[0].price_added, Some(dec!(120)));
+        assert_eq!(portfolio.watchlist[0].target_price, Some(dec!(110)));
+        assert_eq!(portfolio.watchlist[0].note.as_deref(), Some("wait for a pullback"));
+
+        portfolio.save_to_file(&file_path).unwrap();
+        let reloaded = Portfolio::load_from_file(&file_path).unwrap();
+        assert_eq!(reloaded.watchlist, portfolio.watchlist);
+
+        assert!(portfolio.unwatch("NVDA"));
+        assert!(!portfolio.unwatch("NVDA"));
+        portfolio.save_to_file(&file_path).unwrap();
+        assert!(!fs::read_to_string(&file_path).unwrap().contains("watchlist"));
+    }
+
+    #[test]
+    fn test_write_file_atomic_replaces_contents() {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("portfolio.json");
+        fs::write(&file_path, "old").unwrap();
+
+        write_file_atomic(&file_path, b"new").unwrap();
+
+        assert_eq!(fs::read_to_string(&file_path).unwrap(), "new");
+        assert!(!dir.path().join("portfolio.json.tmp").exists());
+    }
+
+    #[test]
+    fn test_portfolio_load_from_file_rejects_invalid_holdings() {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("bad_portfolio.json");
+
+        let test_data = r#"[
+            {
+                "ticker": "AAPL",
+                "quantity": -10.0,
+                "cost_basis": 150.0,
+                "date_purchased": "soon"
+            }
+        ]"#;
+
+        fs::write(&file_path, test_data).unwrap();
+
+        let err = Portfolio::load_from_file(&file_path).unwrap_err();
+        let report = err.downcast_ref::<ValidationReport>().unwrap();
+        assert_eq!(report.error_count(), 2);
+        assert_eq!(report.issues[0].location.line, Some(2));
+    }
+
+    #[test]
+    fn test_holding_purchase_date() {
+        let holding = create_test_holding();
+        assert_eq!(holding.purchase_date().unwrap(), NaiveDate::from_ymd_opt(2023, 1, 1).unwrap());
+
+        let mut bad = create_test_holding();
+        bad.date_purchased = "soon".to_string();
+        assert!(bad.purchase_date().is_err());
+    }
+
+    #[test]
+    fn test_snapshot_capture_merges_lots() {
+        let mut portfolio = create_test_portfolio();
+        portfolio.holdings.push(create_test_holding());
+        let prices = HashMap::from([("AAPL".to_string(), dec!(175)), ("TSLA".to_string(), dec!(250))]);
+
+        let snapshot = Snapshot::capture(&portfolio.holdings_with_prices(&prices), chrono::Utc::now());
+
+        assert_eq!(snapshot.positions.len(), 2);
+        assert_eq!(snapshot.positions[0].ticker, "AAPL");
+        assert_eq!(snapshot.positions[0].quantity, dec!(20));
+        assert_eq!(snapshot.total_value, dec!(20) * dec!(175) + dec!(5) * dec!(250));
+    }
+
+    #[test]
+    fn test_buy_adds_lot_and_transaction() {
+        let mut portfolio = Portfolio::default();
+        portfolio.buy("AAPL", dec!(5), dec!(172), NaiveDate::from_ymd_opt(2024, 3, 1).unwrap());
+
+        assert_eq!(portfolio.holdings.len(), 1);
+        assert_eq!(portfolio.holdings[0].cost_basis, dec!(172));
+        assert_eq!(portfolio.holdings[0].date_purchased, "2024-03-01");
+        assert_eq!(portfolio.transactions[0].kind, TransactionKind::Buy);
+        assert_eq!(portfolio.transactions[0].amount(), dec!(860));
+    }
+
+    #[test]
+    fn test_sell_takes_oldest_lots_first() {
+        let mut portfolio = create_test_portfolio();
+        // A newer AAPL lot listed before the older one
+        portfolio.holdings.insert(0, Holding {
+            ticker: "AAPL".to_string(),
+            quantity: dec!(4),
+            cost_basis: dec!(180),
+            date_purchased: "2023-06-01".to_string(),
+        });
+        let date = NaiveDate::from_ymd_opt(2024, 1, 2).unwrap();
+
+        let sold = portfolio.sell("AAPL", dec!(12), dec!(190), date, CostBasisMethod::Fifo).unwrap();
+
+        assert_eq!(sold.len(), 2);
+        assert_eq!((sold[0].quantity, sold[0].cost_basis), (dec!(10), dec!(150)));
+        assert_eq!((sold[1].quantity, sold[1].cost_basis), (dec!(2), dec!(180)));
+        // The older lot is gone and the newer one is partly left
+        let aapl: Vec<_> = portfolio.holdings.iter().filter(|h| h.ticker == "AAPL").collect();
+        assert_eq!(aapl.len(), 1);
+        assert_eq!(aapl[0].quantity, dec!(2));
+        assert_eq!(portfolio.transactions.last().unwrap().kind, TransactionKind::Sell);
+
+        let error = portfolio.sell("TSLA", dec!(6), dec!(250), date, CostBasisMethod::Fifo).unwrap_err();
+        assert!(error.to_string().contains("only 5 held"));
+        assert_eq!(portfolio.transactions.len(), 1);
+    }
+
+    #[test]
+    fn test_sell_lifo_and_hifo() {
+        let lots = || {
+            let mut portfolio = create_test_portfolio();
+            portfolio.holdings.push(Holding {
+                ticker: "AAPL".to_string(),
+                quantity: dec!(10),
+                cost_basis: dec!(120),
+                date_purchased: "2023-06-01".to_string(),
+            });
+            portfolio.holdings.push(Holding {
+                ticker: "AAPL".to_string(),
+                quantity: dec!(10),
+                cost_basis: dec!(200),
+                date_purchased: "2023-03-01".to_string(),
+            });
+            portfolio
+        };
+        let date = NaiveDate::from_ymd_opt(2024, 1, 2).unwrap();
+
+        let sold = lots().sell("AAPL", dec!(5), dec!(190), date, CostBasisMethod::Lifo).unwrap();
+        assert_eq!(sold[0].date_purchased, "2023-06-01");
+        let sold = lots().sell("AAPL", dec!(5), dec!(190), date, CostBasisMethod::Hifo).unwrap();
+        assert_eq!(sold[0].cost_basis, dec!(200));
+    }
+
     #[test]
     fn test_portfolio_load_from_file_nonexistent() {
         let result = Portfolio::load_from_file("nonexistent_file.json");
@@ -0,0 +1,284 @@
+use anyhow::{bail, Context, Result};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::Serialize;
+use std::collections::HashMap;
+
+use crate::history::{align, PriceSeries};
+use crate::portfolio::Holding;
+use crate::risk::{positions, value_series};
+use crate::stats;
+
+// Monte Carlo projection of the portfolio's value. Each path steps month by
+// month: it grows by a simulated monthly return, then takes the month's
+// contribution or withdrawal. Returns come from the portfolio's own daily
+// history, either resampled as-is (bootstrap) or from a normal distribution
+// with the same mean and volatility (parametric). Paths are drawn from a
+// seeded ChaCha generator, so the same seed always gives the same result.
+
+pub const TRADING_DAYS_PER_MONTH: usize = 21;
+
+/// Percentiles reported at every horizon.
+pub const PERCENTILES: [f64; 5] = [5.0, 25.0, 50.0, 75.0, 95.0];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ProjectionMethod {
+    /// Resample historical daily returns
+    Bootstrap,
+    /// Draw from a normal distribution fitted to historical returns
+    Parametric,
+}
+
+#[derive(Debug, Clone)]
+pub struct ProjectionConfig {
+    pub method: ProjectionMethod,
+    pub paths: usize,
+    /// Horizons in years, e.g. [1, 5, 10].
+    pub horizons: Vec<u32>,
+    /// Added at the end of every month; negative for a withdrawal.
+    pub monthly_cash_flow: f64,
+    pub seed: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HorizonOutcome {
+    pub years: u32,
+    /// Total contributions minus withdrawals up to this horizon.
+    pub net_contributions: f64,
+    /// Portfolio value at each of `PERCENTILES`.
+    pub percentiles: Vec<f64>,
+    pub mean: f64,
+    /// Share of paths (0..=1) that ran out of money by this horizon.
+    pub depleted: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Projection {
+    pub method: ProjectionMethod,
+    pub paths: usize,
+    pub seed: u64,
+    pub starting_value: f64,
+    pub monthly_cash_flow: f64,
+    pub outcomes: Vec<HorizonOutcome>,
+}
+
+/// Simulate `config.paths` futures for a portfolio worth `starting_value`
+/// whose past daily returns were `daily_returns`.
+pub fn project(starting_value: f64, daily_returns: &[f64], config: &ProjectionConfig) -> Result<Projection> {
+    if daily_returns.len() < 2 {
+        bail!("Not enough price history to project from ({} daily returns)", daily_returns.len());
+    }
+    if config.paths == 0 {
+        bail!("Need at least one path to simulate");
+    }
+    let mut horizons = config.horizons.clone();
+    horizons.sort_unstable();
+    horizons.dedup();
+    let Some(&last) = horizons.last().filter(|&&h| h > 0) else {
+        bail!("Need at least one horizon of a year or more");
+    };
+
+    // Log returns compound by addition, which keeps both methods simple
+    let log_returns: Vec<f64> = daily_returns.iter().map(|r| (1.0 + r).max(1e-12).ln()).collect();
+    let monthly_mean = stats::mean(&log_returns) * TRADING_DAYS_PER_MONTH as f64;
+    let monthly_sd = stats::std_dev(&log_returns) * (TRADING_DAYS_PER_MONTH as f64).sqrt();
+
+    let mut rng = ChaCha8Rng::seed_from_u64(config.seed);
+    let months = last as usize * 12;
+    // values[h] holds every path's value at horizon h
+    let mut values = vec![Vec::with_capacity(config.paths); horizons.len()];
+    let mut depleted = vec![0usize; horizons.len()];
+
+    for _ in 0..config.paths {
+        let mut value = starting_value;
+        let mut ran_out = false;
+        let mut next = 0;
+        for month in 1..=months {
+            if !ran_out {
+                let growth = match config.method {
+                    ProjectionMethod::Bootstrap => (0..TRADING_DAYS_PER_MONTH)
+                        .map(|_| log_returns[rng.random_range(0..log_returns.len())])
+                        .sum::<f64>(),
+                    ProjectionMethod::Parametric => monthly_mean + monthly_sd * standard_normal(&mut rng),
+                };
+                value = value * growth.exp() + config.monthly_cash_flow;
+                if value <= 0.0 {
+                    value = 0.0;
+                    ran_out = true;
+                }
+            }
+            if month == horizons[next] as usize * 12 {
+                values[next].push(value);
+                if ran_out {
+                    depleted[next] += 1;
+                }
+                next += 1;
+                if next == horizons.len() {
+                    break;
+                }
+            }
+        }
+    }
+
+    let outcomes = horizons
+        .iter()
+        .zip(values.iter_mut())
+        .zip(&depleted)
+        .map(|((&years, values), &depleted)| {
+            values.sort_by(|a, b| a.total_cmp(b));
+            HorizonOutcome {
+                years,
+                net_contributions: config.monthly_cash_flow * 12.0 * years as f64,
+                percentiles: PERCENTILES.iter().map(|&p| percentile(values, p)).collect(),
+                mean: stats::mean(values),
+                depleted: depleted as f64 / config.paths as f64,
+            }
+        })
+        .collect();
+
+    Ok(Projection {
+        method: config.method,
+        paths: config.paths,
+        seed: config.seed,
+        starting_value,
+        monthly_cash_flow: config.monthly_cash_flow,
+        outcomes,
+    })
+}
+
+/// Daily returns of the current positions over the price history, the
+/// same series the risk report measures.
+pub fn historical_returns(holdings: &[Holding], history: &HashMap<String, PriceSeries>) -> Result<Vec<f64>> {
+    let positions = positions(holdings);
+    if positions.is_empty() {
+        bail!("The portfolio has no holdings to project");
+    }
+    let series: Vec<&PriceSeries> = positions
+        .keys()
+        .map(|t| history.get(t).with_context(|| format!("No price history for {}", t)))
+        .collect::<Result<_>>()?;
+    let (_, closes) = align(&series);
+    let quantities: Vec<f64> = positions.values().copied().collect();
+    Ok(stats::returns(&value_series(&quantities, &closes)))
+}
+
+/// Value at `p` (0..=100) of already sorted values, interpolating linearly.
+pub fn percentile(sorted: &[f64], p: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let rank = (p / 100.0).clamp(0.0, 1.0) * (sorted.len() - 1) as f64;
+    let (low, high) = (rank.floor() as usize, rank.ceil() as usize);
+    sorted[low] + (sorted[high] - sorted[low]) * (rank - low as f64)
+}
+
+/// Box-Muller draw from the standard normal distribution.
+fn standard_normal(rng: &mut ChaCha8Rng) -> f64 {
+    let u1: f64 = 1.0 - rng.random::<f64>(); // (0, 1], so ln is finite
+    let u2: f64 = rng.random();
+    (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(method: ProjectionMethod, seed: u64) -> ProjectionConfig {
+        ProjectionConfig {
+            method,
+            paths: 500,
+            horizons: vec![10, 1, 5],
+            monthly_cash_flow: 0.0,
+            seed,
+        }
+    }
+
+    fn history() -> Vec<f64> {
+        (0..250).map(|i| if i % 2 == 0 { 0.012 } else { -0.008 }).collect()
+    }
+
+    #[test]
+    fn test_same_seed_same_result() {
+        for method in [ProjectionMethod::Bootstrap, ProjectionMethod::Parametric] {
+            let a = project(1000.0, &history(), &config(method, 7)).unwrap();
+            let b = project(1000.0, &history(), &config(method, 7)).unwrap();
+            let c = project(1000.0, &history(), &config(method, 8)).unwrap();
+            assert_eq!(a.outcomes[2].percentiles, b.outcomes[2].percentiles);
+            assert_ne!(a.outcomes[2].percentiles, c.outcomes[2].percentiles);
+        }
+    }
+
+    #[test]
+    fn test_horizons_sorted_and_percentiles_ordered() {
+        let projection = project(1000.0, &history(), &config(ProjectionMethod::Parametric, 1)).unwrap();
+        let years: Vec<u32> = projection.outcomes.iter().map(|o| o.years).collect();
+        assert_eq!(years, vec![1, 5, 10]);
+        for outcome in &projection.outcomes {
+            assert!(outcome.percentiles.windows(2).all(|w| w[0] <= w[1]));
+        }
+        // Later horizons spread wider
+        let spread = |o: &HorizonOutcome| o.percentiles[4] - o.percentiles[0];
+        assert!(spread(&projection.outcomes[2]) > spread(&projection.outcomes[0]));
+    }
+
+    #[test]
+    fn test_flat_history_with_contributions_is_exact() {
+        let mut config = config(ProjectionMethod::Bootstrap, 3);
+        config.monthly_cash_flow = 100.0;
+        let projection = project(1000.0, &[0.0, 0.0, 0.0], &config).unwrap();
+        let one_year = &projection.outcomes[0];
+        assert!(one_year.percentiles.iter().all(|v| (v - 2200.0).abs() < 1e-6));
+        assert_eq!(one_year.net_contributions, 1200.0);
+        assert_eq!(one_year.depleted, 0.0);
+    }
+
+    #[test]
+    fn test_withdrawals_deplete_paths() {
+        let mut config = config(ProjectionMethod::Bootstrap, 3);
+        config.monthly_cash_flow = -200.0;
+        let projection = project(1000.0, &[0.0, 0.0, 0.0], &config).unwrap();
+        assert_eq!(projection.outcomes[0].depleted, 1.0);
+        assert_eq!(projection.outcomes[0].percentiles[2], 0.0);
+    }
+
+    #[test]
+    fn test_needs_history_and_horizon() {
+        assert!(project(1000.0, &[0.01], &config(ProjectionMethod::Bootstrap, 1)).is_err());
+        let mut no_horizon = config(ProjectionMethod::Bootstrap, 1);
+        no_horizon.horizons = vec![0];
+        assert!(project(1000.0, &history(), &no_horizon).is_err());
+    }
+
+    #[test]
+    fn test_historical_returns_of_positions() {
+        use chrono::NaiveDate;
+        use rust_decimal_macros::dec;
+
+        let day = |d| NaiveDate::from_ymd_opt(2024, 1, d).unwrap();
+        let history = HashMap::from([
+            ("AAA".to_string(), PriceSeries::from([(day(1), dec!(10)), (day(2), dec!(11)), (day(3), dec!(11))])),
+            ("BBB".to_string(), PriceSeries::from([(day(1), dec!(10)), (day(2), dec!(10)), (day(3), dec!(5))])),
+        ]);
+        let holding = |ticker: &str| Holding {
+            ticker: ticker.to_string(),
+            quantity: dec!(1),
+            cost_basis: dec!(10),
+            date_purchased: "2024-01-01".to_string(),
+        };
+
+        let returns = historical_returns(&[holding("AAA"), holding("BBB")], &history).unwrap();
+        assert_eq!(returns.len(), 2);
+        assert!((returns[0] - 0.05).abs() < 1e-12);
+        assert!((returns[1] + 5.0 / 21.0).abs() < 1e-12);
+        assert!(historical_returns(&[holding("CCC")], &history).is_err());
+    }
+
+    #[test]
+    fn test_percentile_interpolates() {
+        let sorted = [1.0, 2.0, 3.0, 4.0, 5.0];
+        assert_eq!(percentile(&sorted, 50.0), 3.0);
+        assert_eq!(percentile(&sorted, 100.0), 5.0);
+        assert!((percentile(&sorted, 10.0) - 1.4).abs() < 1e-9);
+    }
+}
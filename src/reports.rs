@@ -0,0 +1,568 @@
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use utoipa::ToSchema;
+
+use crate::money;
+use crate::portfolio::{HoldingWithPrice, Transaction, WatchItem};
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+// Report builders turn priced holdings into plain rows and totals.
+// The terminal commands and the exporters both render from these structs,
+// so every output format shows exactly the same numbers.
+//
+// Amounts are rounded to the portfolio currency per row, and totals are the
+// sum of the rounded rows, so a report always adds up to the cent.
+
+/// How `balances`, `allocation` and `performance` print their report.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// Coloured tables
+    #[default]
+    Table,
+    /// The report as JSON, for scripts
+    Json,
+}
+
+static OUTPUT_FORMAT: OnceLock<OutputFormat> = OnceLock::new();
+
+/// Called once from `main` with `--output-format` or the configured
+/// `output_format`.
+pub fn set_output_format(format: OutputFormat) {
+    let _ = OUTPUT_FORMAT.set(format);
+}
+
+pub fn output_format() -> OutputFormat {
+    OUTPUT_FORMAT.get().copied().unwrap_or_default()
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct BalanceRow {
+    pub ticker: String,
+    pub quantity: Decimal,
+    pub current_price: Decimal,
+    pub value: Decimal,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct BalanceReport {
+    pub rows: Vec<BalanceRow>,
+    pub total_value: Decimal,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct AllocationRow {
+    pub ticker: String,
+    pub value: Decimal,
+    pub percentage: Decimal,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct AllocationReport {
+    pub rows: Vec<AllocationRow>,
+    pub total_value: Decimal,
+    pub concentration: Concentration,
+}
+
+/// What the allocation chart adds up: each ticker (lots merged) or each
+/// asset class.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum AllocationGroup {
+    Ticker,
+    Class,
+}
+
+/// Index funds and ETFs the price data knows about.
+const FUNDS: [&str; 6] = ["SPY", "VTI", "QQQ", "AGG", "BND", "GLD"];
+
+/// Asset class of a ticker: crypto pairs, known funds, or stocks.
+pub fn asset_class(ticker: &str) -> &'static str {
+    if money::is_crypto(ticker) {
+        "Crypto"
+    } else if FUNDS.contains(&ticker.to_ascii_uppercase().as_str()) {
+        "Funds"
+    } else {
+        "Stocks"
+    }
+}
+
+/// Allocation rows merged per ticker or asset class, largest first.
+pub fn allocation_groups(report: &AllocationReport, by: AllocationGroup) -> Vec<AllocationRow> {
+    let mut groups: BTreeMap<String, Decimal> = BTreeMap::new();
+    for row in &report.rows {
+        let key = match by {
+            AllocationGroup::Ticker => row.ticker.clone(),
+            AllocationGroup::Class => asset_class(&row.ticker).to_string(),
+        };
+        *groups.entry(key).or_default() += row.value;
+    }
+    let mut rows: Vec<AllocationRow> = groups
+        .into_iter()
+        .map(|(ticker, value)| AllocationRow {
+            ticker,
+            value,
+            percentage: money::round_percent(money::percentage(value, report.total_value)),
+        })
+        .collect();
+    rows.sort_by(|a, b| b.value.cmp(&a.value).then_with(|| a.ticker.cmp(&b.ticker)));
+    rows
+}
+
+/// How concentrated the portfolio is across tickers (lots are merged).
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Concentration {
+    /// Herfindahl index: the sum of squared weights, from 1/n for n equal
+    /// positions up to 1 for a single position.
+    pub herfindahl: Decimal,
+    /// 1 / Herfindahl: how many equally weighted positions would be just
+    /// as concentrated.
+    pub effective_bets: Decimal,
+}
+
+pub fn concentration(holdings: &[HoldingWithPrice], currency: &str) -> Concentration {
+    let mut by_ticker: BTreeMap<&str, Decimal> = BTreeMap::new();
+    for h in holdings {
+        *by_ticker.entry(h.holding.ticker.as_str()).or_default() += money::round_money(h.current_value(), currency);
+    }
+    let total: Decimal = by_ticker.values().sum();
+    if total <= Decimal::ZERO {
+        return Concentration { herfindahl: Decimal::ZERO, effective_bets: Decimal::ZERO };
+    }
+
+    let herfindahl: Decimal = by_ticker.values().map(|v| (v / total) * (v / total)).sum();
+    Concentration {
+        herfindahl: money::round(herfindahl, 4),
+        effective_bets: money::round(Decimal::ONE / herfindahl, 2),
+    }
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct PerformanceRow {
+    pub ticker: String,
+    pub cost_basis: Decimal,
+    pub current_price: Decimal,
+    pub total_cost: Decimal,
+    pub current_value: Decimal,
+    pub gain_loss: Decimal,
+    pub return_percentage: Decimal,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct PerformanceReport {
+    pub rows: Vec<PerformanceRow>,
+    pub total_cost: Decimal,
+    pub total_value: Decimal,
+    pub total_gain_loss: Decimal,
+    pub total_return_percentage: Decimal,
+}
+
+/// One row per ticker, with lots of the same ticker merged together.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct PositionRow {
+    pub ticker: String,
+    pub lots: usize,
+    pub quantity: Decimal,
+    pub average_cost: Decimal,
+    pub current_price: Decimal,
+    pub total_cost: Decimal,
+    pub current_value: Decimal,
+}
+
+/// One row per purchase lot, as recorded in the portfolio file.
+#[derive(Debug, Clone, Serialize)]
+pub struct LotRow {
+    pub ticker: String,
+    pub date_purchased: String,
+    pub quantity: Decimal,
+    pub cost_basis: Decimal,
+    pub total_cost: Decimal,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TransactionRow {
+    pub date: String,
+    pub kind: String,
+    pub ticker: String,
+    pub quantity: Decimal,
+    pub price: Decimal,
+    pub amount: Decimal,
+}
+
+/// A watched ticker against its current quote. Percentages are `None` when
+/// there is no current quote, or the quote when added or the target was not
+/// recorded.
+#[derive(Debug, Clone, Serialize)]
+pub struct WatchRow {
+    pub ticker: String,
+    pub added: String,
+    pub quote: Decimal,
+    pub change_since_added: Option<Decimal>,
+    pub target_price: Option<Decimal>,
+    /// How far the quote has to move to reach the target: negative when it
+    /// has to fall, zero or positive once it is at or below the target.
+    pub distance_to_target: Option<Decimal>,
+    pub at_target: bool,
+    pub note: Option<String>,
+}
+
+pub fn watchlist_report(watchlist: &[WatchItem], quotes: &HashMap<String, Decimal>) -> Vec<WatchRow> {
+    watchlist
+        .iter()
+        .map(|w| {
+            let quote = quotes.get(&w.ticker).copied().unwrap_or_default();
+            let change_since_added = w
+                .price_added
+                .filter(|p| !p.is_zero() && !quote.is_zero())
+                .map(|p| money::round_percent(money::percentage(quote - p, p)));
+            let distance_to_target = w
+                .target_price
+                .filter(|_| !quote.is_zero())
+                .map(|t| money::round_percent(money::percentage(t - quote, quote)));
+            WatchRow {
+                ticker: w.ticker.clone(),
+                added: w.added.clone(),
+                quote,
+                change_since_added,
+                target_price: w.target_price,
+                distance_to_target,
+                at_target: w.target_price.is_some_and(|t| quote > Decimal::ZERO && quote <= t),
+                note: w.note.clone(),
+            }
+        })
+        .collect()
+}
+
+pub fn balance_report(holdings: &[HoldingWithPrice], currency: &str) -> BalanceReport {
+    let rows: Vec<BalanceRow> = holdings
+        .iter()
+        .map(|h| BalanceRow {
+            ticker: h.holding.ticker.clone(),
+            quantity: h.holding.quantity,
+            current_price: h.current_price,
+            value: money::round_money(h.current_value(), currency),
+        })
+        .collect();
+    let total_value = rows.iter().map(|r| r.value).sum();
+
+    BalanceReport { rows, total_value }
+}
+
+pub fn allocation_report(holdings: &[HoldingWithPrice], currency: &str) -> AllocationReport {
+    let values: Vec<Decimal> = holdings
+        .iter()
+        .map(|h| money::round_money(h.current_value(), currency))
+        .collect();
+    let total_value: Decimal = values.iter().sum();
+    let rows = holdings
+        .iter()
+        .zip(values)
+        .map(|(h, value)| AllocationRow {
+            ticker: h.holding.ticker.clone(),
+            value,
+            percentage: money::round_percent(money::percentage(value, total_value)),
+        })
+        .collect();
+
+    AllocationReport { rows, total_value, concentration: concentration(holdings, currency) }
+}
+
+pub fn performance_report(holdings: &[HoldingWithPrice], currency: &str) -> PerformanceReport {
+    let rows: Vec<PerformanceRow> = holdings
+        .iter()
+        .map(|h| {
+            let total_cost = money::round_money(h.total_cost(), currency);
+            let current_value = money::round_money(h.current_value(), currency);
+            PerformanceRow {
+                ticker: h.holding.ticker.clone(),
+                cost_basis: h.holding.cost_basis,
+                current_price: h.current_price,
+                total_cost,
+                current_value,
+                gain_loss: current_value - total_cost,
+                return_percentage: money::round_percent(h.gain_loss_percentage()),
+            }
+        })
+        .collect();
+
+    let total_cost: Decimal = rows.iter().map(|r| r.total_cost).sum();
+    let total_value: Decimal = rows.iter().map(|r| r.current_value).sum();
+    let total_gain_loss = total_value - total_cost;
+    let total_return_percentage = money::round_percent(money::percentage(total_gain_loss, total_cost));
+
+    PerformanceReport {
+        rows,
+        total_cost,
+        total_value,
+        total_gain_loss,
+        total_return_percentage,
+    }
+}
+
+pub fn position_rows(holdings: &[HoldingWithPrice], currency: &str) -> Vec<PositionRow> {
+    let mut by_ticker: BTreeMap<&str, PositionRow> = BTreeMap::new();
+    // The average cost comes from the unrounded lot costs
+    let mut exact_cost: BTreeMap<&str, Decimal> = BTreeMap::new();
+
+    for h in holdings {
+        *exact_cost.entry(h.holding.ticker.as_str()).or_default() += h.total_cost();
+        let row = by_ticker
+            .entry(h.holding.ticker.as_str())
+            .or_insert_with(|| PositionRow {
+                ticker: h.holding.ticker.clone(),
+                lots: 0,
+                quantity: Decimal::ZERO,
+                average_cost: Decimal::ZERO,
+                current_price: h.current_price,
+                total_cost: Decimal::ZERO,
+                current_value: Decimal::ZERO,
+            });
+        row.lots += 1;
+        row.quantity += h.holding.quantity;
+        row.total_cost += money::round_money(h.total_cost(), currency);
+        row.current_value += money::round_money(h.current_value(), currency);
+    }
+
+    by_ticker
+        .into_iter()
+        .map(|(ticker, mut row)| {
+            if row.quantity > Decimal::ZERO {
+                // Per-unit prices keep four extra places beyond the currency
+                let decimals = money::currency_decimals(currency) + 4;
+                row.average_cost = money::round(exact_cost[ticker] / row.quantity, decimals);
+            }
+            row
+        })
+        .collect()
+}
+
+pub fn lot_rows(holdings: &[HoldingWithPrice], currency: &str) -> Vec<LotRow> {
+    holdings
+        .iter()
+        .map(|h| LotRow {
+            ticker: h.holding.ticker.clone(),
+            date_purchased: h.holding.date_purchased.clone(),
+            quantity: h.holding.quantity,
+            cost_basis: h.holding.cost_basis,
+            total_cost: money::round_money(h.total_cost(), currency),
+        })
+        .collect()
+}
+
+/// The recorded ledger in date order. Files without a ledger (such as
+/// migrated version 1 files) report every lot as the buy that opened it.
+pub fn transaction_rows(holdings: &[HoldingWithPrice], transactions: &[Transaction], currency: &str) -> Vec<TransactionRow> {
+    let mut rows: Vec<TransactionRow> = if transactions.is_empty() {
+        holdings
+            .iter()
+            .map(|h| TransactionRow {
+                date: h.holding.date_purchased.clone(),
+                kind: "BUY".to_string(),
+                ticker: h.holding.ticker.clone(),
+                quantity: h.holding.quantity,
+                price: h.holding.cost_basis,
+                amount: money::round_money(h.total_cost(), currency),
+            })
+            .collect()
+    } else {
+        transactions
+            .iter()
+            .map(|t| TransactionRow {
+                date: t.date.clone(),
+                kind: t.kind.label().to_string(),
+                ticker: t.ticker.clone().unwrap_or_default(),
+                quantity: t.quantity,
+                price: t.price,
+                amount: money::round_money(t.amount(), currency),
+            })
+            .collect()
+    };
+    rows.sort_by(|a, b| a.date.cmp(&b.date));
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::portfolio::{Holding, TransactionKind};
+    use rust_decimal_macros::dec;
+
+    fn priced(ticker: &str, quantity: Decimal, cost_basis: Decimal, date: &str, price: Decimal) -> HoldingWithPrice {
+        HoldingWithPrice {
+            holding: Holding {
+                ticker: ticker.to_string(),
+                quantity,
+                cost_basis,
+                date_purchased: date.to_string(),
+            },
+            current_price: price,
+        }
+    }
+
+    fn sample() -> Vec<HoldingWithPrice> {
+        vec![
+            priced("AAPL", dec!(10.0), dec!(150.0), "2023-03-01", dec!(170.0)),
+            priced("TSLA", dec!(5.0), dec!(200.0), "2023-01-01", dec!(700.0)),
+            priced("AAPL", dec!(10.0), dec!(130.0), "2023-02-01", dec!(170.0)),
+        ]
+    }
+
+    #[test]
+    fn test_watchlist_report_change_and_distance() {
+        let item = |ticker: &str, price_added, target_price| WatchItem {
+            ticker: ticker.to_string(),
+            added: "2024-06-01".to_string(),
+            price_added,
+            target_price,
+            note: None,
+        };
+        let watchlist = vec![
+            item("NVDA", Some(dec!(100)), Some(dec!(90))),
+            item("AMD", None, Some(dec!(160))),
+            item("INTC", Some(dec!(40)), None),
+        ];
+        let quotes = HashMap::from([
+            ("NVDA".to_string(), dec!(120)),
+            ("AMD".to_string(), dec!(150)),
+            ("INTC".to_string(), dec!(30)),
+        ]);
+
+        let rows = watchlist_report(&watchlist, &quotes);
+        assert_eq!(rows[0].change_since_added, Some(dec!(20)));
+        assert_eq!(rows[0].distance_to_target, Some(dec!(-25)));
+        assert!(!rows[0].at_target);
+        assert_eq!(rows[1].change_since_added, None);
+        assert_eq!(rows[1].distance_to_target, Some(dec!(6.67)));
+        assert!(rows[1].at_target);
+        assert_eq!(rows[2].change_since_added, Some(dec!(-25)));
+        assert_eq!(rows[2].distance_to_target, None);
+
+        // No quote: no percentages rather than a 100% fall
+        let rows = watchlist_report(&watchlist[..1], &HashMap::new());
+        assert_eq!(rows[0].change_since_added, None);
+        assert_eq!(rows[0].distance_to_target, None);
+    }
+
+    #[test]
+    fn test_balance_report_totals() {
+        let report = balance_report(&sample(), "USD");
+        assert_eq!(report.rows.len(), 3);
+        assert_eq!(report.total_value, dec!(1700) + dec!(3500) + dec!(1700));
+    }
+
+    #[test]
+    fn test_allocation_report_percentages_sum_to_100() {
+        let report = allocation_report(&sample(), "USD");
+        let sum: Decimal = report.rows.iter().map(|r| r.percentage).sum();
+        assert!((sum - dec!(100)).abs() <= dec!(0.01));
+    }
+
+    #[test]
+    fn test_concentration_merges_lots() {
+        // AAPL 3400 and TSLA 3500: nearly two equal bets
+        let report = allocation_report(&sample(), "USD");
+        assert_eq!(report.concentration.herfindahl, dec!(0.5001));
+        assert_eq!(report.concentration.effective_bets, dec!(2.00));
+
+        let single = allocation_report(&sample()[..1], "USD");
+        assert_eq!(single.concentration.herfindahl, dec!(1));
+        assert_eq!(single.concentration.effective_bets, dec!(1));
+    }
+
+    #[test]
+    fn test_allocation_groups_by_ticker_and_class() {
+        let mut holdings = sample();
+        holdings.push(priced("BTC-USD", dec!(0.1), dec!(40000), "2023-04-01", dec!(45000)));
+        holdings.push(priced("SPY", dec!(2), dec!(400), "2023-04-01", dec!(450)));
+        let report = allocation_report(&holdings, "USD");
+
+        // AAPL 3400, TSLA 3500, BTC 4500, SPY 900 of 12300
+        let by_ticker = allocation_groups(&report, AllocationGroup::Ticker);
+        let tickers: Vec<&str> = by_ticker.iter().map(|r| r.ticker.as_str()).collect();
+        assert_eq!(tickers, vec!["BTC-USD", "TSLA", "AAPL", "SPY"]);
+        assert_eq!(by_ticker[2].value, dec!(3400));
+
+        let by_class = allocation_groups(&report, AllocationGroup::Class);
+        let classes: Vec<(&str, Decimal)> = by_class.iter().map(|r| (r.ticker.as_str(), r.percentage)).collect();
+        assert_eq!(classes, vec![("Stocks", dec!(56.10)), ("Crypto", dec!(36.59)), ("Funds", dec!(7.32))]);
+    }
+
+    #[test]
+    fn test_allocation_report_empty_portfolio() {
+        let report = allocation_report(&[], "USD");
+        assert!(report.rows.is_empty());
+        assert_eq!(report.total_value, Decimal::ZERO);
+    }
+
+    #[test]
+    fn test_performance_report_totals() {
+        let report = performance_report(&sample(), "USD");
+        assert_eq!(report.total_cost, dec!(1500) + dec!(1000) + dec!(1300));
+        assert_eq!(report.total_value, dec!(6900));
+        assert_eq!(report.total_gain_loss, dec!(6900) - dec!(3800));
+        assert_eq!(report.total_return_percentage, dec!(81.58)); // 3100 / 3800
+    }
+
+    #[test]
+    fn test_position_rows_merge_lots() {
+        let rows = position_rows(&sample(), "USD");
+        assert_eq!(rows.len(), 2);
+
+        let aapl = rows.iter().find(|r| r.ticker == "AAPL").unwrap();
+        assert_eq!(aapl.lots, 2);
+        assert_eq!(aapl.quantity, dec!(20));
+        assert_eq!(aapl.average_cost, dec!(140));
+        assert_eq!(aapl.current_value, dec!(3400));
+    }
+
+    #[test]
+    fn test_transaction_rows_sorted_by_date() {
+        let rows = transaction_rows(&sample(), &[], "USD");
+        let dates: Vec<&str> = rows.iter().map(|r| r.date.as_str()).collect();
+        assert_eq!(dates, vec!["2023-01-01", "2023-02-01", "2023-03-01"]);
+        assert!(rows.iter().all(|r| r.kind == "BUY"));
+    }
+
+    #[test]
+    fn test_transaction_rows_prefer_recorded_ledger() {
+        let ledger = vec![Transaction {
+            date: "2023-01-01".to_string(),
+            kind: TransactionKind::Deposit,
+            ticker: None,
+            quantity: Decimal::ZERO,
+            price: dec!(5000),
+            fees: Decimal::ZERO,
+            note: None,
+        }];
+        let rows = transaction_rows(&sample(), &ledger, "USD");
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].kind, "DEPOSIT");
+        assert_eq!(rows[0].amount, dec!(5000));
+    }
+
+    #[test]
+    fn test_totals_reconcile_with_rounded_rows() {
+        // Three lots worth 33.335 each: every row shows 33.34, so the
+        // total must be 100.02 rather than the unrounded 100.005.
+        let holdings = vec![
+            priced("AAPL", dec!(1), dec!(30), "2023-01-01", dec!(33.335)),
+            priced("AAPL", dec!(1), dec!(30), "2023-02-01", dec!(33.335)),
+            priced("AAPL", dec!(1), dec!(30), "2023-03-01", dec!(33.335)),
+        ];
+        let report = balance_report(&holdings, "USD");
+        assert!(report.rows.iter().all(|r| r.value == dec!(33.34)));
+        assert_eq!(report.total_value, dec!(100.02));
+
+        let report = performance_report(&holdings, "USD");
+        assert_eq!(report.total_gain_loss, dec!(10.02));
+    }
+
+    #[test]
+    fn test_rounding_follows_currency() {
+        let holdings = vec![priced("7203.T", dec!(3), dec!(2500.5), "2023-01-01", dec!(2600.5))];
+        let report = balance_report(&holdings, "JPY");
+        assert_eq!(report.total_value, dec!(7802));
+    }
+}
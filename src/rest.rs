@@ -0,0 +1,688 @@
+use anyhow::Result;
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::routing::get;
+use axum::{Json, Router};
+use chrono::{NaiveDate, Utc};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::Path as FilePath;
+use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{IntoParams, Modify, OpenApi, ToSchema};
+
+use crate::api::fetch_prices_with_failures;
+use crate::portfolio::{Holding, Portfolio, Snapshot, Transaction};
+use crate::reports::{
+    allocation_groups, allocation_report, balance_report, performance_report, position_rows, AllocationGroup,
+    BalanceReport, PerformanceReport, PositionRow,
+};
+use crate::server::{AllocationResponse, ApiError, AppState, ErrorBody, PortfolioEntry};
+use crate::validation::{validate_document, EntryLines};
+
+// Version 1 of the JSON API, mounted under /api/v1 by `server`. Holdings
+// and transactions can be listed, added, replaced and deleted; ids are the
+// entry's position in the portfolio file, as `validate` reports them.
+//
+// A change is applied to the loaded portfolio and the whole result is run
+// through the same validator as every CLI load, so the API accepts exactly
+// what the portfolio file would. Anything with errors is refused with 422
+// and the issues, and nothing is saved.
+//
+// The OpenAPI document at /api/v1/openapi.json is generated from the
+// request and response types below.
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Portfolio API", version = "1", description = "Holdings, transactions, quotes, reports and snapshots"),
+    servers((url = "/api/v1")),
+    paths(
+        list_portfolios,
+        list_holdings, create_holding, get_holding, replace_holding, delete_holding,
+        list_transactions, create_transaction, get_transaction, replace_transaction, delete_transaction,
+        quotes,
+        balances_report, allocation_report_v1, performance_report_v1, positions_report,
+        list_snapshots, take_snapshot,
+    ),
+    modifiers(&BearerToken),
+    security(("token" = [])),
+)]
+pub struct ApiDoc;
+
+/// Documents the optional `--token` as a bearer token.
+struct BearerToken;
+
+impl Modify for BearerToken {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme("token", SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()));
+    }
+}
+
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/openapi.json", get(openapi))
+        .route("/portfolios", get(list_portfolios))
+        .route("/holdings", get(list_holdings).post(create_holding))
+        .route("/holdings/{id}", get(get_holding).put(replace_holding).delete(delete_holding))
+        .route("/transactions", get(list_transactions).post(create_transaction))
+        .route("/transactions/{id}", get(get_transaction).put(replace_transaction).delete(delete_transaction))
+        .route("/quotes", get(quotes))
+        .route("/reports/balances", get(balances_report))
+        .route("/reports/allocation", get(allocation_report_v1))
+        .route("/reports/performance", get(performance_report_v1))
+        .route("/reports/positions", get(positions_report))
+        .route("/snapshots", get(list_snapshots).post(take_snapshot))
+}
+
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PortfolioParam {
+    /// Portfolio name (the file name without extension); defaults to the first one served
+    portfolio: Option<String>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct QuoteParams {
+    portfolio: Option<String>,
+    /// Comma-separated tickers; defaults to the portfolio's holdings and watchlist
+    tickers: Option<String>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AllocationParams {
+    portfolio: Option<String>,
+    /// Group the chart rows per ticker (default) or per asset class
+    by: Option<AllocationGroup>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SnapshotParams {
+    portfolio: Option<String>,
+    /// First day, inclusive
+    from: Option<NaiveDate>,
+    /// Last day, inclusive
+    to: Option<NaiveDate>,
+}
+
+/// A holding with its position in the portfolio file.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct HoldingEntry {
+    pub id: usize,
+    #[serde(flatten)]
+    pub holding: Holding,
+}
+
+/// A transaction with its position in the ledger.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TransactionEntry {
+    pub id: usize,
+    #[serde(flatten)]
+    pub transaction: Transaction,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Quote {
+    pub ticker: String,
+    pub price: rust_decimal::Decimal,
+}
+
+/// The two editable sections of a portfolio.
+#[derive(Debug, Clone, Copy)]
+enum Section {
+    Holdings,
+    Transactions,
+}
+
+impl Section {
+    fn key(self) -> &'static str {
+        match self {
+            Section::Holdings => "holdings",
+            Section::Transactions => "transactions",
+        }
+    }
+
+    fn len(self, portfolio: &Portfolio) -> usize {
+        match self {
+            Section::Holdings => portfolio.holdings.len(),
+            Section::Transactions => portfolio.transactions.len(),
+        }
+    }
+}
+
+/// What a change does to one entry of a section.
+enum Change {
+    Add(Value),
+    Replace(usize, Value),
+    Delete(usize),
+}
+
+/// The entry a change added or replaced, as it was validated and saved.
+enum Saved {
+    Holding(HoldingEntry),
+    Transaction(TransactionEntry),
+    Deleted,
+}
+
+/// Run `entry` through the validator as part of `portfolio`, at `index`
+/// (or appended), and deserialize it once the result has no errors.
+fn checked_entry<T: DeserializeOwned>(portfolio: &Portfolio, section: Section, index: Option<usize>, entry: Value) -> Result<T, ApiError> {
+    let mut document = serde_json::to_value(portfolio.to_document()).map_err(|e| ApiError::internal(e.into()))?;
+    let list = document
+        .get_mut(section.key())
+        .and_then(Value::as_array_mut)
+        .ok_or_else(|| ApiError::internal(anyhow::anyhow!("Portfolio has no {} list", section.key())))?;
+    match index {
+        Some(index) => list[index] = entry.clone(),
+        None => list.push(entry.clone()),
+    }
+
+    let report = validate_document(&document, &EntryLines::default());
+    if report.has_errors() {
+        return Err(ApiError::invalid(&report));
+    }
+    serde_json::from_value(entry).map_err(|e| ApiError::new(StatusCode::UNPROCESSABLE_ENTITY, e.to_string()))
+}
+
+fn not_found(section: Section, id: usize) -> ApiError {
+    ApiError::new(StatusCode::NOT_FOUND, format!("No {} entry with id {}", section.key(), id))
+}
+
+/// Apply a change to the named portfolio and save it. Returns the entry
+/// added or replaced, so the response shows what was written even if
+/// another request changes the file straight after.
+async fn modify(state: &AppState, name: Option<&str>, section: Section, change: Change) -> Result<Saved, ApiError> {
+    let _guard = state.write_lock.lock().await;
+    let mut portfolio = state.load(name)?;
+    let len = section.len(&portfolio);
+    let id = match change {
+        Change::Replace(id, _) | Change::Delete(id) if id >= len => return Err(not_found(section, id)),
+        Change::Add(_) => len,
+        Change::Replace(id, _) | Change::Delete(id) => id,
+    };
+
+    let saved = match (section, change) {
+        (Section::Holdings, Change::Add(entry)) => {
+            let holding: Holding = checked_entry(&portfolio, section, None, entry)?;
+            portfolio.holdings.push(holding.clone());
+            Saved::Holding(HoldingEntry { id, holding })
+        }
+        (Section::Holdings, Change::Replace(_, entry)) => {
+            let holding: Holding = checked_entry(&portfolio, section, Some(id), entry)?;
+            portfolio.holdings[id] = holding.clone();
+            Saved::Holding(HoldingEntry { id, holding })
+        }
+        (Section::Holdings, Change::Delete(_)) => {
+            portfolio.holdings.remove(id);
+            Saved::Deleted
+        }
+        (Section::Transactions, Change::Add(entry)) => {
+            let transaction: Transaction = checked_entry(&portfolio, section, None, entry)?;
+            portfolio.transactions.push(transaction.clone());
+            Saved::Transaction(TransactionEntry { id, transaction })
+        }
+        (Section::Transactions, Change::Replace(_, entry)) => {
+            let transaction: Transaction = checked_entry(&portfolio, section, Some(id), entry)?;
+            portfolio.transactions[id] = transaction.clone();
+            Saved::Transaction(TransactionEntry { id, transaction })
+        }
+        (Section::Transactions, Change::Delete(_)) => {
+            portfolio.transactions.remove(id);
+            Saved::Deleted
+        }
+    };
+
+    let file = &state.entry(name)?.file;
+    portfolio.save_to_file(file).map_err(ApiError::internal)?;
+    Ok(saved)
+}
+
+impl Saved {
+    fn holding(self) -> HoldingEntry {
+        match self {
+            Saved::Holding(entry) => entry,
+            _ => unreachable!("a holdings change saves a holding"),
+        }
+    }
+
+    fn transaction(self) -> TransactionEntry {
+        match self {
+            Saved::Transaction(entry) => entry,
+            _ => unreachable!("a transactions change saves a transaction"),
+        }
+    }
+}
+
+async fn openapi() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+/// The portfolios this server reads from.
+#[utoipa::path(get, path = "/portfolios", tag = "portfolios",
+    responses((status = 200, body = Vec<PortfolioEntry>)))]
+async fn list_portfolios(State(state): State<AppState>) -> Json<Vec<PortfolioEntry>> {
+    Json(state.portfolios().to_vec())
+}
+
+#[utoipa::path(get, path = "/holdings", tag = "holdings", params(PortfolioParam),
+    responses((status = 200, body = Vec<HoldingEntry>), (status = 404, body = ErrorBody)))]
+async fn list_holdings(State(state): State<AppState>, Query(query): Query<PortfolioParam>) -> Result<Json<Vec<HoldingEntry>>, ApiError> {
+    let portfolio = state.load(query.portfolio.as_deref())?;
+    let entries = portfolio.holdings.into_iter().enumerate().map(|(id, holding)| HoldingEntry { id, holding }).collect();
+    Ok(Json(entries))
+}
+
+/// Add a lot. It is validated like a lot in the portfolio file.
+#[utoipa::path(post, path = "/holdings", tag = "holdings", params(PortfolioParam), request_body = Holding,
+    responses((status = 201, body = HoldingEntry), (status = 422, body = ErrorBody)))]
+async fn create_holding(
+    State(state): State<AppState>,
+    Query(query): Query<PortfolioParam>,
+    Json(body): Json<Value>,
+) -> Result<(StatusCode, Json<HoldingEntry>), ApiError> {
+    let saved = modify(&state, query.portfolio.as_deref(), Section::Holdings, Change::Add(body)).await?;
+    Ok((StatusCode::CREATED, Json(saved.holding())))
+}
+
+#[utoipa::path(get, path = "/holdings/{id}", tag = "holdings", params(("id" = usize, Path), PortfolioParam),
+    responses((status = 200, body = HoldingEntry), (status = 404, body = ErrorBody)))]
+async fn get_holding(
+    State(state): State<AppState>,
+    Path(id): Path<usize>,
+    Query(query): Query<PortfolioParam>,
+) -> Result<Json<HoldingEntry>, ApiError> {
+    holding_at(&state, query.portfolio.as_deref(), id)
+}
+
+#[utoipa::path(put, path = "/holdings/{id}", tag = "holdings", params(("id" = usize, Path), PortfolioParam), request_body = Holding,
+    responses((status = 200, body = HoldingEntry), (status = 404, body = ErrorBody), (status = 422, body = ErrorBody)))]
+async fn replace_holding(
+    State(state): State<AppState>,
+    Path(id): Path<usize>,
+    Query(query): Query<PortfolioParam>,
+    Json(body): Json<Value>,
+) -> Result<Json<HoldingEntry>, ApiError> {
+    let saved = modify(&state, query.portfolio.as_deref(), Section::Holdings, Change::Replace(id, body)).await?;
+    Ok(Json(saved.holding()))
+}
+
+/// Delete a lot. Later lots move up one id.
+#[utoipa::path(delete, path = "/holdings/{id}", tag = "holdings", params(("id" = usize, Path), PortfolioParam),
+    responses((status = 204), (status = 404, body = ErrorBody)))]
+async fn delete_holding(
+    State(state): State<AppState>,
+    Path(id): Path<usize>,
+    Query(query): Query<PortfolioParam>,
+) -> Result<StatusCode, ApiError> {
+    modify(&state, query.portfolio.as_deref(), Section::Holdings, Change::Delete(id)).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+fn holding_at(state: &AppState, name: Option<&str>, id: usize) -> Result<Json<HoldingEntry>, ApiError> {
+    let mut portfolio = state.load(name)?;
+    if id >= portfolio.holdings.len() {
+        return Err(not_found(Section::Holdings, id));
+    }
+    Ok(Json(HoldingEntry { id, holding: portfolio.holdings.swap_remove(id) }))
+}
+
+#[utoipa::path(get, path = "/transactions", tag = "transactions", params(PortfolioParam),
+    responses((status = 200, body = Vec<TransactionEntry>), (status = 404, body = ErrorBody)))]
+async fn list_transactions(
+    State(state): State<AppState>,
+    Query(query): Query<PortfolioParam>,
+) -> Result<Json<Vec<TransactionEntry>>, ApiError> {
+    let portfolio = state.load(query.portfolio.as_deref())?;
+    let entries = portfolio
+        .transactions
+        .into_iter()
+        .enumerate()
+        .map(|(id, transaction)| TransactionEntry { id, transaction })
+        .collect();
+    Ok(Json(entries))
+}
+
+/// Record a transaction. It is validated like one in the portfolio file.
+#[utoipa::path(post, path = "/transactions", tag = "transactions", params(PortfolioParam), request_body = Transaction,
+    responses((status = 201, body = TransactionEntry), (status = 422, body = ErrorBody)))]
+async fn create_transaction(
+    State(state): State<AppState>,
+    Query(query): Query<PortfolioParam>,
+    Json(body): Json<Value>,
+) -> Result<(StatusCode, Json<TransactionEntry>), ApiError> {
+    let saved = modify(&state, query.portfolio.as_deref(), Section::Transactions, Change::Add(body)).await?;
+    Ok((StatusCode::CREATED, Json(saved.transaction())))
+}
+
+#[utoipa::path(get, path = "/transactions/{id}", tag = "transactions", params(("id" = usize, Path), PortfolioParam),
+    responses((status = 200, body = TransactionEntry), (status = 404, body = ErrorBody)))]
+async fn get_transaction(
+    State(state): State<AppState>,
+    Path(id): Path<usize>,
+    Query(query): Query<PortfolioParam>,
+) -> Result<Json<TransactionEntry>, ApiError> {
+    transaction_at(&state, query.portfolio.as_deref(), id)
+}
+
+#[utoipa::path(put, path = "/transactions/{id}", tag = "transactions", params(("id" = usize, Path), PortfolioParam),
+    request_body = Transaction,
+    responses((status = 200, body = TransactionEntry), (status = 404, body = ErrorBody), (status = 422, body = ErrorBody)))]
+async fn replace_transaction(
+    State(state): State<AppState>,
+    Path(id): Path<usize>,
+    Query(query): Query<PortfolioParam>,
+    Json(body): Json<Value>,
+) -> Result<Json<TransactionEntry>, ApiError> {
+    let saved = modify(&state, query.portfolio.as_deref(), Section::Transactions, Change::Replace(id, body)).await?;
+    Ok(Json(saved.transaction()))
+}
+
+/// Delete a transaction. Later transactions move up one id.
+#[utoipa::path(delete, path = "/transactions/{id}", tag = "transactions", params(("id" = usize, Path), PortfolioParam),
+    responses((status = 204), (status = 404, body = ErrorBody)))]
+async fn delete_transaction(
+    State(state): State<AppState>,
+    Path(id): Path<usize>,
+    Query(query): Query<PortfolioParam>,
+) -> Result<StatusCode, ApiError> {
+    modify(&state, query.portfolio.as_deref(), Section::Transactions, Change::Delete(id)).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+fn transaction_at(state: &AppState, name: Option<&str>, id: usize) -> Result<Json<TransactionEntry>, ApiError> {
+    let mut portfolio = state.load(name)?;
+    if id >= portfolio.transactions.len() {
+        return Err(not_found(Section::Transactions, id));
+    }
+    Ok(Json(TransactionEntry { id, transaction: portfolio.transactions.swap_remove(id) }))
+}
+
+/// Current quotes for the given tickers, or for everything the portfolio
+/// holds or watches. Tickers that could not be quoted are left out.
+#[utoipa::path(get, path = "/quotes", tag = "quotes", params(QuoteParams),
+    responses((status = 200, body = Vec<Quote>), (status = 400, body = ErrorBody)))]
+async fn quotes(State(state): State<AppState>, Query(query): Query<QuoteParams>) -> Result<Json<Vec<Quote>>, ApiError> {
+    let mut tickers: Vec<String> = match query.tickers.as_deref() {
+        Some(list) => list.split(',').map(|t| t.trim().to_uppercase()).filter(|t| !t.is_empty()).collect(),
+        None => {
+            let portfolio = state.load(query.portfolio.as_deref())?;
+            let watched = portfolio.watchlist.iter().map(|w| w.ticker.clone());
+            portfolio.get_tickers().into_iter().chain(watched).collect()
+        }
+    };
+    tickers.sort();
+    tickers.dedup();
+    if query.tickers.is_some() && tickers.is_empty() {
+        return Err(ApiError::new(StatusCode::BAD_REQUEST, "No tickers given"));
+    }
+
+    let (prices, failed) = fetch_prices_with_failures(&tickers).await.map_err(ApiError::internal)?;
+    let quotes = tickers
+        .into_iter()
+        .filter(|ticker| !failed.contains(ticker))
+        .filter_map(|ticker| prices.get(&ticker).map(|&price| Quote { ticker, price }))
+        .collect();
+    Ok(Json(quotes))
+}
+
+#[utoipa::path(get, path = "/reports/balances", tag = "reports", params(PortfolioParam),
+    responses((status = 200, body = BalanceReport), (status = 404, body = ErrorBody), (status = 502, body = ErrorBody)))]
+async fn balances_report(State(state): State<AppState>, Query(query): Query<PortfolioParam>) -> Result<Json<BalanceReport>, ApiError> {
+    let (portfolio, holdings) = state.priced(query.portfolio.as_deref()).await?;
+    Ok(Json(balance_report(&holdings, &portfolio.base_currency)))
+}
+
+#[utoipa::path(get, path = "/reports/allocation", tag = "reports", params(AllocationParams),
+    responses((status = 200, body = AllocationResponse), (status = 404, body = ErrorBody), (status = 502, body = ErrorBody)))]
+async fn allocation_report_v1(
+    State(state): State<AppState>,
+    Query(query): Query<AllocationParams>,
+) -> Result<Json<AllocationResponse>, ApiError> {
+    let (portfolio, holdings) = state.priced(query.portfolio.as_deref()).await?;
+    let report = allocation_report(&holdings, &portfolio.base_currency);
+    let groups = allocation_groups(&report, query.by.unwrap_or(AllocationGroup::Ticker));
+    Ok(Json(AllocationResponse { report, groups }))
+}
+
+#[utoipa::path(get, path = "/reports/performance", tag = "reports", params(PortfolioParam),
+    responses((status = 200, body = PerformanceReport), (status = 404, body = ErrorBody), (status = 502, body = ErrorBody)))]
+async fn performance_report_v1(
+    State(state): State<AppState>,
+    Query(query): Query<PortfolioParam>,
+) -> Result<Json<PerformanceReport>, ApiError> {
+    let (portfolio, holdings) = state.priced(query.portfolio.as_deref()).await?;
+    Ok(Json(performance_report(&holdings, &portfolio.base_currency)))
+}
+
+/// One row per ticker, lots merged.
+#[utoipa::path(get, path = "/reports/positions", tag = "reports", params(PortfolioParam),
+    responses((status = 200, body = Vec<PositionRow>), (status = 404, body = ErrorBody), (status = 502, body = ErrorBody)))]
+async fn positions_report(State(state): State<AppState>, Query(query): Query<PortfolioParam>) -> Result<Json<Vec<PositionRow>>, ApiError> {
+    let (portfolio, holdings) = state.priced(query.portfolio.as_deref()).await?;
+    Ok(Json(position_rows(&holdings, &portfolio.base_currency)))
+}
+
+/// Snapshots taken between two days, oldest first. Only SQLite portfolios
+/// keep snapshots.
+#[utoipa::path(get, path = "/snapshots", tag = "snapshots", params(SnapshotParams),
+    responses((status = 200, body = Vec<Snapshot>), (status = 400, body = ErrorBody)))]
+async fn list_snapshots(State(state): State<AppState>, Query(query): Query<SnapshotParams>) -> Result<Json<Vec<Snapshot>>, ApiError> {
+    let entry = state.entry(query.portfolio.as_deref())?;
+    let from = query.from.map(|d| d.and_hms_opt(0, 0, 0).expect("midnight").and_utc());
+    let to = query.to.map(|d| d.and_hms_opt(23, 59, 59).expect("end of day").and_utc());
+    if matches!((from, to), (Some(from), Some(to)) if from > to) {
+        return Err(ApiError::new(StatusCode::BAD_REQUEST, "'from' must not be after 'to'"));
+    }
+    Ok(Json(stored_snapshots(&entry.file, from, to)?))
+}
+
+/// Value the portfolio at current quotes and store the snapshot. Nothing is
+/// stored when a quote is missing.
+#[utoipa::path(post, path = "/snapshots", tag = "snapshots", params(PortfolioParam),
+    responses((status = 201, body = Snapshot), (status = 400, body = ErrorBody), (status = 502, body = ErrorBody)))]
+async fn take_snapshot(
+    State(state): State<AppState>,
+    Query(query): Query<PortfolioParam>,
+) -> Result<(StatusCode, Json<Snapshot>), ApiError> {
+    let name = query.portfolio.as_deref();
+    let file = state.entry(name)?.file.clone();
+    // Check before fetching quotes
+    stored_snapshots(&file, Some(Utc::now()), None)?;
+    let (_, holdings) = state.priced(name).await?;
+    let snapshot = Snapshot::capture(&holdings, Utc::now());
+    let _guard = state.write_lock.lock().await;
+    record_snapshot(&file, &snapshot)?;
+    Ok((StatusCode::CREATED, Json(snapshot)))
+}
+
+#[cfg(feature = "sqlite")]
+fn snapshot_store(file: &FilePath) -> Result<crate::sqlite::SqliteStore, ApiError> {
+    if !crate::storage::is_database_path(file) {
+        return Err(no_snapshots());
+    }
+    crate::sqlite::SqliteStore::open(file).map_err(ApiError::internal)
+}
+
+#[cfg(feature = "sqlite")]
+fn stored_snapshots(
+    file: &FilePath,
+    from: Option<chrono::DateTime<Utc>>,
+    to: Option<chrono::DateTime<Utc>>,
+) -> Result<Vec<Snapshot>, ApiError> {
+    snapshot_store(file)?.snapshots(from, to).map_err(ApiError::internal)
+}
+
+#[cfg(feature = "sqlite")]
+fn record_snapshot(file: &FilePath, snapshot: &Snapshot) -> Result<(), ApiError> {
+    snapshot_store(file)?.record_snapshot(snapshot).map_err(ApiError::internal)
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn stored_snapshots(
+    _file: &FilePath,
+    _from: Option<chrono::DateTime<Utc>>,
+    _to: Option<chrono::DateTime<Utc>>,
+) -> Result<Vec<Snapshot>, ApiError> {
+    Err(no_snapshots())
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn record_snapshot(_file: &FilePath, _snapshot: &Snapshot) -> Result<(), ApiError> {
+    Err(no_snapshots())
+}
+
+fn no_snapshots() -> ApiError {
+    ApiError::new(StatusCode::BAD_REQUEST, "Snapshots are only kept in SQLite portfolios (.db, .sqlite)")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::router;
+    use axum::body::{to_bytes, Body};
+    use axum::http::{header, Request};
+    use std::fs;
+    use std::path::PathBuf;
+    use tempfile::tempdir;
+    use tower::ServiceExt;
+
+    const PORTFOLIO: &str = r#"{
+        "schema_version": 3,
+        "holdings": [{"ticker": "AAPL", "quantity": 10, "cost_basis": 150, "date_purchased": "2024-01-01"}],
+        "transactions": [{"date": "2024-01-01", "kind": "buy", "ticker": "AAPL", "quantity": 10, "price": 150}]
+    }"#;
+
+    fn state(path: &FilePath) -> AppState {
+        AppState::new(&[path.to_path_buf()], None).unwrap()
+    }
+
+    async fn send(state: &AppState, method: &str, uri: &str, body: Option<Value>) -> (StatusCode, Value) {
+        let request = Request::builder().method(method).uri(uri).header(header::CONTENT_TYPE, "application/json");
+        let body = body.map(|b| Body::from(b.to_string())).unwrap_or_default();
+        let response = router(state.clone()).oneshot(request.body(body).unwrap()).await.unwrap();
+        let status = response.status();
+        let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&bytes).unwrap_or(Value::Null))
+    }
+
+    fn portfolio_file(dir: &tempfile::TempDir) -> PathBuf {
+        let path = dir.path().join("main.json");
+        fs::write(&path, PORTFOLIO).unwrap();
+        path
+    }
+
+    #[tokio::test]
+    async fn test_holdings_crud_saves_the_file() {
+        let dir = tempdir().unwrap();
+        let path = portfolio_file(&dir);
+        let state = state(&path);
+
+        let lot = serde_json::json!({"ticker": "MSFT", "quantity": 2, "cost_basis": 300, "date_purchased": "2024-02-01"});
+        let (status, created) = send(&state, "POST", "/api/v1/holdings", Some(lot)).await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(created["id"], 1);
+        assert_eq!(created["ticker"], "MSFT");
+        assert_eq!(Portfolio::load_from_file(&path).unwrap().holdings.len(), 2);
+
+        let replacement = serde_json::json!({"ticker": "MSFT", "quantity": 3, "cost_basis": 310, "date_purchased": "2024-02-01"});
+        let (status, replaced) = send(&state, "PUT", "/api/v1/holdings/1", Some(replacement)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(replaced["quantity"], 3.0);
+
+        let (status, _) = send(&state, "DELETE", "/api/v1/holdings/0", None).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let (_, holdings) = send(&state, "GET", "/api/v1/holdings", None).await;
+        assert_eq!(holdings.as_array().unwrap().len(), 1);
+        assert_eq!(holdings[0]["ticker"], "MSFT");
+        assert_eq!(holdings[0]["id"], 0);
+
+        let (status, _) = send(&state, "GET", "/api/v1/holdings/5", None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_entries_are_refused_with_issues() {
+        let dir = tempdir().unwrap();
+        let path = portfolio_file(&dir);
+        let before = fs::read_to_string(&path).unwrap();
+        let state = state(&path);
+
+        let lot = serde_json::json!({"ticker": "MSFT", "quantity": 2, "cost_basis": -5, "date_purchased": "2024-13-01"});
+        let (status, body) = send(&state, "POST", "/api/v1/holdings", Some(lot)).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        let issues = body["issues"].as_array().unwrap();
+        assert!(issues.iter().any(|i| i["location"] == "holdings[1].cost_basis"), "{:?}", issues);
+        assert!(issues.iter().any(|i| i["location"] == "holdings[1].date_purchased"), "{:?}", issues);
+        assert_eq!(fs::read_to_string(&path).unwrap(), before);
+
+        let transaction = serde_json::json!({"date": "2024-01-02", "kind": "swap", "ticker": "AAPL"});
+        let (status, _) = send(&state, "PUT", "/api/v1/transactions/0", Some(transaction)).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[tokio::test]
+    async fn test_transactions_quotes_and_reports() {
+        let dir = tempdir().unwrap();
+        let state = state(&portfolio_file(&dir));
+
+        let dividend = serde_json::json!({"date": "2024-03-01", "kind": "dividend", "ticker": "AAPL", "price": 2.4});
+        let (status, created) = send(&state, "POST", "/api/v1/transactions", Some(dividend)).await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(created["id"], 1);
+        assert_eq!(created["kind"], "dividend");
+
+        let (_, quotes) = send(&state, "GET", "/api/v1/quotes?tickers=aapl,msft", None).await;
+        assert_eq!(quotes[0]["ticker"], "AAPL");
+        assert_eq!(quotes[1]["ticker"], "MSFT");
+        let (_, quotes) = send(&state, "GET", "/api/v1/quotes?tickers=AAPL,ZZZQ", None).await;
+        assert_eq!(quotes.as_array().unwrap().len(), 1, "{}", quotes);
+        let (status, _) = send(&state, "GET", "/api/v1/quotes?tickers=,", None).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let (_, balances) = send(&state, "GET", "/api/v1/reports/balances", None).await;
+        assert_eq!(balances["total_value"], 1700.0);
+        let (_, positions) = send(&state, "GET", "/api/v1/reports/positions", None).await;
+        assert_eq!(positions[0]["lots"], 1);
+
+        let (status, _) = send(&state, "GET", "/api/v1/snapshots", None).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn test_snapshots_in_database() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("ledger.db");
+        Portfolio::from_json_str(PORTFOLIO).unwrap().save_to_file(&path).unwrap();
+        let state = state(&path);
+
+        let (status, snapshot) = send(&state, "POST", "/api/v1/snapshots", None).await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(snapshot["total_value"], 1700.0);
+        let (_, snapshots) = send(&state, "GET", "/api/v1/snapshots", None).await;
+        assert_eq!(snapshots.as_array().unwrap().len(), 1);
+
+        // No snapshot at a made-up price
+        let lot = serde_json::json!({"ticker": "ZZZQ", "quantity": 1, "cost_basis": 10, "date_purchased": "2024-02-01"});
+        send(&state, "POST", "/api/v1/holdings", Some(lot)).await;
+        let (status, body) = send(&state, "POST", "/api/v1/snapshots", None).await;
+        assert_eq!(status, StatusCode::BAD_GATEWAY);
+        assert!(body["error"].as_str().unwrap().contains("ZZZQ"), "{}", body);
+        let (_, snapshots) = send(&state, "GET", "/api/v1/snapshots", None).await;
+        assert_eq!(snapshots.as_array().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_openapi_covers_every_route() {
+        let doc = serde_json::to_value(ApiDoc::openapi()).unwrap();
+        let paths = doc["paths"].as_object().unwrap();
+        for path in ["/holdings", "/holdings/{id}", "/transactions/{id}", "/quotes", "/reports/allocation", "/snapshots"] {
+            assert!(paths.contains_key(path), "missing {}", path);
+        }
+        assert!(doc["components"]["schemas"]["Holding"]["properties"]["cost_basis"].is_object());
+        assert!(doc["components"]["schemas"]["BalanceReport"].is_object());
+    }
+}
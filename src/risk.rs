@@ -0,0 +1,293 @@
+use anyhow::{bail, Context, Result};
+use chrono::NaiveDate;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::benchmark::Benchmark;
+use crate::history::{align, PriceSeries, TRADING_DAYS};
+use crate::money;
+use crate::portfolio::Holding;
+use crate::stats;
+
+// Risk statistics from daily closes. The portfolio is measured as if the
+// current positions had been held through the whole period, which is what
+// matters when deciding whether to keep them.
+
+/// Annualized figures are fractions (0.2 is 20%).
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct RiskMetrics {
+    pub annual_return: f64,
+    pub volatility: f64,
+    pub downside_deviation: f64,
+    pub beta: Option<f64>,
+    pub sharpe: Option<f64>,
+    pub sortino: Option<f64>,
+    pub max_drawdown: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RiskRow {
+    pub ticker: String,
+    /// Share of the portfolio's value at the end of the period, in percent.
+    pub weight: f64,
+    pub metrics: RiskMetrics,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RiskReport {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub observations: usize,
+    pub benchmark: String,
+    /// Annual risk-free rate in percent.
+    pub risk_free_rate: f64,
+    pub rows: Vec<RiskRow>,
+    pub portfolio: RiskMetrics,
+    pub benchmark_metrics: RiskMetrics,
+}
+
+/// Quantity held per ticker, with lots merged.
+pub fn positions(holdings: &[Holding]) -> BTreeMap<String, f64> {
+    let mut positions = BTreeMap::new();
+    for h in holdings {
+        *positions.entry(h.ticker.clone()).or_insert(0.0) += money::to_f64(h.quantity);
+    }
+    positions
+}
+
+/// Daily value of fixed positions over the aligned dates.
+pub fn value_series(quantities: &[f64], closes: &[Vec<f64>]) -> Vec<f64> {
+    let days = closes.first().map(|c| c.len()).unwrap_or(0);
+    (0..days)
+        .map(|day| quantities.iter().zip(closes).map(|(q, c)| q * c[day]).sum())
+        .collect()
+}
+
+/// Risk figures of a value (or price) series. `risk_free_rate` is an annual
+/// fraction; it is also the target return for the downside deviation.
+pub fn metrics(values: &[f64], benchmark_returns: &[f64], risk_free_rate: f64) -> RiskMetrics {
+    let returns = stats::returns(values);
+    let annual_return = stats::mean(&returns) * TRADING_DAYS;
+    let volatility = stats::std_dev(&returns) * TRADING_DAYS.sqrt();
+    let downside_deviation =
+        stats::downside_deviation(&returns, risk_free_rate / TRADING_DAYS) * TRADING_DAYS.sqrt();
+    let excess = annual_return - risk_free_rate;
+
+    RiskMetrics {
+        annual_return,
+        volatility,
+        downside_deviation,
+        beta: stats::beta(&returns, benchmark_returns),
+        sharpe: (volatility > 0.0).then(|| excess / volatility),
+        sortino: (downside_deviation > 0.0).then(|| excess / downside_deviation),
+        max_drawdown: stats::max_drawdown(values).depth,
+    }
+}
+
+pub fn risk_report(
+    holdings: &[Holding],
+    history: &HashMap<String, PriceSeries>,
+    benchmark: &Benchmark,
+    risk_free_rate: f64,
+) -> Result<RiskReport> {
+    let positions = positions(holdings);
+    if positions.is_empty() {
+        bail!("The portfolio has no holdings to measure");
+    }
+
+    let index = benchmark.index_series(history)?;
+    let mut series: Vec<&PriceSeries> = positions
+        .keys()
+        .map(|t| history.get(t).with_context(|| format!("No price history for {}", t)))
+        .collect::<Result<_>>()?;
+    series.push(&index);
+    let (dates, closes) = align(&series);
+    if dates.len() < 3 {
+        bail!("Not enough price history: {} common trading day(s), need at least 3", dates.len());
+    }
+
+    let rf = risk_free_rate / 100.0;
+    let (benchmark_closes, holding_closes) = closes.split_last().expect("benchmark column");
+    let benchmark_returns = stats::returns(benchmark_closes);
+
+    let quantities: Vec<f64> = positions.values().copied().collect();
+    let values = value_series(&quantities, holding_closes);
+    let total = values.last().copied().unwrap_or(0.0);
+
+    let rows = positions
+        .keys()
+        .zip(&quantities)
+        .zip(holding_closes)
+        .map(|((ticker, quantity), closes)| RiskRow {
+            ticker: ticker.clone(),
+            weight: if total > 0.0 { quantity * closes[closes.len() - 1] / total * 100.0 } else { 0.0 },
+            metrics: metrics(closes, &benchmark_returns, rf),
+        })
+        .collect();
+
+    Ok(RiskReport {
+        from: dates[0],
+        to: dates[dates.len() - 1],
+        observations: dates.len(),
+        benchmark: benchmark.to_string(),
+        risk_free_rate,
+        rows,
+        portfolio: metrics(&values, &benchmark_returns, rf),
+        benchmark_metrics: metrics(benchmark_closes, &benchmark_returns, rf),
+    })
+}
+
+/// Pairwise correlation of daily returns. `None` where a series never moved.
+#[derive(Debug, Clone, Serialize)]
+pub struct CorrelationMatrix {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub observations: usize,
+    pub tickers: Vec<String>,
+    pub values: Vec<Vec<Option<f64>>>,
+}
+
+impl CorrelationMatrix {
+    /// Mean correlation over all distinct pairs.
+    pub fn average(&self) -> Option<f64> {
+        let pairs: Vec<f64> = (0..self.tickers.len())
+            .flat_map(|i| (i + 1..self.tickers.len()).filter_map(move |j| self.values[i][j]))
+            .collect();
+        (!pairs.is_empty()).then(|| stats::mean(&pairs))
+    }
+
+    /// The most correlated distinct pair.
+    pub fn highest_pair(&self) -> Option<(&str, &str, f64)> {
+        (0..self.tickers.len())
+            .flat_map(|i| (i + 1..self.tickers.len()).map(move |j| (i, j)))
+            .filter_map(|(i, j)| self.values[i][j].map(|c| (self.tickers[i].as_str(), self.tickers[j].as_str(), c)))
+            .max_by(|a, b| a.2.total_cmp(&b.2))
+    }
+}
+
+pub fn correlation_matrix(tickers: &[String], history: &HashMap<String, PriceSeries>) -> Result<CorrelationMatrix> {
+    let series: Vec<&PriceSeries> = tickers
+        .iter()
+        .map(|t| history.get(t).with_context(|| format!("No price history for {}", t)))
+        .collect::<Result<_>>()?;
+    let (dates, closes) = align(&series);
+    if dates.len() < 3 {
+        bail!("Not enough price history: {} common trading day(s), need at least 3", dates.len());
+    }
+
+    let returns: Vec<Vec<f64>> = closes.iter().map(|c| stats::returns(c)).collect();
+    let values = returns
+        .iter()
+        .map(|a| returns.iter().map(|b| stats::correlation(a, b)).collect())
+        .collect();
+
+    Ok(CorrelationMatrix {
+        from: dates[0],
+        to: dates[dates.len() - 1],
+        observations: dates.len(),
+        tickers: tickers.to_vec(),
+        values,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use rust_decimal::Decimal;
+    use rust_decimal_macros::dec;
+
+    fn series(start: NaiveDate, closes: &[f64]) -> PriceSeries {
+        closes
+            .iter()
+            .enumerate()
+            .map(|(i, c)| (start + Duration::days(i as i64), money::from_f64(*c)))
+            .collect()
+    }
+
+    fn spy() -> Benchmark {
+        "SPY".parse().unwrap()
+    }
+
+    fn holding(ticker: &str, quantity: Decimal) -> Holding {
+        Holding {
+            ticker: ticker.to_string(),
+            quantity,
+            cost_basis: dec!(1),
+            date_purchased: "2024-01-01".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_metrics_of_steady_growth() {
+        let values: Vec<f64> = (0..10).map(|i| 100.0 * 1.001_f64.powi(i)).collect();
+        let m = metrics(&values, &stats::returns(&values), 0.0);
+        assert!((m.annual_return - 0.252).abs() < 1e-9);
+        assert!(m.volatility < 1e-9);
+        assert_eq!(m.downside_deviation, 0.0);
+        assert_eq!(m.sortino, None);
+        assert_eq!(m.max_drawdown, 0.0);
+    }
+
+    #[test]
+    fn test_risk_report_portfolio_and_holdings() {
+        let start = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let history = HashMap::from([
+            ("AAA".to_string(), series(start, &[10.0, 11.0, 9.9, 10.89, 9.801])),
+            ("BBB".to_string(), series(start, &[20.0, 20.0, 20.0, 20.0, 20.0])),
+            ("SPY".to_string(), series(start, &[100.0, 105.0, 99.75, 104.7375, 99.500625])),
+        ]);
+        let holdings = vec![holding("AAA", dec!(5)), holding("BBB", dec!(1)), holding("AAA", dec!(5))];
+
+        let report = risk_report(&holdings, &history, &spy(), 2.0).unwrap();
+
+        assert_eq!(report.observations, 5);
+        assert_eq!(report.rows.len(), 2);
+        let aaa = &report.rows[0];
+        assert_eq!(aaa.ticker, "AAA");
+        assert!((aaa.weight - 98.01 / 118.01 * 100.0).abs() < 1e-9);
+        // AAA moves exactly twice as much as SPY each day
+        assert!((aaa.metrics.beta.unwrap() - 2.0).abs() < 1e-9);
+        assert!((aaa.metrics.max_drawdown - (1.0 - 9.801 / 11.0)).abs() < 1e-9);
+        assert_eq!(report.rows[1].metrics.volatility, 0.0);
+        assert_eq!(report.rows[1].metrics.sharpe, None);
+        // The flat BBB position damps the portfolio's swings
+        assert!(report.portfolio.volatility < aaa.metrics.volatility);
+        assert!((report.benchmark_metrics.beta.unwrap() - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_risk_report_needs_history() {
+        let start = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let history = HashMap::from([
+            ("AAA".to_string(), series(start, &[10.0, 11.0])),
+            ("SPY".to_string(), series(start, &[100.0, 101.0])),
+        ]);
+        assert!(risk_report(&[holding("AAA", dec!(1))], &history, &spy(), 0.0).is_err());
+        assert!(risk_report(&[], &history, &spy(), 0.0).is_err());
+    }
+
+    #[test]
+    fn test_correlation_matrix() {
+        let start = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let history = HashMap::from([
+            ("AAA".to_string(), series(start, &[10.0, 11.0, 9.9, 10.89])),
+            ("BBB".to_string(), series(start, &[20.0, 24.0, 19.2, 23.04])),
+            ("CCC".to_string(), series(start, &[5.0, 4.5, 4.95, 4.455])),
+            ("FLAT".to_string(), series(start, &[1.0, 1.0, 1.0, 1.0])),
+        ]);
+        let tickers: Vec<String> = ["AAA", "BBB", "CCC", "FLAT"].iter().map(|t| t.to_string()).collect();
+
+        let matrix = correlation_matrix(&tickers, &history).unwrap();
+
+        assert_eq!(matrix.observations, 4);
+        assert!((matrix.values[0][0].unwrap() - 1.0).abs() < 1e-9);
+        assert!((matrix.values[0][1].unwrap() - 1.0).abs() < 1e-9);
+        assert!((matrix.values[0][2].unwrap() + 1.0).abs() < 1e-9);
+        assert_eq!(matrix.values[0][3], None);
+        let (a, b, c) = matrix.highest_pair().unwrap();
+        assert_eq!((a, b), ("AAA", "BBB"));
+        assert!((c - 1.0).abs() < 1e-9);
+        assert!((matrix.average().unwrap() + 1.0 / 3.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,171 @@
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::OnceLock;
+
+use crate::portfolio::{Holding, Transaction, WatchItem};
+
+// Versioned on-disk layout of a portfolio file.
+//
+// Version 1 was a bare JSON array of holdings. From version 2 on the file is
+// an object ("envelope") with an explicit `schema_version`, so the model can
+// grow without breaking files written by older releases. Every change to the
+// layout gets a new version number and a migration step below.
+
+pub const CURRENT_SCHEMA_VERSION: u32 = 3;
+pub const DEFAULT_BASE_CURRENCY: &str = "USD";
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Metadata {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+static BASE_CURRENCY: OnceLock<String> = OnceLock::new();
+
+/// Use `currency` for portfolios that do not name one. Called once from
+/// `main` with the config file's `base_currency`.
+pub fn set_default_base_currency(currency: String) {
+    let _ = BASE_CURRENCY.set(currency);
+}
+
+/// The currency of a portfolio that does not name one: the configured
+/// default, or USD.
+pub fn default_base_currency() -> String {
+    BASE_CURRENCY.get().cloned().unwrap_or_else(|| DEFAULT_BASE_CURRENCY.to_string())
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PortfolioDocument {
+    pub schema_version: u32,
+    #[serde(default)]
+    pub metadata: Metadata,
+    #[serde(default = "default_base_currency")]
+    pub base_currency: String,
+    #[serde(default)]
+    pub holdings: Vec<Holding>,
+    #[serde(default)]
+    pub transactions: Vec<Transaction>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub watchlist: Vec<WatchItem>,
+}
+
+/// Which schema version a parsed file is written in.
+pub fn detect_version(value: &Value) -> Result<u32> {
+    match value {
+        Value::Array(_) => Ok(1),
+        Value::Object(map) => match map.get("schema_version") {
+            Some(Value::Number(n)) => n
+                .as_u64()
+                .and_then(|v| u32::try_from(v).ok())
+                .filter(|v| *v >= 2)
+                .with_context(|| format!("Invalid schema_version: {}", n)),
+            Some(other) => bail!("Invalid schema_version: {}", other),
+            None => bail!("Portfolio file is an object without a schema_version field"),
+        },
+        _ => bail!("Portfolio file must be a JSON array (version 1) or object (version 2+)"),
+    }
+}
+
+/// Upgrade a parsed file to the current schema version, one step at a time.
+/// Returns the upgraded value and the version it started at.
+pub fn migrate(mut value: Value) -> Result<(Value, u32)> {
+    let original = detect_version(&value)?;
+    if original > CURRENT_SCHEMA_VERSION {
+        bail!(
+            "Portfolio file uses schema version {}, but this build only understands up to version {}; please upgrade portfolio_rs",
+            original,
+            CURRENT_SCHEMA_VERSION
+        );
+    }
+
+    let mut version = original;
+    while version < CURRENT_SCHEMA_VERSION {
+        value = match version {
+            1 => migrate_v1_to_v2(value),
+            2 => migrate_v2_to_v3(value),
+            _ => unreachable!("no migration from schema version {}", version),
+        };
+        version += 1;
+    }
+
+    Ok((value, original))
+}
+
+/// Version 1 -> 2: wrap the bare holdings array in an envelope.
+fn migrate_v1_to_v2(value: Value) -> Value {
+    json!({
+        "schema_version": 2,
+        "metadata": {},
+        "base_currency": default_base_currency(),
+        "holdings": value,
+        "transactions": [],
+    })
+}
+
+/// Version 2 -> 3: adds the optional watchlist. Nothing to convert, but the
+/// bump keeps older releases from loading (and then dropping) a watchlist.
+fn migrate_v2_to_v3(mut value: Value) -> Value {
+    value["schema_version"] = json!(3);
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_version() {
+        assert_eq!(detect_version(&json!([])).unwrap(), 1);
+        assert_eq!(detect_version(&json!({"schema_version": 2})).unwrap(), 2);
+        assert!(detect_version(&json!({"holdings": []})).is_err());
+        assert!(detect_version(&json!({"schema_version": "2"})).is_err());
+        assert!(detect_version(&json!({"schema_version": 1})).is_err());
+        assert!(detect_version(&json!("text")).is_err());
+    }
+
+    #[test]
+    fn test_migrate_legacy_array() {
+        let legacy = json!([
+            {"ticker": "AAPL", "quantity": 10, "cost_basis": 150, "date_purchased": "2024-06-01"}
+        ]);
+        let (value, from) = migrate(legacy).unwrap();
+        assert_eq!(from, 1);
+
+        let document: PortfolioDocument = serde_json::from_value(value).unwrap();
+        assert_eq!(document.schema_version, CURRENT_SCHEMA_VERSION);
+        assert_eq!(document.base_currency, "USD");
+        assert_eq!(document.holdings.len(), 1);
+        assert!(document.transactions.is_empty());
+    }
+
+    #[test]
+    fn test_migrate_current_is_unchanged() {
+        let current = json!({"schema_version": 3, "base_currency": "EUR", "holdings": []});
+        let (value, from) = migrate(current.clone()).unwrap();
+        assert_eq!(from, 3);
+        assert_eq!(value, current);
+    }
+
+    #[test]
+    fn test_migrate_v2_keeps_contents() {
+        let v2 = json!({"schema_version": 2, "base_currency": "EUR", "holdings": [], "transactions": []});
+        let (value, from) = migrate(v2).unwrap();
+        assert_eq!(from, 2);
+        assert_eq!(value, json!({"schema_version": 3, "base_currency": "EUR", "holdings": [], "transactions": []}));
+
+        let document: PortfolioDocument = serde_json::from_value(value).unwrap();
+        assert!(document.watchlist.is_empty());
+    }
+
+    #[test]
+    fn test_migrate_rejects_newer_versions() {
+        let err = migrate(json!({"schema_version": 99})).unwrap_err();
+        assert!(err.to_string().contains("upgrade"));
+    }
+}
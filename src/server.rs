@@ -0,0 +1,502 @@
+use anyhow::{bail, Context, Result};
+use axum::extract::{Query, Request, State};
+use axum::http::{header, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{Html, IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use utoipa::ToSchema;
+
+use crate::api::fetch_prices_with_failures;
+use crate::history::{align, fetch_price_history, PriceSeries};
+use crate::portfolio::{HoldingWithPrice, Portfolio};
+use crate::reports::{
+    allocation_groups, allocation_report, balance_report, performance_report, AllocationGroup, AllocationReport,
+    AllocationRow, BalanceReport, PerformanceReport,
+};
+use crate::risk::{positions, value_series};
+use crate::validation::ValidationReport;
+
+// Local web dashboard. `serve` answers the same reports as the terminal
+// commands as JSON under /api, plus one HTML page at / that draws them.
+// Every request reads the portfolio file again and fetches fresh quotes, so
+// edits made with the CLI show up on the next reload.
+//
+// The server listens on 127.0.0.1 unless told otherwise. With a token, every
+// request must carry it, either as `Authorization: Bearer <token>` or as
+// `?token=<token>` (which is how the browser opens the page).
+//
+// Any web page can send requests to 127.0.0.1, and after rebinding its own
+// DNS name to 127.0.0.1 it can read the answers too. On a loopback address
+// only requests naming a local host (`localhost`, `127.0.0.1`, `[::1]`) are
+// served; on any other address, changes need a token.
+//
+// The versioned API under /api/v1 (see `rest`) adds reading and changing
+// holdings and transactions on top of these read-only endpoints.
+
+const PAGE: &str = include_str!("server.html");
+
+/// How far back the history endpoint goes without `from`.
+const DEFAULT_HISTORY_DAYS: i64 = 365;
+
+pub struct ServeOptions {
+    pub bind: SocketAddr,
+    pub token: Option<String>,
+}
+
+/// A portfolio file the server reads from, named after its file stem.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct PortfolioEntry {
+    pub name: String,
+    #[schema(value_type = String)]
+    pub file: PathBuf,
+}
+
+#[derive(Clone)]
+pub struct AppState {
+    portfolios: Arc<Vec<PortfolioEntry>>,
+    token: Option<Arc<str>>,
+    /// Listening on a loopback address, where the Host header is checked.
+    local_only: bool,
+    /// Held while a request reads, changes and saves a portfolio, so two
+    /// writes cannot overwrite each other.
+    pub(crate) write_lock: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl AppState {
+    pub fn new(files: &[PathBuf], token: Option<String>) -> Result<AppState> {
+        if files.is_empty() {
+            bail!("At least one portfolio file is required");
+        }
+        let mut portfolios: Vec<PortfolioEntry> = Vec::new();
+        for file in files {
+            let name = file
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .with_context(|| format!("Cannot name portfolio {}", file.display()))?
+                .to_string();
+            if portfolios.iter().any(|p| p.name == name) {
+                bail!("Two portfolio files are named '{}'; rename one of them", name);
+            }
+            portfolios.push(PortfolioEntry { name, file: file.clone() });
+        }
+        Ok(AppState {
+            portfolios: Arc::new(portfolios),
+            token: token.map(Arc::from),
+            local_only: true,
+            write_lock: Arc::new(tokio::sync::Mutex::new(())),
+        })
+    }
+
+    pub(crate) fn portfolios(&self) -> &[PortfolioEntry] {
+        &self.portfolios
+    }
+
+    /// The named portfolio, or the first one when no name is given.
+    pub(crate) fn entry(&self, name: Option<&str>) -> Result<&PortfolioEntry, ApiError> {
+        match name {
+            None => Ok(&self.portfolios[0]),
+            Some(name) => self
+                .portfolios
+                .iter()
+                .find(|p| p.name == name)
+                .ok_or_else(|| ApiError::new(StatusCode::NOT_FOUND, format!("No portfolio named '{}'", name))),
+        }
+    }
+
+    pub(crate) fn load(&self, name: Option<&str>) -> Result<Portfolio, ApiError> {
+        let entry = self.entry(name)?;
+        Portfolio::load_from_file(&entry.file).map_err(ApiError::internal)
+    }
+
+    /// The portfolio with current quotes. Fails with 502 rather than value a
+    /// holding at a made-up price when any quote could not be fetched.
+    pub(crate) async fn priced(&self, name: Option<&str>) -> Result<(Portfolio, Vec<HoldingWithPrice>), ApiError> {
+        let portfolio = self.load(name)?;
+        let (prices, failed) = fetch_prices_with_failures(&portfolio.get_tickers()).await.map_err(ApiError::internal)?;
+        if !failed.is_empty() {
+            return Err(ApiError::new(
+                StatusCode::BAD_GATEWAY,
+                format!("Could not fetch quotes for {}", failed.join(", ")),
+            ));
+        }
+        let holdings = portfolio.holdings_with_prices(&prices);
+        Ok((portfolio, holdings))
+    }
+}
+
+/// An error answered as `{"error": "..."}` with its status code, plus the
+/// validation issues when a request body was rejected.
+#[derive(Debug)]
+pub struct ApiError {
+    status: StatusCode,
+    body: ErrorBody,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ErrorBody {
+    pub error: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub issues: Vec<IssueBody>,
+}
+
+/// One problem found by the portfolio validator, as `validate` prints it.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct IssueBody {
+    pub severity: String,
+    pub location: String,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hint: Option<String>,
+}
+
+impl ApiError {
+    pub(crate) fn new(status: StatusCode, message: impl Into<String>) -> ApiError {
+        ApiError { status, body: ErrorBody { error: message.into(), issues: Vec::new() } }
+    }
+
+    pub(crate) fn internal(err: anyhow::Error) -> ApiError {
+        ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, format!("{:#}", err))
+    }
+
+    /// 422 listing every error (and warning) in the report.
+    pub(crate) fn invalid(report: &ValidationReport) -> ApiError {
+        let mut error = ApiError::new(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!("Validation failed ({} error(s), {} warning(s))", report.error_count(), report.warning_count()),
+        );
+        error.body.issues = report
+            .issues
+            .iter()
+            .map(|issue| IssueBody {
+                severity: issue.severity.to_string(),
+                location: issue.location.to_string(),
+                message: issue.message.clone(),
+                hint: issue.hint.clone(),
+            })
+            .collect();
+        error
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.status, Json(self.body)).into_response()
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ReportQuery {
+    portfolio: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AllocationQuery {
+    portfolio: Option<String>,
+    by: Option<AllocationGroup>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HistoryQuery {
+    portfolio: Option<String>,
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct AllocationResponse {
+    #[serde(flatten)]
+    pub report: AllocationReport,
+    /// The rows merged per ticker or asset class, largest first.
+    pub groups: Vec<AllocationRow>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HistoryResponse {
+    pub dates: Vec<NaiveDate>,
+    /// The current positions valued at each day's closes.
+    pub values: Vec<f64>,
+    pub closes: BTreeMap<String, Vec<f64>>,
+}
+
+pub fn router(state: AppState) -> Router {
+    Router::new()
+        .route("/", get(page))
+        .route("/api/portfolios", get(list_portfolios))
+        .route("/api/balances", get(balances))
+        .route("/api/allocation", get(allocation))
+        .route("/api/performance", get(performance))
+        .route("/api/history", get(history))
+        .nest("/api/v1", crate::rest::routes())
+        .layer(middleware::from_fn_with_state(state.clone(), require_token))
+        .layer(middleware::from_fn_with_state(state.clone(), check_host))
+        .with_state(state)
+}
+
+async fn check_host(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    if state.local_only {
+        let host = request.headers().get(header::HOST).map(|value| value.to_str().unwrap_or(""));
+        if host.is_some_and(|host| !is_local_host(host)) {
+            return ApiError::new(StatusCode::FORBIDDEN, "Requests must be addressed to localhost").into_response();
+        }
+    } else if state.token.is_none() && !request.method().is_safe() {
+        return ApiError::new(StatusCode::FORBIDDEN, "Changes over the network need --token").into_response();
+    }
+    next.run(request).await
+}
+
+/// `localhost` or a loopback address, with or without a port.
+fn is_local_host(host: &str) -> bool {
+    let name = match host.strip_prefix('[') {
+        Some(bracketed) => bracketed.split(']').next().unwrap_or(""),
+        None => host.rsplit_once(':').map_or(host, |(name, _)| name),
+    };
+    let name = name.trim_end_matches('.').to_ascii_lowercase();
+    name == "localhost" || name.parse::<std::net::IpAddr>().is_ok_and(|ip| ip.is_loopback())
+}
+
+async fn require_token(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let Some(token) = state.token.as_deref() else {
+        return next.run(request).await;
+    };
+    let bearer = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    let query = request
+        .uri()
+        .query()
+        .into_iter()
+        .flat_map(|query| query.split('&'))
+        .find_map(|pair| pair.strip_prefix("token="));
+
+    if bearer.or(query).is_some_and(|given| same_token(given, token)) {
+        next.run(request).await
+    } else {
+        ApiError::new(StatusCode::UNAUTHORIZED, "Missing or wrong token").into_response()
+    }
+}
+
+/// Compare without stopping at the first different byte, so the response
+/// time does not give away how much of the token was right.
+fn same_token(given: &str, token: &str) -> bool {
+    given.len() == token.len() && given.bytes().zip(token.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+async fn page() -> Html<&'static str> {
+    Html(PAGE)
+}
+
+async fn list_portfolios(State(state): State<AppState>) -> Json<Vec<PortfolioEntry>> {
+    Json(state.portfolios.to_vec())
+}
+
+async fn balances(State(state): State<AppState>, Query(query): Query<ReportQuery>) -> Result<Json<BalanceReport>, ApiError> {
+    let (portfolio, holdings) = state.priced(query.portfolio.as_deref()).await?;
+    Ok(Json(balance_report(&holdings, &portfolio.base_currency)))
+}
+
+async fn allocation(
+    State(state): State<AppState>,
+    Query(query): Query<AllocationQuery>,
+) -> Result<Json<AllocationResponse>, ApiError> {
+    let (portfolio, holdings) = state.priced(query.portfolio.as_deref()).await?;
+    let report = allocation_report(&holdings, &portfolio.base_currency);
+    let groups = allocation_groups(&report, query.by.unwrap_or(AllocationGroup::Ticker));
+    Ok(Json(AllocationResponse { report, groups }))
+}
+
+async fn performance(
+    State(state): State<AppState>,
+    Query(query): Query<ReportQuery>,
+) -> Result<Json<PerformanceReport>, ApiError> {
+    let (portfolio, holdings) = state.priced(query.portfolio.as_deref()).await?;
+    Ok(Json(performance_report(&holdings, &portfolio.base_currency)))
+}
+
+async fn history(State(state): State<AppState>, Query(query): Query<HistoryQuery>) -> Result<Json<HistoryResponse>, ApiError> {
+    let portfolio = state.load(query.portfolio.as_deref())?;
+    let to = query.to.unwrap_or_else(|| chrono::Utc::now().date_naive());
+    let from = query.from.unwrap_or(to - chrono::Duration::days(DEFAULT_HISTORY_DAYS));
+    if from > to {
+        return Err(ApiError::new(StatusCode::BAD_REQUEST, "'from' must not be after 'to'"));
+    }
+
+    let positions = positions(&portfolio.holdings);
+    let tickers: Vec<String> = positions.keys().cloned().collect();
+    if tickers.is_empty() {
+        return Ok(Json(HistoryResponse { dates: Vec::new(), values: Vec::new(), closes: BTreeMap::new() }));
+    }
+    let history = fetch_price_history(&tickers, from, to).await.map_err(ApiError::internal)?;
+    let series: Vec<&PriceSeries> = tickers
+        .iter()
+        .map(|t| history.get(t).with_context(|| format!("No price history for {}", t)))
+        .collect::<Result<_>>()
+        .map_err(ApiError::internal)?;
+    let (dates, closes) = align(&series);
+    let quantities: Vec<f64> = positions.values().copied().collect();
+    let values = value_series(&quantities, &closes);
+
+    Ok(Json(HistoryResponse { dates, values, closes: tickers.into_iter().zip(closes).collect() }))
+}
+
+/// Serve until Ctrl-C.
+pub async fn serve(files: &[PathBuf], options: ServeOptions) -> Result<()> {
+    let mut state = AppState::new(files, options.token.clone())?;
+    state.local_only = options.bind.ip().is_loopback();
+    // Fail on a broken file now rather than on the first request
+    for entry in state.portfolios.iter() {
+        Portfolio::load_from_file(&entry.file)?;
+    }
+
+    let listener = tokio::net::TcpListener::bind(options.bind)
+        .await
+        .with_context(|| format!("Failed to listen on {}", options.bind))?;
+    if !options.bind.ip().is_loopback() && options.token.is_none() {
+        eprintln!("Warning: listening on {} without --token; anyone who can reach it can read the portfolio", options.bind);
+    }
+    let mut url = format!("http://{}/", listener.local_addr()?);
+    if let Some(token) = &options.token {
+        url.push_str(&format!("?token={}", token));
+    }
+    println!("Serving {} portfolio(s) at {}", state.portfolios.len(), url);
+    println!("Press Ctrl-C to stop");
+
+    axum::serve(listener, router(state))
+        .with_graceful_shutdown(async {
+            let _ = tokio::signal::ctrl_c().await;
+        })
+        .await
+        .context("The web server failed")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::{to_bytes, Body};
+    use std::fs;
+    use tempfile::tempdir;
+    use tower::ServiceExt;
+
+    const PORTFOLIO: &str = r#"[
+        {"ticker": "AAPL", "quantity": 10, "cost_basis": 150, "date_purchased": "2024-01-01"},
+        {"ticker": "MSFT", "quantity": 5, "cost_basis": 300, "date_purchased": "2024-01-01"}
+    ]"#;
+
+    fn app(dir: &tempfile::TempDir, token: Option<&str>) -> Router {
+        let path = dir.path().join("main.json");
+        fs::write(&path, PORTFOLIO).unwrap();
+        router(AppState::new(&[path], token.map(str::to_string)).unwrap())
+    }
+
+    async fn get_json(app: Router, uri: &str) -> (StatusCode, serde_json::Value) {
+        let response = app.oneshot(Request::get(uri).body(Body::empty()).unwrap()).await.unwrap();
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap_or(serde_json::Value::Null))
+    }
+
+    #[tokio::test]
+    async fn test_report_endpoints() {
+        let dir = tempdir().unwrap();
+
+        let (status, balances) = get_json(app(&dir, None), "/api/balances").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(balances["rows"].as_array().unwrap().len(), 2);
+        assert_eq!(balances["total_value"], 3450.0);
+
+        let (_, allocation) = get_json(app(&dir, None), "/api/allocation?by=class").await;
+        assert_eq!(allocation["groups"][0]["ticker"], "Stocks");
+        assert_eq!(allocation["rows"].as_array().unwrap().len(), 2);
+
+        let (_, performance) = get_json(app(&dir, None), "/api/performance?portfolio=main").await;
+        assert_eq!(performance["rows"].as_array().unwrap().len(), 2);
+
+        let (status, _) = get_json(app(&dir, None), "/api/balances?portfolio=other").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_history_endpoint() {
+        let dir = tempdir().unwrap();
+        let (status, history) = get_json(app(&dir, None), "/api/history?from=2024-01-01&to=2024-01-31").await;
+        assert_eq!(status, StatusCode::OK);
+        let dates = history["dates"].as_array().unwrap();
+        assert!(!dates.is_empty());
+        assert_eq!(history["values"].as_array().unwrap().len(), dates.len());
+        assert_eq!(history["closes"]["AAPL"].as_array().unwrap().len(), dates.len());
+
+        let (status, _) = get_json(app(&dir, None), "/api/history?from=2024-02-01&to=2024-01-01").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_token_required_when_set() {
+        let dir = tempdir().unwrap();
+        let (status, body) = get_json(app(&dir, Some("s3cret")), "/api/balances").await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert!(body["error"].as_str().unwrap().contains("token"));
+
+        let (status, _) = get_json(app(&dir, Some("s3cret")), "/api/balances?token=wrong").await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let (status, _) = get_json(app(&dir, Some("s3cret")), "/api/balances?token=s3cret").await;
+        assert_eq!(status, StatusCode::OK);
+
+        let request = Request::get("/").header(header::AUTHORIZATION, "Bearer s3cret").body(Body::empty()).unwrap();
+        let response = app(&dir, Some("s3cret")).oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_host_check_and_changes_over_the_network() {
+        let dir = tempdir().unwrap();
+        let get = |host: &str| Request::get("/api/balances").header(header::HOST, host).body(Body::empty()).unwrap();
+
+        // A rebinding page's requests carry its own host name
+        let response = app(&dir, None).oneshot(get("attacker.example:8080")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        for host in ["localhost:8080", "127.0.0.1:8080", "[::1]:8080", "LOCALHOST"] {
+            let response = app(&dir, None).oneshot(get(host)).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK, "{}", host);
+        }
+
+        // On another address any host name will do, but changes need a token
+        let path = dir.path().join("main.json");
+        let mut state = AppState::new(std::slice::from_ref(&path), None).unwrap();
+        state.local_only = false;
+        let response = router(state.clone()).oneshot(get("192.168.1.5:8080")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let delete = Request::delete("/api/v1/holdings/0").body(Body::empty()).unwrap();
+        let response = router(state).oneshot(delete).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(Portfolio::load_from_file(&path).unwrap().holdings.len(), 2);
+    }
+
+    #[test]
+    fn test_is_local_host() {
+        assert!(is_local_host("localhost"));
+        assert!(is_local_host("localhost.:3000"));
+        assert!(is_local_host("127.0.0.2"));
+        assert!(is_local_host("[::1]"));
+        assert!(!is_local_host("localhost.attacker.example"));
+        assert!(!is_local_host("10.0.0.1:3000"));
+        assert!(!is_local_host(""));
+    }
+
+    #[test]
+    fn test_portfolio_names_must_differ() {
+        assert!(AppState::new(&[], None).is_err());
+        let files = [PathBuf::from("a/main.json"), PathBuf::from("b/main.toml")];
+        assert!(AppState::new(&files, None).is_err());
+        let state = AppState::new(&[PathBuf::from("a/main.json"), PathBuf::from("b/alt.json")], None).unwrap();
+        assert_eq!(state.entry(None).unwrap().name, "main");
+        assert_eq!(state.entry(Some("alt")).unwrap().name, "alt");
+    }
+}
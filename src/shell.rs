@@ -0,0 +1,531 @@
+use anyhow::{Context, Result};
+use chrono::NaiveDate;
+use colored::*;
+use rust_decimal::Decimal;
+use rustyline::completion::{Completer, Pair};
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Editor, Helper};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::api::fetch_prices_with_failures;
+use crate::commands::{print_allocation, print_balances, print_performance};
+use crate::money;
+use crate::portfolio::{CostBasisMethod, Portfolio};
+use crate::reports::{allocation_report, balance_report, performance_report};
+use crate::validation::{validate_document, EntryLines, DATE_FORMAT};
+
+// Interactive prompt over one portfolio. The file is loaded once and every
+// change is saved straight away; quotes are fetched the first time a ticker
+// needs one and reused until `refresh`.
+
+const COMMANDS: [&str; 11] = [
+    "balances", "allocation", "performance", "add", "sell", "show", "quote", "refresh", "undo", "help", "exit",
+];
+const SHOW_TOPICS: [&str; 3] = ["lots", "lot", "transactions"];
+
+const HELP: &str = "\
+  balances | allocation | performance     Reports at the session's quotes
+  add TICKER QTY @PRICE [YYYY-MM-DD]      Record a purchase as a new lot
+  sell TICKER QTY [@PRICE] [YYYY-MM-DD]   Sell by the cost-basis method (default price: the quote)
+  show lots [TICKER]                      List lots with their numbers
+  show lot N                              One lot at the current quote
+  show transactions                       The ledger
+  quote [TICKER...]                       Quotes, fetched once per session
+  refresh                                 Drop cached quotes
+  undo                                    Revert the last add or sell
+  help | exit";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ShellCommand {
+    Balances,
+    Allocation,
+    Performance,
+    Add { ticker: String, quantity: Decimal, price: Decimal, date: Option<NaiveDate> },
+    Sell { ticker: String, quantity: Decimal, price: Option<Decimal>, date: Option<NaiveDate> },
+    ShowLots { ticker: Option<String> },
+    /// 1-based, as numbered by `show lots`.
+    ShowLot(usize),
+    ShowTransactions,
+    Quote(Vec<String>),
+    Refresh,
+    Undo,
+    Help,
+    Exit,
+}
+
+/// Parse one line of input. Blank lines are `None`.
+pub fn parse_command(line: &str) -> Result<Option<ShellCommand>, String> {
+    // `@ 172` and `@172` read the same
+    let line = line.replace('@', " @ ");
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let Some((&command, args)) = words.split_first() else {
+        return Ok(None);
+    };
+
+    let command = match command.to_lowercase().as_str() {
+        "balances" | "balance" => no_args(args, "balances", ShellCommand::Balances)?,
+        "allocation" => no_args(args, "allocation", ShellCommand::Allocation)?,
+        "performance" => no_args(args, "performance", ShellCommand::Performance)?,
+        "add" | "buy" => {
+            let (ticker, quantity, price, date) = trade(args, "add TICKER QTY @PRICE [YYYY-MM-DD]")?;
+            let price = price.ok_or("add needs a price, e.g. add AAPL 5 @172")?;
+            ShellCommand::Add { ticker, quantity, price, date }
+        }
+        "sell" => {
+            let (ticker, quantity, price, date) = trade(args, "sell TICKER QTY [@PRICE] [YYYY-MM-DD]")?;
+            ShellCommand::Sell { ticker, quantity, price, date }
+        }
+        "show" => match args {
+            ["lots"] => ShellCommand::ShowLots { ticker: None },
+            ["lots", ticker] => ShellCommand::ShowLots { ticker: Some(ticker.to_uppercase()) },
+            ["lot", number] => match number.parse::<usize>() {
+                Ok(number) if number > 0 => ShellCommand::ShowLot(number),
+                _ => return Err(format!("Not a lot number: {}", number)),
+            },
+            ["transactions"] => ShellCommand::ShowTransactions,
+            _ => return Err("Usage: show lots [TICKER] | show lot N | show transactions".to_string()),
+        },
+        "quote" | "quotes" => ShellCommand::Quote(args.iter().map(|t| t.to_uppercase()).collect()),
+        "refresh" => no_args(args, "refresh", ShellCommand::Refresh)?,
+        "undo" => no_args(args, "undo", ShellCommand::Undo)?,
+        "help" | "?" => ShellCommand::Help,
+        "exit" | "quit" => ShellCommand::Exit,
+        other => return Err(format!("Unknown command '{}' (try help)", other)),
+    };
+    Ok(Some(command))
+}
+
+fn no_args(args: &[&str], name: &str, command: ShellCommand) -> Result<ShellCommand, String> {
+    match args {
+        [] => Ok(command),
+        _ => Err(format!("{} takes no arguments", name)),
+    }
+}
+
+type Trade = (String, Decimal, Option<Decimal>, Option<NaiveDate>);
+
+fn trade(args: &[&str], usage: &str) -> Result<Trade, String> {
+    let (ticker, quantity, rest) = match args {
+        [ticker, quantity, rest @ ..] => (ticker.to_uppercase(), *quantity, rest),
+        _ => return Err(format!("Usage: {}", usage)),
+    };
+    let quantity = positive(quantity, "quantity")?;
+
+    let mut price = None;
+    let mut date = None;
+    let mut rest = rest.iter();
+    while let Some(word) = rest.next() {
+        if *word == "@" {
+            let value = rest.next().ok_or("Missing price after @")?;
+            price = Some(positive(value, "price")?);
+        } else if let Ok(parsed) = NaiveDate::parse_from_str(word, DATE_FORMAT) {
+            date = Some(parsed);
+        } else {
+            return Err(format!("Unexpected '{}'. Usage: {}", word, usage));
+        }
+    }
+    Ok((ticker, quantity, price, date))
+}
+
+fn positive(text: &str, what: &str) -> Result<Decimal, String> {
+    match Decimal::from_str(text) {
+        Ok(value) if value > Decimal::ZERO => Ok(value),
+        _ => Err(format!("The {} must be a positive number, not '{}'", what, text)),
+    }
+}
+
+/// Completes command names, `show` topics, and tickers from the portfolio.
+pub struct ShellHelper {
+    tickers: Vec<String>,
+}
+
+impl ShellHelper {
+    fn candidates(&self, line: &str, pos: usize) -> (usize, Vec<String>) {
+        let before = &line[..pos];
+        let start = before.rfind(char::is_whitespace).map(|i| i + 1).unwrap_or(0);
+        let word = &before[start..];
+        let previous: Vec<String> = before[..start].split_whitespace().map(str::to_lowercase).collect();
+
+        let options: Vec<&str> = match previous.iter().map(String::as_str).collect::<Vec<_>>().as_slice() {
+            [] => COMMANDS.to_vec(),
+            ["show"] => SHOW_TOPICS.to_vec(),
+            ["show", "lots"] | ["add" | "buy" | "sell"] | ["quote" | "quotes", ..] => {
+                self.tickers.iter().map(String::as_str).collect()
+            }
+            _ => Vec::new(),
+        };
+        let matches = options
+            .into_iter()
+            .filter(|option| option.to_lowercase().starts_with(&word.to_lowercase()))
+            .map(str::to_string)
+            .collect();
+        (start, matches)
+    }
+}
+
+impl Completer for ShellHelper {
+    type Candidate = Pair;
+
+    fn complete(&self, line: &str, pos: usize, _ctx: &rustyline::Context<'_>) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let (start, matches) = self.candidates(line, pos);
+        let pairs = matches.into_iter().map(|m| Pair { display: m.clone(), replacement: format!("{} ", m) }).collect();
+        Ok((start, pairs))
+    }
+}
+
+impl Hinter for ShellHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ShellHelper {}
+
+impl Validator for ShellHelper {}
+
+impl Helper for ShellHelper {}
+
+pub struct Session {
+    path: PathBuf,
+    pub portfolio: Portfolio,
+    quotes: HashMap<String, Decimal>,
+    method: CostBasisMethod,
+    /// What each change was, and the portfolio before it.
+    undo: Vec<(String, Portfolio)>,
+}
+
+impl Session {
+    pub fn open(path: &Path, method: CostBasisMethod) -> Result<Self> {
+        Ok(Session {
+            path: path.to_path_buf(),
+            portfolio: Portfolio::load_from_file(path)?,
+            quotes: HashMap::new(),
+            method,
+            undo: Vec::new(),
+        })
+    }
+
+    /// Held and watched tickers, for completion.
+    fn tickers(&self) -> Vec<String> {
+        let mut tickers = self.portfolio.get_tickers();
+        tickers.extend(self.portfolio.watchlist.iter().map(|w| w.ticker.clone()));
+        tickers.sort();
+        tickers.dedup();
+        tickers
+    }
+
+    /// Quotes for `tickers`, fetching only those not already cached. Tickers
+    /// whose quote could not be fetched are left out (and not cached), rather
+    /// than given the default price.
+    async fn quotes(&mut self, tickers: &[String]) -> Result<HashMap<String, Decimal>> {
+        let missing: Vec<String> = tickers.iter().filter(|t| !self.quotes.contains_key(*t)).cloned().collect();
+        if !missing.is_empty() {
+            let (mut prices, failed) = fetch_prices_with_failures(&missing).await?;
+            for ticker in &failed {
+                eprintln!("Warning: Could not fetch price for {}", ticker);
+                prices.remove(ticker);
+            }
+            self.quotes.extend(prices);
+        }
+        Ok(tickers.iter().filter_map(|t| self.quotes.get(t).map(|p| (t.clone(), *p))).collect())
+    }
+
+    /// Apply a change, check the result and save it. The file is left alone
+    /// when the change fails or would make the portfolio invalid.
+    fn change(&mut self, description: String, apply: impl FnOnce(&mut Portfolio) -> Result<()>) -> Result<()> {
+        let before = self.portfolio.clone();
+        let mut after = self.portfolio.clone();
+        apply(&mut after)?;
+
+        let document = serde_json::to_value(after.to_document())?;
+        let report = validate_document(&document, &EntryLines::default());
+        if report.has_errors() {
+            return Err(report.into());
+        }
+        after.save_to_file(&self.path)?;
+        self.portfolio = after;
+        self.undo.push((description, before));
+        Ok(())
+    }
+
+    /// Run one command. Returns false on `exit`.
+    pub async fn execute(&mut self, command: ShellCommand) -> Result<bool> {
+        let today = crate::validation::local_today();
+        let currency = self.portfolio.base_currency.clone();
+        match command {
+            ShellCommand::Balances | ShellCommand::Allocation | ShellCommand::Performance => {
+                let prices = self.quotes(&self.portfolio.get_tickers()).await?;
+                let holdings = self.portfolio.holdings_with_prices(&prices);
+                match command {
+                    ShellCommand::Balances => print_balances(&balance_report(&holdings, &currency), None),
+                    ShellCommand::Allocation => print_allocation(&allocation_report(&holdings, &currency), None, None),
+                    _ => print_performance(&performance_report(&holdings, &currency), None),
+                }
+            }
+            ShellCommand::Add { ticker, quantity, price, date } => {
+                let description = format!("add {} {} @{}", ticker, quantity.normalize(), price.normalize());
+                self.change(description, |p| {
+                    p.buy(&ticker, quantity, price, date.unwrap_or(today));
+                    Ok(())
+                })?;
+                println!("{} {} {} at {:.2} ({:.2} {})", "Bought".green(), quantity, ticker.yellow(),
+                         price, money::round_money(quantity * price, &currency), currency);
+            }
+            ShellCommand::Sell { ticker, quantity, price, date } => {
+                let price = match price {
+                    Some(price) => price,
+                    None => *self
+                        .quotes(std::slice::from_ref(&ticker))
+                        .await?
+                        .get(&ticker)
+                        .with_context(|| format!("No quote for {}; give a price with @", ticker))?,
+                };
+                let description = format!("sell {} {} @{}", ticker, quantity.normalize(), price.normalize());
+                let mut sold = Vec::new();
+                let method = self.method;
+                self.change(description, |p| {
+                    sold = p.sell(&ticker, quantity, price, date.unwrap_or(today), method)?;
+                    Ok(())
+                })?;
+                let gain: Decimal = sold.iter().map(|lot| lot.quantity * (price - lot.cost_basis)).sum();
+                let gain = money::round_money(gain, &currency);
+                let gain = if gain >= Decimal::ZERO { format!("+{:.2}", gain).green() } else { format!("{:.2}", gain).red() };
+                println!("{} {} {} at {:.2} from {} lot(s), realized {} {}", "Sold".green(), quantity, ticker.yellow(),
+                         price, sold.len(), gain, currency);
+            }
+            ShellCommand::ShowLots { ticker } => {
+                println!("{}", "--------------------------------------".cyan());
+                println!("{:<4} | {:<8} | {:<10} | {:<10} | {:<10}",
+                         "#".bold(), "Ticker".bold(), "Quantity".bold(), "Cost".bold(), "Purchased".bold());
+                println!("{}", "--------------------------------------".cyan());
+                for (index, lot) in self.portfolio.holdings.iter().enumerate() {
+                    if ticker.as_ref().is_some_and(|t| *t != lot.ticker) {
+                        continue;
+                    }
+                    println!("{:<4} | {:<8} | {:<10} | {:<10.2} | {:<10}",
+                             index + 1, lot.ticker.yellow(), lot.quantity.normalize(), lot.cost_basis, lot.date_purchased);
+                }
+            }
+            ShellCommand::ShowLot(number) => {
+                let lot = self
+                    .portfolio
+                    .holdings
+                    .get(number - 1)
+                    .with_context(|| format!("No lot {} (there are {})", number, self.portfolio.holdings.len()))?
+                    .clone();
+                let price = self.quotes(std::slice::from_ref(&lot.ticker)).await?.get(&lot.ticker).copied().unwrap_or_default();
+                let value = lot.quantity * price;
+                let cost = lot.quantity * lot.cost_basis;
+                println!("{}", "--------------------------------------".cyan());
+                println!("{} {}", format!("Lot {}:", number).bold(), lot.ticker.yellow());
+                println!("{}", "--------------------------------------".cyan());
+                println!("Quantity:      {}", lot.quantity.normalize());
+                println!("Purchased:     {}", lot.date_purchased);
+                println!("Cost basis:    {:.2} ({:.2} {})", lot.cost_basis, money::round_money(cost, &currency), currency);
+                println!("Current price: {:.2} ({:.2} {})", money::round(price, 2), money::round_money(value, &currency), currency);
+                let gain = money::round_money(value - cost, &currency);
+                let percentage = money::round(money::percentage(value - cost, cost), 1);
+                let text = format!("{:+.2} {} ({:+.1}%)", gain, currency, percentage);
+                println!("Gain/Loss:     {}", if gain >= Decimal::ZERO { text.green() } else { text.red() });
+            }
+            ShellCommand::ShowTransactions => {
+                println!("{}", "--------------------------------------".cyan());
+                for t in &self.portfolio.transactions {
+                    println!("{} {:<10} {:<8} {:>10} @ {:<10.2} {}", t.date, t.kind.label(),
+                             t.ticker.as_deref().unwrap_or("").yellow(), t.quantity.normalize(), t.price,
+                             t.note.as_deref().unwrap_or(""));
+                }
+                if self.portfolio.transactions.is_empty() {
+                    println!("No transactions");
+                }
+            }
+            ShellCommand::Quote(tickers) => {
+                let tickers = if tickers.is_empty() { self.tickers() } else { tickers };
+                let prices = self.quotes(&tickers).await?;
+                for ticker in &tickers {
+                    match prices.get(ticker) {
+                        Some(price) => println!("{}: {:.2}", ticker.yellow(), money::round(*price, 2)),
+                        None => println!("{}: {}", ticker.yellow(), "no quote".red()),
+                    }
+                }
+            }
+            ShellCommand::Refresh => {
+                self.quotes.clear();
+                println!("Quotes will be fetched again");
+            }
+            ShellCommand::Undo => {
+                let (description, before) = self.undo.pop().context("Nothing to undo")?;
+                let mut restored = before;
+                restored.save_to_file(&self.path)?;
+                self.portfolio = restored;
+                println!("Undid: {}", description);
+            }
+            ShellCommand::Help => println!("{}", HELP),
+            ShellCommand::Exit => return Ok(false),
+        }
+        Ok(true)
+    }
+}
+
+fn history_path() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".portfolio_rs_history"))
+}
+
+/// Prompt for commands until `exit` or Ctrl-D. Sales take lots in the order
+/// `method` picks.
+pub async fn run(portfolio_file: &Path, method: CostBasisMethod) -> Result<()> {
+    let mut session = Session::open(portfolio_file, method)?;
+    let mut editor: Editor<ShellHelper, DefaultHistory> = Editor::new().context("Failed to start the shell")?;
+    editor.set_helper(Some(ShellHelper { tickers: session.tickers() }));
+    let history = history_path();
+    if let Some(history) = &history {
+        // There is no history file on the first run
+        let _ = editor.load_history(history);
+    }
+
+    println!("{} {} ({} lot(s)). Type help for commands.", "Portfolio".bold(),
+             portfolio_file.display(), session.portfolio.holdings.len());
+    loop {
+        let line = match editor.readline("portfolio> ") {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(err) => return Err(err).context("Failed to read input"),
+        };
+        if !line.trim().is_empty() {
+            let _ = editor.add_history_entry(line.as_str());
+        }
+
+        let command = match parse_command(&line) {
+            Ok(Some(command)) => command,
+            Ok(None) => continue,
+            Err(message) => {
+                eprintln!("{}", message.red());
+                continue;
+            }
+        };
+        match session.execute(command).await {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(err) => eprintln!("{} {:#}", "Error:".red().bold(), err),
+        }
+        if let Some(helper) = editor.helper_mut() {
+            helper.tickers = session.tickers();
+        }
+    }
+
+    if let Some(history) = &history {
+        let _ = editor.save_history(history);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_parse_trades() {
+        assert_eq!(
+            parse_command("add aapl 5 @172").unwrap(),
+            Some(ShellCommand::Add { ticker: "AAPL".to_string(), quantity: dec!(5), price: dec!(172), date: None })
+        );
+        assert_eq!(
+            parse_command("  sell MSFT 2.5 @ 410.10 2024-03-01 ").unwrap(),
+            Some(ShellCommand::Sell {
+                ticker: "MSFT".to_string(),
+                quantity: dec!(2.5),
+                price: Some(dec!(410.10)),
+                date: NaiveDate::from_ymd_opt(2024, 3, 1),
+            })
+        );
+        assert_eq!(
+            parse_command("sell TSLA 1").unwrap(),
+            Some(ShellCommand::Sell { ticker: "TSLA".to_string(), quantity: dec!(1), price: None, date: None })
+        );
+    }
+
+    #[test]
+    fn test_parse_other_commands_and_errors() {
+        assert_eq!(parse_command("").unwrap(), None);
+        assert_eq!(parse_command("show lot 3").unwrap(), Some(ShellCommand::ShowLot(3)));
+        assert_eq!(parse_command("show lots btc").unwrap(), Some(ShellCommand::ShowLots { ticker: Some("BTC".to_string()) }));
+        assert_eq!(parse_command("Balances").unwrap(), Some(ShellCommand::Balances));
+
+        assert!(parse_command("add AAPL 5").unwrap_err().contains("needs a price"));
+        assert!(parse_command("add AAPL -5 @172").unwrap_err().contains("positive"));
+        assert!(parse_command("add AAPL 5 @").unwrap_err().contains("Missing price"));
+        assert!(parse_command("show lot 0").unwrap_err().contains("Not a lot number"));
+        assert!(parse_command("balances now").unwrap_err().contains("no arguments"));
+        assert!(parse_command("frobnicate").unwrap_err().contains("Unknown command"));
+    }
+
+    #[test]
+    fn test_completion() {
+        let helper = ShellHelper { tickers: vec!["AAPL".to_string(), "AMZN".to_string(), "TSLA".to_string()] };
+        assert_eq!(helper.candidates("ba", 2), (0, vec!["balances".to_string()]));
+        assert_eq!(helper.candidates("show l", 6), (5, vec!["lots".to_string(), "lot".to_string()]));
+        assert_eq!(helper.candidates("sell a", 6), (5, vec!["AAPL".to_string(), "AMZN".to_string()]));
+        assert_eq!(helper.candidates("show lots T", 11).1, vec!["TSLA".to_string()]);
+        // Nothing to complete after the ticker
+        assert!(helper.candidates("add AAPL ", 9).1.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_session_add_sell_and_undo_save_the_file() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("portfolio.json");
+        fs::write(&path, r#"[{"ticker": "AAPL", "quantity": 10, "cost_basis": 150, "date_purchased": "2024-01-01"}]"#).unwrap();
+        let mut session = Session::open(&path, CostBasisMethod::Fifo).unwrap();
+        let run = |line: &str| parse_command(line).unwrap().unwrap();
+
+        session.execute(run("add AAPL 5 @172 2024-02-01")).await.unwrap();
+        assert_eq!(Portfolio::load_from_file(&path).unwrap().holdings.len(), 2);
+
+        // No price: sells at the session's quote
+        session.execute(run("sell AAPL 12")).await.unwrap();
+        let saved = Portfolio::load_from_file(&path).unwrap();
+        assert_eq!(saved.holdings.len(), 1);
+        assert_eq!(saved.holdings[0].quantity, dec!(3));
+        assert_eq!(saved.transactions.last().unwrap().price, dec!(170));
+
+        // A failed sell changes nothing and leaves nothing to undo
+        assert!(session.execute(run("sell AAPL 50 @1")).await.is_err());
+
+        // No quote and no price: refuse rather than sell at a made-up price
+        session.execute(run("add ZZZQ 1 @10 2024-02-01")).await.unwrap();
+        let error = session.execute(run("sell ZZZQ 1")).await.unwrap_err().to_string();
+        assert!(error.contains("No quote for ZZZQ"), "{}", error);
+        session.execute(run("undo")).await.unwrap();
+
+        session.execute(run("undo")).await.unwrap();
+        assert_eq!(Portfolio::load_from_file(&path).unwrap().holdings.len(), 2);
+        session.execute(run("undo")).await.unwrap();
+        assert_eq!(Portfolio::load_from_file(&path).unwrap().holdings.len(), 1);
+        assert!(session.execute(run("undo")).await.is_err());
+        assert!(!session.execute(run("exit")).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_quotes_are_cached_for_the_session() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("portfolio.json");
+        fs::write(&path, r#"[{"ticker": "AAPL", "quantity": 1, "cost_basis": 150, "date_purchased": "2024-01-01"}]"#).unwrap();
+        let mut session = Session::open(&path, CostBasisMethod::Fifo).unwrap();
+
+        session.quotes.insert("AAPL".to_string(), dec!(1));
+        let prices = session.quotes(&["AAPL".to_string()]).await.unwrap();
+        assert_eq!(prices["AAPL"], dec!(1));
+
+        session.execute(ShellCommand::Refresh).await.unwrap();
+        let prices = session.quotes(&["AAPL".to_string()]).await.unwrap();
+        assert_eq!(prices["AAPL"], dec!(170));
+    }
+}
@@ -0,0 +1,590 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, NaiveDate, Utc};
+use rusqlite::types::ValueRef;
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension, Row};
+use rust_decimal::Decimal;
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::portfolio::{Holding, Portfolio, Snapshot, SnapshotPosition, Transaction, TransactionKind, WatchItem};
+use crate::schema::{default_base_currency, Metadata, CURRENT_SCHEMA_VERSION};
+use crate::storage::PortfolioStore;
+use crate::validation;
+
+// Amounts are stored as decimal text so they come back exactly as written.
+// The schema version is kept in `meta`, and a database written by a newer
+// version is refused rather than misread.
+const SCHEMA: &str = r#"
+CREATE TABLE IF NOT EXISTS meta (
+    key   TEXT PRIMARY KEY,
+    value TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS holdings (
+    id             INTEGER PRIMARY KEY,
+    ticker         TEXT NOT NULL,
+    quantity       TEXT NOT NULL,
+    cost_basis     TEXT NOT NULL,
+    date_purchased TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS holdings_ticker_date ON holdings (ticker, date_purchased);
+
+CREATE TABLE IF NOT EXISTS transactions (
+    id       INTEGER PRIMARY KEY,
+    date     TEXT NOT NULL,
+    kind     TEXT NOT NULL,
+    ticker   TEXT,
+    quantity TEXT NOT NULL DEFAULT '0',
+    price    TEXT NOT NULL DEFAULT '0',
+    fees     TEXT NOT NULL DEFAULT '0',
+    note     TEXT
+);
+CREATE INDEX IF NOT EXISTS transactions_ticker_date ON transactions (ticker, date);
+CREATE INDEX IF NOT EXISTS transactions_date ON transactions (date);
+
+CREATE TABLE IF NOT EXISTS snapshots (
+    id          INTEGER PRIMARY KEY,
+    taken_at    TEXT NOT NULL,
+    total_value TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS snapshots_taken_at ON snapshots (taken_at);
+
+CREATE TABLE IF NOT EXISTS snapshot_positions (
+    snapshot_id INTEGER NOT NULL REFERENCES snapshots (id) ON DELETE CASCADE,
+    ticker      TEXT NOT NULL,
+    quantity    TEXT NOT NULL,
+    price       TEXT NOT NULL,
+    value       TEXT NOT NULL,
+    PRIMARY KEY (snapshot_id, ticker)
+);
+
+CREATE TABLE IF NOT EXISTS watchlist (
+    ticker       TEXT PRIMARY KEY,
+    added        TEXT NOT NULL,
+    price_added  TEXT,
+    target_price TEXT,
+    note         TEXT
+);
+
+CREATE TABLE IF NOT EXISTS quote_cache (
+    ticker     TEXT PRIMARY KEY,
+    price      TEXT NOT NULL,
+    fetched_at TEXT NOT NULL
+);
+"#;
+
+/// Filter for lot and transaction queries. Dates are inclusive.
+#[derive(Debug, Clone, Default)]
+pub struct LedgerQuery {
+    pub ticker: Option<String>,
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+}
+
+impl LedgerQuery {
+    // Dates are stored as ISO strings, so string comparison is date order
+    fn bounds(&self) -> (String, String) {
+        let from = self.from.map(|d| d.format(validation::DATE_FORMAT).to_string()).unwrap_or_default();
+        let to = self.to.map(|d| d.format(validation::DATE_FORMAT).to_string()).unwrap_or_else(|| "9999-12-31".to_string());
+        (from, to)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CachedQuote {
+    pub price: Decimal,
+    pub fetched_at: DateTime<Utc>,
+}
+
+pub struct SqliteStore {
+    conn: Connection,
+}
+
+impl SqliteStore {
+    /// Open an existing database. A missing file is an error, as it is for
+    /// portfolio files, rather than a new empty database.
+    pub fn open(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            anyhow::bail!("SQLite database not found: {:?}", path);
+        }
+        let flags = OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_URI | OpenFlags::SQLITE_OPEN_NO_MUTEX;
+        let conn = Connection::open_with_flags(path, flags)
+            .with_context(|| format!("Failed to open SQLite database: {:?}", path))?;
+        Self::init(conn)
+    }
+
+    /// Open a database, creating it if it does not exist yet.
+    pub fn create(path: &Path) -> Result<Self> {
+        let conn = Connection::open(path)
+            .with_context(|| format!("Failed to create SQLite database: {:?}", path))?;
+        Self::init(conn)
+    }
+
+    pub fn open_in_memory() -> Result<Self> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(conn: Connection) -> Result<Self> {
+        conn.execute_batch("PRAGMA foreign_keys = ON;")?;
+        conn.execute_batch(SCHEMA).context("Failed to create database tables")?;
+        let store = SqliteStore { conn };
+        match store.meta("schema_version")? {
+            Some(version) => {
+                let version: u32 = version
+                    .parse()
+                    .with_context(|| format!("Invalid schema version in database: {:?}", version))?;
+                if version > CURRENT_SCHEMA_VERSION {
+                    anyhow::bail!(
+                        "Database uses schema version {}, but this build only understands up to version {}; please upgrade portfolio_rs",
+                        version,
+                        CURRENT_SCHEMA_VERSION
+                    );
+                }
+            }
+            None => {
+                store.conn.execute(
+                    "INSERT INTO meta (key, value) VALUES ('schema_version', ?1)",
+                    params![CURRENT_SCHEMA_VERSION.to_string()],
+                )?;
+            }
+        }
+        Ok(store)
+    }
+
+    fn meta(&self, key: &str) -> Result<Option<String>> {
+        Ok(self
+            .conn
+            .query_row("SELECT value FROM meta WHERE key = ?1", params![key], |row| row.get(0))
+            .optional()?)
+    }
+
+    /// Lots matching the query, in purchase order, without loading the rest.
+    pub fn lots(&self, query: &LedgerQuery) -> Result<Vec<Holding>> {
+        let (from, to) = query.bounds();
+        let mut stmt = self.conn.prepare(
+            "SELECT ticker, quantity, cost_basis, date_purchased FROM holdings
+             WHERE (?1 IS NULL OR ticker = ?1) AND date_purchased BETWEEN ?2 AND ?3
+             ORDER BY date_purchased, id",
+        )?;
+        let rows = stmt.query_map(params![query.ticker, from, to], |row| {
+            Ok(Holding {
+                ticker: row.get(0)?,
+                quantity: decimal(row, 1)?,
+                cost_basis: decimal(row, 2)?,
+                date_purchased: row.get(3)?,
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Transactions matching the query, in date order.
+    pub fn transactions(&self, query: &LedgerQuery) -> Result<Vec<Transaction>> {
+        let (from, to) = query.bounds();
+        let mut stmt = self.conn.prepare(
+            "SELECT date, kind, ticker, quantity, price, fees, note FROM transactions
+             WHERE (?1 IS NULL OR ticker = ?1) AND date BETWEEN ?2 AND ?3
+             ORDER BY date, id",
+        )?;
+        let rows = stmt.query_map(params![query.ticker, from, to], |row| {
+            let kind: String = row.get(1)?;
+            Ok((kind, Transaction {
+                date: row.get(0)?,
+                kind: TransactionKind::Buy,
+                ticker: row.get(2)?,
+                quantity: decimal(row, 3)?,
+                price: decimal(row, 4)?,
+                fees: decimal(row, 5)?,
+                note: row.get(6)?,
+            }))
+        })?;
+
+        rows.map(|row| {
+            let (kind, mut transaction) = row?;
+            transaction.kind = serde_json::from_value(serde_json::Value::String(kind.clone()))
+                .with_context(|| format!("Unknown transaction kind in database: {}", kind))?;
+            Ok(transaction)
+        })
+        .collect()
+    }
+
+    pub fn record_snapshot(&mut self, snapshot: &Snapshot) -> Result<()> {
+        let tx = self.conn.transaction()?;
+        tx.execute(
+            "INSERT INTO snapshots (taken_at, total_value) VALUES (?1, ?2)",
+            params![snapshot.taken_at.to_rfc3339(), snapshot.total_value.to_string()],
+        )?;
+        let id = tx.last_insert_rowid();
+        for position in &snapshot.positions {
+            tx.execute(
+                "INSERT INTO snapshot_positions (snapshot_id, ticker, quantity, price, value)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    id,
+                    position.ticker,
+                    position.quantity.to_string(),
+                    position.price.to_string(),
+                    position.value.to_string()
+                ],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Snapshots taken between two instants (inclusive), oldest first.
+    pub fn snapshots(&self, from: Option<DateTime<Utc>>, to: Option<DateTime<Utc>>) -> Result<Vec<Snapshot>> {
+        let from = from.map(|t| t.to_rfc3339()).unwrap_or_default();
+        let to = to.map(|t| t.to_rfc3339()).unwrap_or_else(|| "9999".to_string());
+
+        let mut stmt = self.conn.prepare(
+            "SELECT id, taken_at, total_value FROM snapshots
+             WHERE taken_at BETWEEN ?1 AND ?2 ORDER BY taken_at, id",
+        )?;
+        let headers = stmt
+            .query_map(params![from, to], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?, decimal(row, 2)?))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        let mut positions_stmt = self.conn.prepare(
+            "SELECT ticker, quantity, price, value FROM snapshot_positions
+             WHERE snapshot_id = ?1 ORDER BY ticker",
+        )?;
+
+        headers
+            .into_iter()
+            .map(|(id, taken_at, total_value)| {
+                let positions = positions_stmt
+                    .query_map(params![id], |row| {
+                        Ok(SnapshotPosition {
+                            ticker: row.get(0)?,
+                            quantity: decimal(row, 1)?,
+                            price: decimal(row, 2)?,
+                            value: decimal(row, 3)?,
+                        })
+                    })?
+                    .collect::<rusqlite::Result<Vec<_>>>()?;
+                Ok(Snapshot {
+                    taken_at: DateTime::parse_from_rfc3339(&taken_at)
+                        .with_context(|| format!("Invalid snapshot timestamp: {}", taken_at))?
+                        .with_timezone(&Utc),
+                    total_value,
+                    positions,
+                })
+            })
+            .collect()
+    }
+
+    /// Watched tickers in the order they were added.
+    pub fn watchlist(&self) -> Result<Vec<WatchItem>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT ticker, added, price_added, target_price, note FROM watchlist ORDER BY rowid",
+        )?;
+        let optional_decimal = |row: &Row, idx| -> rusqlite::Result<Option<Decimal>> {
+            match row.get_ref(idx)? {
+                ValueRef::Null => Ok(None),
+                _ => decimal(row, idx).map(Some),
+            }
+        };
+        let items = stmt
+            .query_map([], |row| {
+                Ok(WatchItem {
+                    ticker: row.get(0)?,
+                    added: row.get(1)?,
+                    price_added: optional_decimal(row, 2)?,
+                    target_price: optional_decimal(row, 3)?,
+                    note: row.get(4)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(items)
+    }
+
+    pub fn cached_quotes(&self, tickers: &[String]) -> Result<HashMap<String, CachedQuote>> {
+        let mut stmt = self.conn.prepare("SELECT price, fetched_at FROM quote_cache WHERE ticker = ?1")?;
+        let mut quotes = HashMap::new();
+        for ticker in tickers {
+            let row = stmt
+                .query_row(params![ticker], |row| Ok((decimal(row, 0)?, row.get::<_, String>(1)?)))
+                .optional()?;
+            if let Some((price, fetched_at)) = row {
+                let fetched_at = DateTime::parse_from_rfc3339(&fetched_at)
+                    .with_context(|| format!("Invalid quote timestamp: {}", fetched_at))?
+                    .with_timezone(&Utc);
+                quotes.insert(ticker.clone(), CachedQuote { price, fetched_at });
+            }
+        }
+        Ok(quotes)
+    }
+
+    pub fn store_quotes(&mut self, prices: &HashMap<String, Decimal>, fetched_at: DateTime<Utc>) -> Result<()> {
+        let tx = self.conn.transaction()?;
+        for (ticker, price) in prices {
+            tx.execute(
+                "INSERT INTO quote_cache (ticker, price, fetched_at) VALUES (?1, ?2, ?3)
+                 ON CONFLICT (ticker) DO UPDATE SET price = excluded.price, fetched_at = excluded.fetched_at",
+                params![ticker, price.to_string(), fetched_at.to_rfc3339()],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+/// Read an amount column written as decimal text.
+fn decimal(row: &Row, idx: usize) -> rusqlite::Result<Decimal> {
+    let value = row.get_ref(idx)?;
+    let parsed = match value {
+        ValueRef::Text(text) => std::str::from_utf8(text).ok().and_then(|t| t.parse().ok()),
+        _ => None,
+    };
+    parsed.ok_or_else(|| {
+        let name = row.as_ref().column_name(idx).unwrap_or_default().to_string();
+        rusqlite::Error::InvalidColumnType(idx, name, value.data_type())
+    })
+}
+
+impl PortfolioStore for SqliteStore {
+    fn load(&self) -> Result<Portfolio> {
+        let parse_time = |value: Option<String>| -> Result<Option<DateTime<Utc>>> {
+            value
+                .map(|v| Ok(DateTime::parse_from_rfc3339(&v)?.with_timezone(&Utc)))
+                .transpose()
+        };
+
+        let portfolio = Portfolio {
+            holdings: self.lots(&LedgerQuery::default())?,
+            transactions: self.transactions(&LedgerQuery::default())?,
+            watchlist: self.watchlist()?,
+            base_currency: self.meta("base_currency")?.unwrap_or_else(default_base_currency),
+            metadata: Metadata {
+                name: self.meta("name")?,
+                created_at: parse_time(self.meta("created_at")?)?,
+                updated_at: parse_time(self.meta("updated_at")?)?,
+            },
+        };
+
+        // Same checks as for files, so bad rows written by other tools are caught
+        let document = serde_json::to_value(portfolio.to_document())?;
+        let report = validation::validate_document(&document, &validation::EntryLines::default());
+        if report.has_errors() {
+            return Err(report.into());
+        }
+
+        Ok(portfolio)
+    }
+
+    /// Replace the holdings, ledger and watchlist in a single database
+    /// transaction.
+    /// Snapshots and cached quotes are left alone.
+    fn save(&mut self, portfolio: &mut Portfolio) -> Result<()> {
+        let now = Utc::now();
+        portfolio.metadata.created_at.get_or_insert(now);
+        portfolio.metadata.updated_at = Some(now);
+
+        let tx = self.conn.transaction()?;
+        tx.execute("DELETE FROM holdings", [])?;
+        tx.execute("DELETE FROM transactions", [])?;
+        tx.execute("DELETE FROM watchlist", [])?;
+
+        for h in &portfolio.holdings {
+            tx.execute(
+                "INSERT INTO holdings (ticker, quantity, cost_basis, date_purchased) VALUES (?1, ?2, ?3, ?4)",
+                params![h.ticker, h.quantity.to_string(), h.cost_basis.to_string(), h.date_purchased],
+            )?;
+        }
+        for t in &portfolio.transactions {
+            tx.execute(
+                "INSERT INTO transactions (date, kind, ticker, quantity, price, fees, note)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![
+                    t.date,
+                    t.kind.label().to_lowercase(),
+                    t.ticker,
+                    t.quantity.to_string(),
+                    t.price.to_string(),
+                    t.fees.to_string(),
+                    t.note
+                ],
+            )?;
+        }
+
+        for w in &portfolio.watchlist {
+            tx.execute(
+                "INSERT INTO watchlist (ticker, added, price_added, target_price, note) VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    w.ticker,
+                    w.added,
+                    w.price_added.map(|p| p.to_string()),
+                    w.target_price.map(|p| p.to_string()),
+                    w.note
+                ],
+            )?;
+        }
+
+        let metadata = &portfolio.metadata;
+        let entries = [
+            ("base_currency", Some(portfolio.base_currency.clone())),
+            ("name", metadata.name.clone()),
+            ("created_at", metadata.created_at.map(|t| t.to_rfc3339())),
+            ("updated_at", metadata.updated_at.map(|t| t.to_rfc3339())),
+        ];
+        for (key, value) in entries {
+            match value {
+                Some(value) => tx.execute(
+                    "INSERT INTO meta (key, value) VALUES (?1, ?2)
+                     ON CONFLICT (key) DO UPDATE SET value = excluded.value",
+                    params![key, value],
+                )?,
+                None => tx.execute("DELETE FROM meta WHERE key = ?1", params![key])?,
+            };
+        }
+
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn holding(ticker: &str, date: &str) -> Holding {
+        Holding {
+            ticker: ticker.to_string(),
+            quantity: dec!(1.0),
+            cost_basis: dec!(100.0),
+            date_purchased: date.to_string(),
+        }
+    }
+
+    fn sample_portfolio() -> Portfolio {
+        Portfolio {
+            holdings: vec![
+                holding("AAPL", "2020-06-01"),
+                holding("AAPL", "2021-03-15"),
+                holding("MSFT", "2021-05-01"),
+                holding("AAPL", "2021-11-30"),
+                holding("AAPL", "2022-01-03"),
+            ],
+            transactions: vec![Transaction {
+                date: "2021-03-15".to_string(),
+                kind: TransactionKind::Buy,
+                ticker: Some("AAPL".to_string()),
+                quantity: dec!(1.0),
+                price: dec!(100.0),
+                fees: dec!(1.0),
+                note: Some("first".to_string()),
+            }],
+            watchlist: vec![WatchItem {
+                ticker: "NVDA".to_string(),
+                added: "2022-02-01".to_string(),
+                price_added: Some(dec!(120.50)),
+                target_price: None,
+                note: Some("wait for earnings".to_string()),
+            }],
+            base_currency: "EUR".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_save_and_load_round_trip() {
+        let mut store = SqliteStore::open_in_memory().unwrap();
+        let mut portfolio = sample_portfolio();
+        store.save(&mut portfolio).unwrap();
+
+        let loaded = store.load().unwrap();
+        assert_eq!(loaded.holdings.len(), 5);
+        assert_eq!(loaded.transactions, portfolio.transactions);
+        assert_eq!(loaded.watchlist, portfolio.watchlist);
+        assert_eq!(loaded.base_currency, "EUR");
+        assert!(loaded.metadata.updated_at.is_some());
+    }
+
+    #[test]
+    fn test_lots_query_by_ticker_and_year() {
+        let mut store = SqliteStore::open_in_memory().unwrap();
+        store.save(&mut sample_portfolio()).unwrap();
+
+        let query = LedgerQuery {
+            ticker: Some("AAPL".to_string()),
+            from: NaiveDate::from_ymd_opt(2021, 1, 1),
+            to: NaiveDate::from_ymd_opt(2021, 12, 31),
+        };
+        let lots = store.lots(&query).unwrap();
+        let dates: Vec<&str> = lots.iter().map(|l| l.date_purchased.as_str()).collect();
+        assert_eq!(dates, vec!["2021-03-15", "2021-11-30"]);
+    }
+
+    #[test]
+    fn test_snapshots_round_trip() {
+        let mut store = SqliteStore::open_in_memory().unwrap();
+        let snapshot = Snapshot {
+            taken_at: DateTime::parse_from_rfc3339("2024-06-01T20:00:00Z").unwrap().with_timezone(&Utc),
+            total_value: dec!(1700.0),
+            positions: vec![SnapshotPosition {
+                ticker: "AAPL".to_string(),
+                quantity: dec!(10.0),
+                price: dec!(170.0),
+                value: dec!(1700.0),
+            }],
+        };
+        store.record_snapshot(&snapshot).unwrap();
+
+        assert_eq!(store.snapshots(None, None).unwrap(), vec![snapshot]);
+        let later = DateTime::parse_from_rfc3339("2025-01-01T00:00:00Z").unwrap().with_timezone(&Utc);
+        assert!(store.snapshots(Some(later), None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_quote_cache_upserts() {
+        let mut store = SqliteStore::open_in_memory().unwrap();
+        let now = Utc::now();
+        store.store_quotes(&HashMap::from([("AAPL".to_string(), dec!(170))]), now).unwrap();
+        store.store_quotes(&HashMap::from([("AAPL".to_string(), dec!(171.5))]), now).unwrap();
+
+        let quotes = store.cached_quotes(&["AAPL".to_string(), "TSLA".to_string()]).unwrap();
+        assert_eq!(quotes.len(), 1);
+        assert_eq!(quotes["AAPL"].price, dec!(171.5));
+    }
+
+    #[test]
+    fn test_save_keeps_snapshots() {
+        let mut store = SqliteStore::open_in_memory().unwrap();
+        let snapshot = Snapshot { taken_at: Utc::now(), total_value: dec!(0.0), positions: vec![] };
+        store.record_snapshot(&snapshot).unwrap();
+        store.save(&mut sample_portfolio()).unwrap();
+        assert_eq!(store.snapshots(None, None).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_amounts_round_trip_exactly() {
+        let mut store = SqliteStore::open_in_memory().unwrap();
+        let mut portfolio = sample_portfolio();
+        portfolio.holdings[0].quantity = dec!(0.12345678);
+        portfolio.holdings[0].cost_basis = dec!(0.1);
+        store.save(&mut portfolio).unwrap();
+
+        let lots = store.lots(&LedgerQuery::default()).unwrap();
+        assert_eq!(lots[0].quantity, dec!(0.12345678));
+        assert_eq!(lots[0].cost_basis, dec!(0.1));
+    }
+
+    #[test]
+    fn test_refuses_newer_schema_version() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE meta (key TEXT PRIMARY KEY, value TEXT NOT NULL);
+             INSERT INTO meta (key, value) VALUES ('schema_version', '99');",
+        )
+        .unwrap();
+        let error = SqliteStore::init(conn).err().unwrap().to_string();
+        assert!(error.contains("schema version 99"), "{}", error);
+
+        let store = SqliteStore::open_in_memory().unwrap();
+        assert_eq!(store.meta("schema_version").unwrap(), Some(CURRENT_SCHEMA_VERSION.to_string()));
+        assert!(SqliteStore::init(store.conn).is_ok());
+    }
+}
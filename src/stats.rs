@@ -0,0 +1,146 @@
+// Statistics over daily price and value series. These work in floating
+// point: they describe risk, they are never written back as amounts.
+
+pub fn mean(values: &[f64]) -> f64 {
+    if values.is_empty() {
+        0.0
+    } else {
+        values.iter().sum::<f64>() / values.len() as f64
+    }
+}
+
+/// Sample variance (n - 1 in the denominator).
+pub fn variance(values: &[f64]) -> f64 {
+    covariance(values, values)
+}
+
+pub fn std_dev(values: &[f64]) -> f64 {
+    variance(values).sqrt()
+}
+
+/// Sample covariance of two equally long series.
+pub fn covariance(a: &[f64], b: &[f64]) -> f64 {
+    let n = a.len().min(b.len());
+    if n < 2 {
+        return 0.0;
+    }
+    let (mean_a, mean_b) = (mean(&a[..n]), mean(&b[..n]));
+    a.iter()
+        .zip(b)
+        .map(|(x, y)| (x - mean_a) * (y - mean_b))
+        .sum::<f64>()
+        / (n - 1) as f64
+}
+
+/// Pearson correlation, or `None` when either series is flat.
+pub fn correlation(a: &[f64], b: &[f64]) -> Option<f64> {
+    let denominator = std_dev(a) * std_dev(b);
+    if denominator == 0.0 {
+        None
+    } else {
+        Some((covariance(a, b) / denominator).clamp(-1.0, 1.0))
+    }
+}
+
+/// Simple returns between consecutive values: (p1 / p0) - 1.
+pub fn returns(values: &[f64]) -> Vec<f64> {
+    values
+        .windows(2)
+        .map(|w| if w[0] == 0.0 { 0.0 } else { w[1] / w[0] - 1.0 })
+        .collect()
+}
+
+/// Root mean square of the returns below `target`, counting returns above
+/// it as zero.
+pub fn downside_deviation(returns: &[f64], target: f64) -> f64 {
+    if returns.is_empty() {
+        return 0.0;
+    }
+    let sum: f64 = returns.iter().map(|r| (r - target).min(0.0).powi(2)).sum();
+    (sum / returns.len() as f64).sqrt()
+}
+
+/// Slope of `asset` against `benchmark`, or `None` if the benchmark is flat.
+pub fn beta(asset: &[f64], benchmark: &[f64]) -> Option<f64> {
+    let var = variance(benchmark);
+    if var == 0.0 {
+        None
+    } else {
+        Some(covariance(asset, benchmark) / var)
+    }
+}
+
+/// Largest peak-to-trough fall of a value series.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Drawdown {
+    /// Fraction lost from the peak (0.25 is a 25% fall).
+    pub depth: f64,
+    pub peak: usize,
+    pub trough: usize,
+}
+
+pub fn max_drawdown(values: &[f64]) -> Drawdown {
+    let mut worst = Drawdown::default();
+    let mut peak = 0;
+    for (i, &value) in values.iter().enumerate() {
+        if value > values[peak] {
+            peak = i;
+        }
+        if values[peak] > 0.0 {
+            let depth = 1.0 - value / values[peak];
+            if depth > worst.depth {
+                worst = Drawdown { depth, peak, trough: i };
+            }
+        }
+    }
+    worst
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn test_mean_and_sample_variance() {
+        let values = [2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0];
+        assert!(close(mean(&values), 5.0));
+        assert!(close(variance(&values), 32.0 / 7.0));
+        assert_eq!(variance(&[1.0]), 0.0);
+    }
+
+    #[test]
+    fn test_returns() {
+        let r = returns(&[100.0, 110.0, 99.0]);
+        assert!(close(r[0], 0.1));
+        assert!(close(r[1], -0.1));
+    }
+
+    #[test]
+    fn test_beta_and_correlation() {
+        let benchmark = [0.01, -0.02, 0.03, 0.0, -0.01];
+        let levered: Vec<f64> = benchmark.iter().map(|r| 2.0 * r).collect();
+        assert!(close(beta(&levered, &benchmark).unwrap(), 2.0));
+        assert!(close(correlation(&levered, &benchmark).unwrap(), 1.0));
+        let inverse: Vec<f64> = benchmark.iter().map(|r| -r).collect();
+        assert!(close(correlation(&inverse, &benchmark).unwrap(), -1.0));
+        assert_eq!(correlation(&[1.0, 1.0, 1.0], &benchmark[..3]), None);
+    }
+
+    #[test]
+    fn test_downside_deviation_ignores_gains() {
+        assert_eq!(downside_deviation(&[0.01, 0.02], 0.0), 0.0);
+        assert!(close(downside_deviation(&[-0.02, 0.02], 0.0), (0.0004_f64 / 2.0).sqrt()));
+    }
+
+    #[test]
+    fn test_max_drawdown() {
+        let drawdown = max_drawdown(&[100.0, 120.0, 90.0, 130.0, 104.0]);
+        assert!(close(drawdown.depth, 0.25));
+        assert_eq!((drawdown.peak, drawdown.trough), (1, 2));
+        assert_eq!(max_drawdown(&[1.0, 2.0, 3.0]).depth, 0.0);
+    }
+}
@@ -0,0 +1,131 @@
+use anyhow::Result;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::crypto;
+use crate::formats;
+use crate::portfolio::{read_portfolio_text, Portfolio};
+
+// Where a portfolio lives. Plain files (JSON, TOML, YAML, CSV, optionally
+// encrypted) are read and written whole; the SQLite backend keeps holdings,
+// transactions, snapshots and cached quotes in indexed tables so large
+// ledgers can be queried without loading everything.
+
+pub trait PortfolioStore {
+    fn load(&self) -> Result<Portfolio>;
+    fn save(&mut self, portfolio: &mut Portfolio) -> Result<()>;
+}
+
+/// Paths ending in `.db`, `.sqlite` or `.sqlite3` are SQLite databases.
+pub fn is_database_path(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|e| e.to_str()).map(|e| e.to_ascii_lowercase()).as_deref(),
+        Some("db") | Some("sqlite") | Some("sqlite3")
+    )
+}
+
+/// Open the store for an existing portfolio, picking the backend from the
+/// path.
+pub fn open(path: &Path) -> Result<Box<dyn PortfolioStore>> {
+    if is_database_path(path) {
+        open_database(path, false)
+    } else {
+        Ok(Box::new(FileStore::new(path)))
+    }
+}
+
+/// Like `open`, but a database that does not exist yet is created, for
+/// writing a portfolio out.
+pub fn create(path: &Path) -> Result<Box<dyn PortfolioStore>> {
+    if is_database_path(path) {
+        open_database(path, true)
+    } else {
+        Ok(Box::new(FileStore::new(path)))
+    }
+}
+
+#[cfg(feature = "sqlite")]
+fn open_database(path: &Path, create: bool) -> Result<Box<dyn PortfolioStore>> {
+    if create {
+        Ok(Box::new(crate::sqlite::SqliteStore::create(path)?))
+    } else {
+        Ok(Box::new(crate::sqlite::SqliteStore::open(path)?))
+    }
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn open_database(path: &Path, _create: bool) -> Result<Box<dyn PortfolioStore>> {
+    anyhow::bail!("{:?} is a SQLite database, but this build has no SQLite support (enable the `sqlite` feature)", path)
+}
+
+pub struct FileStore {
+    path: PathBuf,
+}
+
+impl FileStore {
+    pub fn new(path: &Path) -> Self {
+        FileStore { path: path.to_path_buf() }
+    }
+}
+
+impl PortfolioStore for FileStore {
+    fn load(&self) -> Result<Portfolio> {
+        let content = read_portfolio_text(&self.path)?;
+        Portfolio::from_str_with_format(&content, formats::input_format_for(&self.path))
+    }
+
+    /// Saves in the format the file is read in. If the file is already
+    /// encrypted it stays encrypted under the same passphrase.
+    fn save(&mut self, portfolio: &mut Portfolio) -> Result<()> {
+        let encrypt = fs::read(&self.path)
+            .map(|existing| crypto::is_encrypted(&existing))
+            .unwrap_or(false);
+        portfolio.save_to_file_as(&self.path, formats::input_format_for(&self.path), encrypt)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_is_database_path() {
+        assert!(is_database_path(Path::new("ledger.db")));
+        assert!(is_database_path(Path::new("ledger.SQLITE")));
+        assert!(is_database_path(Path::new("ledger.sqlite3")));
+        assert!(!is_database_path(Path::new("portfolio.json")));
+        assert!(!is_database_path(Path::new("portfolio")));
+    }
+
+    #[test]
+    fn test_file_store_round_trip() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("portfolio.toml");
+        fs::write(&path, "schema_version = 2\n\n[[holdings]]\nticker = \"AAPL\"\nquantity = 1\ncost_basis = 100.0\ndate_purchased = \"2024-01-01\"\n").unwrap();
+
+        let mut store = open(&path).unwrap();
+        let mut portfolio = store.load().unwrap();
+        portfolio.holdings[0].quantity = dec!(2);
+        store.save(&mut portfolio).unwrap();
+
+        assert!(fs::read_to_string(&path).unwrap().contains("[[holdings]]"));
+        assert_eq!(store.load().unwrap().holdings[0].quantity, dec!(2));
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn test_missing_database_is_only_created_for_writing() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("typo.db");
+
+        let error = open(&path).err().unwrap().to_string();
+        assert!(error.contains("not found"), "{}", error);
+        assert!(!path.exists());
+
+        let mut portfolio = Portfolio::default();
+        create(&path).unwrap().save(&mut portfolio).unwrap();
+        assert!(open(&path).unwrap().load().unwrap().holdings.is_empty());
+    }
+}
@@ -0,0 +1,668 @@
+use chrono::{Local, NaiveDate};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::schema::CURRENT_SCHEMA_VERSION;
+
+// Validation runs on the parsed (and migrated) JSON value rather than on the
+// typed structs, so that every problem in the file can be reported at once
+// instead of stopping at the first field serde fails to deserialize.
+
+pub const DATE_FORMAT: &str = "%Y-%m-%d";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// Where in the file a problem was found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    /// Top-level section, e.g. `holdings`; empty for the file itself.
+    pub section: String,
+    pub index: Option<usize>,
+    pub field: Option<String>,
+    /// 1-based line of the array entry in the source file, when known.
+    pub line: Option<usize>,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.section.is_empty() {
+            write!(f, "file")?;
+        } else {
+            write!(f, "{}", self.section)?;
+        }
+        if let Some(index) = self.index {
+            write!(f, "[{}]", index)?;
+        }
+        if let Some(field) = &self.field {
+            write!(f, ".{}", field)?;
+        }
+        if let Some(line) = self.line {
+            write!(f, " (line {})", line)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Issue {
+    pub severity: Severity,
+    pub location: Location,
+    pub message: String,
+    pub hint: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ValidationReport {
+    pub issues: Vec<Issue>,
+}
+
+impl ValidationReport {
+    pub fn has_errors(&self) -> bool {
+        self.issues.iter().any(|i| i.severity == Severity::Error)
+    }
+
+    pub fn error_count(&self) -> usize {
+        self.issues.iter().filter(|i| i.severity == Severity::Error).count()
+    }
+
+    pub fn warning_count(&self) -> usize {
+        self.issues.iter().filter(|i| i.severity == Severity::Warning).count()
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Portfolio file failed validation ({} error(s), {} warning(s)):",
+            self.error_count(),
+            self.warning_count()
+        )?;
+        for issue in &self.issues {
+            writeln!(f, "  {}: {}: {}", issue.severity, issue.location, issue.message)?;
+            if let Some(hint) = &issue.hint {
+                writeln!(f, "    hint: {}", hint)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ValidationReport {}
+
+/// Source line of every entry in the `holdings`, `transactions` and
+/// `watchlist` arrays.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EntryLines {
+    pub holdings: Vec<usize>,
+    pub transactions: Vec<usize>,
+    pub watchlist: Vec<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Section {
+    Holdings,
+    Transactions,
+    Watchlist,
+}
+
+enum Frame {
+    Object { key: Option<String>, after_colon: bool },
+    Array { section: Option<Section>, expecting_value: bool },
+}
+
+/// Find the line each array entry starts on by scanning the JSON text.
+/// A bare top-level array (schema version 1) is the holdings list.
+pub fn json_entry_lines(text: &str) -> EntryLines {
+    let mut lines = EntryLines::default();
+    let mut stack: Vec<Frame> = Vec::new();
+    let mut line = 1;
+    let mut chars = text.chars().peekable();
+
+    fn value_starts(stack: &mut [Frame], lines: &mut EntryLines, line: usize) {
+        if let Some(Frame::Array { section, expecting_value }) = stack.last_mut() {
+            if *expecting_value {
+                match section {
+                    Some(Section::Holdings) => lines.holdings.push(line),
+                    Some(Section::Transactions) => lines.transactions.push(line),
+                    Some(Section::Watchlist) => lines.watchlist.push(line),
+                    None => {}
+                }
+                *expecting_value = false;
+            }
+        }
+    }
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\n' => line += 1,
+            c if c.is_whitespace() => {}
+            '"' => {
+                let mut s = String::new();
+                while let Some(c) = chars.next() {
+                    match c {
+                        '\\' => {
+                            chars.next();
+                        }
+                        '"' => break,
+                        c => s.push(c),
+                    }
+                }
+                match stack.last_mut() {
+                    Some(Frame::Object { key, after_colon: false }) => *key = Some(s),
+                    _ => value_starts(&mut stack, &mut lines, line),
+                }
+            }
+            '{' => {
+                value_starts(&mut stack, &mut lines, line);
+                stack.push(Frame::Object { key: None, after_colon: false });
+            }
+            '[' => {
+                value_starts(&mut stack, &mut lines, line);
+                let section = match stack.as_slice() {
+                    [] => Some(Section::Holdings),
+                    [Frame::Object { key: Some(key), .. }] if key == "holdings" => Some(Section::Holdings),
+                    [Frame::Object { key: Some(key), .. }] if key == "transactions" => Some(Section::Transactions),
+                    [Frame::Object { key: Some(key), .. }] if key == "watchlist" => Some(Section::Watchlist),
+                    _ => None,
+                };
+                stack.push(Frame::Array { section, expecting_value: true });
+            }
+            ':' => {
+                if let Some(Frame::Object { after_colon, .. }) = stack.last_mut() {
+                    *after_colon = true;
+                }
+            }
+            ',' => match stack.last_mut() {
+                Some(Frame::Object { key, after_colon }) => {
+                    *key = None;
+                    *after_colon = false;
+                }
+                Some(Frame::Array { expecting_value, .. }) => *expecting_value = true,
+                None => {}
+            },
+            '}' | ']' => {
+                stack.pop();
+            }
+            _ => {
+                // Start of a number, true, false or null
+                value_starts(&mut stack, &mut lines, line);
+                while let Some(&next) = chars.peek() {
+                    if next == ',' || next == ']' || next == '}' || next.is_whitespace() {
+                        break;
+                    }
+                    chars.next();
+                }
+            }
+        }
+    }
+
+    lines
+}
+
+struct Validator<'a> {
+    report: ValidationReport,
+    lines: &'a EntryLines,
+    today: NaiveDate,
+}
+
+impl Validator<'_> {
+    fn location(&self, section: &str, index: Option<usize>, field: Option<&str>) -> Location {
+        let line = index.and_then(|i| match section {
+            "holdings" => self.lines.holdings.get(i).copied(),
+            "transactions" => self.lines.transactions.get(i).copied(),
+            "watchlist" => self.lines.watchlist.get(i).copied(),
+            _ => None,
+        });
+        Location {
+            section: section.to_string(),
+            index,
+            field: field.map(str::to_string),
+            line,
+        }
+    }
+
+    fn push(&mut self, severity: Severity, location: Location, message: String, hint: Option<&str>) {
+        self.report.issues.push(Issue {
+            severity,
+            location,
+            message,
+            hint: hint.map(str::to_string),
+        });
+    }
+
+    fn error(&mut self, section: &str, index: Option<usize>, field: Option<&str>, message: String, hint: &str) {
+        let location = self.location(section, index, field);
+        self.push(Severity::Error, location, message, Some(hint));
+    }
+
+    fn warning(&mut self, section: &str, index: Option<usize>, field: Option<&str>, message: String, hint: &str) {
+        let location = self.location(section, index, field);
+        self.push(Severity::Warning, location, message, Some(hint));
+    }
+
+    fn string_field<'v>(&mut self, entry: &'v Map<String, Value>, section: &str, index: usize, field: &str) -> Option<&'v str> {
+        match entry.get(field) {
+            Some(Value::String(s)) => Some(s),
+            Some(other) => {
+                self.error(section, Some(index), Some(field), format!("expected a string, found {}", other),
+                           &format!("write {} in double quotes", field));
+                None
+            }
+            None => {
+                self.error(section, Some(index), Some(field), format!("missing field `{}`", field),
+                           &format!("add a \"{}\" entry", field));
+                None
+            }
+        }
+    }
+
+    fn number_field(&mut self, entry: &Map<String, Value>, section: &str, index: usize, field: &str, required: bool) -> Option<f64> {
+        match entry.get(field) {
+            Some(Value::Number(n)) => n.as_f64(),
+            Some(other) => {
+                self.error(section, Some(index), Some(field), format!("expected a number, found {}", other),
+                           "write the number without quotes, e.g. 10 or 0.5");
+                None
+            }
+            None if required => {
+                self.error(section, Some(index), Some(field), format!("missing field `{}`", field),
+                           &format!("add a numeric \"{}\" entry", field));
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn date_field(&mut self, entry: &Map<String, Value>, section: &str, index: usize, field: &str) -> Option<NaiveDate> {
+        let raw = self.string_field(entry, section, index, field)?;
+        match NaiveDate::parse_from_str(raw, DATE_FORMAT) {
+            Ok(date) if date > self.today => {
+                self.error(section, Some(index), Some(field), format!("date {} is in the future", raw),
+                           "record the date the trade actually happened");
+                None
+            }
+            Ok(date) => Some(date),
+            Err(_) => {
+                self.error(section, Some(index), Some(field), format!("`{}` is not a valid date", raw),
+                           "use the YYYY-MM-DD format, e.g. 2024-06-01");
+                None
+            }
+        }
+    }
+
+    fn unknown_fields(&mut self, entry: &Map<String, Value>, section: &str, index: usize, known: &[&str]) {
+        for key in entry.keys() {
+            if !known.contains(&key.as_str()) {
+                self.warning(section, Some(index), Some(key), format!("unknown field `{}` will be ignored", key),
+                             &format!("expected one of: {}", known.join(", ")));
+            }
+        }
+    }
+
+    fn validate_holdings(&mut self, holdings: &[Value]) {
+        let mut seen: HashMap<String, usize> = HashMap::new();
+
+        for (index, entry) in holdings.iter().enumerate() {
+            let Some(entry) = entry.as_object() else {
+                self.error("holdings", Some(index), None, "holding must be an object".to_string(),
+                           "use {\"ticker\": ..., \"quantity\": ..., \"cost_basis\": ..., \"date_purchased\": ...}");
+                continue;
+            };
+
+            if let Some(ticker) = self.string_field(entry, "holdings", index, "ticker") {
+                if ticker.trim().is_empty() {
+                    self.error("holdings", Some(index), Some("ticker"), "ticker is empty".to_string(),
+                               "set the ticker symbol, e.g. AAPL or BTC-USD");
+                } else if ticker != ticker.trim().to_uppercase() {
+                    self.warning("holdings", Some(index), Some("ticker"),
+                                 format!("ticker `{}` is not upper-case", ticker),
+                                 &format!("price lookups expect `{}`", ticker.trim().to_uppercase()));
+                }
+            }
+
+            if let Some(quantity) = self.number_field(entry, "holdings", index, "quantity", true) {
+                if quantity < 0.0 {
+                    self.error("holdings", Some(index), Some("quantity"), format!("quantity is negative ({})", quantity),
+                               "holdings must be positive; record sales as sell transactions");
+                } else if quantity == 0.0 {
+                    self.warning("holdings", Some(index), Some("quantity"), "quantity is zero".to_string(),
+                                 "remove the holding if the position was closed");
+                }
+            }
+
+            if let Some(cost_basis) = self.number_field(entry, "holdings", index, "cost_basis", true) {
+                if cost_basis < 0.0 {
+                    self.error("holdings", Some(index), Some("cost_basis"),
+                               format!("cost basis is negative ({})", cost_basis),
+                               "use the price paid per unit, which cannot be negative");
+                } else if cost_basis == 0.0 {
+                    self.error("holdings", Some(index), Some("cost_basis"), "cost basis is zero".to_string(),
+                               "use the price paid per unit; returns cannot be computed from a zero cost");
+                }
+            }
+
+            self.date_field(entry, "holdings", index, "date_purchased");
+            self.unknown_fields(entry, "holdings", index, &["ticker", "quantity", "cost_basis", "date_purchased"]);
+
+            let key = serde_json::to_string(entry).unwrap_or_default();
+            if let Some(first) = seen.get(&key) {
+                self.warning("holdings", Some(index), None, format!("duplicate of holdings[{}]", first),
+                             "delete one of them unless you really bought the same lot twice");
+            } else {
+                seen.insert(key, index);
+            }
+        }
+    }
+
+    fn validate_transactions(&mut self, transactions: &[Value]) {
+        const KINDS: [&str; 6] = ["buy", "sell", "dividend", "deposit", "withdrawal", "fee"];
+
+        for (index, entry) in transactions.iter().enumerate() {
+            let Some(entry) = entry.as_object() else {
+                self.error("transactions", Some(index), None, "transaction must be an object".to_string(),
+                           "use {\"date\": ..., \"kind\": ..., ...}");
+                continue;
+            };
+
+            self.date_field(entry, "transactions", index, "date");
+
+            let kind = self.string_field(entry, "transactions", index, "kind");
+            if let Some(kind) = kind {
+                if !KINDS.contains(&kind) {
+                    self.error("transactions", Some(index), Some("kind"), format!("unknown transaction kind `{}`", kind),
+                               &format!("use one of: {}", KINDS.join(", ")));
+                }
+            }
+
+            let ticker = match entry.get("ticker") {
+                None | Some(Value::Null) => None,
+                Some(_) => self.string_field(entry, "transactions", index, "ticker"),
+            };
+            if matches!(kind, Some("buy" | "sell" | "dividend")) && ticker.is_none() {
+                self.error("transactions", Some(index), Some("ticker"),
+                           format!("{} transaction without a ticker", kind.unwrap_or_default()),
+                           "add the ticker the transaction refers to");
+            }
+
+            for field in ["quantity", "price", "fees"] {
+                if let Some(value) = self.number_field(entry, "transactions", index, field, false) {
+                    if value < 0.0 {
+                        self.error("transactions", Some(index), Some(field), format!("{} is negative ({})", field, value),
+                                   "amounts are always positive; the kind says which way the money moves");
+                    }
+                }
+            }
+
+            self.unknown_fields(entry, "transactions", index, &["date", "kind", "ticker", "quantity", "price", "fees", "note"]);
+        }
+    }
+
+    fn validate_watchlist(&mut self, watchlist: &[Value]) {
+        let mut seen: HashMap<String, usize> = HashMap::new();
+
+        for (index, entry) in watchlist.iter().enumerate() {
+            let Some(entry) = entry.as_object() else {
+                self.error("watchlist", Some(index), None, "watchlist entry must be an object".to_string(),
+                           "use {\"ticker\": ..., \"added\": ..., \"target_price\": ...}");
+                continue;
+            };
+
+            if let Some(ticker) = self.string_field(entry, "watchlist", index, "ticker") {
+                if ticker.trim().is_empty() {
+                    self.error("watchlist", Some(index), Some("ticker"), "ticker is empty".to_string(),
+                               "set the ticker symbol, e.g. AAPL or BTC-USD");
+                } else if let Some(first) = seen.get(ticker) {
+                    self.warning("watchlist", Some(index), Some("ticker"),
+                                 format!("{} is already watched in watchlist[{}]", ticker, first),
+                                 "keep one entry per ticker");
+                } else {
+                    seen.insert(ticker.to_string(), index);
+                }
+            }
+
+            self.date_field(entry, "watchlist", index, "added");
+            for field in ["price_added", "target_price"] {
+                if let Some(price) = self.number_field(entry, "watchlist", index, field, false) {
+                    if price <= 0.0 {
+                        self.error("watchlist", Some(index), Some(field), format!("{} must be positive ({})", field, price),
+                                   "use a price per unit, or leave the field out");
+                    }
+                }
+            }
+            if entry.contains_key("note") {
+                self.string_field(entry, "watchlist", index, "note");
+            }
+            self.unknown_fields(entry, "watchlist", index, &["ticker", "added", "price_added", "target_price", "note"]);
+        }
+    }
+}
+
+/// The day future-date checks compare against: the local calendar day, so
+/// dates stamped with it (a new watchlist entry, a shell `add`) always pass.
+pub fn local_today() -> NaiveDate {
+    Local::now().date_naive()
+}
+
+/// Validate a portfolio document in the current schema version.
+pub fn validate_document(document: &Value, lines: &EntryLines) -> ValidationReport {
+    validate_document_on(document, lines, local_today())
+}
+
+/// Like `validate_document`, with "today" fixed for future-date checks.
+pub fn validate_document_on(document: &Value, lines: &EntryLines, today: NaiveDate) -> ValidationReport {
+    let mut validator = Validator {
+        report: ValidationReport::default(),
+        lines,
+        today,
+    };
+
+    let Some(root) = document.as_object() else {
+        validator.error("", None, None, "portfolio must be a JSON object".to_string(),
+                        &format!("run `portfolio_rs migrate` to upgrade to schema version {}", CURRENT_SCHEMA_VERSION));
+        return validator.report;
+    };
+
+    match root.get("base_currency") {
+        None => {}
+        Some(Value::String(code)) if code.len() == 3 && code.chars().all(|c| c.is_ascii_uppercase()) => {}
+        Some(other) => validator.error("base_currency", None, None,
+                                       format!("invalid currency code {}", other),
+                                       "use a three-letter ISO 4217 code such as USD or EUR"),
+    }
+
+    match root.get("holdings") {
+        None => {}
+        Some(Value::Array(holdings)) => validator.validate_holdings(holdings),
+        Some(_) => validator.error("holdings", None, None, "holdings must be a list".to_string(),
+                                   "use [ ... ] around the holdings"),
+    }
+
+    match root.get("transactions") {
+        None => {}
+        Some(Value::Array(transactions)) => validator.validate_transactions(transactions),
+        Some(_) => validator.error("transactions", None, None, "transactions must be a list".to_string(),
+                                   "use [ ... ] around the transactions"),
+    }
+
+    match root.get("watchlist") {
+        None => {}
+        Some(Value::Array(watchlist)) => validator.validate_watchlist(watchlist),
+        Some(_) => validator.error("watchlist", None, None, "watchlist must be a list".to_string(),
+                                   "use [ ... ] around the watchlist"),
+    }
+
+    validator.report.issues.sort_by_key(|i| (i.location.line.unwrap_or(0), i.severity));
+    validator.report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{FixedOffset, TimeZone, Utc};
+    use serde_json::json;
+
+    fn today() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2025, 1, 1).unwrap()
+    }
+
+    fn holdings_doc(holdings: Value) -> Value {
+        json!({ "schema_version": 2, "holdings": holdings })
+    }
+
+    fn messages(report: &ValidationReport) -> Vec<String> {
+        report.issues.iter().map(|i| format!("{} {}", i.location, i.message)).collect()
+    }
+
+    #[test]
+    fn test_valid_document_has_no_issues() {
+        let doc = holdings_doc(json!([
+            {"ticker": "AAPL", "quantity": 10, "cost_basis": 150, "date_purchased": "2024-06-01"}
+        ]));
+        let report = validate_document_on(&doc, &EntryLines::default(), today());
+        assert!(report.issues.is_empty(), "{:?}", messages(&report));
+    }
+
+    #[test]
+    fn test_reports_every_problem() {
+        let doc = holdings_doc(json!([
+            {"ticker": "AAPL", "quantity": -5, "cost_basis": 0, "date_purchased": "soon"},
+            {"ticker": "TSLA", "quantity": 1, "cost_basis": 200, "date_purchased": "2030-01-01"}
+        ]));
+        let report = validate_document_on(&doc, &EntryLines::default(), today());
+
+        assert_eq!(report.error_count(), 4, "{:?}", messages(&report));
+        let text = messages(&report).join("\n");
+        assert!(text.contains("holdings[0].quantity quantity is negative"));
+        assert!(text.contains("holdings[0].cost_basis cost basis is zero"));
+        assert!(text.contains("holdings[0].date_purchased `soon` is not a valid date"));
+        assert!(text.contains("holdings[1].date_purchased date 2030-01-01 is in the future"));
+    }
+
+    #[test]
+    fn test_type_errors_and_missing_fields() {
+        let doc = holdings_doc(json!([{"ticker": "AAPL", "quantity": "ten"}]));
+        let report = validate_document_on(&doc, &EntryLines::default(), today());
+        let text = messages(&report).join("\n");
+        assert!(text.contains("quantity expected a number"));
+        assert!(text.contains("missing field `cost_basis`"));
+        assert!(text.contains("missing field `date_purchased`"));
+    }
+
+    #[test]
+    fn test_duplicates_are_warnings() {
+        let lot = json!({"ticker": "AAPL", "quantity": 10, "cost_basis": 150, "date_purchased": "2024-06-01"});
+        let doc = holdings_doc(json!([lot.clone(), lot]));
+        let report = validate_document_on(&doc, &EntryLines::default(), today());
+        assert!(!report.has_errors());
+        assert_eq!(report.warning_count(), 1);
+        assert_eq!(report.issues[0].message, "duplicate of holdings[0]");
+    }
+
+    #[test]
+    fn test_transactions_checks() {
+        let doc = json!({
+            "schema_version": 2,
+            "base_currency": "usd",
+            "transactions": [
+                {"date": "2024-01-01", "kind": "buy", "quantity": 1, "price": 10},
+                {"date": "2024-01-02", "kind": "gift", "price": -3}
+            ]
+        });
+        let report = validate_document_on(&doc, &EntryLines::default(), today());
+        let text = messages(&report).join("\n");
+        assert!(text.contains("invalid currency code"));
+        assert!(text.contains("transactions[0].ticker buy transaction without a ticker"));
+        assert!(text.contains("unknown transaction kind `gift`"));
+        assert!(text.contains("transactions[1].price price is negative"));
+    }
+
+    #[test]
+    fn test_watchlist_checks() {
+        let doc = json!({
+            "schema_version": 3,
+            "watchlist": [
+                {"ticker": "NVDA", "added": "2024-06-01", "target_price": 100, "note": "wait for a pullback"},
+                {"ticker": "NVDA", "added": "2024-06-02"},
+                {"ticker": "AMD", "target_price": -1, "note": 5, "alert": true}
+            ]
+        });
+        let report = validate_document_on(&doc, &EntryLines::default(), today());
+        let text = messages(&report).join("\n");
+        assert_eq!(report.error_count(), 3, "{}", text);
+        assert!(text.contains("watchlist[1].ticker NVDA is already watched in watchlist[0]"));
+        assert!(text.contains("watchlist[2].added missing field `added`"));
+        assert!(text.contains("watchlist[2].target_price target_price must be positive"));
+        assert!(text.contains("watchlist[2].note expected a string"));
+        assert!(text.contains("watchlist[2].alert unknown field `alert`"));
+    }
+
+    #[test]
+    fn test_watch_stamp_is_never_in_the_future() {
+        let watch = |added: NaiveDate| json!({
+            "schema_version": 3,
+            "watchlist": [{"ticker": "NVDA", "added": added.format(DATE_FORMAT).to_string()}]
+        });
+
+        // 20:00 on January 1st five hours west of UTC is already January 2nd in UTC
+        let evening = FixedOffset::west_opt(5 * 3600).unwrap().with_ymd_and_hms(2025, 1, 1, 20, 0, 0).unwrap();
+        let local = evening.date_naive();
+        let utc = evening.with_timezone(&Utc).date_naive();
+        assert!(!validate_document_on(&watch(local), &EntryLines::default(), local).has_errors());
+        assert!(validate_document_on(&watch(utc), &EntryLines::default(), local).has_errors());
+
+        assert!(!validate_document(&watch(local_today()), &EntryLines::default()).has_errors());
+    }
+
+    #[test]
+    fn test_json_entry_lines_legacy_array() {
+        let text = "[\n  {\"ticker\": \"AAPL\"},\n\n  {\n    \"ticker\": \"[TSLA]\"\n  }\n]";
+        let lines = json_entry_lines(text);
+        assert_eq!(lines.holdings, vec![2, 4]);
+        assert!(lines.transactions.is_empty());
+    }
+
+    #[test]
+    fn test_json_entry_lines_envelope() {
+        let text = r#"{
+  "schema_version": 2,
+  "metadata": {"tags": ["a", "b"]},
+  "holdings": [
+    {"ticker": "AAPL"},
+    {"ticker": "MSFT"}
+  ],
+  "transactions": [
+    {"date": "2024-01-01", "kind": "deposit", "price": 5}
+  ],
+  "watchlist": [
+    {"ticker": "NVDA", "added": "2024-06-01"}
+  ]
+}"#;
+        let lines = json_entry_lines(text);
+        assert_eq!(lines.holdings, vec![5, 6]);
+        assert_eq!(lines.transactions, vec![9]);
+        assert_eq!(lines.watchlist, vec![12]);
+    }
+
+    #[test]
+    fn test_location_display_includes_line() {
+        let doc = holdings_doc(json!([{"ticker": "AAPL", "quantity": -1, "cost_basis": 1, "date_purchased": "2024-01-01"}]));
+        let lines = EntryLines { holdings: vec![7], ..EntryLines::default() };
+        let report = validate_document_on(&doc, &lines, today());
+        assert_eq!(report.issues[0].location.to_string(), "holdings[0].quantity (line 7)");
+    }
+}
@@ -2,6 +2,7 @@
 mod benchmark_tests {
     use portfolio::portfolio::Portfolio;
     use portfolio::api::fetch_current_prices;
+    use rust_decimal::Decimal;
     use std::time::Instant;
     use tempfile::tempdir;
     use std::fs;
@@ -54,13 +55,13 @@ mod benchmark_tests {
 
         // Perform calculations on all holdings
         let start = Instant::now();
-        let total_value: f64 = holdings_with_prices.iter()
+        let total_value: Decimal = holdings_with_prices.iter()
             .map(|h| h.current_value())
             .sum();
-        let total_cost: f64 = holdings_with_prices.iter()
+        let total_cost: Decimal = holdings_with_prices.iter()
             .map(|h| h.total_cost())
             .sum();
-        let total_gain_loss: f64 = holdings_with_prices.iter()
+        let total_gain_loss: Decimal = holdings_with_prices.iter()
             .map(|h| h.gain_loss())
             .sum();
         let analysis_time = start.elapsed();
@@ -69,8 +70,8 @@ mod benchmark_tests {
         assert_eq!(portfolio.holdings.len(), 10);
         assert_eq!(prices.len(), 10);
         assert_eq!(holdings_with_prices.len(), 10);
-        assert!(total_value > 0.0);
-        assert!(total_cost > 0.0);
+        assert!(total_value > Decimal::ZERO);
+        assert!(total_cost > Decimal::ZERO);
 
         // Print timing information (these will show up in test output)
         println!("Benchmark results:");
@@ -1,5 +1,7 @@
 use portfolio::api::{fetch_current_prices};
 use portfolio::portfolio::{Portfolio};
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
 use std::fs;
 use tempfile::tempdir;
 
@@ -44,9 +46,9 @@ async fn test_portfolio_loading_and_price_fetching() {
     assert_eq!(holdings_with_prices.len(), 2);
 
     for holding_with_price in &holdings_with_prices {
-        assert!(holding_with_price.current_price > 0.0);
-        assert!(holding_with_price.current_value() > 0.0);
-        assert!(holding_with_price.total_cost() > 0.0);
+        assert!(holding_with_price.current_price > Decimal::ZERO);
+        assert!(holding_with_price.current_value() > Decimal::ZERO);
+        assert!(holding_with_price.total_cost() > Decimal::ZERO);
     }
 }
 
@@ -74,17 +76,17 @@ async fn test_portfolio_calculations() {
     let holding_with_price = &holdings_with_prices[0];
     
     // Test calculations
-    assert_eq!(holding_with_price.total_cost(), 1500.0); // 10 * 150
+    assert_eq!(holding_with_price.total_cost(), dec!(1500)); // 10 * 150
     
     // Current value should be 10 * current_price (which is 170.0 for AAPL mock)
-    assert_eq!(holding_with_price.current_value(), 1700.0); // 10 * 170
+    assert_eq!(holding_with_price.current_value(), dec!(1700)); // 10 * 170
     
     // Gain/loss should be current_value - total_cost
-    assert_eq!(holding_with_price.gain_loss(), 200.0); // 1700 - 1500
+    assert_eq!(holding_with_price.gain_loss(), dec!(200)); // 1700 - 1500
     
     // Percentage should be (gain_loss / total_cost) * 100
-    let expected_percentage = (200.0 / 1500.0) * 100.0;
-    assert!((holding_with_price.gain_loss_percentage() - expected_percentage).abs() < 0.001);
+    let expected_percentage = dec!(200) / dec!(1500) * dec!(100);
+    assert_eq!(holding_with_price.gain_loss_percentage(), expected_percentage);
 }
 
 #[tokio::test]
@@ -106,3 +108,31 @@ async fn test_empty_portfolio() {
     let holdings_with_prices = portfolio.holdings_with_prices(&prices);
     assert!(holdings_with_prices.is_empty());
 }
+
+#[tokio::test]
+async fn test_encrypted_portfolio_loads_transparently() {
+    use portfolio::crypto::{encrypt_with_params, KdfParams, PASSPHRASE_ENV};
+
+    let dir = tempdir().unwrap();
+    let file_path = dir.path().join("encrypted_portfolio.json");
+
+    let test_data = r#"[
+        {
+            "ticker": "BTC-USD",
+            "quantity": 0.5,
+            "cost_basis": 45000.0,
+            "date_purchased": "2024-01-15"
+        }
+    ]"#;
+
+    let fast = KdfParams { memory_kib: 64, iterations: 1, parallelism: 1 };
+    let encrypted = encrypt_with_params(test_data.as_bytes(), "correct horse", fast).unwrap();
+    fs::write(&file_path, encrypted).unwrap();
+
+    std::env::set_var(PASSPHRASE_ENV, "correct horse");
+    let portfolio = Portfolio::load_from_file(&file_path).unwrap();
+
+    assert_eq!(portfolio.holdings.len(), 1);
+    assert_eq!(portfolio.holdings[0].ticker, "BTC-USD");
+    assert_eq!(portfolio.holdings[0].quantity, dec!(0.5));
+}